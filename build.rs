@@ -0,0 +1,67 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resolve the directory of the interfaces embedded with the `embedded-interfaces` feature.
+
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=EDGEHOG_INTERFACES_DIR");
+
+    if std::env::var_os("CARGO_FEATURE_EMBEDDED_INTERFACES").is_none() {
+        return;
+    }
+
+    let directory = match std::env::var_os("EDGEHOG_INTERFACES_DIR") {
+        Some(directory) => {
+            let directory = PathBuf::from(directory);
+
+            if !directory.is_dir() {
+                panic!(
+                    "EDGEHOG_INTERFACES_DIR is not a directory: {}",
+                    directory.display()
+                );
+            }
+
+            println!("cargo:rerun-if-changed={}", directory.display());
+
+            directory
+        }
+        None => {
+            println!(
+                "cargo:warning=EDGEHOG_INTERFACES_DIR is not set, no interfaces will be embedded"
+            );
+
+            let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo");
+            let directory = PathBuf::from(out_dir).join("interfaces");
+            std::fs::create_dir_all(&directory).expect("couldn't create the interfaces directory");
+
+            directory
+        }
+    };
+
+    let directory = directory
+        .canonicalize()
+        .expect("couldn't resolve the interfaces directory");
+
+    println!(
+        "cargo:rustc-env=EDGEHOG_EMBEDDED_INTERFACES_DIR={}",
+        directory.display()
+    );
+}
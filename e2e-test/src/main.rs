@@ -27,6 +27,7 @@ use tempdir::TempDir;
 
 use edgehog_device_runtime::data::astarte_device_sdk_lib::AstarteDeviceSdkConfigOptions;
 use edgehog_device_runtime::data::connect_store;
+use edgehog_device_runtime::data::connection::{ConnectionState, ConnectionTracker};
 use edgehog_device_runtime::e2e_test::{get_hardware_info, get_os_info, get_runtime_info};
 use edgehog_device_runtime::{AstarteLibrary, DeviceManager, DeviceManagerOptions};
 
@@ -78,17 +79,47 @@ async fn main() -> Result<(), edgehog_device_runtime::error::DeviceManagerError>
         pairing_url: pairing_url.to_string(),
         pairing_token: None,
         ignore_ssl,
+        ca_bundle: None,
     };
 
     let device_options = DeviceManagerOptions {
         astarte_library: AstarteLibrary::AstarteDeviceSDK,
         astarte_device_sdk: Some(astarte_options.clone()),
-        interfaces_directory,
+        interfaces_directory: interfaces_directory.clone().into(),
         store_directory: store_path.path().to_owned(),
         download_directory: PathBuf::new(),
         telemetry_config: Some(vec![]),
+        telemetry_overrides_file: None,
+        attributes: Default::default(),
+        rate_limits: Default::default(),
+        update_polling: None,
+        ota_self_test: None,
+        ota_download: Default::default(),
+        geolocation: None,
+        property_cache: Default::default(),
+        offline_queue: Default::default(),
+        decimation: Vec::new(),
+        watchdog: Default::default(),
+        health: None,
+        logging: Default::default(),
+        secondary_connections: Vec::new(),
+        custom_commands: Default::default(),
+        factory_reset: Default::default(),
+        shutdown: Default::default(),
+        dbus_service: None,
+        connectivity: None,
+        proxy: Default::default(),
+        integrity: None,
+        log_forwarding: None,
+        crash_reports: Default::default(),
+        #[cfg(feature = "systemd")]
+        logs: None,
+        #[cfg(feature = "metrics")]
+        metrics: None,
         #[cfg(feature = "message-hub")]
         astarte_message_hub: None,
+        #[cfg(feature = "forwarder")]
+        forwarder: Default::default(),
     };
 
     let store = connect_store(store_path.path())
@@ -99,12 +130,15 @@ async fn main() -> Result<(), edgehog_device_runtime::error::DeviceManagerError>
         .connect(
             store,
             &device_options.store_directory,
-            &device_options.interfaces_directory,
+            &interfaces_directory,
         )
         .await
         .expect("couldn't connect to astarte");
 
-    let dm = DeviceManager::new(device_options, publisher, subscriber).await?;
+    let connection = ConnectionState::default();
+    let publisher = ConnectionTracker::new(publisher, connection.clone());
+
+    let dm = DeviceManager::new(device_options, publisher, subscriber, connection).await?;
 
     dm.init().await?;
 
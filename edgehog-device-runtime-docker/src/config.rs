@@ -0,0 +1,184 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Configuration to reach the container engine.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::error::DockerError;
+
+/// Default timeout in seconds for the requests to the container engine.
+pub const DEFAULT_TIMEOUT: u64 = 120;
+
+/// Configuration of the containers service.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ContainersConfig {
+    /// Address of the container engine.
+    ///
+    /// It can be a Unix socket `unix:///run/docker.sock` or a TCP address `tcp://host:port`. If
+    /// missing, the local defaults are used.
+    pub host: Option<String>,
+    /// Client certificates used to connect to a TCP address with TLS.
+    ///
+    /// They are required for an `https://` address, and can't be used with a Unix socket.
+    pub tls: Option<TlsConfig>,
+    /// Timeout in seconds for the requests, defaults to [`DEFAULT_TIMEOUT`].
+    pub timeout: Option<u64>,
+}
+
+/// Certificates for a TLS connection to the container engine.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TlsConfig {
+    /// Path to the CA certificate.
+    pub ca: PathBuf,
+    /// Path to the client certificate.
+    pub cert: PathBuf,
+    /// Path to the client private key.
+    pub key: PathBuf,
+}
+
+/// Parsed address of the container engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Host<'a> {
+    /// Local defaults of the client.
+    Local,
+    /// Unix socket.
+    Unix(&'a str),
+    /// TCP address.
+    Tcp(&'a str),
+    /// TCP address with TLS.
+    Tls(&'a str, &'a TlsConfig),
+}
+
+impl ContainersConfig {
+    /// Host to connect to, checking the TLS configuration matches it.
+    pub(crate) fn host(&self) -> Result<Host<'_>, DockerError> {
+        let Some(host) = self.host.as_deref() else {
+            return match self.tls {
+                Some(_) => Err(DockerError::UnexpectedTls),
+                None => Ok(Host::Local),
+            };
+        };
+
+        if host.starts_with("unix://") {
+            if self.tls.is_some() {
+                return Err(DockerError::UnexpectedTls);
+            }
+
+            return Ok(Host::Unix(host));
+        }
+
+        if !["tcp://", "http://", "https://"]
+            .iter()
+            .any(|scheme| host.starts_with(scheme))
+        {
+            return Err(DockerError::InvalidHost(host.to_string()));
+        }
+
+        match &self.tls {
+            Some(tls) => Ok(Host::Tls(host, tls)),
+            None if host.starts_with("https://") => Err(DockerError::MissingTls(host.to_string())),
+            None => Ok(Host::Tcp(host)),
+        }
+    }
+
+    pub(crate) fn timeout(&self) -> u64 {
+        self.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(host: &str) -> ContainersConfig {
+        ContainersConfig {
+            host: Some(host.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_host() {
+        assert_eq!(ContainersConfig::default().host().unwrap(), Host::Local);
+        assert_eq!(
+            config("unix:///run/docker.sock").host().unwrap(),
+            Host::Unix("unix:///run/docker.sock")
+        );
+        assert_eq!(
+            config("tcp://10.0.0.1:2376").host().unwrap(),
+            Host::Tcp("tcp://10.0.0.1:2376")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_host() {
+        let res = config("/run/docker.sock").host();
+
+        assert!(matches!(res, Err(DockerError::InvalidHost(host)) if host == "/run/docker.sock"));
+    }
+
+    fn tls() -> TlsConfig {
+        TlsConfig {
+            ca: PathBuf::from("/etc/docker/ca.pem"),
+            cert: PathBuf::from("/etc/docker/cert.pem"),
+            key: PathBuf::from("/etc/docker/key.pem"),
+        }
+    }
+
+    #[test]
+    fn parse_tls_host() {
+        let tls = tls();
+        let config = ContainersConfig {
+            host: Some("https://10.0.0.1:2376".to_string()),
+            tls: Some(tls.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.host().unwrap(),
+            Host::Tls("https://10.0.0.1:2376", &tls)
+        );
+    }
+
+    #[test]
+    fn https_without_tls() {
+        let res = config("https://10.0.0.1:2376").host();
+
+        assert!(
+            matches!(res, Err(DockerError::MissingTls(host)) if host == "https://10.0.0.1:2376")
+        );
+    }
+
+    #[test]
+    fn tls_without_tcp_host() {
+        let unix = ContainersConfig {
+            tls: Some(tls()),
+            ..config("unix:///run/docker.sock")
+        };
+        assert!(matches!(unix.host(), Err(DockerError::UnexpectedTls)));
+
+        let local = ContainersConfig {
+            tls: Some(tls()),
+            ..Default::default()
+        };
+        assert!(matches!(local.host(), Err(DockerError::UnexpectedTls)));
+    }
+}
@@ -23,7 +23,12 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
+#[cfg(not(feature = "mock"))]
+use bollard::API_DEFAULT_VERSION;
+use tracing::debug;
+
 use crate::client::*;
+use crate::config::{ContainersConfig, Host};
 use crate::error::DockerError;
 
 /// Docker container manager
@@ -34,16 +39,55 @@ pub struct Docker {
 
 impl Docker {
     /// Create a new Docker container manager
-    #[cfg(not(feature = "mock"))]
     pub fn connect() -> Result<Self, DockerError> {
-        let client = Client::connect_with_local_defaults().map_err(DockerError::Connection)?;
+        Self::connect_with(&ContainersConfig::default())
+    }
+
+    /// Create a new Docker container manager connecting to the configured host
+    #[cfg(not(feature = "mock"))]
+    pub fn connect_with(config: &ContainersConfig) -> Result<Self, DockerError> {
+        let timeout = config.timeout();
+
+        let client = match config.host()? {
+            Host::Local => Client::connect_with_local_defaults(),
+            Host::Unix(path) => {
+                debug!("connecting to unix socket {path}");
+
+                Client::connect_with_unix(path, timeout, API_DEFAULT_VERSION)
+            }
+            Host::Tls(addr, tls) => {
+                debug!("connecting to {addr} with TLS");
+
+                Client::connect_with_ssl(
+                    addr,
+                    &tls.key,
+                    &tls.cert,
+                    &tls.ca,
+                    timeout,
+                    API_DEFAULT_VERSION,
+                )
+            }
+            Host::Tcp(addr) => {
+                debug!("connecting to {addr}");
+
+                Client::connect_with_http(addr, timeout, API_DEFAULT_VERSION)
+            }
+        }
+        .map_err(DockerError::Connection)?;
 
         Ok(Self { client })
     }
 
-    /// Create a new Docker container manager
+    /// Create a new Docker container manager connecting to the configured host
     #[cfg(feature = "mock")]
-    pub fn connect() -> Result<Self, DockerError> {
+    pub fn connect_with(config: &ContainersConfig) -> Result<Self, DockerError> {
+        match config.host()? {
+            Host::Local => debug!("mocking the local connection"),
+            Host::Unix(addr) | Host::Tcp(addr) | Host::Tls(addr, _) => {
+                debug!("mocking the connection to {addr}")
+            }
+        }
+
         let client = Client::new();
 
         Ok(Self { client })
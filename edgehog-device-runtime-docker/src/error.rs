@@ -26,4 +26,25 @@ pub enum DockerError {
     Connection(#[source] bollard::errors::Error),
     /// couldn't ping the docker daemon
     Ping(#[source] bollard::errors::Error),
+    /// invalid docker host {0}, expected a unix:// or tcp:// address
+    InvalidHost(String),
+    /// the docker host {0} uses https, but the tls certificates are missing
+    MissingTls(String),
+    /// the tls certificates can be used only with a tcp:// or https:// docker host
+    UnexpectedTls,
+    /// couldn't get the information of the docker daemon
+    Info(#[source] bollard::errors::Error),
+    /// couldn't pull the image
+    PullImage(#[source] bollard::errors::Error),
+    /// couldn't inspect the image
+    InspectImage(#[source] bollard::errors::Error),
+    /// image {image} is built for {image_platform}, but the device is {device_platform}
+    IncompatibleImage {
+        /// Name of the image.
+        image: String,
+        /// Operating system and architecture of the image.
+        image_platform: String,
+        /// Operating system and architecture of the device.
+        device_platform: String,
+    },
 }
@@ -0,0 +1,446 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pull of the images for the device platform.
+
+use std::fmt::Display;
+
+use bollard::image::CreateImageOptions;
+use bollard::models::SystemInfo;
+use futures::{future, TryStreamExt};
+use tracing::{debug, warn};
+
+#[cfg(feature = "mock")]
+use crate::client::DockerTrait;
+use crate::docker::Docker;
+use crate::error::DockerError;
+
+/// Operating system and architecture, using the names of the OCI image specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    /// Operating system, e.g. `linux`.
+    pub os: String,
+    /// CPU architecture, e.g. `amd64` or `arm64`.
+    pub architecture: String,
+    /// Variant of the CPU architecture, e.g. `v7` for `arm`.
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    /// Platform the runtime was compiled for.
+    pub fn runtime() -> Self {
+        let (architecture, variant) = oci_architecture(std::env::consts::ARCH);
+
+        Self {
+            os: std::env::consts::OS.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.or_else(runtime_variant).map(str::to_string),
+        }
+    }
+
+    /// Platform of the host, as reported by the `/info` of the container engine.
+    ///
+    /// Falls back to the platform of the runtime if the engine doesn't report it.
+    fn from_engine(info: &SystemInfo) -> Self {
+        let (Some(os), Some(arch)) = (&info.os_type, &info.architecture) else {
+            warn!("the container engine didn't report its platform, using the runtime one");
+
+            return Self::runtime();
+        };
+
+        let (architecture, variant) = oci_architecture(arch);
+
+        Self {
+            os: os.clone(),
+            architecture: architecture.to_string(),
+            variant: variant.map(str::to_string),
+        }
+    }
+
+    /// Check that an image built for `image` can run on this platform.
+    ///
+    /// Missing information in the image configuration is accepted.
+    fn is_compatible(&self, image: &PartialPlatform) -> bool {
+        let os = image.os.as_deref().map_or(true, |os| os == self.os);
+        let architecture = image
+            .architecture
+            .as_deref()
+            .map_or(true, |arch| arch == self.architecture);
+
+        os && architecture && self.is_variant_compatible(image.variant.as_deref())
+    }
+
+    /// An image for an older variant runs on a newer one, e.g. `arm/v6` on `arm/v7`.
+    fn is_variant_compatible(&self, image: Option<&str>) -> bool {
+        let (Some(device), Some(image)) = (self.variant.as_deref(), image) else {
+            return true;
+        };
+
+        match (variant_version(device), variant_version(image)) {
+            (Some(device), Some(image)) => image <= device,
+            _ => device == image,
+        }
+    }
+}
+
+/// Formats the platform as `os/architecture[/variant]`, like the `platform` option of the engine.
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Platform of an image, as reported by the container engine.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PartialPlatform {
+    os: Option<String>,
+    architecture: Option<String>,
+    variant: Option<String>,
+}
+
+impl Display for PartialPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            self.os.as_deref().unwrap_or("unknown"),
+            self.architecture.as_deref().unwrap_or("unknown")
+        )?;
+
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert an architecture name into the architecture and variant used by the OCI images.
+///
+/// Both the Rust names and the kernel ones, reported by the container engine, are accepted, e.g.
+/// `aarch64` is `arm64` and `armv7l` is `arm` with the `v7` variant. The variant of the Rust `arm`
+/// architecture isn't part of the name, so it's `None`.
+pub fn oci_architecture(arch: &str) -> (&str, Option<&str>) {
+    let architecture = match arch {
+        "x86_64" => "amd64",
+        "x86" | "i386" | "i486" | "i586" | "i686" => "386",
+        "aarch64" => "arm64",
+        "loongarch64" => "loong64",
+        "powerpc64" => "ppc64",
+        arch => arch,
+    };
+
+    // Kernel names of the 32 bit ARM, like `armv7l` or `armv5tel`
+    let digits = arch.strip_prefix("armv").map_or(0, |rest| {
+        rest.bytes().take_while(u8::is_ascii_digit).count()
+    });
+
+    if digits > 0 {
+        // Skip the `arm` prefix, keeping the `v` and the version
+        return ("arm", Some(&arch[3..4 + digits]));
+    }
+
+    (architecture, None)
+}
+
+/// Variant of the architecture the runtime was compiled for.
+///
+/// Only the 32 bit ARM variants are detected, the other architectures have a single variant in
+/// practice.
+fn runtime_variant() -> Option<&'static str> {
+    if !cfg!(target_arch = "arm") {
+        None
+    } else if cfg!(target_feature = "v7") {
+        Some("v7")
+    } else if cfg!(target_feature = "v6") {
+        Some("v6")
+    } else {
+        Some("v5")
+    }
+}
+
+/// Version number of a variant like `v7`.
+fn variant_version(variant: &str) -> Option<u32> {
+    variant.strip_prefix('v')?.parse().ok()
+}
+
+impl Docker {
+    /// Platform of the device, as reported by the container engine.
+    pub async fn platform(&self) -> Result<Platform, DockerError> {
+        let info = self.client.info().await.map_err(DockerError::Info)?;
+
+        Ok(Platform::from_engine(&info))
+    }
+
+    /// Pull an image for the device platform and check that it can run on the device.
+    ///
+    /// The platform is passed to the engine, so a multi-platform image resolves to the device
+    /// one. An image with a single platform is pulled anyway, so it's checked after the pull to
+    /// fail with a clear error instead of the one returned by the engine when the container is
+    /// started.
+    pub async fn pull_image(&self, image: &str) -> Result<(), DockerError> {
+        let device_platform = self.platform().await?;
+
+        let options = CreateImageOptions {
+            from_image: image.to_string(),
+            platform: device_platform.to_string(),
+            ..Default::default()
+        };
+
+        debug!("pulling image {image} for {device_platform}");
+
+        self.client
+            .create_image(Some(options), None, None)
+            .try_for_each(|info| {
+                if let Some(status) = info.status {
+                    debug!("pulling image {image}: {status}");
+                }
+
+                future::ok(())
+            })
+            .await
+            .map_err(DockerError::PullImage)?;
+
+        self.check_platform(image, &device_platform).await
+    }
+
+    /// Check that a pulled image can run on the device.
+    ///
+    /// This should be called before creating a container, to fail with a clear error instead of
+    /// the one returned by the engine when the container is started.
+    pub async fn check_image_platform(&self, image: &str) -> Result<(), DockerError> {
+        let device_platform = self.platform().await?;
+
+        self.check_platform(image, &device_platform).await
+    }
+
+    async fn check_platform(
+        &self,
+        image: &str,
+        device_platform: &Platform,
+    ) -> Result<(), DockerError> {
+        let inspect = self
+            .client
+            .inspect_image(image)
+            .await
+            .map_err(DockerError::InspectImage)?;
+
+        let image_platform = PartialPlatform {
+            os: inspect.os,
+            architecture: inspect.architecture,
+            variant: inspect.variant,
+        };
+        debug!("image {image} built for {image_platform}, device is {device_platform}");
+
+        if !device_platform.is_compatible(&image_platform) {
+            return Err(DockerError::IncompatibleImage {
+                image: image.to_string(),
+                image_platform: image_platform.to_string(),
+                device_platform: device_platform.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(os: &str, arch: &str) -> Platform {
+        Platform {
+            os: os.to_string(),
+            architecture: arch.to_string(),
+            variant: None,
+        }
+    }
+
+    fn image(os: Option<&str>, arch: Option<&str>) -> PartialPlatform {
+        PartialPlatform {
+            os: os.map(str::to_string),
+            architecture: arch.map(str::to_string),
+            variant: None,
+        }
+    }
+
+    #[test]
+    fn compatible_platform() {
+        let device = platform("linux", "arm64");
+
+        assert!(device.is_compatible(&image(Some("linux"), Some("arm64"))));
+        assert!(device.is_compatible(&image(None, None)));
+        assert!(!device.is_compatible(&image(Some("linux"), Some("amd64"))));
+        assert!(!device.is_compatible(&image(Some("windows"), Some("arm64"))));
+    }
+
+    #[test]
+    fn compatible_variant() {
+        let device = Platform {
+            variant: Some("v7".to_string()),
+            ..platform("linux", "arm")
+        };
+        let variant = |variant: Option<&str>| PartialPlatform {
+            variant: variant.map(str::to_string),
+            ..image(Some("linux"), Some("arm"))
+        };
+
+        assert!(device.is_compatible(&variant(Some("v7"))));
+        assert!(device.is_compatible(&variant(Some("v6"))));
+        assert!(device.is_compatible(&variant(None)));
+        assert!(!device.is_compatible(&variant(Some("v8"))));
+        assert!(platform("linux", "arm").is_compatible(&variant(Some("v7"))));
+
+        assert_eq!(device.to_string(), "linux/arm/v7");
+        assert_eq!(platform("linux", "arm64").to_string(), "linux/arm64");
+    }
+
+    #[test]
+    fn architecture_names() {
+        let cases = [
+            ("x86_64", ("amd64", None)),
+            ("i686", ("386", None)),
+            ("aarch64", ("arm64", None)),
+            ("arm64", ("arm64", None)),
+            ("armv7l", ("arm", Some("v7"))),
+            ("armv6l", ("arm", Some("v6"))),
+            ("armv5tel", ("arm", Some("v5"))),
+            ("arm", ("arm", None)),
+            ("riscv64", ("riscv64", None)),
+        ];
+
+        for (arch, exp) in cases {
+            assert_eq!(oci_architecture(arch), exp, "wrong name for {arch}");
+        }
+    }
+
+    #[test]
+    fn engine_platform() {
+        let info = SystemInfo {
+            os_type: Some("linux".to_string()),
+            architecture: Some("armv7l".to_string()),
+            ..Default::default()
+        };
+
+        let platform = Platform::from_engine(&info);
+
+        assert_eq!(platform.to_string(), "linux/arm/v7");
+        assert_eq!(
+            Platform::from_engine(&SystemInfo::default()),
+            Platform::runtime()
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn check_platform_of_the_engine() {
+        use bollard::models::ImageInspect;
+
+        use crate::client::Client;
+        use crate::docker_mock;
+
+        let docker = docker_mock!({
+            let mut mock = Client::new();
+
+            mock.expect_info().returning(|| {
+                Ok(SystemInfo {
+                    os_type: Some("linux".to_string()),
+                    architecture: Some("aarch64".to_string()),
+                    ..Default::default()
+                })
+            });
+            mock.expect_inspect_image().returning(|image| {
+                let architecture = if image == "arm64" { "arm64" } else { "amd64" };
+
+                Ok(ImageInspect {
+                    os: Some("linux".to_string()),
+                    architecture: Some(architecture.to_string()),
+                    ..Default::default()
+                })
+            });
+
+            mock
+        });
+
+        docker.check_image_platform("arm64").await.unwrap();
+
+        let err = docker.check_image_platform("amd64").await.unwrap_err();
+        let DockerError::IncompatibleImage {
+            device_platform, ..
+        } = err
+        else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!(device_platform, "linux/arm64");
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn pull_image_for_the_device() {
+        use bollard::models::{CreateImageInfo, ImageInspect};
+
+        use crate::client::Client;
+        use crate::docker_mock;
+
+        let docker = docker_mock!({
+            let mut mock = Client::new();
+
+            mock.expect_info().returning(|| {
+                Ok(SystemInfo {
+                    os_type: Some("linux".to_string()),
+                    architecture: Some("armv7l".to_string()),
+                    ..Default::default()
+                })
+            });
+            mock.expect_create_image()
+                .withf(|options, _, _| {
+                    options.as_ref().is_some_and(|options| {
+                        options.from_image == "nginx" && options.platform == "linux/arm/v7"
+                    })
+                })
+                .once()
+                .returning(|_, _, _| {
+                    Box::pin(futures::stream::iter([Ok(CreateImageInfo {
+                        status: Some("Downloaded newer image".to_string()),
+                        ..Default::default()
+                    })]))
+                });
+            mock.expect_inspect_image()
+                .withf(|image| image == "nginx")
+                .once()
+                .returning(|_| {
+                    Ok(ImageInspect {
+                        os: Some("linux".to_string()),
+                        architecture: Some("arm".to_string()),
+                        variant: Some("v7".to_string()),
+                        ..Default::default()
+                    })
+                });
+
+            mock
+        });
+
+        docker.pull_image("nginx").await.unwrap();
+    }
+}
@@ -26,8 +26,10 @@
 //! Astarte.
 
 pub(crate) mod client;
+pub mod config;
 pub mod docker;
 pub mod error;
+pub mod image;
 
 #[cfg(feature = "mock")]
 mod mock;
@@ -34,7 +34,7 @@ use bollard::{
     image::{CreateImageOptions, ListImagesOptions, RemoveImageOptions},
     models::{
         ContainerCreateResponse, ContainerWaitResponse, CreateImageInfo, EventMessage,
-        ImageInspect, ImageSummary,
+        ImageInspect, ImageSummary, SystemInfo,
     },
     service::{ContainerSummary, ImageDeleteResponseItem},
     system::EventsOptions,
@@ -92,6 +92,7 @@ pub trait DockerTrait: Sized {
     ) -> Result<Vec<ImageDeleteResponseItem>, Error>;
     fn events<'a>(&'a self, options: Option<EventsOptions<&'a str>>) -> DockerStream<EventMessage>;
     async fn ping(&self) -> Result<String, Error>;
+    async fn info(&self) -> Result<SystemInfo, Error>;
     async fn inspect_image(&self, image_name: &str) -> Result<ImageInspect, Error>;
     fn wait_container<'a>(
         &'a self,
@@ -157,6 +158,7 @@ mock! {
         ) -> Result<Vec<ImageDeleteResponseItem>, Error>;
         fn events<'a>(&'a self, options: Option<EventsOptions<&'a str>>) -> DockerStream<EventMessage>;
         async fn ping(&self) -> Result<String, Error>;
+        async fn info(&self) -> Result<SystemInfo, Error>;
         async fn inspect_image(&self, image_name: &str) -> Result<ImageInspect, Error>;
         fn wait_container<'a>(
             &'a self,
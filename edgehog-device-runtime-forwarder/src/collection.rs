@@ -6,6 +6,8 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use tokio::sync::mpsc::Sender;
 use tracing::{debug, error, instrument, trace};
@@ -16,6 +18,34 @@ use crate::messages::{
     Http as ProtoHttp, HttpRequest, Id, ProtoMessage, WebSocket as ProtoWebSocket,
 };
 
+/// Bytes exchanged with Edgehog.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// Bytes sent to Edgehog.
+    pub sent: u64,
+    /// Bytes received from Edgehog.
+    pub received: u64,
+}
+
+/// Total traffic of a session, shared with the owner of the [`ConnectionsManager`].
+///
+/// [`ConnectionsManager`]: crate::connections_manager::ConnectionsManager
+#[derive(Debug, Clone, Default)]
+pub struct SessionTraffic {
+    sent: Arc<AtomicU64>,
+    received: Arc<AtomicU64>,
+}
+
+impl SessionTraffic {
+    /// Bytes exchanged since the session was opened.
+    pub fn get(&self) -> Traffic {
+        Traffic {
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+        }
+    }
+}
+
 /// Connections' collection between the device and Edgehog.
 pub(crate) struct Connections {
     /// Collection mapping every Connection ID with the corresponding [`tokio task`](tokio::task) spawned to
@@ -24,6 +54,10 @@ pub(crate) struct Connections {
     /// Write side of the channel used by each connection to send data to the [`ConnectionsManager`].
     /// This field is only cloned and passed to every connection when created.
     tx_ws: Sender<ProtoMessage>,
+    /// Bytes exchanged by each connection.
+    traffic: HashMap<Id, Traffic>,
+    /// Bytes exchanged by all the connections of the session.
+    total: SessionTraffic,
 }
 
 impl Debug for Connections {
@@ -40,9 +74,37 @@ impl Connections {
         Self {
             connections: HashMap::new(),
             tx_ws,
+            traffic: HashMap::new(),
+            total: SessionTraffic::default(),
         }
     }
 
+    /// Handle to the total traffic of the session.
+    pub(crate) fn session_traffic(&self) -> SessionTraffic {
+        self.total.clone()
+    }
+
+    /// Bytes exchanged by a connection.
+    pub(crate) fn traffic(&self, id: &Id) -> Option<Traffic> {
+        self.traffic.get(id).copied()
+    }
+
+    /// Account the bytes sent to Edgehog by a connection.
+    pub(crate) fn add_sent(&mut self, id: &Id, bytes: usize) {
+        let bytes = bytes as u64;
+
+        self.traffic.entry(id.clone()).or_default().sent += bytes;
+        self.total.sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Account the bytes received from Edgehog for a connection.
+    pub(crate) fn add_received(&mut self, id: &Id, bytes: usize) {
+        let bytes = bytes as u64;
+
+        self.traffic.entry(id.clone()).or_default().received += bytes;
+        self.total.received.fetch_add(bytes, Ordering::Relaxed);
+    }
+
     /// Handle the reception of an HTTP proto message from Edgehog.
     #[instrument(skip_all)]
     pub(crate) fn handle_http(&mut self, http: ProtoHttp) -> Result<(), Error> {
@@ -144,6 +206,20 @@ impl Connections {
         trace!("removing terminated connections");
         self.connections
             .retain(|_k, con_handle| !con_handle.is_finished());
+
+        let connections = &self.connections;
+        self.traffic.retain(|id, traffic| {
+            let running = connections.contains_key(id);
+
+            if !running {
+                debug!(
+                    "connection {id} sent {} and received {} bytes",
+                    traffic.sent, traffic.received
+                );
+            }
+
+            running
+        });
         trace!("terminated connections removed");
     }
 
@@ -151,6 +227,7 @@ impl Connections {
     pub(crate) fn disconnect(&mut self) {
         self.connections.values_mut().for_each(|con| con.abort());
         self.connections.clear();
+        self.traffic.clear();
     }
 }
 
@@ -206,4 +283,45 @@ mod tests {
 
         assert!(res.is_err());
     }
+
+    #[tokio::test]
+    async fn test_traffic() {
+        let (tx, _rx) = tokio::sync::mpsc::channel::<ProtoMessage>(50);
+        let mut collection = Connections::new(tx);
+        let session = collection.session_traffic();
+
+        let id = Id::try_from(b"test_id".to_vec()).unwrap();
+        let other = Id::try_from(b"other_id".to_vec()).unwrap();
+
+        collection
+            .try_add(id.clone(), || {
+                create_con_handle(tokio::time::sleep(Duration::from_secs(10)))
+            })
+            .unwrap();
+
+        collection.add_received(&id, 100);
+        collection.add_sent(&id, 20);
+        collection.add_sent(&other, 5);
+
+        assert_eq!(
+            collection.traffic(&id),
+            Some(Traffic {
+                sent: 20,
+                received: 100
+            })
+        );
+        assert_eq!(
+            session.get(),
+            Traffic {
+                sent: 25,
+                received: 100
+            }
+        );
+
+        // the traffic of terminated connections is removed, the session total is kept
+        collection.remove_terminated();
+        assert!(collection.traffic(&other).is_none());
+        assert!(collection.traffic(&id).is_some());
+        assert_eq!(session.get().sent, 25);
+    }
 }
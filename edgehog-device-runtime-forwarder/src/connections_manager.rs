@@ -3,7 +3,9 @@
 
 //! Handle the interaction between the device connections and Edgehog.
 
+use std::io;
 use std::ops::ControlFlow;
+use std::time::Duration;
 
 use backoff::{Error as BackoffError, ExponentialBackoff};
 use futures::{future, SinkExt, StreamExt, TryFutureExt};
@@ -11,17 +13,22 @@ use thiserror::Error as ThisError;
 use tokio::net::TcpStream;
 use tokio::select;
 use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::error::UrlError;
+use tokio_tungstenite::tungstenite::handshake::client::Response;
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
 use tokio_tungstenite::{
-    connect_async_tls_with_config, tungstenite::Error as TungError,
+    client_async_tls_with_config, connect_async_tls_with_config, tungstenite::Error as TungError,
     tungstenite::Message as TungMessage, Connector, MaybeTlsStream, WebSocketStream,
 };
 use tracing::{debug, error, info, instrument, trace, warn};
 use url::Url;
 
-use crate::collection::Connections;
+use crate::collection::{Connections, SessionTraffic};
 use crate::connection::ConnectionError;
 use crate::messages::{Id, ProtoMessage, ProtocolError};
-use crate::tls::{device_tls_config, Error as TlsError};
+use crate::proxy::{self, ProxyConfig};
+use crate::tls::{device_tls_config, Error as TlsError, TlsConfig};
 
 /// Size of the channels where to send proto messages.
 pub(crate) const CHANNEL_SIZE: usize = 50;
@@ -61,6 +68,63 @@ pub struct Disconnected(#[from] pub TungError);
 /// WebSocket stream alias.
 pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
+/// Limits on the duration of a session, the session is closed when one is exceeded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// Time without data exchanged after which the session is closed.
+    pub idle_timeout: Option<Duration>,
+    /// Maximum time since the session was opened.
+    pub max_duration: Option<Duration>,
+    /// Maximum bytes per second exchanged with Edgehog, in both directions.
+    pub bandwidth: Option<u64>,
+}
+
+/// Pings sent to detect a connection with Edgehog that was silently lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// Interval between two pings.
+    pub interval: Duration,
+    /// Pings left without a response after which the connection is considered lost.
+    pub max_missed: u32,
+}
+
+/// Token bucket limiting the bytes exchanged per second.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    /// Bytes per second.
+    rate: f64,
+    /// Bytes that can be exchanged without waiting, negative if the rate was exceeded.
+    available: f64,
+    last: Instant,
+}
+
+impl Throttle {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: rate as f64,
+            available: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Consume the bytes, returning the time to wait to respect the rate.
+    fn consume(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+
+        // allow bursts of at most one second of traffic
+        self.available = (self.available + elapsed * self.rate).min(self.rate);
+        self.available -= bytes as f64;
+
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / self.rate)
+        }
+    }
+}
+
 /// Handler responsible for
 /// - establishing a WebSocket connection between a device and Edgehog
 /// - receiving and sending data from/to it.
@@ -76,20 +140,56 @@ pub struct ConnectionsManager {
     pub(crate) url: Url,
     /// Flag to indicate if TLS should be enabled.
     pub(crate) secure: bool,
+    /// Certificates used for the TLS connection.
+    pub(crate) tls: TlsConfig,
+    /// Proxies of the WebSocket connection.
+    pub(crate) proxy: ProxyConfig,
+    /// Limits on the duration of the session.
+    pub(crate) limits: SessionLimits,
+    /// Bandwidth limit of the session.
+    pub(crate) throttle: Option<Throttle>,
+    /// Keepalive of the WebSocket connection.
+    pub(crate) keepalive: Option<Keepalive>,
+    /// Pings sent since the last message received from Edgehog.
+    pub(crate) missed_pings: u32,
+    /// Time the next ping is sent.
+    pub(crate) next_ping: Instant,
+    /// Time the session was opened.
+    pub(crate) started: Instant,
+    /// Time of the last data exchanged, reset on traffic.
+    pub(crate) last_activity: Instant,
 }
 
 impl ConnectionsManager {
     /// Establish a new WebSocket connection between the device and Edgehog.
     #[instrument]
     pub async fn connect(url: Url, secure: bool) -> Result<Self, Error> {
+        Self::connect_with_tls(url, secure, TlsConfig::default()).await
+    }
+
+    /// Establish a new WebSocket connection, using the certificates for the TLS connection.
+    #[instrument(skip(tls))]
+    pub async fn connect_with_tls(url: Url, secure: bool, tls: TlsConfig) -> Result<Self, Error> {
+        Self::connect_with_proxy(url, secure, tls, ProxyConfig::from_env()).await
+    }
+
+    /// Establish a new WebSocket connection through the proxies, using the certificates for the
+    /// TLS connection.
+    #[instrument(skip(tls))]
+    pub async fn connect_with_proxy(
+        url: Url,
+        secure: bool,
+        tls: TlsConfig,
+        proxy: ProxyConfig,
+    ) -> Result<Self, Error> {
         // compute the TLS connector information or use a plain ws connection
         let connector = if secure {
-            device_tls_config()?
+            device_tls_config(&tls)?
         } else {
             Connector::Plain
         };
 
-        let ws_stream = Self::ws_connect(&url, connector).await?;
+        let ws_stream = Self::ws_connect(&url, connector, &proxy).await?;
 
         // this channel is used by tasks associated with the current bridge-device session to exchange
         // available information on a given connection between the device and another service.
@@ -99,20 +199,100 @@ impl ConnectionsManager {
 
         let connections = Connections::new(tx_ws);
 
+        let now = Instant::now();
+
         Ok(Self {
             connections,
             ws_stream,
             rx_ws,
             url,
             secure,
+            tls,
+            proxy,
+            limits: SessionLimits::default(),
+            throttle: None,
+            keepalive: None,
+            missed_pings: 0,
+            next_ping: now,
+            started: now,
+            last_activity: now,
         })
     }
 
+    /// Set the limits on the duration and bandwidth of the session.
+    pub fn with_limits(mut self, limits: SessionLimits) -> Self {
+        self.limits = limits;
+        self.throttle = limits.bandwidth.filter(|rate| *rate > 0).map(Throttle::new);
+        self
+    }
+
+    /// Periodically send pings, reconnecting when too many are left without a response.
+    pub fn with_keepalive(mut self, keepalive: Keepalive) -> Self {
+        self.keepalive = Some(keepalive);
+        self.reset_keepalive();
+        self
+    }
+
+    /// Restart counting the missed pings from now.
+    fn reset_keepalive(&mut self) {
+        self.missed_pings = 0;
+
+        if let Some(keepalive) = self.keepalive {
+            self.next_ping = Instant::now() + keepalive.interval;
+        }
+    }
+
+    /// Send a ping, or fail if too many pings were left without a response.
+    async fn ping(&mut self) -> Result<(), TungError> {
+        let Some(keepalive) = self.keepalive else {
+            return Ok(());
+        };
+
+        if self.missed_pings >= keepalive.max_missed {
+            warn!(
+                "no message received after {} pings, connection lost",
+                self.missed_pings
+            );
+
+            return Err(TungError::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "keepalive timeout",
+            )));
+        }
+
+        self.missed_pings += 1;
+        self.next_ping = Instant::now() + keepalive.interval;
+
+        trace!("sending ping");
+
+        self.send_to_ws(TungMessage::Ping(Vec::new())).await
+    }
+
+    /// Handle to the bytes exchanged by the session.
+    pub fn traffic(&self) -> SessionTraffic {
+        self.connections.session_traffic()
+    }
+
+    /// Wait to keep the traffic under the bandwidth limit.
+    async fn throttle(&mut self, bytes: usize) {
+        let Some(throttle) = &mut self.throttle else {
+            return;
+        };
+
+        let wait = throttle.consume(bytes);
+        if !wait.is_zero() {
+            trace!("bandwidth limit exceeded, waiting {wait:?}");
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
     /// Perform exponential backoff while trying to connect with Edgehog.
     #[instrument(skip_all)]
     pub(crate) async fn ws_connect(
         url: &Url,
         connector: Connector,
+        proxy: &ProxyConfig,
     ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Error> {
         // try opening a WebSocket connection using exponential backoff
         let (ws_stream, http_res) =
@@ -122,7 +302,7 @@ impl ConnectionsManager {
                 let connector_cpy = connector.clone();
 
                 // if the connector id Connector::Plain, a plain ws connection will be established
-                connect_async_tls_with_config(url, None, false, Some(connector_cpy))
+                Self::open_ws(url, connector_cpy, proxy)
                     .await
                     .map_err(|err| match err {
                         TungError::Http(http_res) if http_res.status().is_client_error() => {
@@ -154,6 +334,26 @@ impl ConnectionsManager {
         Ok(ws_stream)
     }
 
+    /// Open the WebSocket connection, through the proxy of the URL if any.
+    async fn open_ws(
+        url: &Url,
+        connector: Connector,
+        proxy: &ProxyConfig,
+    ) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), TungError> {
+        let Some(proxy) = proxy.select(url) else {
+            return connect_async_tls_with_config(url, None, false, Some(connector)).await;
+        };
+
+        let host = url.host_str().ok_or(TungError::Url(UrlError::NoHostName))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or(TungError::Url(UrlError::UnsupportedUrlScheme))?;
+
+        let stream = proxy::tunnel(&proxy, host, port).await?;
+
+        client_async_tls_with_config(url, stream, None, Some(connector)).await
+    }
+
     /// Manage the reception and transmission of data between the WebSocket and each device connection.
     #[instrument(skip_all)]
     pub async fn handle_connections(&mut self) -> Result<(), Disconnected> {
@@ -195,27 +395,75 @@ impl ConnectionsManager {
         match event {
             // receive data from Edgehog
             WebSocketEvents::Receive(msg) => {
+                // any message proves the connection is still alive
+                if msg.is_ok() {
+                    self.missed_pings = 0;
+                }
+
                 future::ready(msg)
                     .and_then(|msg| self.handle_tung_msg(msg))
                     .await
             }
             // receive data from a device connection (e.g., TTYD)
             WebSocketEvents::Send(tung_msg) => {
+                self.last_activity = Instant::now();
+
+                let id = tung_msg.id().clone();
                 let msg = match tung_msg.encode() {
-                    Ok(msg) => TungMessage::Binary(msg),
+                    Ok(msg) => msg,
                     Err(err) => {
                         error!("discard message due to {err:?}");
                         return Ok(ControlFlow::Continue(()));
                     }
                 };
 
+                self.connections.add_sent(&id, msg.len());
+                self.throttle(msg.len()).await;
+
+                let msg = TungMessage::Binary(msg);
+
                 self.send_to_ws(msg)
                     .await
                     .map(|_| ControlFlow::Continue(()))
             }
+            WebSocketEvents::Ping => self.ping().await.map(|_| ControlFlow::Continue(())),
+            WebSocketEvents::Expired(reason) => {
+                info!("closing the session, {reason} exceeded");
+
+                self.disconnect();
+
+                let frame = CloseFrame {
+                    code: CloseCode::Policy,
+                    reason: reason.into(),
+                };
+                if let Err(err) = self.ws_stream.close(Some(frame)).await {
+                    warn!("failed to close the WebSocket, {err}");
+                }
+
+                Ok(ControlFlow::Break(()))
+            }
         }
     }
 
+    /// Time after which the session is closed for inactivity.
+    fn idle_deadline(&self) -> Option<Instant> {
+        self.limits
+            .idle_timeout
+            .map(|timeout| self.last_activity + timeout)
+    }
+
+    /// Time after which the session is closed, regardless of the traffic.
+    fn max_deadline(&self) -> Option<Instant> {
+        self.limits
+            .max_duration
+            .map(|duration| self.started + duration)
+    }
+
+    /// Time the next keepalive ping is sent.
+    fn ping_deadline(&self) -> Option<Instant> {
+        self.keepalive.map(|_| self.next_ping)
+    }
+
     /// Check when a WebSocket event occurs.
     #[instrument(skip_all)]
     pub(crate) async fn select_ws_event(&mut self) -> WebSocketEvents {
@@ -238,6 +486,11 @@ impl ConnectionsManager {
                     WebSocketEvents::Send(msg)
                 }
                 None => unreachable!("BUG: tx_ws channel should never be closed"),
+            },
+            _ = sleep_until(self.ping_deadline()) => WebSocketEvents::Ping,
+            _ = sleep_until(self.idle_deadline()) => WebSocketEvents::Expired("idle timeout"),
+            _ = sleep_until(self.max_deadline()) => {
+                WebSocketEvents::Expired("maximum session duration")
             }
         }
     }
@@ -270,10 +523,15 @@ impl ConnectionsManager {
             // text frames should never be sent
             TungMessage::Text(data) => warn!("received Text WebSocket frame, {data}"),
             TungMessage::Binary(bytes) => {
+                self.last_activity = Instant::now();
+
+                self.throttle(bytes.len()).await;
+
                 match ProtoMessage::decode(&bytes) {
                     // handle the actual protocol message
                     Ok(proto_msg) => {
                         trace!("message received from Edgehog: {proto_msg:?}");
+                        self.connections.add_received(proto_msg.id(), bytes.len());
                         if let Err(err) = self.handle_proto_msg(proto_msg).await {
                             error!("failed to handle protobuf message due to {err:?}");
                         }
@@ -313,12 +571,13 @@ impl ConnectionsManager {
         debug!("trying to reconnect");
 
         let connector = if self.secure {
-            device_tls_config()?
+            device_tls_config(&self.tls)?
         } else {
             Connector::Plain
         };
 
-        self.ws_stream = Self::ws_connect(&self.url, connector).await?;
+        self.ws_stream = Self::ws_connect(&self.url, connector, &self.proxy).await?;
+        self.reset_keepalive();
 
         info!("reconnected");
         Ok(())
@@ -338,8 +597,42 @@ pub(crate) fn get_token(url: &Url) -> Result<String, Error> {
         .ok_or(Error::TokenNotFound)
 }
 
+/// Sleep until the deadline, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Possible events happening on a WebSocket connection.
 pub(crate) enum WebSocketEvents {
     Receive(Result<TungMessage, TungError>),
     Send(ProtoMessage),
+    /// A keepalive ping must be sent.
+    Ping,
+    /// A limit of the session was exceeded.
+    Expired(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle() {
+        let mut throttle = Throttle::new(1000);
+
+        // the first second of traffic is available immediately
+        assert_eq!(throttle.consume(1000), Duration::ZERO);
+        assert_eq!(throttle.consume(500), Duration::from_millis(500));
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(throttle.consume(0), Duration::ZERO);
+
+        // the unused bandwidth is accumulated up to one second
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(throttle.consume(1000), Duration::ZERO);
+        assert_eq!(throttle.consume(100), Duration::from_millis(100));
+    }
 }
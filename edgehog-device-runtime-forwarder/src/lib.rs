@@ -12,6 +12,7 @@ pub mod collection;
 pub mod connection;
 pub mod connections_manager;
 mod messages;
+pub mod proxy;
 pub mod tls;
 
 // re-exported dependencies
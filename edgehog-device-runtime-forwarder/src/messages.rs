@@ -135,6 +135,14 @@ impl ProtoMessage {
         }))
     }
 
+    /// Return the ID of the connection the message refers to.
+    pub(crate) fn id(&self) -> &Id {
+        match self {
+            ProtoMessage::Http(http) => &http.request_id,
+            ProtoMessage::WebSocket(ws) => &ws.socket_id,
+        }
+    }
+
     /// Return the internal WebSocket message if it matches the type.
     pub(crate) fn into_ws(self) -> Option<WebSocket> {
         match self {
@@ -323,7 +331,10 @@ impl HttpRequest {
         let url = url::Url::parse(&url_str)?;
         let method = http::method::Method::from_str(self.method.as_str())?;
 
-        let http_builder = reqwest::Client::new()
+        // the request is sent to a local service, so it must never go through a proxy
+        let client = reqwest::Client::builder().no_proxy().build()?;
+
+        let http_builder = client
             .request(method, url)
             .headers(self.headers)
             .body(self.body);
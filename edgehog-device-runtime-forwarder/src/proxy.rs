@@ -0,0 +1,231 @@
+// Copyright 2024 SECO Mind Srl
+// SPDX-License-Identifier: Apache-2.0
+
+//! Module tunneling the WebSocket connections through an HTTP proxy.
+//!
+//! The proxies are passed by the device runtime, or read from the `http_proxy`, `https_proxy` and
+//! `no_proxy` environment variables.
+
+use std::io;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::debug;
+use url::Url;
+
+/// Maximum size of the proxy response headers.
+const MAX_RESPONSE_SIZE: usize = 8 * 1024;
+
+/// Read the first set environment variable.
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .filter(|value| !value.is_empty())
+}
+
+/// Proxies of the WebSocket connections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Proxy for the `ws` connections.
+    pub http: Option<String>,
+    /// Proxy for the `wss` connections.
+    pub https: Option<String>,
+    /// Comma separated list of hosts and domains connected to directly.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Read the proxies from the environment.
+    pub fn from_env() -> Self {
+        Self {
+            http: env_var(&["http_proxy", "HTTP_PROXY"]),
+            https: env_var(&["https_proxy", "HTTPS_PROXY"]),
+            no_proxy: env_var(&["no_proxy", "NO_PROXY"]),
+        }
+    }
+
+    /// Proxy to use for the URL, if any.
+    pub(crate) fn select(&self, url: &Url) -> Option<Url> {
+        let proxy = match url.scheme() {
+            "wss" | "https" => self.https.as_deref(),
+            _ => self.http.as_deref(),
+        }?;
+
+        select_proxy(url, proxy, self.no_proxy.as_deref().unwrap_or_default())
+    }
+}
+
+/// Parse the proxy, unless the host of the URL matches an entry of `no_proxy`.
+fn select_proxy(url: &Url, proxy: &str, no_proxy: &str) -> Option<Url> {
+    let host = url.host_str()?;
+
+    let excluded = no_proxy
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            let domain = entry.trim_start_matches('.');
+
+            entry == "*"
+                || host == domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.'))
+        });
+
+    if excluded {
+        return None;
+    }
+
+    let proxy = if proxy.contains("://") {
+        Url::parse(proxy)
+    } else {
+        Url::parse(&format!("http://{proxy}"))
+    };
+
+    proxy.ok()
+}
+
+/// Open a tunnel to the host with an HTTP `CONNECT` request to the proxy.
+pub(crate) async fn tunnel(proxy: &Url, host: &str, port: u16) -> io::Result<TcpStream> {
+    let proxy_host = proxy
+        .host_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "proxy without host"))?;
+    let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+
+    debug!("connecting to {host}:{port} through the proxy {proxy_host}:{proxy_port}");
+
+    let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+
+    let request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // the server doesn't send anything before the end of the response, so it can be read in chunks
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.windows(4).any(|window| window == b"\r\n\r\n") {
+        if response.len() > MAX_RESPONSE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "proxy response too long",
+            ));
+        }
+
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "proxy closed the connection",
+            ));
+        }
+
+        response.extend_from_slice(&buf[..read]);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+
+    if status != "200" {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy responded with status {status}"),
+        ));
+    }
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn select_proxy_no_proxy() {
+        let url = Url::parse("wss://edgehog.example.com/session").unwrap();
+
+        let proxy = select_proxy(&url, "proxy.local:3128", "").unwrap();
+        assert_eq!(proxy.as_str(), "http://proxy.local:3128/");
+
+        assert!(select_proxy(&url, "http://proxy.local:3128", "localhost, .example.com").is_none());
+        assert!(select_proxy(&url, "http://proxy.local:3128", "edgehog.example.com").is_none());
+        assert!(select_proxy(&url, "http://proxy.local:3128", "*").is_none());
+        assert!(select_proxy(&url, "http://proxy.local:3128", "ample.com").is_some());
+    }
+
+    #[test]
+    fn select_proxy_by_scheme() {
+        let config = ProxyConfig {
+            http: None,
+            https: Some("http://proxy.local:3128".to_string()),
+            no_proxy: Some("localhost".to_string()),
+        };
+
+        let proxy = config
+            .select(&Url::parse("wss://edgehog.example.com/session").unwrap())
+            .unwrap();
+        assert_eq!(proxy.as_str(), "http://proxy.local:3128/");
+
+        assert!(config
+            .select(&Url::parse("ws://edgehog.example.com/session").unwrap())
+            .is_none());
+        assert!(config
+            .select(&Url::parse("wss://localhost/session").unwrap())
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn tunnel_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..read]).to_string();
+
+            stream
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+
+            request
+        });
+
+        tunnel(&proxy, "edgehog.example.com", 443).await.unwrap();
+
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("CONNECT edgehog.example.com:443 HTTP/1.1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn tunnel_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+
+            stream
+                .write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let err = tunnel(&proxy, "edgehog.example.com", 443)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -6,8 +6,10 @@
 //! This module provides the necessary functionalities to establish a TLS layer on top of the
 //! WebSocket communication between a device and Edgehog.
 
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use rustls::{ClientConfig, RootCertStore};
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use tokio_tungstenite::Connector;
@@ -38,9 +40,46 @@ pub enum Error {
     RootCert(#[source] rustls::Error),
 }
 
+/// Certificates used for the TLS connection with Edgehog.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// Additional CA bundle to verify the Edgehog forwarder endpoint.
+    pub ca_bundle: Option<PathBuf>,
+    /// Client certificate to authenticate the device with mutual TLS.
+    pub client_auth: Option<ClientAuth>,
+}
+
+/// Client certificate chain and private key of the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientAuth {
+    /// Path to the PEM certificate chain.
+    pub cert: PathBuf,
+    /// Path to the PEM private key.
+    pub key: PathBuf,
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    // I'm using std::fs because rustls-pemfile requires a sync read call
+    let file = std::fs::File::open(path).map_err(Error::ReadFile)?;
+    let mut reader = BufReader::new(file);
+
+    rustls_pemfile::certs(&mut reader)
+        .collect::<Result<_, _>>()
+        .map_err(Error::ReadCert)
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, Error> {
+    let file = std::fs::File::open(path).map_err(Error::ReadFile)?;
+    let mut reader = BufReader::new(file);
+
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(Error::ReadCert)?
+        .ok_or_else(|| Error::WrongItem(format!("no private key in {}", path.display())))
+}
+
 /// Given the CA certificate, compute the device TLS configuration and return a Device connector.
 #[instrument(skip_all)]
-pub fn device_tls_config() -> Result<Connector, Error> {
+pub fn device_tls_config(tls: &TlsConfig) -> Result<Connector, Error> {
     let mut root_certs = RootCertStore::empty();
 
     // add native root certificates
@@ -64,9 +103,25 @@ pub fn device_tls_config() -> Result<Connector, Error> {
         }
     }
 
-    let config = ClientConfig::builder()
-        .with_root_certificates(root_certs)
-        .with_no_client_auth();
+    if let Some(ca_bundle) = &tls.ca_bundle {
+        for cert in read_certs(ca_bundle)? {
+            root_certs.add(cert)?;
+        }
+        debug!("CA bundle {} added", ca_bundle.display());
+    }
+
+    let builder = ClientConfig::builder().with_root_certificates(root_certs);
+
+    let config = match &tls.client_auth {
+        Some(client_auth) => {
+            let cert_chain = read_certs(&client_auth.cert)?;
+            let key = read_private_key(&client_auth.key)?;
+
+            debug!("using client certificate authentication");
+            builder.with_client_auth_cert(cert_chain, key)?
+        }
+        None => builder.with_no_client_auth(),
+    };
 
     Ok(Connector::Rustls(Arc::new(config)))
 }
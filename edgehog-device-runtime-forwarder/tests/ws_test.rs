@@ -12,8 +12,8 @@ use tokio_tungstenite::tungstenite::Message as TungMessage;
 use edgehog_device_runtime_forwarder::test_utils::create_ws_msg;
 #[cfg(feature = "_test-utils")]
 use edgehog_device_runtime_forwarder::test_utils::{
-    create_http_upgrade_req, is_ws_upgrade_response, send_ws_and_wait_next, MockWebSocket,
-    TestConnections,
+    bind_port, create_http_upgrade_req, is_ws_upgrade_response, send_ws_and_wait_next,
+    MockWebSocket, TestConnections,
 };
 
 #[cfg(feature = "_test-utils")]
@@ -79,3 +79,92 @@ async fn test_internal_ws() {
         }
     );
 }
+
+#[cfg(feature = "_test-utils")]
+#[tokio::test]
+async fn test_idle_timeout() {
+    use std::time::Duration;
+
+    use edgehog_device_runtime_forwarder::connections_manager::{
+        ConnectionsManager, SessionLimits,
+    };
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    let (listener, port) = bind_port().await;
+    let url = format!("ws://localhost:{port}/remote-terminal?session=abcd");
+
+    let handle = tokio::spawn(async move {
+        let limits = SessionLimits {
+            idle_timeout: Some(Duration::from_millis(100)),
+            max_duration: None,
+            bandwidth: None,
+        };
+
+        let mut con_manager = ConnectionsManager::connect(url.as_str().try_into().unwrap(), false)
+            .await
+            .expect("failed to connect connections manager")
+            .with_limits(limits);
+
+        con_manager.handle_connections().await
+    });
+
+    let (stream, _) = listener
+        .accept()
+        .await
+        .expect("failed to accept connection");
+    let mut ws_bridge = tokio_tungstenite::accept_async(stream)
+        .await
+        .expect("failed to open a ws with the device");
+
+    // the device closes the session after the idle timeout
+    let msg = ws_bridge
+        .next()
+        .await
+        .expect("ws already closed")
+        .expect("failed to receive from ws");
+
+    assert!(
+        matches!(&msg, TungMessage::Close(Some(frame)) if frame.code == CloseCode::Policy),
+        "unexpected message {msg:?}"
+    );
+    assert!(handle.await.expect("task join failed").is_ok());
+}
+
+#[cfg(feature = "_test-utils")]
+#[tokio::test]
+async fn test_keepalive_timeout() {
+    use std::time::Duration;
+
+    use edgehog_device_runtime_forwarder::connections_manager::{ConnectionsManager, Keepalive};
+
+    let (listener, port) = bind_port().await;
+    let url = format!("ws://localhost:{port}/remote-terminal?session=abcd");
+
+    let handle = tokio::spawn(async move {
+        let keepalive = Keepalive {
+            interval: Duration::from_millis(50),
+            max_missed: 2,
+        };
+
+        let mut con_manager = ConnectionsManager::connect(url.as_str().try_into().unwrap(), false)
+            .await
+            .expect("failed to connect connections manager")
+            .with_keepalive(keepalive);
+
+        con_manager.handle_connections().await
+    });
+
+    let (stream, _) = listener
+        .accept()
+        .await
+        .expect("failed to accept connection");
+    // never read from the WebSocket, so no pong is sent back
+    let _ws_bridge = tokio_tungstenite::accept_async(stream)
+        .await
+        .expect("failed to open a ws with the device");
+
+    // the device detects the connection as lost, so the caller can reconnect
+    let res = handle.await.expect("task join failed");
+    assert!(res.is_err());
+}
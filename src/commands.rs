@@ -18,16 +18,811 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
-use log::error;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::data::Publisher;
+use crate::executor::{ExecOutput, Executor, ExecutorError};
+
+const CUSTOM_COMMAND_RESULT_INTERFACE: &str = "io.edgehog.devicemanager.CustomCommandResult";
+const PING_RESPONSE_INTERFACE: &str = "io.edgehog.devicemanager.PingResponse";
+const FACTORY_RESET_EVENT_INTERFACE: &str = "io.edgehog.devicemanager.FactoryResetEvent";
+
+/// Properties published by the runtime, unset on a factory reset.
+const RUNTIME_PROPERTIES: &[&str] = &[
+    "io.edgehog.devicemanager.OSInfo",
+    "io.edgehog.devicemanager.HardwareInfo",
+    "io.edgehog.devicemanager.BoardInfo",
+    "io.edgehog.devicemanager.SystemInfo",
+    "io.edgehog.devicemanager.RuntimeInfo",
+    "io.edgehog.devicemanager.RuntimeDetails",
+    "io.edgehog.devicemanager.BaseImage",
+    "io.edgehog.devicemanager.NetworkInterfaceProperties",
+    "io.edgehog.devicemanager.CellularConnectionProperties",
+    "io.edgehog.devicemanager.ForwarderSessionState",
+    "io.edgehog.devicemanager.RuntimeDiagnostics",
+];
+
+/// Name of the OS reset hook in the allowlist of the factory reset.
+const OS_RESET: &str = "OsReset";
+
+const fn default_timeout() -> u64 {
+    60
+}
+
+/// Response to a `Ping` command, the timestamps are in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct PingResponse {
+    /// Timestamp set by the backend in the command, 0 if missing.
+    pub request_timestamp: i64,
+    /// Timestamp the command was received by the device.
+    pub received_timestamp: i64,
+    /// Timestamp the response was sent by the device.
+    pub processed_timestamp: i64,
+}
+
+/// Current Unix timestamp in milliseconds.
+pub(crate) fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
+
+/// Parse the timestamp of a `Ping:<timestamp>` command.
+fn parse_ping_timestamp(argument: Option<&str>) -> i64 {
+    let Some(argument) = argument else {
+        return 0;
+    };
+
+    argument.parse().unwrap_or_else(|err| {
+        warn!("invalid ping timestamp {argument}: {err}");
+
+        0
+    })
+}
+
+async fn ping<P>(publisher: &P, argument: Option<&str>, received_timestamp: i64)
+where
+    P: Publisher + Send + Sync,
+{
+    let response = PingResponse {
+        request_timestamp: parse_ping_timestamp(argument),
+        received_timestamp,
+        processed_timestamp: now_millis(),
+    };
+
+    if let Err(err) = publisher
+        .send_object(PING_RESPONSE_INTERFACE, "/response", response)
+        .await
+    {
+        error!("couldn't send ping response: {err}");
+    }
+}
+
+/// Command received on `io.edgehog.devicemanager.Commands`.
+#[derive(Debug, PartialEq, Eq)]
+enum DeviceCommand<'a> {
+    Reboot,
+    /// Ping with the optional timestamp of the request.
+    Ping(Option<&'a str>),
+}
+
+impl<'a> DeviceCommand<'a> {
+    /// Match the command name exactly, only the commands taking an argument accept the
+    /// `<name>:<argument>` form.
+    fn parse(command: &'a str) -> Option<Self> {
+        match command {
+            "Reboot" => Some(Self::Reboot),
+            "Ping" => Some(Self::Ping(None)),
+            _ => command
+                .strip_prefix("Ping:")
+                .map(|argument| Self::Ping(Some(argument))),
+        }
+    }
+}
 
 /// handle io.edgehog.devicemanager.Commands
-pub(crate) async fn execute_command(command: &str) {
-    match command {
-        "Reboot" => {
+pub(crate) async fn execute_command<P>(publisher: &P, command: &str)
+where
+    P: Publisher + Send + Sync,
+{
+    let received_timestamp = now_millis();
+
+    match DeviceCommand::parse(command) {
+        Some(DeviceCommand::Reboot) => {
             crate::power_management::reboot().await.unwrap();
         }
-        _ => {
-            error!("command not recognized");
+        Some(DeviceCommand::Ping(argument)) => ping(publisher, argument, received_timestamp).await,
+        None => {
+            error!("command not recognized: {command}");
+        }
+    }
+}
+
+/// Command that can be triggered from Astarte.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CustomCommandConfig {
+    /// Program and arguments. An argument `{name}` is replaced with the value of the parameter
+    /// `name` of the request, as a single argument.
+    pub argv: Vec<String>,
+    /// Seconds after which the command is killed.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Local user the command runs as, defaults to the user of the runtime.
+    pub user: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CommandError {
+    /// invalid request, {0}
+    InvalidRequest(&'static str),
+    /// command {0} is not allowed
+    NotAllowed(String),
+    /// missing parameter {0}
+    MissingParameter(String),
+    /// unknown parameter {0}
+    UnknownParameter(String),
+    /// couldn't execute the command
+    Executor(#[from] ExecutorError),
+}
+
+/// Request received on `io.edgehog.devicemanager.CustomCommandRequest`.
+#[derive(Debug, PartialEq, Eq)]
+struct CustomCommandRequest {
+    id: String,
+    name: String,
+    parameters: HashMap<String, String>,
+}
+
+impl TryFrom<HashMap<String, AstarteType>> for CustomCommandRequest {
+    type Error = CommandError;
+
+    fn try_from(mut value: HashMap<String, AstarteType>) -> Result<Self, Self::Error> {
+        let Some(AstarteType::String(id)) = value.remove("id") else {
+            return Err(CommandError::InvalidRequest("missing id"));
+        };
+
+        let Some(AstarteType::String(name)) = value.remove("name") else {
+            return Err(CommandError::InvalidRequest("missing name"));
+        };
+
+        let parameters = match value.remove("parameters") {
+            Some(AstarteType::StringArray(parameters)) => parameters
+                .iter()
+                .map(|parameter| {
+                    parameter
+                        .split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .ok_or(CommandError::InvalidRequest("parameters must be key=value"))
+                })
+                .collect::<Result<_, _>>()?,
+            None | Some(AstarteType::Unset) => HashMap::new(),
+            Some(_) => return Err(CommandError::InvalidRequest("invalid parameters")),
+        };
+
+        Ok(Self {
+            id,
+            name,
+            parameters,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct CustomCommandResult {
+    pub id: String,
+    pub name: String,
+    /// Exit code of the command, -1 if it wasn't executed or was killed by a signal.
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the output was truncated.
+    pub truncated: bool,
+    /// Reason the command couldn't be executed, empty on success.
+    pub error: String,
+}
+
+impl CustomCommandResult {
+    fn from_output(id: String, name: String, output: ExecOutput) -> Self {
+        Self {
+            id,
+            name,
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            truncated: output.truncated,
+            error: String::new(),
+        }
+    }
+
+    fn from_error(id: String, name: String, err: &CommandError) -> Self {
+        Self {
+            id,
+            name,
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false,
+            error: err.to_string(),
+        }
+    }
+}
+
+/// Allowlist of the commands that can be triggered from Astarte.
+///
+/// Only the declared commands can run, the request can only fill the parameters of the template.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CustomCommands {
+    commands: HashMap<String, CustomCommandConfig>,
+}
+
+impl CustomCommands {
+    pub(crate) fn new(commands: HashMap<String, CustomCommandConfig>) -> Self {
+        Self { commands }
+    }
+
+    fn executor(
+        &self,
+        name: &str,
+        parameters: &HashMap<String, String>,
+    ) -> Result<Executor, CommandError> {
+        let config = self
+            .commands
+            .get(name)
+            .ok_or_else(|| CommandError::NotAllowed(name.to_string()))?;
+
+        let mut used = Vec::new();
+        let mut argv = Vec::with_capacity(config.argv.len());
+        for arg in &config.argv {
+            let Some(parameter) = arg.strip_prefix('{').and_then(|arg| arg.strip_suffix('}'))
+            else {
+                argv.push(arg.clone());
+
+                continue;
+            };
+
+            let value = parameters
+                .get(parameter)
+                .ok_or_else(|| CommandError::MissingParameter(parameter.to_string()))?;
+
+            used.push(parameter);
+            argv.push(value.clone());
+        }
+
+        if let Some(unknown) = parameters.keys().find(|key| !used.contains(&key.as_str())) {
+            return Err(CommandError::UnknownParameter(unknown.clone()));
+        }
+
+        let mut argv = argv.into_iter();
+        let program = argv
+            .next()
+            .ok_or(CommandError::InvalidRequest("empty command"))?;
+
+        let mut executor = Executor::new(program)
+            .args(argv)
+            .timeout(Duration::from_secs(config.timeout));
+
+        if let Some(user) = &config.user {
+            executor = executor.user(user);
+        }
+
+        Ok(executor)
+    }
+
+    async fn run(&self, request: &CustomCommandRequest) -> Result<ExecOutput, CommandError> {
+        let executor = self.executor(&request.name, &request.parameters)?;
+
+        info!("executing custom command {} ({})", request.name, request.id);
+
+        executor.output().await.map_err(CommandError::from)
+    }
+
+    /// handle io.edgehog.devicemanager.CustomCommandRequest
+    pub(crate) async fn handle_request<P>(&self, publisher: &P, data: HashMap<String, AstarteType>)
+    where
+        P: Publisher + Send + Sync,
+    {
+        let request = match CustomCommandRequest::try_from(data) {
+            Ok(request) => request,
+            Err(err) => {
+                error!("invalid custom command request: {err}");
+
+                return;
+            }
+        };
+
+        let result = match self.run(&request).await {
+            Ok(output) => CustomCommandResult::from_output(request.id, request.name, output),
+            Err(err) => {
+                warn!("custom command {} failed: {err}", request.name);
+
+                CustomCommandResult::from_error(request.id, request.name, &err)
+            }
+        };
+
+        if let Err(err) = publisher
+            .send_object(CUSTOM_COMMAND_RESULT_INTERFACE, "/result", result)
+            .await
+        {
+            error!("couldn't send custom command result: {err}");
+        }
+    }
+}
+
+/// Configuration of the factory reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct FactoryResetConfig {
+    /// Hook resetting the operating system, run only if requested.
+    pub os_reset: Option<CustomCommandConfig>,
+}
+
+/// Request received on `io.edgehog.devicemanager.FactoryResetRequest`.
+#[derive(Debug, PartialEq, Eq)]
+struct FactoryResetRequest {
+    id: String,
+    os_reset: bool,
+}
+
+impl TryFrom<HashMap<String, AstarteType>> for FactoryResetRequest {
+    type Error = CommandError;
+
+    fn try_from(mut value: HashMap<String, AstarteType>) -> Result<Self, Self::Error> {
+        let Some(AstarteType::String(id)) = value.remove("id") else {
+            return Err(CommandError::InvalidRequest("missing id"));
+        };
+
+        let os_reset = match value.remove("osReset") {
+            Some(AstarteType::Boolean(os_reset)) => os_reset,
+            None | Some(AstarteType::Unset) => false,
+            Some(_) => return Err(CommandError::InvalidRequest("invalid osReset")),
+        };
+
+        Ok(Self { id, os_reset })
+    }
+}
+
+/// Progress of a factory reset, sent after each step.
+#[derive(Debug, Clone, PartialEq, Eq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct FactoryResetEvent {
+    pub id: String,
+    /// One of `Properties`, `Store`, `Downloads`, `OsReset` or `Completed`.
+    pub step: String,
+    /// One of `Done`, `Skipped` or `Failed`.
+    pub status: String,
+    pub message: String,
+}
+
+/// Remove the files of the directory, keeping the ones matching the predicate.
+async fn clear_directory<F>(directory: &Path, keep: F) -> std::io::Result<()>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut entries = match tokio::fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if keep(&path) {
+            continue;
+        }
+
+        if entry.file_type().await?.is_dir() {
+            tokio::fs::remove_dir_all(&path).await?;
+        } else {
+            tokio::fs::remove_file(&path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the file of the store must survive a factory reset.
+///
+/// Only the runtime state files are removed: the database is owned by the Astarte SDK, the
+/// credentials are needed to connect again, and the directories hold the merged interfaces and
+/// the stores of the secondary connections.
+fn keep_in_store(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return true;
+    };
+
+    path.is_dir() || !name.ends_with(".json") || name.starts_with("credentials_")
+}
+
+/// Factory reset of the runtime state.
+#[derive(Debug, Clone)]
+pub(crate) struct FactoryReset {
+    hooks: CustomCommands,
+    store_directory: PathBuf,
+    download_directory: PathBuf,
+}
+
+impl FactoryReset {
+    pub(crate) fn new(
+        config: FactoryResetConfig,
+        store_directory: &Path,
+        download_directory: &Path,
+    ) -> Self {
+        let hooks = config
+            .os_reset
+            .map(|os_reset| HashMap::from([(OS_RESET.to_string(), os_reset)]))
+            .unwrap_or_default();
+
+        Self {
+            hooks: CustomCommands::new(hooks),
+            store_directory: store_directory.to_path_buf(),
+            download_directory: download_directory.to_path_buf(),
+        }
+    }
+
+    async fn unset_properties<P>(publisher: &P) -> Result<(), String>
+    where
+        P: Publisher + Send + Sync,
+    {
+        let mut errors = Vec::new();
+
+        for interface in RUNTIME_PROPERTIES {
+            let props = match publisher.interface_props(interface).await {
+                Ok(props) => props,
+                Err(err) => {
+                    errors.push(format!("{interface}: {err}"));
+
+                    continue;
+                }
+            };
+
+            for prop in props {
+                if let Err(err) = publisher.unset(interface, &prop.path).await {
+                    errors.push(format!("{interface}{}: {err}", prop.path));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    async fn os_reset(&self) -> Result<(), String> {
+        let executor = self
+            .hooks
+            .executor(OS_RESET, &HashMap::new())
+            .map_err(|_| "no OS reset hook configured".to_string())?;
+
+        let output = executor.output().await.map_err(|err| err.to_string())?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    async fn send_event<P>(publisher: &P, id: &str, step: &str, res: &Result<(), String>)
+    where
+        P: Publisher + Send + Sync,
+    {
+        let (status, message) = match res {
+            Ok(()) => ("Done", String::new()),
+            Err(err) => {
+                error!("factory reset step {step} failed: {err}");
+
+                ("Failed", err.clone())
+            }
+        };
+
+        let event = FactoryResetEvent {
+            id: id.to_string(),
+            step: step.to_string(),
+            status: status.to_string(),
+            message,
+        };
+
+        if let Err(err) = publisher
+            .send_object(FACTORY_RESET_EVENT_INTERFACE, "/event", event)
+            .await
+        {
+            error!("couldn't send factory reset event: {err}");
+        }
+    }
+
+    /// handle io.edgehog.devicemanager.FactoryResetRequest
+    pub(crate) async fn handle_request<P>(&self, publisher: &P, data: HashMap<String, AstarteType>)
+    where
+        P: Publisher + Send + Sync,
+    {
+        let request = match FactoryResetRequest::try_from(data) {
+            Ok(request) => request,
+            Err(err) => {
+                error!("invalid factory reset request: {err}");
+
+                return;
+            }
+        };
+
+        info!("factory reset requested ({})", request.id);
+
+        let mut failed = false;
+
+        let res = Self::unset_properties(publisher).await;
+        failed |= res.is_err();
+        Self::send_event(publisher, &request.id, "Properties", &res).await;
+
+        let res = clear_directory(&self.store_directory, keep_in_store)
+            .await
+            .map_err(|err| err.to_string());
+        failed |= res.is_err();
+        Self::send_event(publisher, &request.id, "Store", &res).await;
+
+        let res = clear_directory(&self.download_directory, |_| false)
+            .await
+            .map_err(|err| err.to_string());
+        failed |= res.is_err();
+        Self::send_event(publisher, &request.id, "Downloads", &res).await;
+
+        let completed = if failed {
+            Err("some steps failed".to_string())
+        } else {
+            Ok(())
+        };
+        Self::send_event(publisher, &request.id, "Completed", &completed).await;
+
+        if !request.os_reset {
+            info!("factory reset completed ({})", request.id);
+
+            return;
+        }
+
+        // The hook usually reboots the device, so it runs after reporting the completion.
+        let res = self.os_reset().await;
+        Self::send_event(publisher, &request.id, OS_RESET, &res).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    fn commands() -> CustomCommands {
+        CustomCommands::new(HashMap::from([
+            (
+                "echo".to_string(),
+                CustomCommandConfig {
+                    argv: vec!["echo".to_string(), "{message}".to_string()],
+                    timeout: default_timeout(),
+                    user: None,
+                },
+            ),
+            (
+                "fail".to_string(),
+                CustomCommandConfig {
+                    argv: vec!["false".to_string()],
+                    timeout: default_timeout(),
+                    user: None,
+                },
+            ),
+        ]))
+    }
+
+    fn request(name: &str, parameters: &[&str]) -> HashMap<String, AstarteType> {
+        HashMap::from([
+            ("id".to_string(), AstarteType::String("42".to_string())),
+            ("name".to_string(), AstarteType::String(name.to_string())),
+            (
+                "parameters".to_string(),
+                AstarteType::StringArray(parameters.iter().map(|p| p.to_string()).collect()),
+            ),
+        ])
+    }
+
+    #[test]
+    fn parse_command() {
+        assert_eq!(DeviceCommand::parse("Reboot"), Some(DeviceCommand::Reboot));
+        assert_eq!(DeviceCommand::parse("Reboot:now"), None);
+        assert_eq!(DeviceCommand::parse("Rebooting"), None);
+        assert_eq!(
+            DeviceCommand::parse("Ping"),
+            Some(DeviceCommand::Ping(None))
+        );
+        assert_eq!(
+            DeviceCommand::parse("Ping:1700000000000"),
+            Some(DeviceCommand::Ping(Some("1700000000000")))
+        );
+        assert_eq!(DeviceCommand::parse("Unknown"), None);
+    }
+
+    #[test]
+    fn parse_ping() {
+        assert_eq!(parse_ping_timestamp(None), 0);
+        assert_eq!(parse_ping_timestamp(Some("1700000000000")), 1700000000000);
+        assert_eq!(parse_ping_timestamp(Some("yesterday")), 0);
+    }
+
+    #[tokio::test]
+    async fn execute_ping() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, path: &str, response: &PingResponse| {
+                iface == PING_RESPONSE_INTERFACE
+                    && path == "/response"
+                    && response.request_timestamp == 1700000000000
+                    && response.received_timestamp > 0
+                    && response.processed_timestamp >= response.received_timestamp
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: PingResponse| Ok(()));
+
+        execute_command(&publisher, "Ping:1700000000000").await;
+    }
+
+    #[test]
+    fn parse_request() {
+        let parsed = CustomCommandRequest::try_from(request("echo", &["message=a=b"])).unwrap();
+
+        assert_eq!(
+            parsed,
+            CustomCommandRequest {
+                id: "42".to_string(),
+                name: "echo".to_string(),
+                parameters: HashMap::from([("message".to_string(), "a=b".to_string())]),
+            }
+        );
+
+        let res = CustomCommandRequest::try_from(request("echo", &["message"]));
+        assert!(matches!(res, Err(CommandError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn executor_parameters() {
+        let commands = commands();
+
+        let res = commands.executor("rm", &HashMap::new());
+        assert!(matches!(res, Err(CommandError::NotAllowed(name)) if name == "rm"));
+
+        let res = commands.executor("echo", &HashMap::new());
+        assert!(matches!(res, Err(CommandError::MissingParameter(name)) if name == "message"));
+
+        let parameters = HashMap::from([
+            ("message".to_string(), "hello".to_string()),
+            ("other".to_string(), "; rm -rf /".to_string()),
+        ]);
+        let res = commands.executor("echo", &parameters);
+        assert!(matches!(res, Err(CommandError::UnknownParameter(name)) if name == "other"));
+    }
+
+    #[tokio::test]
+    async fn handle_custom_command() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, path: &str, result: &CustomCommandResult| {
+                iface == CUSTOM_COMMAND_RESULT_INTERFACE
+                    && path == "/result"
+                    && result.id == "42"
+                    && result.exit_code == 0
+                    && result.stdout == "hello world\n"
+                    && result.error.is_empty()
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: CustomCommandResult| Ok(()));
+
+        commands()
+            .handle_request(&publisher, request("echo", &["message=hello world"]))
+            .await;
+    }
+
+    #[test]
+    fn parse_factory_reset_request() {
+        let parsed = FactoryResetRequest::try_from(HashMap::from([
+            ("id".to_string(), AstarteType::String("42".to_string())),
+            ("osReset".to_string(), AstarteType::Boolean(true)),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            parsed,
+            FactoryResetRequest {
+                id: "42".to_string(),
+                os_reset: true,
+            }
+        );
+
+        let parsed = FactoryResetRequest::try_from(HashMap::from([(
+            "id".to_string(),
+            AstarteType::String("42".to_string()),
+        )]))
+        .unwrap();
+        assert!(!parsed.os_reset);
+    }
+
+    #[tokio::test]
+    async fn factory_reset_keeps_credentials() {
+        let store = tempdir::TempDir::new("edgehog-store").unwrap();
+        let downloads = tempdir::TempDir::new("edgehog-downloads").unwrap();
+
+        for file in ["state.json", "credentials_device.json", "database.db"] {
+            std::fs::write(store.path().join(file), "").unwrap();
         }
+        std::fs::create_dir(store.path().join("interfaces")).unwrap();
+        std::fs::write(downloads.path().join("update.bin"), "").unwrap();
+
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_interface_props()
+            .returning(|_: &str| Ok(Vec::new()));
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, _: &str, event: &FactoryResetEvent| {
+                iface == FACTORY_RESET_EVENT_INTERFACE && event.id == "42" && event.status == "Done"
+            })
+            .times(4)
+            .returning(|_: &str, _: &str, _: FactoryResetEvent| Ok(()));
+
+        let reset = FactoryReset::new(Default::default(), store.path(), downloads.path());
+        reset
+            .handle_request(
+                &publisher,
+                HashMap::from([("id".to_string(), AstarteType::String("42".to_string()))]),
+            )
+            .await;
+
+        assert!(!store.path().join("state.json").exists());
+        assert!(store.path().join("credentials_device.json").exists());
+        assert!(store.path().join("database.db").exists());
+        assert!(store.path().join("interfaces").exists());
+        assert!(!downloads.path().join("update.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn handle_failed_command() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send_object()
+            .withf(|_: &str, _: &str, result: &CustomCommandResult| {
+                result.name == "fail" && result.exit_code == 1 && result.error.is_empty()
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: CustomCommandResult| Ok(()));
+        publisher
+            .expect_send_object()
+            .withf(|_: &str, _: &str, result: &CustomCommandResult| {
+                result.name == "rm" && result.exit_code == -1 && !result.error.is_empty()
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: CustomCommandResult| Ok(()));
+
+        let commands = commands();
+        commands
+            .handle_request(&publisher, request("fail", &[]))
+            .await;
+        commands
+            .handle_request(&publisher, request("rm", &[]))
+            .await;
     }
 }
@@ -0,0 +1,376 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Monitor of the network connectivity of the device.
+//!
+//! The state is read from NetworkManager or systemd-networkd on D-Bus, falling back to a TCP
+//! probe of a configured address. The OTA downloads wait while the device is offline, or
+//! optionally on a metered connection.
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use astarte_device_sdk::types::AstarteType;
+use log::{debug, error, info};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use zbus::dbus_proxy;
+
+use crate::data::Publisher;
+
+const CONNECTIVITY_INTERFACE: &str = "io.edgehog.devicemanager.Connectivity";
+
+/// Timeout of the TCP probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[dbus_proxy(
+    interface = "org.freedesktop.NetworkManager",
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager"
+)]
+trait NetworkManager {
+    /// The result of the last connectivity check.
+    #[dbus_proxy(property)]
+    fn connectivity(&self) -> zbus::Result<u32>;
+
+    /// Whether the primary connection is metered.
+    #[dbus_proxy(property)]
+    fn metered(&self) -> zbus::Result<u32>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.network1.Manager",
+    default_service = "org.freedesktop.network1",
+    default_path = "/org/freedesktop/network1"
+)]
+trait Networkd {
+    /// Online state of the links managed by systemd-networkd.
+    #[dbus_proxy(property)]
+    fn online_state(&self) -> zbus::Result<String>;
+}
+
+const fn default_interval() -> u64 {
+    30
+}
+
+/// Configuration of the connectivity monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ConnectivityConfig {
+    /// Seconds between the checks, defaults to 30.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Address probed with a TCP connection when no network manager is available.
+    pub probe: Option<String>,
+    /// Pause the downloads also on metered connections.
+    #[serde(default)]
+    pub pause_on_metered: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectivityState {
+    Online,
+    Metered,
+    Offline,
+}
+
+impl ConnectivityState {
+    /// State from the NetworkManager `NMConnectivityState` and `NMMetered` values.
+    fn from_network_manager(connectivity: u32, metered: u32) -> Self {
+        match (connectivity, metered) {
+            // none, portal or limited
+            (1..=3, _) => Self::Offline,
+            // yes or guess-yes
+            (_, 1 | 3) => Self::Metered,
+            _ => Self::Online,
+        }
+    }
+
+    fn allows_download(&self, pause_on_metered: bool) -> bool {
+        match self {
+            ConnectivityState::Online => true,
+            ConnectivityState::Metered => !pause_on_metered,
+            ConnectivityState::Offline => false,
+        }
+    }
+}
+
+impl Display for ConnectivityState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectivityState::Online => write!(f, "online"),
+            ConnectivityState::Metered => write!(f, "metered"),
+            ConnectivityState::Offline => write!(f, "offline"),
+        }
+    }
+}
+
+/// Current connectivity of the device, always online if the monitor is not configured.
+#[derive(Debug, Clone)]
+pub struct ConnectivityWatch {
+    rx: watch::Receiver<ConnectivityState>,
+    pause_on_metered: bool,
+}
+
+impl ConnectivityWatch {
+    /// Wait until the connectivity allows to download.
+    pub async fn wait_for_download(&self) {
+        let mut rx = self.rx.clone();
+        let pause_on_metered = self.pause_on_metered;
+
+        let state = *rx.borrow_and_update();
+        if state.allows_download(pause_on_metered) {
+            return;
+        }
+
+        info!("download paused, the device is {state}");
+
+        // the monitor stopped, don't wait forever
+        if rx
+            .wait_for(|state| state.allows_download(pause_on_metered))
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        info!("download resumed");
+    }
+}
+
+impl Default for ConnectivityWatch {
+    fn default() -> Self {
+        let (_tx, rx) = watch::channel(ConnectivityState::Online);
+
+        Self {
+            rx,
+            pause_on_metered: false,
+        }
+    }
+}
+
+async fn network_manager_state() -> zbus::Result<ConnectivityState> {
+    let connection = zbus::Connection::system().await?;
+    let network_manager = NetworkManagerProxy::new(&connection).await?;
+
+    let connectivity = network_manager.connectivity().await?;
+    let metered = network_manager.metered().await?;
+
+    Ok(ConnectivityState::from_network_manager(
+        connectivity,
+        metered,
+    ))
+}
+
+async fn networkd_state() -> zbus::Result<ConnectivityState> {
+    let connection = zbus::Connection::system().await?;
+    let networkd = NetworkdProxy::new(&connection).await?;
+
+    let state = match networkd.online_state().await?.as_str() {
+        "offline" => ConnectivityState::Offline,
+        _ => ConnectivityState::Online,
+    };
+
+    Ok(state)
+}
+
+async fn probe(address: &str) -> ConnectivityState {
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => ConnectivityState::Online,
+        Ok(Err(err)) => {
+            debug!("connectivity probe of {address} failed: {err}");
+
+            ConnectivityState::Offline
+        }
+        Err(_) => {
+            debug!("connectivity probe of {address} timed out");
+
+            ConnectivityState::Offline
+        }
+    }
+}
+
+/// Periodically check the connectivity and send it to Astarte when it changes.
+pub struct ConnectivityMonitor<P> {
+    config: ConnectivityConfig,
+    publisher: P,
+    tx: watch::Sender<ConnectivityState>,
+}
+
+impl<P> ConnectivityMonitor<P>
+where
+    P: Publisher + Send + Sync,
+{
+    pub fn new(config: ConnectivityConfig, publisher: P) -> Self {
+        let (tx, _rx) = watch::channel(ConnectivityState::Online);
+
+        Self {
+            config,
+            publisher,
+            tx,
+        }
+    }
+
+    pub fn watch(&self) -> ConnectivityWatch {
+        ConnectivityWatch {
+            rx: self.tx.subscribe(),
+            pause_on_metered: self.config.pause_on_metered,
+        }
+    }
+
+    async fn check(&self) -> ConnectivityState {
+        match network_manager_state().await {
+            Ok(state) => return state,
+            Err(err) => debug!("couldn't get the state from NetworkManager: {err}"),
+        }
+
+        match networkd_state().await {
+            Ok(state) => return state,
+            Err(err) => debug!("couldn't get the state from systemd-networkd: {err}"),
+        }
+
+        match &self.config.probe {
+            Some(address) => probe(address).await,
+            None => ConnectivityState::Online,
+        }
+    }
+
+    async fn update(&self, state: ConnectivityState, last: &mut Option<ConnectivityState>) {
+        if *last == Some(state) {
+            return;
+        }
+
+        info!("the device is {state}");
+
+        self.tx.send_replace(state);
+
+        match self
+            .publisher
+            .send(
+                CONNECTIVITY_INTERFACE,
+                "/state",
+                AstarteType::String(state.to_string()),
+            )
+            .await
+        {
+            Ok(()) => *last = Some(state),
+            Err(err) => error!("couldn't send the connectivity state: {err}"),
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+        let mut last = None;
+
+        loop {
+            interval.tick().await;
+
+            let state = self.check().await;
+
+            self.update(state, &mut last).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    #[test]
+    fn network_manager_connectivity() {
+        let cases = [
+            (4, 2, ConnectivityState::Online),
+            (0, 0, ConnectivityState::Online),
+            (4, 1, ConnectivityState::Metered),
+            (4, 3, ConnectivityState::Metered),
+            (1, 2, ConnectivityState::Offline),
+            (2, 1, ConnectivityState::Offline),
+            (3, 0, ConnectivityState::Offline),
+        ];
+
+        for (connectivity, metered, exp) in cases {
+            assert_eq!(
+                ConnectivityState::from_network_manager(connectivity, metered),
+                exp,
+                "connectivity {connectivity}, metered {metered}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume_download() {
+        let config = ConnectivityConfig {
+            interval: 30,
+            probe: None,
+            pause_on_metered: true,
+        };
+
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send()
+            .withf(|iface: &str, path: &str, _: &AstarteType| {
+                iface == CONNECTIVITY_INTERFACE && path == "/state"
+            })
+            .times(2)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let monitor = ConnectivityMonitor::new(config, publisher);
+        let watch = monitor.watch();
+        let mut last = None;
+
+        monitor.update(ConnectivityState::Metered, &mut last).await;
+        // unchanged state is not sent again
+        monitor.update(ConnectivityState::Metered, &mut last).await;
+
+        let download = tokio::spawn(async move { watch.wait_for_download().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!download.is_finished());
+
+        monitor.update(ConnectivityState::Online, &mut last).await;
+
+        tokio::time::timeout(Duration::from_secs(1), download)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn download_without_monitor() {
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            ConnectivityWatch::default().wait_for_download(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn probe_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        assert_eq!(probe(&address).await, ConnectivityState::Online);
+
+        drop(listener);
+
+        assert_eq!(probe(&address).await, ConnectivityState::Offline);
+    }
+}
@@ -0,0 +1,421 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Crash reports of the panics and of the errors stopping the runtime.
+//!
+//! The report, with the backtrace, the runtime version and the last log lines, is written to the
+//! store directory when the runtime crashes. On the next startup a summary of each new report is
+//! sent to Astarte, while the reports are kept on the device to be inspected.
+
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io;
+use std::panic::{self, PanicInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use astarte_device_sdk::AstarteAggregate;
+use log::{error, info, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::commands::now_millis;
+use crate::data::Publisher;
+
+const CRASH_REPORT_INTERFACE: &str = "io.edgehog.devicemanager.CrashReport";
+
+/// Sub-directory of the store with the reports.
+const REPORTS_DIRECTORY: &str = "crash_reports";
+
+/// Number of log lines kept for the report.
+const RECENT_LOGS: usize = 100;
+
+static REPORTER: OnceLock<CrashReporter> = OnceLock::new();
+static RECORD_LOGS: AtomicBool = AtomicBool::new(false);
+static LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+const fn default_enabled() -> bool {
+    true
+}
+
+const fn default_max_size() -> usize {
+    64 * 1024
+}
+
+const fn default_max_reports() -> usize {
+    5
+}
+
+/// Configuration of the crash reports.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CrashReportConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Maximum size in bytes of a report, the oldest log lines and the backtrace are truncated.
+    #[serde(default = "default_max_size")]
+    pub max_size: usize,
+    /// Number of reports kept, the oldest are removed.
+    #[serde(default = "default_max_reports")]
+    pub max_reports: usize,
+}
+
+impl Default for CrashReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            max_size: default_max_size(),
+            max_reports: default_max_reports(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CrashReport {
+    /// Either `panic` or `error`.
+    kind: String,
+    message: String,
+    version: String,
+    /// Unix time in milliseconds of the crash.
+    timestamp: i64,
+    backtrace: String,
+    logs: Vec<String>,
+}
+
+impl CrashReport {
+    fn new(kind: &str, message: String) -> Self {
+        let logs = LOGS
+            .lock()
+            .map(|logs| logs.iter().cloned().collect())
+            .unwrap_or_default();
+
+        Self {
+            kind: kind.to_string(),
+            message,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: now_millis(),
+            backtrace: Backtrace::force_capture().to_string(),
+            logs,
+        }
+    }
+
+    /// Serialize the report, dropping the oldest log lines and truncating the backtrace to fit.
+    fn to_json(mut self, max_size: usize) -> Result<Vec<u8>, serde_json::Error> {
+        loop {
+            let json = serde_json::to_vec_pretty(&self)?;
+
+            if json.len() <= max_size {
+                return Ok(json);
+            }
+
+            if !self.logs.is_empty() {
+                self.logs.remove(0);
+
+                continue;
+            }
+
+            if self.backtrace.is_empty() {
+                return Ok(json);
+            }
+
+            let excess = json.len() - max_size;
+            let mut len = self.backtrace.len().saturating_sub(excess);
+            while !self.backtrace.is_char_boundary(len) {
+                len -= 1;
+            }
+            self.backtrace.truncate(len);
+        }
+    }
+}
+
+/// Summary of a report sent to Astarte.
+#[derive(Debug, Clone, PartialEq, Eq, AstarteAggregate)]
+pub struct CrashReportEvent {
+    pub kind: String,
+    pub message: String,
+    pub version: String,
+    pub timestamp: i64,
+}
+
+impl From<CrashReport> for CrashReportEvent {
+    fn from(value: CrashReport) -> Self {
+        Self {
+            kind: value.kind,
+            message: value.message,
+            version: value.version,
+            timestamp: value.timestamp,
+        }
+    }
+}
+
+struct CrashReporter {
+    directory: PathBuf,
+    config: CrashReportConfig,
+}
+
+/// Keep the log line for the crash reports.
+pub(crate) fn record(record: &Record) {
+    if !RECORD_LOGS.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let line = format!(
+        "{} {} {}: {}",
+        now_millis(),
+        record.level(),
+        record.target(),
+        record.args()
+    );
+
+    if let Ok(mut logs) = LOGS.lock() {
+        if logs.len() == RECENT_LOGS {
+            logs.pop_front();
+        }
+
+        logs.push_back(line);
+    }
+}
+
+fn write_report(
+    directory: &Path,
+    config: &CrashReportConfig,
+    report: CrashReport,
+) -> io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+
+    let path = directory.join(format!("crash-{:016}.json", report.timestamp));
+    let json = report.to_json(config.max_size)?;
+
+    std::fs::write(path, json)
+}
+
+fn report(kind: &str, message: String) {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+
+    let report = CrashReport::new(kind, message);
+
+    if let Err(err) = write_report(&reporter.directory, &reporter.config, report) {
+        error!("couldn't write the crash report: {err}");
+    }
+}
+
+fn panic_message(panic_info: &PanicInfo) -> String {
+    let payload = panic_info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panic occurred");
+
+    match panic_info.location() {
+        Some(location) => format!("{message} at {location}"),
+        None => message.to_string(),
+    }
+}
+
+/// Write a report on panic, after the already installed hook.
+pub fn install(config: &CrashReportConfig, store_directory: &Path) {
+    if !config.enabled {
+        return;
+    }
+
+    let reporter = CrashReporter {
+        directory: store_directory.join(REPORTS_DIRECTORY),
+        config: config.clone(),
+    };
+
+    if REPORTER.set(reporter).is_err() {
+        return;
+    }
+
+    RECORD_LOGS.store(true, Ordering::Relaxed);
+
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        previous_hook(panic_info);
+        report("panic", panic_message(panic_info));
+    }));
+}
+
+/// Write a report for the error that stopped the runtime.
+pub fn write_error_report(err: &dyn Display) {
+    report("error", err.to_string());
+}
+
+/// Report files in the directory, sorted from the oldest.
+fn report_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        let is_report = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("crash-") && name.ends_with(".json"));
+
+        if is_report {
+            files.push(path);
+        }
+    }
+
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    Ok(files)
+}
+
+fn is_sent(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.ends_with(".sent.json"))
+}
+
+async fn send_reports<P>(publisher: &P, directory: &Path, config: &CrashReportConfig)
+where
+    P: Publisher + Send + Sync,
+{
+    let files = match report_files(directory) {
+        Ok(files) => files,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            error!("couldn't list the crash reports: {err}");
+
+            return;
+        }
+    };
+
+    for path in files.iter().filter(|path| !is_sent(path)) {
+        let report: CrashReport = match std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|content| serde_json::from_slice(&content).map_err(|err| err.to_string()))
+        {
+            Ok(report) => report,
+            Err(err) => {
+                error!("couldn't read the crash report {}: {err}", path.display());
+
+                continue;
+            }
+        };
+
+        info!("sending crash report {}", path.display());
+
+        if let Err(err) = publisher
+            .send_object(
+                CRASH_REPORT_INTERFACE,
+                "/report",
+                CrashReportEvent::from(report),
+            )
+            .await
+        {
+            error!("couldn't send the crash report: {err}");
+
+            return;
+        }
+
+        if let Err(err) = std::fs::rename(path, path.with_extension("sent.json")) {
+            error!("couldn't mark the crash report as sent: {err}");
+        }
+    }
+
+    let Ok(files) = report_files(directory) else {
+        return;
+    };
+
+    let excess = files.len().saturating_sub(config.max_reports);
+    for path in &files[..excess] {
+        if let Err(err) = std::fs::remove_file(path) {
+            error!("couldn't remove the crash report {}: {err}", path.display());
+        }
+    }
+}
+
+/// Send the summary of the reports written since the last startup.
+pub(crate) async fn send_pending_reports<P>(publisher: &P)
+where
+    P: Publisher + Send + Sync,
+{
+    if let Some(reporter) = REPORTER.get() {
+        send_reports(publisher, &reporter.directory, &reporter.config).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::data::tests::MockPublisher;
+
+    fn crash_report(timestamp: i64) -> CrashReport {
+        CrashReport {
+            kind: "panic".to_string(),
+            message: "index out of bounds".to_string(),
+            version: "0.7.1".to_string(),
+            timestamp,
+            backtrace: "0: main\n".repeat(100),
+            logs: (0..50).map(|i| format!("line {i}")).collect(),
+        }
+    }
+
+    #[test]
+    fn truncate_report() {
+        let json = crash_report(0).to_json(1024).unwrap();
+        assert!(json.len() <= 1024);
+
+        let report: CrashReport = serde_json::from_slice(&json).unwrap();
+        assert!(report.logs.is_empty());
+        assert!(report.backtrace.starts_with("0: main"));
+
+        let json = crash_report(0).to_json(usize::MAX).unwrap();
+        let report: CrashReport = serde_json::from_slice(&json).unwrap();
+        assert_eq!(report, crash_report(0));
+    }
+
+    #[tokio::test]
+    async fn send_and_prune_reports() {
+        let dir = TempDir::new("edgehog-crash").unwrap();
+        let config = CrashReportConfig {
+            max_reports: 2,
+            ..Default::default()
+        };
+
+        for timestamp in [1, 2, 3] {
+            write_report(dir.path(), &config, crash_report(timestamp)).unwrap();
+        }
+
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, path: &str, event: &CrashReportEvent| {
+                iface == CRASH_REPORT_INTERFACE && path == "/report" && event.kind == "panic"
+            })
+            .times(3)
+            .returning(|_: &str, _: &str, _: CrashReportEvent| Ok(()));
+
+        send_reports(&publisher, dir.path(), &config).await;
+
+        let files = report_files(dir.path()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|path| is_sent(path)));
+
+        // the reports already sent are not sent again
+        send_reports(&MockPublisher::new(), dir.path(), &config).await;
+    }
+}
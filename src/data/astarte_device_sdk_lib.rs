@@ -18,7 +18,8 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
-use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
 use astarte_device_sdk::builder::DeviceBuilder;
 use astarte_device_sdk::store::SqliteStore;
@@ -28,7 +29,8 @@ use astarte_device_sdk::types::AstarteType;
 use astarte_device_sdk::{error::Error as AstarteError, AstarteDeviceDataEvent, AstarteDeviceSdk};
 use astarte_device_sdk::{prelude::*, EventReceiver};
 use async_trait::async_trait;
-use log::error;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
 use serde::Deserialize;
 use tokio::task::JoinHandle;
 
@@ -37,6 +39,9 @@ use crate::device::DeviceProxy;
 use crate::repository::file_state_repository::{FileStateError, FileStateRepository};
 use crate::repository::StateRepository;
 
+/// Permissions of the stored credentials secret, readable only by the runtime.
+const CREDENTIALS_SECRET_MODE: u32 = 0o600;
+
 /// Error returned by the [`astarte_device_sdk`].
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum DeviceSdkError {
@@ -56,6 +61,12 @@ pub enum DeviceSdkError {
     Interfaces(#[source] astarte_device_sdk::builder::BuilderError),
     /// couldn't connect to Astarte
     Connect(#[source] astarte_device_sdk::Error),
+    /// couldn't read the CA bundle {path}
+    CaBundle {
+        #[source]
+        backtrace: std::io::Error,
+        path: String,
+    },
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -67,6 +78,8 @@ pub struct AstarteDeviceSdkConfigOptions {
     pub pairing_token: Option<String>,
     #[serde(default)]
     pub ignore_ssl: bool,
+    /// PEM file with the certificate authorities trusted for Astarte, instead of the system ones.
+    pub ca_bundle: Option<PathBuf>,
 }
 
 impl AstarteDeviceSdkConfigOptions {
@@ -94,19 +107,49 @@ impl AstarteDeviceSdkConfigOptions {
         let registry = FileStateRepository::new(
             store_directory.as_ref(),
             format!("credentials_{}.json", device_id),
-        );
+        )
+        .with_mode(CREDENTIALS_SECRET_MODE);
 
         if StateRepository::<String>::exists(&registry).await {
+            info!("using the stored credentials secret");
+
+            restrict_permissions(&registry.path).await;
+
             return registry.read().await.map_err(DeviceSdkError::ReadSecret);
         }
 
         if let Some(token) = &self.pairing_token {
-            return self.register_device(device_id, token, registry).await;
+            info!("registering device {device_id}");
+
+            let secret = self.register_device(device_id, token, registry).await?;
+
+            info!("device registered, credentials secret stored");
+
+            return Ok(secret);
         }
 
         Err(DeviceSdkError::MissingCredentialSecret)
     }
 
+    /// Trust the certificate authorities of the CA bundle.
+    ///
+    /// The TLS connections of the SDK read the trusted certificates from the `SSL_CERT_FILE`
+    /// environment variable when it's set.
+    fn apply_ca_bundle(&self) -> Result<(), DeviceSdkError> {
+        let Some(path) = &self.ca_bundle else {
+            return Ok(());
+        };
+
+        std::fs::metadata(path).map_err(|err| DeviceSdkError::CaBundle {
+            backtrace: err,
+            path: path.display().to_string(),
+        })?;
+
+        std::env::set_var("SSL_CERT_FILE", path);
+
+        Ok(())
+    }
+
     async fn register_device(
         &self,
         device_id: &str,
@@ -135,6 +178,8 @@ impl AstarteDeviceSdkConfigOptions {
     {
         let device_id = self.device_id_or_from_dbus().await?;
 
+        self.apply_ca_bundle()?;
+
         let credentials_secret = self.credentials_secret(&device_id, store_dir).await?;
 
         let mut mqtt_cfg = MqttConfig::new(
@@ -145,6 +190,8 @@ impl AstarteDeviceSdkConfigOptions {
         );
 
         if self.ignore_ssl {
+            warn!("the Astarte certificates are not verified, set a ca_bundle instead");
+
             mqtt_cfg.ignore_ssl_errors();
         }
 
@@ -167,6 +214,18 @@ impl AstarteDeviceSdkConfigOptions {
     }
 }
 
+/// Restrict the permissions of a secret stored by a previous version.
+async fn restrict_permissions(path: &Path) {
+    let permissions = std::fs::Permissions::from_mode(CREDENTIALS_SECRET_MODE);
+
+    if let Err(err) = tokio::fs::set_permissions(path, permissions).await {
+        warn!(
+            "couldn't restrict the permissions of {}: {err}",
+            path.display()
+        );
+    }
+}
+
 pub async fn hardware_id_from_dbus() -> Result<Option<String>, DeviceSdkError> {
     let connection = zbus::Connection::system().await?;
     let proxy = DeviceProxy::new(&connection).await?;
@@ -199,6 +258,21 @@ impl Publisher for DeviceSdkPublisher {
             .await
     }
 
+    async fn send_object_with_timestamp<T: 'static>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send,
+    {
+        self.0
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
     async fn send(
         &self,
         interface_name: &str,
@@ -208,6 +282,18 @@ impl Publisher for DeviceSdkPublisher {
         self.0.send(interface_name, interface_path, data).await
     }
 
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        self.0
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
     async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
         self.0.interface_props(interface).await
     }
@@ -263,6 +349,7 @@ mod tests {
             pairing_url: String::new(),
             pairing_token: None,
             ignore_ssl: false,
+            ca_bundle: None,
         };
 
         let id = opts.device_id_or_from_dbus().await.unwrap();
@@ -281,6 +368,7 @@ mod tests {
             pairing_url: "".to_string(),
             pairing_token: None,
             ignore_ssl: false,
+            ca_bundle: None,
         };
 
         let secret = options.credentials_secret("device_id", path).await.unwrap();
@@ -288,6 +376,35 @@ mod tests {
         assert_eq!(secret, "credentials_secret");
     }
 
+    #[tokio::test]
+    async fn stored_credentials_secret() {
+        let dir = TempDir::new("sdk_cred").unwrap();
+        let file = dir.path().join("credentials_device_id.json");
+
+        std::fs::write(&file, "\"stored_secret\"").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let options = AstarteDeviceSdkConfigOptions {
+            realm: "".to_string(),
+            device_id: None,
+            credentials_secret: None,
+            pairing_url: "".to_string(),
+            pairing_token: Some("token".to_string()),
+            ignore_ssl: false,
+            ca_bundle: None,
+        };
+
+        let secret = options
+            .credentials_secret("device_id", dir.path())
+            .await
+            .unwrap();
+
+        assert_eq!(secret, "stored_secret");
+
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, CREDENTIALS_SECRET_MODE);
+    }
+
     #[tokio::test]
     async fn not_enough_arguments_credentials_secret_test() {
         let _dir = TempDir::new("sdk_cred").unwrap();
@@ -300,6 +417,7 @@ mod tests {
             pairing_url: "".to_string(),
             pairing_token: None,
             ignore_ssl: false,
+            ca_bundle: None,
         };
 
         let res = options.credentials_secret("device_id", &path).await;
@@ -325,6 +443,7 @@ mod tests {
             pairing_url: "".to_string(),
             pairing_token: None,
             ignore_ssl: true,
+            ca_bundle: None,
         };
 
         let res = options.credentials_secret(device_id, path).await;
@@ -353,6 +472,7 @@ mod tests {
             pairing_url: "".to_string(),
             pairing_token: None,
             ignore_ssl: false,
+            ca_bundle: None,
         };
 
         let secret = options.credentials_secret(device_id, path).await.unwrap();
@@ -371,6 +491,7 @@ mod tests {
             pairing_url: String::new(),
             pairing_token: Some(token.to_string()),
             ignore_ssl: false,
+            ca_bundle: None,
         };
 
         let state_mock = MockStateRepository::<String>::new();
@@ -381,4 +502,21 @@ mod tests {
             Err(DeviceSdkError::Pairing(PairingError::InvalidUrl(_)))
         ));
     }
+
+    #[test]
+    fn missing_ca_bundle() {
+        let options = AstarteDeviceSdkConfigOptions {
+            realm: String::new(),
+            device_id: None,
+            credentials_secret: None,
+            pairing_url: String::new(),
+            pairing_token: None,
+            ignore_ssl: false,
+            ca_bundle: Some(PathBuf::from("/nonexistent/ca.pem")),
+        };
+
+        let res = options.apply_ca_bundle();
+
+        assert!(matches!(res, Err(DeviceSdkError::CaBundle { .. })));
+    }
 }
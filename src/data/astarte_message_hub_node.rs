@@ -19,6 +19,9 @@
  */
 
 //! Contains the implementation for the Astarte message hub node.
+//!
+//! The node is attached again, with an exponential backoff, when the Message Hub restarts. The
+//! publishes wait for the node to be attached, and the state is exposed to the runtime.
 
 use astarte_device_sdk::builder::DeviceBuilder;
 use astarte_device_sdk::prelude::*;
@@ -32,10 +35,15 @@ use astarte_device_sdk::AstarteDeviceSdk;
 use astarte_device_sdk::Error as AstarteError;
 use astarte_device_sdk::EventReceiver;
 use async_trait::async_trait;
-use log::error;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
 use serde::Deserialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch, RwLock};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use uuid::uuid;
 use uuid::Uuid;
 
@@ -44,6 +52,15 @@ use crate::data::{Publisher, Subscriber};
 /// Device runtime node identifier.
 const DEVICE_RUNTIME_NODE_UUID: Uuid = uuid!("d72a6187-7cf1-44cc-87e8-e991936166db");
 
+/// First wait before attaching the node again, doubled at every failed attempt.
+const REATTACH_INITIAL_INTERVAL: Duration = Duration::from_secs(1);
+/// Maximum wait between the attempts, the backoff is reset after staying attached this long.
+const REATTACH_MAX_INTERVAL: Duration = Duration::from_secs(60);
+/// Maximum time a publish waits for the node to be attached before being sent anyway.
+const PUBLISH_WAIT: Duration = Duration::from_secs(30);
+
+type Device = AstarteDeviceSdk<SqliteStore, Grpc>;
+
 /// Error returned by the [`astarte_device_sdk`].
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum MessageHubError {
@@ -71,30 +88,170 @@ impl AstarteMessageHubOptions {
     where
         P: AsRef<Path>,
     {
+        let node = Node {
+            endpoint: self.endpoint.clone(),
+            store,
+            interface_dir: interface_dir.as_ref().to_path_buf(),
+        };
+
+        let (device, rx) = node.attach().await?;
+
+        let device = Arc::new(RwLock::new(device));
+        let (state_tx, state_rx) = watch::channel(NodeState::Attached);
+        let (events_tx, events_rx) = mpsc::channel(32);
+
+        let handle = tokio::spawn(node.run(device.clone(), rx, events_tx, state_tx));
+
+        Ok((
+            MessageHubPublisher {
+                device,
+                state: state_rx,
+            },
+            MessageHubSubscriber {
+                rx: events_rx,
+                handle,
+            },
+        ))
+    }
+}
+
+/// Connection state of the node to the Message Hub.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    Attached,
+    Detached,
+}
+
+/// Information to attach the node to the Message Hub.
+#[derive(Debug)]
+struct Node {
+    endpoint: String,
+    store: SqliteStore,
+    interface_dir: PathBuf,
+}
+
+impl Node {
+    async fn attach(&self) -> Result<(Device, EventReceiver), MessageHubError> {
         let grpc_cfg = GrpcConfig::new(DEVICE_RUNTIME_NODE_UUID, self.endpoint.clone());
 
         let (device, rx) = DeviceBuilder::new()
-            .store(store)
-            .interface_directory(interface_dir)
+            .store(self.store.clone())
+            .interface_directory(&self.interface_dir)
             .map_err(MessageHubError::Interfaces)?
             .connect(grpc_cfg)
             .await
             .map_err(MessageHubError::Connect)?
             .build();
 
-        let mut device_cl = device.clone();
-        let handle = tokio::spawn(async move { device_cl.handle_events().await });
+        Ok((device, rx))
+    }
 
-        Ok((
-            MessageHubPublisher(device),
-            MessageHubSubscriber { rx, handle },
-        ))
+    /// Handle the events of the node, attaching it again when detached.
+    async fn run(
+        self,
+        device: Arc<RwLock<Device>>,
+        mut rx: EventReceiver,
+        events: mpsc::Sender<Result<AstarteDeviceDataEvent, AstarteError>>,
+        state: watch::Sender<NodeState>,
+    ) -> Result<(), AstarteError> {
+        let mut interval = REATTACH_INITIAL_INTERVAL;
+
+        loop {
+            let attached_at = Instant::now();
+            let mut device_cl = device.read().await.clone();
+
+            let res = {
+                let mut handle_events = std::pin::pin!(device_cl.handle_events());
+
+                loop {
+                    tokio::select! {
+                        res = &mut handle_events => break res,
+                        event = rx.recv() => {
+                            let Some(event) = event else {
+                                break Ok(());
+                            };
+
+                            // the subscriber was dropped
+                            if events.send(event).await.is_err() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+            };
+
+            // forward the events received before the detach
+            while let Ok(event) = rx.try_recv() {
+                if events.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+
+            match res {
+                Ok(()) => return Ok(()),
+                Err(err) => error!("node detached from the Message Hub: {err}"),
+            }
+
+            state.send_replace(NodeState::Detached);
+
+            if attached_at.elapsed() >= REATTACH_MAX_INTERVAL {
+                interval = REATTACH_INITIAL_INTERVAL;
+            }
+
+            loop {
+                debug!("attaching the node again in {interval:?}");
+                tokio::time::sleep(interval).await;
+
+                interval = (interval * 2).min(REATTACH_MAX_INTERVAL);
+
+                match self.attach().await {
+                    Ok((new_device, new_rx)) => {
+                        *device.write().await = new_device;
+                        rx = new_rx;
+
+                        break;
+                    }
+                    Err(err) => warn!("couldn't attach the node to the Message Hub: {err}"),
+                }
+            }
+
+            info!("node attached to the Message Hub");
+
+            state.send_replace(NodeState::Attached);
+        }
     }
 }
 
 /// Sender for the MessageHub
 #[derive(Debug, Clone)]
-pub struct MessageHubPublisher(AstarteDeviceSdk<SqliteStore, Grpc>);
+pub struct MessageHubPublisher {
+    device: Arc<RwLock<Device>>,
+    state: watch::Receiver<NodeState>,
+}
+
+impl MessageHubPublisher {
+    /// Receiver of the state of the node, to pause the operations while it's detached.
+    pub fn node_state(&self) -> watch::Receiver<NodeState> {
+        self.state.clone()
+    }
+
+    /// Device attached to the Message Hub, waiting for the node to be attached.
+    async fn device(&self) -> Device {
+        let mut state = self.state.clone();
+
+        let attached = tokio::time::timeout(
+            PUBLISH_WAIT,
+            state.wait_for(|state| *state == NodeState::Attached),
+        )
+        .await;
+
+        if attached.is_err() {
+            debug!("node still detached, sending anyway");
+        }
+
+        self.device.read().await.clone()
+    }
+}
 
 #[async_trait]
 impl Publisher for MessageHubPublisher {
@@ -107,26 +264,62 @@ impl Publisher for MessageHubPublisher {
     where
         T: AstarteAggregate + Send,
     {
-        self.0
+        self.device()
+            .await
             .send_object(interface_name, interface_path, data)
             .await
     }
 
+    async fn send_object_with_timestamp<T: 'static>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send,
+    {
+        self.device()
+            .await
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
     async fn send(
         &self,
         interface_name: &str,
         interface_path: &str,
         data: AstarteType,
     ) -> Result<(), AstarteError> {
-        self.0.send(interface_name, interface_path, data).await
+        self.device()
+            .await
+            .send(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        self.device()
+            .await
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
     }
 
     async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
-        self.0.interface_props(interface).await
+        self.device().await.interface_props(interface).await
     }
 
     async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
-        self.0.unset(interface_name, interface_path).await
+        self.device()
+            .await
+            .unset(interface_name, interface_path)
+            .await
     }
 }
 
@@ -134,7 +327,7 @@ impl Publisher for MessageHubPublisher {
 #[derive(Debug)]
 pub struct MessageHubSubscriber {
     handle: JoinHandle<Result<(), AstarteError>>,
-    rx: EventReceiver,
+    rx: mpsc::Receiver<Result<AstarteDeviceDataEvent, AstarteError>>,
 }
 
 #[async_trait]
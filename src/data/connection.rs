@@ -0,0 +1,305 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! State of the connection to Astarte.
+//!
+//! The SDK doesn't expose the state of the underlying connection, so it's tracked from the outcome
+//! of the publishes, from the events and errors received from Astarte and, with the Message Hub,
+//! from the state of the node. This way an idle device is marked offline too.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+#[cfg(feature = "message-hub")]
+use crate::data::astarte_message_hub_node::NodeState;
+use crate::data::Publisher;
+
+/// Connection to Astarte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connection {
+    Connected,
+    /// The connection was lost at the given instant.
+    Disconnected(Instant),
+}
+
+/// Handle to the connection state, notifying the subscribers only on changes.
+#[derive(Debug, Clone)]
+pub struct ConnectionState(Arc<watch::Sender<Connection>>);
+
+impl ConnectionState {
+    pub fn set_connected(&self) {
+        self.0.send_if_modified(|connection| {
+            if *connection == Connection::Connected {
+                return false;
+            }
+
+            info!("connection to Astarte re-established");
+
+            *connection = Connection::Connected;
+
+            true
+        });
+    }
+
+    /// Mark the connection as lost, keeping the instant it was first lost at.
+    pub fn set_disconnected(&self) {
+        self.0.send_if_modified(|connection| {
+            if *connection != Connection::Connected {
+                return false;
+            }
+
+            warn!("connection to Astarte lost");
+
+            *connection = Connection::Disconnected(Instant::now());
+
+            true
+        });
+    }
+
+    /// Time since the connection was lost, [`None`] while connected.
+    pub fn disconnected_for(&self) -> Option<Duration> {
+        match *self.0.borrow() {
+            Connection::Connected => None,
+            Connection::Disconnected(since) => Some(since.elapsed()),
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Connection> {
+        self.0.subscribe()
+    }
+
+    /// Update the state from the connection of the node to the Message Hub, until it's dropped.
+    #[cfg(feature = "message-hub")]
+    pub async fn follow_node(self, mut node: watch::Receiver<NodeState>) {
+        loop {
+            match *node.borrow_and_update() {
+                NodeState::Attached => self.set_connected(),
+                NodeState::Detached => self.set_disconnected(),
+            }
+
+            if node.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        let (tx, _rx) = watch::channel(Connection::Connected);
+
+        Self(Arc::new(tx))
+    }
+}
+
+/// Publisher updating the [`ConnectionState`] with the outcome of each publish.
+#[derive(Debug, Clone)]
+pub struct ConnectionTracker<P> {
+    publisher: P,
+    state: ConnectionState,
+}
+
+impl<P> ConnectionTracker<P> {
+    pub fn new(publisher: P, state: ConnectionState) -> Self {
+        Self { publisher, state }
+    }
+
+    fn track(&self, res: Result<(), AstarteError>) -> Result<(), AstarteError> {
+        match &res {
+            Ok(()) => self.state.set_connected(),
+            Err(_) => self.state.set_disconnected(),
+        }
+
+        res
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for ConnectionTracker<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let res = self
+            .publisher
+            .send_object(interface_name, interface_path, data)
+            .await;
+
+        self.track(res)
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let res = self
+            .publisher
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await;
+
+        self.track(res)
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        let res = self
+            .publisher
+            .send(interface_name, interface_path, data)
+            .await;
+
+        self.track(res)
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        let res = self
+            .publisher
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await;
+
+        self.track(res)
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.publisher.interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        let res = self.publisher.unset(interface_name, interface_path).await;
+
+        self.track(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    #[tokio::test(start_paused = true)]
+    async fn disconnected_since_first_failure() {
+        let state = ConnectionState::default();
+        assert_eq!(state.disconnected_for(), None);
+
+        state.set_disconnected();
+        tokio::time::advance(Duration::from_secs(10)).await;
+        state.set_disconnected();
+        assert_eq!(state.disconnected_for(), Some(Duration::from_secs(10)));
+
+        state.set_connected();
+        assert_eq!(state.disconnected_for(), None);
+    }
+
+    #[cfg(feature = "message-hub")]
+    #[tokio::test]
+    async fn follow_node_state() {
+        let (node_tx, node_rx) = watch::channel(NodeState::Attached);
+        let state = ConnectionState::default();
+        let mut rx = state.subscribe();
+
+        let handle = tokio::spawn(state.clone().follow_node(node_rx));
+
+        node_tx.send_replace(NodeState::Detached);
+        rx.changed().await.unwrap();
+        assert!(matches!(
+            *rx.borrow_and_update(),
+            Connection::Disconnected(_)
+        ));
+
+        node_tx.send_replace(NodeState::Attached);
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow_and_update(), Connection::Connected);
+
+        drop(node_tx);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn track_publish_outcome() {
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send()
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Err(AstarteError::ConnectionTimeout));
+        publisher
+            .expect_send()
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let state = ConnectionState::default();
+        let mut rx = state.subscribe();
+        let tracker = ConnectionTracker::new(publisher, state);
+
+        let res = tracker
+            .send(
+                "io.edgehog.devicemanager.SystemStatus",
+                "/systemStatus",
+                AstarteType::Integer(1),
+            )
+            .await;
+        assert!(res.is_err());
+        assert!(matches!(
+            *rx.borrow_and_update(),
+            Connection::Disconnected(_)
+        ));
+
+        tracker
+            .send(
+                "io.edgehog.devicemanager.SystemStatus",
+                "/systemStatus",
+                AstarteType::Integer(1),
+            )
+            .await
+            .unwrap();
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow(), Connection::Connected);
+    }
+}
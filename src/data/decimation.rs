@@ -0,0 +1,478 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decimation of the high-frequency telemetry, to reduce the traffic on metered links.
+//!
+//! For the configured interfaces, the [`Decimation`] publisher either sends a value only when it
+//! changes more than a delta from the last one sent, or aggregates the values of a path over a
+//! number of periods and sends their minimum, maximum or average.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::trace;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::data::Publisher;
+
+/// Decimation of an interface.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DecimationConfig {
+    pub interface: String,
+    #[serde(flatten)]
+    pub mode: DecimationMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum DecimationMode {
+    /// Send the numeric values only when they change more than the delta, the others when they
+    /// change.
+    Delta { delta: f64 },
+    /// Send the aggregate of the numeric values every number of periods, the others are sent with
+    /// the last value.
+    Aggregate {
+        periods: u32,
+        function: AggregateFunction,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateFunction {
+    Min,
+    Max,
+    Avg,
+}
+
+fn numeric(value: &AstarteType) -> Option<f64> {
+    match value {
+        AstarteType::Double(value) => Some(*value),
+        AstarteType::Integer(value) => Some(f64::from(*value)),
+        AstarteType::LongInteger(value) => Some(*value as f64),
+        _ => None,
+    }
+}
+
+/// Numeric value with the same type of the template, to still match the interface mapping.
+fn with_type_of(template: &AstarteType, value: f64) -> AstarteType {
+    match template {
+        AstarteType::Integer(_) => AstarteType::Integer(value.round() as i32),
+        AstarteType::LongInteger(_) => AstarteType::LongInteger(value.round() as i64),
+        _ => AstarteType::Double(value),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u32,
+}
+
+impl Accumulator {
+    fn new(value: f64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn value(&self, function: AggregateFunction) -> f64 {
+        match function {
+            AggregateFunction::Min => self.min,
+            AggregateFunction::Max => self.max,
+            AggregateFunction::Avg => self.sum / f64::from(self.count),
+        }
+    }
+}
+
+/// State of a path, the individual values are stored with an empty field name.
+#[derive(Debug, Default)]
+struct PathState {
+    /// Last values sent, for the delta mode.
+    sent: Option<HashMap<String, AstarteType>>,
+    /// Values received in the current aggregation window.
+    periods: u32,
+    accumulators: HashMap<String, Accumulator>,
+}
+
+impl PathState {
+    /// Whether the values changed beyond the delta from the last ones sent.
+    fn changed(&self, data: &HashMap<String, AstarteType>, delta: f64) -> bool {
+        let Some(sent) = &self.sent else {
+            return true;
+        };
+
+        data.iter().any(|(field, value)| match sent.get(field) {
+            Some(last) => match (numeric(last), numeric(value)) {
+                (Some(last), Some(value)) => (value - last).abs() >= delta,
+                _ => last != value,
+            },
+            None => true,
+        })
+    }
+
+    /// Accumulate the values, returning the aggregated ones at the end of the window.
+    fn aggregate(
+        &mut self,
+        data: HashMap<String, AstarteType>,
+        periods: u32,
+        function: AggregateFunction,
+    ) -> Option<HashMap<String, AstarteType>> {
+        for (field, value) in &data {
+            let Some(value) = numeric(value) else {
+                continue;
+            };
+
+            self.accumulators
+                .entry(field.clone())
+                .and_modify(|acc| acc.add(value))
+                .or_insert_with(|| Accumulator::new(value));
+        }
+
+        self.periods += 1;
+        if self.periods < periods {
+            return None;
+        }
+
+        let aggregated = data
+            .into_iter()
+            .map(|(field, value)| {
+                let value = match self.accumulators.get(&field) {
+                    Some(acc) => with_type_of(&value, acc.value(function)),
+                    None => value,
+                };
+
+                (field, value)
+            })
+            .collect();
+
+        self.periods = 0;
+        self.accumulators.clear();
+
+        Some(aggregated)
+    }
+}
+
+/// Publisher decimating the telemetry of the configured interfaces.
+#[derive(Debug, Clone)]
+pub struct Decimation<P> {
+    publisher: P,
+    modes: Arc<HashMap<String, DecimationMode>>,
+    states: Arc<Mutex<HashMap<(String, String), PathState>>>,
+}
+
+impl<P> Decimation<P>
+where
+    P: Publisher + Send + Sync,
+{
+    pub fn new(publisher: P, config: &[DecimationConfig]) -> Self {
+        let modes = config
+            .iter()
+            .map(|config| (config.interface.clone(), config.mode.clone()))
+            .collect();
+
+        Self {
+            publisher,
+            modes: Arc::new(modes),
+            states: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Decimate the values, returning the ones to send.
+    async fn decimate(
+        &self,
+        mode: &DecimationMode,
+        interface_name: &str,
+        interface_path: &str,
+        data: HashMap<String, AstarteType>,
+    ) -> Option<HashMap<String, AstarteType>> {
+        let mut states = self.states.lock().await;
+        let state = states
+            .entry((interface_name.to_string(), interface_path.to_string()))
+            .or_default();
+
+        let data = match mode {
+            DecimationMode::Delta { delta } => state.changed(&data, *delta).then_some(data),
+            DecimationMode::Aggregate { periods, function } => {
+                state.aggregate(data, *periods, *function)
+            }
+        };
+
+        if data.is_none() {
+            trace!("decimated {interface_name}{interface_path}");
+        }
+
+        data
+    }
+
+    /// Store the values sent, for the delta mode.
+    async fn sent(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: Option<HashMap<String, AstarteType>>,
+    ) {
+        let key = (interface_name.to_string(), interface_path.to_string());
+
+        if let Some(state) = self.states.lock().await.get_mut(&key) {
+            state.sent = data;
+        }
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for Decimation<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let Some(mode) = self.modes.get(interface_name) else {
+            return self
+                .publisher
+                .send_object(interface_name, interface_path, data)
+                .await;
+        };
+
+        let data = data.astarte_aggregate()?;
+
+        let Some(data) = self
+            .decimate(mode, interface_name, interface_path, data)
+            .await
+        else {
+            return Ok(());
+        };
+
+        let res = self
+            .publisher
+            .send_object(interface_name, interface_path, data.clone())
+            .await;
+
+        self.sent(interface_name, interface_path, res.is_ok().then_some(data))
+            .await;
+
+        res
+    }
+
+    /// The data with an explicit timestamp was already sampled, so it's not decimated.
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        self.publisher
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        let Some(mode) = self.modes.get(interface_name) else {
+            return self
+                .publisher
+                .send(interface_name, interface_path, data)
+                .await;
+        };
+
+        let Some(mut data) = self
+            .decimate(
+                mode,
+                interface_name,
+                interface_path,
+                HashMap::from([(String::new(), data)]),
+            )
+            .await
+        else {
+            return Ok(());
+        };
+
+        let value = data.remove("").unwrap_or(AstarteType::Unset);
+
+        let res = self
+            .publisher
+            .send(interface_name, interface_path, value.clone())
+            .await;
+
+        let sent = res.is_ok().then(|| HashMap::from([(String::new(), value)]));
+        self.sent(interface_name, interface_path, sent).await;
+
+        res
+    }
+
+    /// The data with an explicit timestamp was already sampled, so it's not decimated.
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        self.publisher
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.publisher.interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        self.publisher.unset(interface_name, interface_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    const DECIMATED: &str = "io.edgehog.devicemanager.SystemStatus";
+
+    fn expect_send(publisher: &mut MockPublisher, value: AstarteType) {
+        publisher
+            .expect_send()
+            .withf(move |interface_name: &str, _: &str, data: &AstarteType| {
+                interface_name == DECIMATED && *data == value
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+    }
+
+    #[test]
+    fn deserialize_config() {
+        #[derive(Deserialize)]
+        struct Config {
+            decimation: Vec<DecimationConfig>,
+        }
+
+        let config: Config = toml::from_str(
+            r#"
+            [[decimation]]
+            interface = "a"
+            mode = "delta"
+            delta = 0.5
+
+            [[decimation]]
+            interface = "b"
+            mode = "aggregate"
+            periods = 5
+            function = "avg"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.decimation,
+            [
+                DecimationConfig {
+                    interface: "a".to_string(),
+                    mode: DecimationMode::Delta { delta: 0.5 },
+                },
+                DecimationConfig {
+                    interface: "b".to_string(),
+                    mode: DecimationMode::Aggregate {
+                        periods: 5,
+                        function: AggregateFunction::Avg,
+                    },
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_on_change_beyond_delta() {
+        let mut publisher = MockPublisher::new();
+        expect_send(&mut publisher, AstarteType::Double(10.0));
+        expect_send(&mut publisher, AstarteType::Double(11.5));
+
+        let decimation = Decimation::new(
+            publisher,
+            &[DecimationConfig {
+                interface: DECIMATED.to_string(),
+                mode: DecimationMode::Delta { delta: 1.0 },
+            }],
+        );
+
+        for value in [10.0, 10.5, 9.2, 11.5, 11.0] {
+            decimation
+                .send(DECIMATED, "/cpu", AstarteType::Double(value))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn aggregate_over_periods() {
+        let mut publisher = MockPublisher::new();
+        expect_send(&mut publisher, AstarteType::Integer(2));
+        expect_send(&mut publisher, AstarteType::Integer(6));
+
+        let decimation = Decimation::new(
+            publisher,
+            &[DecimationConfig {
+                interface: DECIMATED.to_string(),
+                mode: DecimationMode::Aggregate {
+                    periods: 3,
+                    function: AggregateFunction::Avg,
+                },
+            }],
+        );
+
+        for value in [1, 2, 3, 4, 6, 8, 10] {
+            decimation
+                .send(DECIMATED, "/tasks", AstarteType::Integer(value))
+                .await
+                .unwrap();
+        }
+    }
+}
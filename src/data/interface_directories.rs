@@ -0,0 +1,271 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Interfaces of the primary connection, read from multiple directories.
+//!
+//! The definitions are merged in a single directory in the store, the first directory defining an
+//! interface taking precedence. With the `embedded-interfaces` feature the interfaces embedded in
+//! the binary are used for the ones missing from all the directories, so a device with a missing or
+//! corrupted interfaces directory can still register the baseline interfaces.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::error::DeviceManagerError;
+
+/// Interfaces embedded at build time from the `EDGEHOG_INTERFACES_DIR` directory.
+///
+/// The directory is resolved by the build script, which embeds no interfaces when it's not set.
+#[cfg(feature = "embedded-interfaces")]
+static EMBEDDED_INTERFACES: include_dir::Dir<'_> =
+    include_dir::include_dir!("$EDGEHOG_EMBEDDED_INTERFACES_DIR");
+
+/// Sub-directory of the store with the merged interfaces.
+const MERGED_DIRECTORY: &str = "interfaces";
+
+/// One or more directories with the interfaces JSON files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "OneOrMany")]
+pub struct InterfaceDirectories(Vec<PathBuf>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(PathBuf),
+    Many(Vec<PathBuf>),
+}
+
+impl From<OneOrMany> for InterfaceDirectories {
+    fn from(value: OneOrMany) -> Self {
+        match value {
+            OneOrMany::One(directory) => Self(vec![directory]),
+            OneOrMany::Many(directories) => Self(directories),
+        }
+    }
+}
+
+impl From<PathBuf> for InterfaceDirectories {
+    fn from(value: PathBuf) -> Self {
+        Self(vec![value])
+    }
+}
+
+impl InterfaceDirectories {
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        self.0.iter().map(PathBuf::as_path)
+    }
+}
+
+/// Where the definition of an interface was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceSource {
+    Directory(PathBuf),
+    Embedded,
+}
+
+impl Display for InterfaceSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterfaceSource::Directory(path) => write!(f, "{}", path.display()),
+            InterfaceSource::Embedded => write!(f, "embedded"),
+        }
+    }
+}
+
+/// Directory of the store with the merged interfaces.
+pub fn merged_directory(store_directory: &Path) -> PathBuf {
+    store_directory.join(MERGED_DIRECTORY)
+}
+
+/// Name of the interface defined in the JSON content.
+fn interface_name(content: &str) -> Option<String> {
+    let interface: serde_json::Value = serde_json::from_str(content).ok()?;
+
+    interface
+        .get("interface_name")
+        .and_then(|name| name.as_str())
+        .map(str::to_string)
+}
+
+/// Read the interfaces of a directory, skipping the ones already defined.
+fn read_directory(directory: &Path, interfaces: &mut BTreeMap<String, (InterfaceSource, String)>) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!(
+                "couldn't read the interfaces directory {}: {err}",
+                directory.display()
+            );
+
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("couldn't read the interface {}: {err}", path.display());
+
+                continue;
+            }
+        };
+
+        let Some(name) = interface_name(&content) else {
+            warn!("skipping the invalid interface {}", path.display());
+
+            continue;
+        };
+
+        if interfaces.contains_key(&name) {
+            debug!(
+                "interface {name} already defined, skipping {}",
+                path.display()
+            );
+
+            continue;
+        }
+
+        interfaces.insert(
+            name,
+            (InterfaceSource::Directory(directory.to_path_buf()), content),
+        );
+    }
+}
+
+#[cfg(feature = "embedded-interfaces")]
+fn read_embedded(interfaces: &mut BTreeMap<String, (InterfaceSource, String)>) {
+    for file in EMBEDDED_INTERFACES.files() {
+        let Some(content) = file.contents_utf8() else {
+            continue;
+        };
+
+        let Some(name) = interface_name(content) else {
+            continue;
+        };
+
+        interfaces
+            .entry(name)
+            .or_insert_with(|| (InterfaceSource::Embedded, content.to_string()));
+    }
+}
+
+/// Merge the interfaces of the directories in the destination, returning the source of each one.
+pub fn merge(
+    directories: &InterfaceDirectories,
+    destination: &Path,
+) -> Result<BTreeMap<String, InterfaceSource>, DeviceManagerError> {
+    let mut interfaces = BTreeMap::new();
+
+    for directory in directories.iter() {
+        read_directory(directory, &mut interfaces);
+    }
+
+    #[cfg(feature = "embedded-interfaces")]
+    read_embedded(&mut interfaces);
+
+    if interfaces.is_empty() {
+        return Err(DeviceManagerError::FatalError(
+            "no valid interfaces found in the interfaces directories".to_string(),
+        ));
+    }
+
+    if destination.exists() {
+        std::fs::remove_dir_all(destination)?;
+    }
+    std::fs::create_dir_all(destination)?;
+
+    let mut sources = BTreeMap::new();
+    for (name, (source, content)) in interfaces {
+        std::fs::write(destination.join(format!("{name}.json")), content)?;
+
+        info!("interface {name} loaded from {source}");
+
+        sources.insert(name, source);
+    }
+
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn interface(name: &str, major: i32) -> String {
+        format!(r#"{{"interface_name": "{name}", "version_major": {major}}}"#)
+    }
+
+    #[test]
+    fn deserialize_one_or_many() {
+        #[derive(Deserialize)]
+        struct Config {
+            interfaces_directory: InterfaceDirectories,
+        }
+
+        let one: Config = toml::from_str(r#"interfaces_directory = "/a""#).unwrap();
+        assert_eq!(one.interfaces_directory, PathBuf::from("/a").into());
+
+        let many: Config = toml::from_str(r#"interfaces_directory = ["/a", "/b"]"#).unwrap();
+        assert_eq!(
+            many.interfaces_directory,
+            InterfaceDirectories(vec![PathBuf::from("/a"), PathBuf::from("/b")])
+        );
+    }
+
+    #[test]
+    fn merge_directories() {
+        let dir = TempDir::new("interfaces").unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+
+        std::fs::write(first.join("a.json"), interface("io.test.A", 1)).unwrap();
+        std::fs::write(first.join("broken.json"), "{ not json").unwrap();
+        std::fs::write(second.join("a.json"), interface("io.test.A", 2)).unwrap();
+        std::fs::write(second.join("b.json"), interface("io.test.B", 1)).unwrap();
+
+        let directories = InterfaceDirectories(vec![
+            first.clone(),
+            dir.path().join("missing"),
+            second.clone(),
+        ]);
+        let destination = merged_directory(dir.path());
+
+        let sources = merge(&directories, &destination).unwrap();
+
+        assert_eq!(sources["io.test.A"], InterfaceSource::Directory(first));
+        assert_eq!(sources["io.test.B"], InterfaceSource::Directory(second));
+
+        let merged = std::fs::read_to_string(destination.join("io.test.A.json")).unwrap();
+        assert_eq!(merged, interface("io.test.A", 1));
+        assert!(destination.join("io.test.B.json").exists());
+    }
+}
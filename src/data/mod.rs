@@ -23,12 +23,20 @@ use astarte_device_sdk::store::{SqliteStore, StoredProp};
 use astarte_device_sdk::types::AstarteType;
 use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate, AstarteDeviceDataEvent};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use log::{debug, info};
 use std::path::{Path, PathBuf};
 
 pub mod astarte_device_sdk_lib;
 #[cfg(feature = "message-hub")]
 pub mod astarte_message_hub_node;
+pub mod connection;
+pub mod decimation;
+pub mod interface_directories;
+pub mod multi_realm;
+pub mod offline_queue;
+pub mod property_cache;
+pub mod validation;
 
 #[async_trait]
 pub trait Publisher: Clone {
@@ -40,13 +48,28 @@ pub trait Publisher: Clone {
     ) -> Result<(), AstarteError>
     where
         T: AstarteAggregate + Send + 'static;
-    //TODO add send_object_with_timestamp to this trait
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static;
     async fn send(
         &self,
         interface_name: &str,
         interface_path: &str,
         data: AstarteType,
     ) -> Result<(), AstarteError>;
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>;
     async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError>;
     async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError>;
 }
@@ -104,6 +127,15 @@ pub mod tests {
                 interface_path: &str,
                 data: T,
             ) -> Result<(), AstarteError>
+            where
+                T: AstarteAggregate + Send + 'static;
+            async fn send_object_with_timestamp<T>(
+                &self,
+                interface_name: &str,
+                interface_path: &str,
+                data: T,
+                timestamp: DateTime<Utc>,
+            ) -> Result<(), AstarteError>
             where
                 T: AstarteAggregate + Send + 'static;
             async fn send(
@@ -112,6 +144,13 @@ pub mod tests {
                 interface_path: &str,
                 data: AstarteType,
             ) -> Result<(), AstarteError>;
+            async fn send_with_timestamp(
+                &self,
+                interface_name: &str,
+                interface_path: &str,
+                data: AstarteType,
+                timestamp: DateTime<Utc>,
+            ) -> Result<(), AstarteError>;
             async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError>;
             async fn unset(
                 &self,
@@ -0,0 +1,415 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Secondary Astarte connections with a subset of the interfaces routed to each.
+//!
+//! Integrators can split the data between realms, for example sending the telemetry to an analytics
+//! realm while the device is controlled from the operations realm. The data of an interface is
+//! published on the connection it's routed to, or on the primary one, while the events received
+//! from all the connections are merged.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate, AstarteDeviceDataEvent};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::data::astarte_device_sdk_lib::{
+    AstarteDeviceSdkConfigOptions, DeviceSdkPublisher, DeviceSdkSubscriber,
+};
+use crate::data::{connect_store, Publisher, Subscriber};
+use crate::error::DeviceManagerError;
+
+/// Configuration of a secondary Astarte connection.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecondaryConnectionConfig {
+    /// Name of the connection, used for the logs and as the store sub-directory.
+    pub name: String,
+    /// Directory with the interfaces routed to this connection.
+    pub interfaces_directory: PathBuf,
+    pub astarte_device_sdk: AstarteDeviceSdkConfigOptions,
+}
+
+/// Names of the interfaces defined in the directory.
+fn interface_names(interfaces_directory: &Path) -> Result<Vec<String>, DeviceManagerError> {
+    let mut names = Vec::new();
+
+    for entry in std::fs::read_dir(interfaces_directory)? {
+        let path = entry?.path();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let interface: serde_json::Value = serde_json::from_str(&content)?;
+
+        let Some(name) = interface
+            .get("interface_name")
+            .and_then(|name| name.as_str())
+        else {
+            return Err(DeviceManagerError::FatalError(format!(
+                "missing interface name in {}",
+                path.display()
+            )));
+        };
+
+        names.push(name.to_string());
+    }
+
+    Ok(names)
+}
+
+/// Connect to the secondary realms, wrapping the primary connection.
+pub async fn connect(
+    primary: (DeviceSdkPublisher, DeviceSdkSubscriber),
+    connections: &[SecondaryConnectionConfig],
+    store_directory: &Path,
+) -> Result<
+    (
+        MultiRealmPublisher<DeviceSdkPublisher>,
+        MultiRealmSubscriber<DeviceSdkSubscriber>,
+    ),
+    DeviceManagerError,
+> {
+    let (primary_publisher, primary_subscriber) = primary;
+
+    let mut publishers = Vec::with_capacity(connections.len());
+    let mut subscribers = Vec::with_capacity(connections.len());
+    for config in connections {
+        info!("connecting to the secondary realm {}", config.name);
+
+        let store_dir = store_directory.join(&config.name);
+        tokio::fs::create_dir_all(&store_dir).await?;
+
+        let store = connect_store(&store_dir).await?;
+        let (publisher, subscriber) = config
+            .astarte_device_sdk
+            .connect(
+                store,
+                store_dir.as_path(),
+                config.interfaces_directory.as_path(),
+            )
+            .await?;
+
+        let interfaces = interface_names(&config.interfaces_directory)?;
+        publishers.push((interfaces, publisher));
+        subscribers.push((config.name.clone(), subscriber));
+    }
+
+    Ok((
+        MultiRealmPublisher::new(primary_publisher, publishers),
+        MultiRealmSubscriber::new(primary_subscriber, subscribers),
+    ))
+}
+
+/// Publisher routing each interface to its connection.
+#[derive(Debug, Clone)]
+pub struct MultiRealmPublisher<P> {
+    primary: P,
+    /// Secondary connection for each routed interface.
+    routes: Arc<HashMap<String, P>>,
+}
+
+impl<P> MultiRealmPublisher<P>
+where
+    P: Clone,
+{
+    pub fn new(primary: P, secondaries: Vec<(Vec<String>, P)>) -> Self {
+        let routes = secondaries
+            .into_iter()
+            .flat_map(|(interfaces, publisher)| {
+                interfaces
+                    .into_iter()
+                    .map(move |interface| (interface, publisher.clone()))
+            })
+            .collect();
+
+        Self {
+            primary,
+            routes: Arc::new(routes),
+        }
+    }
+
+    fn route(&self, interface_name: &str) -> &P {
+        self.routes.get(interface_name).unwrap_or(&self.primary)
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for MultiRealmPublisher<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        self.route(interface_name)
+            .send_object(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        self.route(interface_name)
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        self.route(interface_name)
+            .send(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        self.route(interface_name)
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.route(interface).interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        self.route(interface_name)
+            .unset(interface_name, interface_path)
+            .await
+    }
+}
+
+#[derive(Debug)]
+struct SecondarySubscriber {
+    name: String,
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<Result<(), AstarteError>>,
+}
+
+/// Subscriber merging the events of all the connections.
+///
+/// The events of the primary connection are received directly, so the subscriber is closed only
+/// when the primary connection is.
+#[derive(Debug)]
+pub struct MultiRealmSubscriber<S> {
+    primary: S,
+    events: mpsc::Receiver<Result<AstarteDeviceDataEvent, AstarteError>>,
+    secondaries: Vec<SecondarySubscriber>,
+}
+
+impl<S> MultiRealmSubscriber<S>
+where
+    S: Subscriber + Send + 'static,
+{
+    pub fn new(primary: S, secondaries: Vec<(String, S)>) -> Self {
+        let (tx, events) = mpsc::channel(32);
+
+        let secondaries = secondaries
+            .into_iter()
+            .map(|(name, subscriber)| {
+                let (stop, stop_rx) = oneshot::channel();
+                let handle = tokio::spawn(forward_events(
+                    name.clone(),
+                    subscriber,
+                    tx.clone(),
+                    stop_rx,
+                ));
+
+                SecondarySubscriber { name, stop, handle }
+            })
+            .collect();
+
+        Self {
+            primary,
+            events,
+            secondaries,
+        }
+    }
+}
+
+/// Forward the events of a secondary connection until it's closed or stopped.
+async fn forward_events<S>(
+    name: String,
+    mut subscriber: S,
+    tx: mpsc::Sender<Result<AstarteDeviceDataEvent, AstarteError>>,
+    mut stop: oneshot::Receiver<()>,
+) -> Result<(), AstarteError>
+where
+    S: Subscriber,
+{
+    loop {
+        tokio::select! {
+            _ = &mut stop => {
+                debug!("stopping secondary connection {name}");
+
+                break;
+            }
+            event = subscriber.on_event() => {
+                let Some(event) = event else {
+                    error!("secondary connection {name} closed");
+
+                    break;
+                };
+
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    subscriber.exit().await
+}
+
+#[async_trait]
+impl<S> Subscriber for MultiRealmSubscriber<S>
+where
+    S: Subscriber + Send,
+{
+    async fn on_event(&mut self) -> Option<Result<AstarteDeviceDataEvent, AstarteError>> {
+        // The receive of the subscribers must be cancel safe
+        tokio::select! {
+            event = self.primary.on_event() => event,
+            Some(event) = self.events.recv() => Some(event),
+        }
+    }
+
+    async fn exit(self) -> Result<(), AstarteError> {
+        for secondary in self.secondaries {
+            // the task could be already terminated
+            let _ = secondary.stop.send(());
+
+            match secondary.handle.await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => error!(
+                    "couldn't exit secondary connection {}: {err}",
+                    secondary.name
+                ),
+                Err(err) => error!("failed to join task {err}"),
+            }
+        }
+
+        self.primary.exit().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::data::tests::MockPublisher;
+
+    const ROUTED: &str = "io.edgehog.devicemanager.SystemStatus";
+
+    fn publisher(expected: &'static str, times: usize) -> MockPublisher {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send()
+            .withf(move |interface_name: &str, _: &str, _: &AstarteType| interface_name == expected)
+            .times(times)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        publisher
+    }
+
+    #[tokio::test]
+    async fn route_interfaces() {
+        let primary = publisher("io.edgehog.devicemanager.OSInfo", 1);
+        let mut secondary = MockPublisher::new();
+        secondary
+            .expect_clone()
+            .once()
+            .returning(|| publisher(ROUTED, 1));
+
+        let multi = MultiRealmPublisher::new(primary, vec![(vec![ROUTED.to_string()], secondary)]);
+
+        multi
+            .send(ROUTED, "/systemStatus", AstarteType::Integer(1))
+            .await
+            .unwrap();
+        multi
+            .send(
+                "io.edgehog.devicemanager.OSInfo",
+                "/osName",
+                AstarteType::String("linux".to_string()),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn interface_names_from_directory() {
+        let dir = TempDir::new("edgehog-interfaces").unwrap();
+        std::fs::write(
+            dir.path().join(format!("{ROUTED}.json")),
+            format!(r#"{{"interface_name": "{ROUTED}", "version_major": 0}}"#),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "not an interface").unwrap();
+
+        let names = interface_names(dir.path()).unwrap();
+
+        assert_eq!(names, [ROUTED]);
+    }
+
+    #[test]
+    fn interface_names_missing_name() {
+        let dir = TempDir::new("edgehog-interfaces").unwrap();
+        std::fs::write(dir.path().join("invalid.json"), "{}").unwrap();
+
+        let res = interface_names(dir.path());
+
+        assert!(matches!(res, Err(DeviceManagerError::FatalError(_))));
+    }
+}
@@ -0,0 +1,1082 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Queue of the datastreams published while Astarte is unreachable.
+//!
+//! When publishing on a device owned datastream fails, the [`OfflineQueue`] persists the data in the
+//! store directory instead of dropping it. The queued data is flushed in order when the connection
+//! to Astarte is re-established or a publish succeeds, with the time it was queued at as explicit
+//! timestamp.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{watch, Mutex, Notify};
+
+use crate::commands::now_millis;
+use crate::data::connection::Connection;
+use crate::data::validation::Interfaces;
+use crate::data::Publisher;
+
+/// Interval between the flush attempts of the queue.
+///
+/// The connection is known to be re-established only after a successful publish or an event from
+/// Astarte, so the flush is also retried periodically.
+const FLUSH_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Publishes sent before removing them from the file, bounding the ones sent again after a crash.
+const FLUSH_BATCH: usize = 100;
+
+/// Size and age caps of the queue.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OfflineQueueConfig {
+    /// Maximum number of queued publishes, the oldest are dropped first. Zero disables the queue.
+    #[serde(default = "OfflineQueueConfig::default_max_entries")]
+    pub max_entries: usize,
+    /// Publishes older than this are dropped instead of being sent.
+    #[serde(default = "OfflineQueueConfig::default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+impl OfflineQueueConfig {
+    fn default_max_entries() -> usize {
+        1000
+    }
+
+    fn default_max_age_secs() -> u64 {
+        24 * 60 * 60
+    }
+}
+
+impl Default for OfflineQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: Self::default_max_entries(),
+            max_age_secs: Self::default_max_age_secs(),
+        }
+    }
+}
+
+/// Serializable [`AstarteType`], the date times are stored as milliseconds since the epoch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+enum QueuedValue {
+    Double(f64),
+    Integer(i32),
+    Boolean(bool),
+    LongInteger(i64),
+    String(String),
+    BinaryBlob(Vec<u8>),
+    DateTime(i64),
+    DoubleArray(Vec<f64>),
+    IntegerArray(Vec<i32>),
+    BooleanArray(Vec<bool>),
+    LongIntegerArray(Vec<i64>),
+    StringArray(Vec<String>),
+    BinaryBlobArray(Vec<Vec<u8>>),
+    DateTimeArray(Vec<i64>),
+}
+
+fn date_time(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .unwrap_or_default()
+}
+
+impl QueuedValue {
+    /// Convert the value, the unset can't be queued.
+    fn from_astarte(value: AstarteType) -> Option<Self> {
+        let value = match value {
+            AstarteType::Double(value) => QueuedValue::Double(value),
+            AstarteType::Integer(value) => QueuedValue::Integer(value),
+            AstarteType::Boolean(value) => QueuedValue::Boolean(value),
+            AstarteType::LongInteger(value) => QueuedValue::LongInteger(value),
+            AstarteType::String(value) => QueuedValue::String(value),
+            AstarteType::BinaryBlob(value) => QueuedValue::BinaryBlob(value),
+            AstarteType::DateTime(value) => QueuedValue::DateTime(value.timestamp_millis()),
+            AstarteType::DoubleArray(value) => QueuedValue::DoubleArray(value),
+            AstarteType::IntegerArray(value) => QueuedValue::IntegerArray(value),
+            AstarteType::BooleanArray(value) => QueuedValue::BooleanArray(value),
+            AstarteType::LongIntegerArray(value) => QueuedValue::LongIntegerArray(value),
+            AstarteType::StringArray(value) => QueuedValue::StringArray(value),
+            AstarteType::BinaryBlobArray(value) => QueuedValue::BinaryBlobArray(value),
+            AstarteType::DateTimeArray(value) => {
+                QueuedValue::DateTimeArray(value.iter().map(DateTime::timestamp_millis).collect())
+            }
+            AstarteType::Unset => return None,
+        };
+
+        Some(value)
+    }
+
+    fn into_astarte(self) -> AstarteType {
+        match self {
+            QueuedValue::Double(value) => AstarteType::Double(value),
+            QueuedValue::Integer(value) => AstarteType::Integer(value),
+            QueuedValue::Boolean(value) => AstarteType::Boolean(value),
+            QueuedValue::LongInteger(value) => AstarteType::LongInteger(value),
+            QueuedValue::String(value) => AstarteType::String(value),
+            QueuedValue::BinaryBlob(value) => AstarteType::BinaryBlob(value),
+            QueuedValue::DateTime(value) => AstarteType::DateTime(date_time(value)),
+            QueuedValue::DoubleArray(value) => AstarteType::DoubleArray(value),
+            QueuedValue::IntegerArray(value) => AstarteType::IntegerArray(value),
+            QueuedValue::BooleanArray(value) => AstarteType::BooleanArray(value),
+            QueuedValue::LongIntegerArray(value) => AstarteType::LongIntegerArray(value),
+            QueuedValue::StringArray(value) => AstarteType::StringArray(value),
+            QueuedValue::BinaryBlobArray(value) => AstarteType::BinaryBlobArray(value),
+            QueuedValue::DateTimeArray(value) => {
+                AstarteType::DateTimeArray(value.into_iter().map(date_time).collect())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum QueuedData {
+    Individual(QueuedValue),
+    Object(HashMap<String, QueuedValue>),
+}
+
+impl QueuedData {
+    fn object(data: HashMap<String, AstarteType>) -> Option<Self> {
+        data.into_iter()
+            .map(|(key, value)| QueuedValue::from_astarte(value).map(|value| (key, value)))
+            .collect::<Option<HashMap<_, _>>>()
+            .map(QueuedData::Object)
+    }
+}
+
+/// Publish that couldn't be sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedPublish {
+    interface_name: String,
+    interface_path: String,
+    /// Unix time in milliseconds the publish was queued at, sent as explicit timestamp.
+    queued_at: i64,
+    data: QueuedData,
+}
+
+/// Append only file of the queued publishes, one JSON object per line.
+///
+/// A publish is appended when queued, while the file is rewritten only after a batch of publishes
+/// is flushed or once the dropped publishes outnumber the size of the queue.
+#[derive(Debug)]
+struct QueueFile {
+    path: PathBuf,
+    /// Lines in the file, including the publishes already sent or dropped.
+    lines: usize,
+}
+
+impl QueueFile {
+    fn new(store_directory: &Path) -> Self {
+        Self {
+            path: store_directory.join("offline_queue.jsonl"),
+            lines: 0,
+        }
+    }
+
+    async fn load(&mut self) -> Vec<QueuedPublish> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(err) => {
+                error!("couldn't read the offline queue: {err}");
+
+                return Vec::new();
+            }
+        };
+
+        self.lines = content.lines().count();
+
+        content
+            .lines()
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(publish) => Some(publish),
+                Err(err) => {
+                    // the last line could be truncated by a power loss
+                    warn!("skipping a corrupted queued publish: {err}");
+
+                    None
+                }
+            })
+            .collect()
+    }
+
+    async fn append(&mut self, publish: &QueuedPublish) -> io::Result<()> {
+        let mut line = serde_json::to_vec(publish)?;
+        line.push(b'\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+
+        file.write_all(&line).await?;
+        file.flush().await?;
+
+        self.lines += 1;
+
+        Ok(())
+    }
+
+    async fn rewrite(&mut self, queue: &VecDeque<QueuedPublish>) -> io::Result<()> {
+        if queue.is_empty() {
+            match tokio::fs::remove_file(&self.path).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+
+            self.lines = 0;
+
+            return Ok(());
+        }
+
+        let mut content = Vec::new();
+        for publish in queue {
+            serde_json::to_writer(&mut content, publish)?;
+            content.push(b'\n');
+        }
+
+        // replaced atomically, so a crash doesn't lose the queue
+        let tmp = self.path.with_extension("jsonl.tmp");
+        tokio::fs::write(&tmp, &content).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        self.lines = queue.len();
+
+        Ok(())
+    }
+}
+
+/// Queued publishes and the file they are persisted in.
+#[derive(Debug)]
+struct QueueState {
+    file: QueueFile,
+    /// Queued publishes, lazily loaded from the file.
+    entries: Option<VecDeque<QueuedPublish>>,
+}
+
+impl QueueState {
+    /// Queued publishes, loaded from the file on the first access keeping the newest ones.
+    async fn loaded(
+        &mut self,
+        max_entries: usize,
+    ) -> (&mut QueueFile, &mut VecDeque<QueuedPublish>) {
+        if self.entries.is_none() {
+            let mut entries = VecDeque::from(self.file.load().await);
+
+            let excess = entries.len().saturating_sub(max_entries);
+            entries.drain(..excess);
+
+            self.entries = Some(entries);
+        }
+
+        (
+            &mut self.file,
+            self.entries.get_or_insert_with(VecDeque::new),
+        )
+    }
+
+    async fn persist(&mut self, max_entries: usize) {
+        let (file, queue) = self.loaded(max_entries).await;
+
+        if let Err(err) = file.rewrite(queue).await {
+            error!("couldn't persist the offline queue: {err}");
+        }
+    }
+}
+
+/// Publisher queueing the datastreams that couldn't be sent.
+#[derive(Debug, Clone)]
+pub struct OfflineQueue<P> {
+    publisher: P,
+    interfaces: Arc<Interfaces>,
+    config: OfflineQueueConfig,
+    /// Queued publishes, the lock is never held while sending.
+    state: Arc<Mutex<QueueState>>,
+    /// Serializes the flushes, so the queued publishes are sent in order.
+    flushing: Arc<Mutex<()>>,
+    /// Wakes [`OfflineQueue::flush_on_reconnect`] before the retry interval.
+    flush_requested: Arc<Notify>,
+}
+
+impl<P> OfflineQueue<P>
+where
+    P: Publisher + Send + Sync,
+{
+    pub fn new(
+        publisher: P,
+        interfaces: Interfaces,
+        config: &OfflineQueueConfig,
+        store_directory: &Path,
+    ) -> Self {
+        Self {
+            publisher,
+            interfaces: Arc::new(interfaces),
+            config: config.clone(),
+            state: Arc::new(Mutex::new(QueueState {
+                file: QueueFile::new(store_directory),
+                entries: None,
+            })),
+            flushing: Arc::new(Mutex::new(())),
+            flush_requested: Arc::new(Notify::new()),
+        }
+    }
+
+    fn is_queued(&self, interface_name: &str) -> bool {
+        self.config.max_entries > 0 && self.interfaces.is_datastream(interface_name)
+    }
+
+    /// Request a flush after a successful publish, since Astarte is reachable.
+    fn flush_on_success(&self, res: Result<(), AstarteError>) -> Result<(), AstarteError> {
+        if res.is_ok() {
+            self.flush_requested.notify_one();
+        }
+
+        res
+    }
+
+    async fn is_empty(&self) -> bool {
+        let mut state = self.state.lock().await;
+        let (_, queue) = state.loaded(self.config.max_entries).await;
+
+        queue.is_empty()
+    }
+
+    /// Oldest queued publish.
+    async fn front(&self) -> Option<QueuedPublish> {
+        let mut state = self.state.lock().await;
+        let (_, queue) = state.loaded(self.config.max_entries).await;
+
+        queue.front().cloned()
+    }
+
+    /// Queue the publish after the others, appending it to the file.
+    async fn push(&self, publish: QueuedPublish) {
+        let mut state = self.state.lock().await;
+        let (file, queue) = state.loaded(self.config.max_entries).await;
+
+        if let Err(err) = file.append(&publish).await {
+            error!("couldn't persist the offline queue: {err}");
+        }
+
+        queue.push_back(publish);
+
+        if queue.len() > self.config.max_entries {
+            let excess = queue.len() - self.config.max_entries;
+            queue.drain(..excess);
+
+            warn!("offline queue full, dropped the {excess} oldest publishes");
+        }
+
+        // the dropped publishes are removed from the file only once they outnumber the queue
+        if file.lines > self.config.max_entries.saturating_mul(2) {
+            state.persist(self.config.max_entries).await;
+        }
+    }
+
+    /// Drop the publishes queued more than `max_age_secs` ago.
+    async fn drop_expired(&self) {
+        let max_age =
+            i64::try_from(self.config.max_age_secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+        let now = now_millis();
+
+        let mut state = self.state.lock().await;
+        let (_, queue) = state.loaded(self.config.max_entries).await;
+
+        let len = queue.len();
+        queue.retain(|publish| now.saturating_sub(publish.queued_at) <= max_age);
+
+        if queue.len() < len {
+            warn!(
+                "dropped {} queued publishes older than {}s",
+                len - queue.len(),
+                self.config.max_age_secs
+            );
+
+            state.persist(self.config.max_entries).await;
+        }
+    }
+
+    async fn send_queued(&self, publish: &QueuedPublish) -> Result<(), AstarteError> {
+        let timestamp = date_time(publish.queued_at);
+
+        match publish.data.clone() {
+            QueuedData::Individual(value) => {
+                self.publisher
+                    .send_with_timestamp(
+                        &publish.interface_name,
+                        &publish.interface_path,
+                        value.into_astarte(),
+                        timestamp,
+                    )
+                    .await
+            }
+            QueuedData::Object(data) => {
+                let data: HashMap<String, AstarteType> = data
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_astarte()))
+                    .collect();
+
+                self.publisher
+                    .send_object_with_timestamp(
+                        &publish.interface_name,
+                        &publish.interface_path,
+                        data,
+                        timestamp,
+                    )
+                    .await
+            }
+        }
+    }
+
+    /// Send the queued publishes in order, stopping at the first failure.
+    ///
+    /// The sent publishes are removed from the file in batches of [`FLUSH_BATCH`].
+    async fn flush_queued(&self) {
+        let _flushing = self.flushing.lock().await;
+
+        self.drop_expired().await;
+
+        let mut sent = 0usize;
+        while let Some(publish) = self.front().await {
+            if let Err(err) = self.send_queued(&publish).await {
+                debug!("couldn't flush the offline queue: {err}");
+
+                break;
+            }
+
+            sent += 1;
+
+            let mut state = self.state.lock().await;
+            let (_, queue) = state.loaded(self.config.max_entries).await;
+
+            // the publish could have been dropped from a full queue while sending it
+            if queue.front() == Some(&publish) {
+                queue.pop_front();
+            }
+
+            if sent % FLUSH_BATCH == 0 {
+                state.persist(self.config.max_entries).await;
+            }
+        }
+
+        if sent > 0 {
+            info!("sent {sent} queued publishes");
+
+            if sent % FLUSH_BATCH != 0 {
+                self.state
+                    .lock()
+                    .await
+                    .persist(self.config.max_entries)
+                    .await;
+            }
+        }
+    }
+
+    /// Send the data if nothing is queued, otherwise queue it after the other publishes.
+    ///
+    /// The data is also queued if it can't be sent, with the given time in milliseconds.
+    async fn publish<F>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: QueuedData,
+        queued_at: i64,
+        send: F,
+    ) -> Result<(), AstarteError>
+    where
+        F: std::future::Future<Output = Result<(), AstarteError>>,
+    {
+        let publish = QueuedPublish {
+            interface_name: interface_name.to_string(),
+            interface_path: interface_path.to_string(),
+            queued_at,
+            data,
+        };
+
+        if !self.is_empty().await {
+            self.push(publish).await;
+
+            // sending the oldest queued publish checks the connection in place of this one
+            self.flush_requested.notify_one();
+
+            return Ok(());
+        }
+
+        let Err(err) = send.await else {
+            return Ok(());
+        };
+
+        warn!("queueing the publish on {interface_name}{interface_path}: {err}");
+
+        self.push(publish).await;
+
+        Ok(())
+    }
+
+    /// Flush the queue each time the connection to Astarte is re-established or a publish succeeds.
+    pub async fn flush_on_reconnect(&self, mut connection: watch::Receiver<Connection>) {
+        loop {
+            self.flush_queued().await;
+
+            tokio::select! {
+                res = reconnected(&mut connection) => {
+                    if res.is_err() {
+                        debug!("connection state dropped, stop flushing the offline queue");
+
+                        break;
+                    }
+
+                    debug!("connection re-established, flushing the offline queue");
+                }
+                () = self.flush_requested.notified() => {}
+                () = tokio::time::sleep(FLUSH_RETRY_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+/// Wait for the connection to change to connected.
+async fn reconnected(
+    connection: &mut watch::Receiver<Connection>,
+) -> Result<(), watch::error::RecvError> {
+    loop {
+        connection.changed().await?;
+
+        if *connection.borrow_and_update() == Connection::Connected {
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for OfflineQueue<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        if !self.is_queued(interface_name) {
+            let res = self
+                .publisher
+                .send_object(interface_name, interface_path, data)
+                .await;
+
+            return self.flush_on_success(res);
+        }
+
+        let data = data.astarte_aggregate()?;
+
+        let Some(queued) = QueuedData::object(data.clone()) else {
+            let res = self
+                .publisher
+                .send_object(interface_name, interface_path, data)
+                .await;
+
+            return self.flush_on_success(res);
+        };
+
+        self.publish(
+            interface_name,
+            interface_path,
+            queued,
+            now_millis(),
+            self.publisher
+                .send_object(interface_name, interface_path, data),
+        )
+        .await
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        if !self.is_queued(interface_name) {
+            let res = self
+                .publisher
+                .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+                .await;
+
+            return self.flush_on_success(res);
+        }
+
+        let data = data.astarte_aggregate()?;
+
+        let Some(queued) = QueuedData::object(data.clone()) else {
+            let res = self
+                .publisher
+                .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+                .await;
+
+            return self.flush_on_success(res);
+        };
+
+        self.publish(
+            interface_name,
+            interface_path,
+            queued,
+            timestamp.timestamp_millis(),
+            self.publisher.send_object_with_timestamp(
+                interface_name,
+                interface_path,
+                data,
+                timestamp,
+            ),
+        )
+        .await
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        if !self.is_queued(interface_name) {
+            let res = self
+                .publisher
+                .send(interface_name, interface_path, data)
+                .await;
+
+            return self.flush_on_success(res);
+        }
+
+        let Some(queued) = QueuedValue::from_astarte(data.clone()) else {
+            let res = self
+                .publisher
+                .send(interface_name, interface_path, data)
+                .await;
+
+            return self.flush_on_success(res);
+        };
+
+        self.publish(
+            interface_name,
+            interface_path,
+            QueuedData::Individual(queued),
+            now_millis(),
+            self.publisher.send(interface_name, interface_path, data),
+        )
+        .await
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        if !self.is_queued(interface_name) {
+            let res = self
+                .publisher
+                .send_with_timestamp(interface_name, interface_path, data, timestamp)
+                .await;
+
+            return self.flush_on_success(res);
+        }
+
+        let Some(queued) = QueuedValue::from_astarte(data.clone()) else {
+            let res = self
+                .publisher
+                .send_with_timestamp(interface_name, interface_path, data, timestamp)
+                .await;
+
+            return self.flush_on_success(res);
+        };
+
+        self.publish(
+            interface_name,
+            interface_path,
+            QueuedData::Individual(queued),
+            timestamp.timestamp_millis(),
+            self.publisher
+                .send_with_timestamp(interface_name, interface_path, data, timestamp),
+        )
+        .await
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.publisher.interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        let res = self.publisher.unset(interface_name, interface_path).await;
+
+        self.flush_on_success(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mockall::Sequence;
+    use tempdir::TempDir;
+    use tokio::sync::Notify;
+
+    use crate::data::connection::ConnectionState;
+    use crate::data::tests::MockPublisher;
+
+    const TELEMETRY: &str = r#"{
+        "interface_name": "io.edgehog.devicemanager.SystemStatus",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "device",
+        "mappings": [{"endpoint": "/%{id}/value", "type": "integer"}]
+    }"#;
+
+    const PROPERTIES: &str = r#"{
+        "interface_name": "io.edgehog.devicemanager.OSInfo",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "properties",
+        "ownership": "device",
+        "mappings": [{"endpoint": "/osName", "type": "string"}]
+    }"#;
+
+    fn interfaces(dir: &TempDir) -> Interfaces {
+        let interfaces_dir = dir.path().join("interfaces");
+        std::fs::create_dir_all(&interfaces_dir).unwrap();
+        std::fs::write(interfaces_dir.join("telemetry.json"), TELEMETRY).unwrap();
+        std::fs::write(interfaces_dir.join("properties.json"), PROPERTIES).unwrap();
+
+        Interfaces::load([interfaces_dir.as_path()]).unwrap()
+    }
+
+    async fn queued(queue: &OfflineQueue<MockPublisher>) -> Vec<QueuedData> {
+        let mut state = queue.state.lock().await;
+        let (_, queue) = state.loaded(queue.config.max_entries).await;
+
+        queue.iter().map(|publish| publish.data.clone()).collect()
+    }
+
+    fn expect_send(publisher: &mut MockPublisher, seq: &mut Sequence, value: i32, ok: bool) {
+        publisher
+            .expect_send()
+            .withf(move |interface_name: &str, _: &str, data: &AstarteType| {
+                interface_name == "io.edgehog.devicemanager.SystemStatus"
+                    && *data == AstarteType::Integer(value)
+            })
+            .once()
+            .in_sequence(seq)
+            .returning(move |_: &str, _: &str, _: AstarteType| {
+                if ok {
+                    Ok(())
+                } else {
+                    Err(AstarteError::ConnectionTimeout)
+                }
+            });
+    }
+
+    fn expect_flush(publisher: &mut MockPublisher, seq: &mut Sequence, value: i32, ok: bool) {
+        publisher
+            .expect_send_with_timestamp()
+            .withf(
+                move |interface_name: &str, _: &str, data: &AstarteType, _: &DateTime<Utc>| {
+                    interface_name == "io.edgehog.devicemanager.SystemStatus"
+                        && *data == AstarteType::Integer(value)
+                },
+            )
+            .once()
+            .in_sequence(seq)
+            .returning(move |_: &str, _: &str, _: AstarteType, _: DateTime<Utc>| {
+                if ok {
+                    Ok(())
+                } else {
+                    Err(AstarteError::ConnectionTimeout)
+                }
+            });
+    }
+
+    #[tokio::test]
+    async fn queue_and_flush_in_order() {
+        let dir = TempDir::new("edgehog-offline-queue").unwrap();
+        let mut publisher = MockPublisher::new();
+        let mut seq = Sequence::new();
+
+        expect_send(&mut publisher, &mut seq, 1, false);
+        // flush attempt while still offline
+        expect_flush(&mut publisher, &mut seq, 1, false);
+        expect_flush(&mut publisher, &mut seq, 1, true);
+        expect_flush(&mut publisher, &mut seq, 2, true);
+        expect_flush(&mut publisher, &mut seq, 3, true);
+
+        let queue = OfflineQueue::new(
+            publisher,
+            interfaces(&dir),
+            &OfflineQueueConfig::default(),
+            dir.path(),
+        );
+
+        for value in 1..=3 {
+            queue
+                .send(
+                    "io.edgehog.devicemanager.SystemStatus",
+                    "/cpu/value",
+                    AstarteType::Integer(value),
+                )
+                .await
+                .unwrap();
+        }
+
+        queue.flush_queued().await;
+        assert_eq!(queued(&queue).await.len(), 3);
+
+        queue.flush_queued().await;
+        assert!(queued(&queue).await.is_empty());
+        assert!(!dir.path().join("offline_queue.jsonl").exists());
+    }
+
+    #[tokio::test]
+    async fn flush_with_timestamp_on_reconnect() {
+        let dir = TempDir::new("edgehog-offline-queue").unwrap();
+        let timestamp = date_time(1_700_000_000_000);
+        let flushed = Arc::new(Notify::new());
+        let mut publisher = MockPublisher::new();
+        let mut seq = Sequence::new();
+
+        publisher
+            .expect_send_with_timestamp()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_: &str, _: &str, _: AstarteType, _: DateTime<Utc>| {
+                Err(AstarteError::ConnectionTimeout)
+            });
+        // flush attempt on start, while still offline
+        publisher
+            .expect_send_with_timestamp()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_: &str, _: &str, _: AstarteType, _: DateTime<Utc>| {
+                Err(AstarteError::ConnectionTimeout)
+            });
+        let notify = Arc::clone(&flushed);
+        publisher
+            .expect_send_with_timestamp()
+            .withf(
+                move |_: &str, _: &str, data: &AstarteType, ts: &DateTime<Utc>| {
+                    *data == AstarteType::Integer(1) && *ts == timestamp
+                },
+            )
+            .once()
+            .in_sequence(&mut seq)
+            .returning(move |_: &str, _: &str, _: AstarteType, _: DateTime<Utc>| {
+                notify.notify_one();
+
+                Ok(())
+            });
+        let queue = Arc::new(OfflineQueue::new(
+            publisher,
+            interfaces(&dir),
+            &OfflineQueueConfig::default(),
+            dir.path(),
+        ));
+
+        queue
+            .send_with_timestamp(
+                "io.edgehog.devicemanager.SystemStatus",
+                "/cpu/value",
+                AstarteType::Integer(1),
+                timestamp,
+            )
+            .await
+            .unwrap();
+
+        let state = ConnectionState::default();
+        state.set_disconnected();
+
+        let rx = state.subscribe();
+        let handle = tokio::spawn({
+            let queue = Arc::clone(&queue);
+
+            async move { queue.flush_on_reconnect(rx).await }
+        });
+
+        state.set_connected();
+
+        tokio::time::timeout(Duration::from_secs(5), flushed.notified())
+            .await
+            .expect("queue not flushed on reconnection");
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn properties_are_not_queued() {
+        let dir = TempDir::new("edgehog-offline-queue").unwrap();
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send()
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Err(AstarteError::ConnectionTimeout));
+
+        let queue = OfflineQueue::new(
+            publisher,
+            interfaces(&dir),
+            &OfflineQueueConfig::default(),
+            dir.path(),
+        );
+
+        let res = queue
+            .send(
+                "io.edgehog.devicemanager.OSInfo",
+                "/osName",
+                AstarteType::String("Linux".to_string()),
+            )
+            .await;
+
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_when_full() {
+        let dir = TempDir::new("edgehog-offline-queue").unwrap();
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send()
+            .returning(|_: &str, _: &str, _: AstarteType| Err(AstarteError::ConnectionTimeout));
+
+        let config = OfflineQueueConfig {
+            max_entries: 2,
+            ..Default::default()
+        };
+        let queue = OfflineQueue::new(publisher, interfaces(&dir), &config, dir.path());
+
+        for value in 1..=3 {
+            queue
+                .send(
+                    "io.edgehog.devicemanager.SystemStatus",
+                    "/cpu/value",
+                    AstarteType::Integer(value),
+                )
+                .await
+                .unwrap();
+        }
+
+        let expected = [
+            QueuedData::Individual(QueuedValue::Integer(2)),
+            QueuedData::Individual(QueuedValue::Integer(3)),
+        ];
+        assert_eq!(queued(&queue).await, expected);
+
+        // each publish is appended, the dropped ones are skipped when loading the file
+        let file = std::fs::read_to_string(dir.path().join("offline_queue.jsonl")).unwrap();
+        assert_eq!(file.lines().count(), 3);
+
+        let reloaded =
+            OfflineQueue::new(MockPublisher::new(), interfaces(&dir), &config, dir.path());
+        assert_eq!(queued(&reloaded).await, expected);
+    }
+
+    #[tokio::test]
+    async fn successful_publish_requests_flush() {
+        let dir = TempDir::new("edgehog-offline-queue").unwrap();
+        let mut publisher = MockPublisher::new();
+        let mut seq = Sequence::new();
+
+        expect_send(&mut publisher, &mut seq, 1, false);
+        publisher
+            .expect_send()
+            .withf(|interface_name: &str, _: &str, _: &AstarteType| {
+                interface_name == "io.edgehog.devicemanager.OSInfo"
+            })
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let queue = OfflineQueue::new(
+            publisher,
+            interfaces(&dir),
+            &OfflineQueueConfig::default(),
+            dir.path(),
+        );
+
+        queue
+            .send(
+                "io.edgehog.devicemanager.SystemStatus",
+                "/cpu/value",
+                AstarteType::Integer(1),
+            )
+            .await
+            .unwrap();
+
+        queue
+            .send(
+                "io.edgehog.devicemanager.OSInfo",
+                "/osName",
+                AstarteType::String("Linux".to_string()),
+            )
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), queue.flush_requested.notified())
+            .await
+            .expect("flush not requested after a successful publish");
+
+        // queued without being sent, the flush checks the connection instead
+        queue
+            .send(
+                "io.edgehog.devicemanager.SystemStatus",
+                "/cpu/value",
+                AstarteType::Integer(2),
+            )
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), queue.flush_requested.notified())
+            .await
+            .expect("flush not requested after queueing a publish");
+
+        assert_eq!(
+            queued(&queue).await,
+            [
+                QueuedData::Individual(QueuedValue::Integer(1)),
+                QueuedData::Individual(QueuedValue::Integer(2)),
+            ]
+        );
+    }
+}
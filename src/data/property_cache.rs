@@ -0,0 +1,317 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cache of the last property values sent, to skip publishing unchanged values.
+//!
+//! Some tasks periodically re-send the whole state of their properties, even if nothing changed.
+//! For the configured interfaces, the [`PropertyCache`] only forwards a value when it differs from
+//! the last one successfully sent on the same path.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::trace;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::data::Publisher;
+
+/// Interfaces whose properties are cached.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PropertyCacheConfig {
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+}
+
+/// Publisher skipping the properties equal to the last value sent.
+#[derive(Debug, Clone)]
+pub struct PropertyCache<P> {
+    publisher: P,
+    interfaces: Arc<HashSet<String>>,
+    /// Last value sent for each interface and path.
+    values: Arc<RwLock<HashMap<(String, String), AstarteType>>>,
+}
+
+impl<P> PropertyCache<P> {
+    pub fn new(publisher: P, config: &PropertyCacheConfig) -> Self {
+        Self {
+            publisher,
+            interfaces: Arc::new(config.interfaces.iter().cloned().collect()),
+            values: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    fn key(interface_name: &str, interface_path: &str) -> (String, String) {
+        (interface_name.to_string(), interface_path.to_string())
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for PropertyCache<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        self.publisher
+            .send_object(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        self.publisher
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        if !self.interfaces.contains(interface_name) {
+            return self
+                .publisher
+                .send(interface_name, interface_path, data)
+                .await;
+        }
+
+        let key = Self::key(interface_name, interface_path);
+
+        if self.values.read().await.get(&key) == Some(&data) {
+            trace!("skipping unchanged property {interface_name}{interface_path}");
+
+            return Ok(());
+        }
+
+        let res = self
+            .publisher
+            .send(interface_name, interface_path, data.clone())
+            .await;
+
+        let mut values = self.values.write().await;
+        match res {
+            Ok(()) => {
+                values.insert(key, data);
+            }
+            Err(_) => {
+                values.remove(&key);
+            }
+        }
+
+        res
+    }
+
+    /// The properties don't have a timestamp, so there is nothing to cache.
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        self.publisher
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.publisher.interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        self.values
+            .write()
+            .await
+            .remove(&Self::key(interface_name, interface_path));
+
+        self.publisher.unset(interface_name, interface_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mockall::Sequence;
+
+    use crate::data::tests::MockPublisher;
+
+    const CACHED: &str = "io.edgehog.devicemanager.apps.AvailableImages";
+
+    fn cache(publisher: MockPublisher) -> PropertyCache<MockPublisher> {
+        PropertyCache::new(
+            publisher,
+            &PropertyCacheConfig {
+                interfaces: vec![CACHED.to_string()],
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn skip_unchanged_property() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send()
+            .withf(
+                |interface_name: &str, interface_path: &str, data: &AstarteType| {
+                    interface_name == CACHED
+                        && interface_path == "/id/pulled"
+                        && *data == AstarteType::Boolean(true)
+                },
+            )
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+        publisher
+            .expect_send()
+            .withf(
+                |interface_name: &str, interface_path: &str, data: &AstarteType| {
+                    interface_name == CACHED
+                        && interface_path == "/id/pulled"
+                        && *data == AstarteType::Boolean(false)
+                },
+            )
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let cache = cache(publisher);
+
+        cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(true))
+            .await
+            .unwrap();
+        cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(true))
+            .await
+            .unwrap();
+        cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(false))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn forward_not_cached_interface() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send()
+            .withf(
+                |interface_name: &str, interface_path: &str, _: &AstarteType| {
+                    interface_name == "io.edgehog.devicemanager.OSInfo"
+                        && interface_path == "/osName"
+                },
+            )
+            .times(2)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let cache = cache(publisher);
+
+        for _ in 0..2 {
+            cache
+                .send(
+                    "io.edgehog.devicemanager.OSInfo",
+                    "/osName",
+                    AstarteType::String("linux".to_string()),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn resend_after_error() {
+        let mut publisher = MockPublisher::new();
+        let mut seq = Sequence::new();
+
+        publisher
+            .expect_send()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_: &str, _: &str, _: AstarteType| Err(AstarteError::ConnectionTimeout));
+        publisher
+            .expect_send()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let cache = cache(publisher);
+
+        let res = cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(true))
+            .await;
+        assert!(res.is_err());
+
+        cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(true))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn resend_after_unset() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send()
+            .times(2)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+        publisher
+            .expect_unset()
+            .withf(|interface_name: &str, interface_path: &str| {
+                interface_name == CACHED && interface_path == "/id/pulled"
+            })
+            .once()
+            .returning(|_: &str, _: &str| Ok(()));
+
+        let cache = cache(publisher);
+
+        cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(true))
+            .await
+            .unwrap();
+        cache.unset(CACHED, "/id/pulled").await.unwrap();
+        cache
+            .send(CACHED, "/id/pulled", AstarteType::Boolean(true))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,599 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Validation of the outgoing data against the interfaces of the device.
+//!
+//! Astarte disconnects a device that publishes data not matching its introspection. The
+//! [`ValidatingPublisher`] checks the interface, the path and the type of the data against the
+//! interfaces loaded from the interfaces directories, and drops the invalid data with a local error
+//! instead of sending it to the broker.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Deserialize;
+
+use crate::data::Publisher;
+use crate::error::DeviceManagerError;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum ValidationError {
+    /// interface {0} not found
+    InterfaceNotFound(String),
+    /// interface {0} is owned by the server
+    ServerOwned(String),
+    /// interface {interface} is not {expected}
+    Aggregation {
+        interface: String,
+        expected: &'static str,
+    },
+    /// path {path} not found in interface {interface}
+    PathNotFound { interface: String, path: String },
+    /// invalid type for {interface}{path}, expected {expected} but got {got}
+    Type {
+        interface: String,
+        path: String,
+        expected: String,
+        got: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum InterfaceType {
+    Datastream,
+    Properties,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Ownership {
+    Device,
+    Server,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Aggregation {
+    #[default]
+    Individual,
+    Object,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Mapping {
+    endpoint: String,
+    #[serde(rename = "type")]
+    mapping_type: String,
+}
+
+impl Mapping {
+    /// Check if the path matches the endpoint, a `%{name}` segment matches any segment.
+    fn matches(&self, path: &str) -> bool {
+        let mut endpoint = self.endpoint.trim_start_matches('/').split('/');
+        let mut path = path.trim_start_matches('/').split('/');
+
+        loop {
+            match (endpoint.next(), path.next()) {
+                (None, None) => return true,
+                (Some(endpoint), Some(path)) => {
+                    let parameter = endpoint.starts_with("%{") && endpoint.ends_with('}');
+
+                    if path.is_empty() || (!parameter && endpoint != path) {
+                        return false;
+                    }
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Interface {
+    interface_name: String,
+    #[serde(rename = "type")]
+    interface_type: InterfaceType,
+    ownership: Ownership,
+    #[serde(default)]
+    aggregation: Aggregation,
+    mappings: Vec<Mapping>,
+}
+
+impl Interface {
+    fn mapping(&self, path: &str) -> Result<&Mapping, ValidationError> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.matches(path))
+            .ok_or_else(|| ValidationError::PathNotFound {
+                interface: self.interface_name.clone(),
+                path: path.to_string(),
+            })
+    }
+
+    fn validate_type(&self, path: &str, value: &AstarteType) -> Result<(), ValidationError> {
+        let mapping = self.mapping(path)?;
+
+        let got = type_name(value);
+        let valid = match (mapping.mapping_type.as_str(), got) {
+            (expected, got) if expected == got => true,
+            // integers are converted to the wider types
+            ("longinteger" | "double", "integer") => true,
+            ("longintegerarray" | "doublearray", "integerarray") => true,
+            (_, "unset") => self.interface_type == InterfaceType::Properties,
+            _ => false,
+        };
+
+        if !valid {
+            return Err(ValidationError::Type {
+                interface: self.interface_name.clone(),
+                path: path.to_string(),
+                expected: mapping.mapping_type.clone(),
+                got,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn type_name(value: &AstarteType) -> &'static str {
+    match value {
+        AstarteType::Double(_) => "double",
+        AstarteType::Integer(_) => "integer",
+        AstarteType::Boolean(_) => "boolean",
+        AstarteType::LongInteger(_) => "longinteger",
+        AstarteType::String(_) => "string",
+        AstarteType::BinaryBlob(_) => "binaryblob",
+        AstarteType::DateTime(_) => "datetime",
+        AstarteType::DoubleArray(_) => "doublearray",
+        AstarteType::IntegerArray(_) => "integerarray",
+        AstarteType::BooleanArray(_) => "booleanarray",
+        AstarteType::LongIntegerArray(_) => "longintegerarray",
+        AstarteType::StringArray(_) => "stringarray",
+        AstarteType::BinaryBlobArray(_) => "binaryblobarray",
+        AstarteType::DateTimeArray(_) => "datetimearray",
+        AstarteType::Unset => "unset",
+    }
+}
+
+/// Interfaces of the device, indexed by name.
+#[derive(Debug, Clone, Default)]
+pub struct Interfaces {
+    interfaces: HashMap<String, Interface>,
+}
+
+impl Interfaces {
+    /// Load the interfaces from the JSON files in the directories.
+    pub fn load<'a, I>(directories: I) -> Result<Self, DeviceManagerError>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let mut interfaces = HashMap::new();
+
+        for directory in directories {
+            for entry in std::fs::read_dir(directory)? {
+                let path = entry?.path();
+
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    continue;
+                }
+
+                let content = std::fs::read_to_string(&path)?;
+                let interface: Interface = serde_json::from_str(&content)?;
+
+                interfaces.insert(interface.interface_name.clone(), interface);
+            }
+        }
+
+        Ok(Self { interfaces })
+    }
+
+    /// Whether the interface is a device owned datastream.
+    pub fn is_datastream(&self, interface_name: &str) -> bool {
+        self.interfaces
+            .get(interface_name)
+            .is_some_and(|interface| {
+                interface.interface_type == InterfaceType::Datastream
+                    && interface.ownership == Ownership::Device
+            })
+    }
+
+    fn interface(
+        &self,
+        interface_name: &str,
+        aggregation: Aggregation,
+    ) -> Result<&Interface, ValidationError> {
+        let interface = self
+            .interfaces
+            .get(interface_name)
+            .ok_or_else(|| ValidationError::InterfaceNotFound(interface_name.to_string()))?;
+
+        if interface.ownership == Ownership::Server {
+            return Err(ValidationError::ServerOwned(interface_name.to_string()));
+        }
+
+        if interface.aggregation != aggregation {
+            let expected = match aggregation {
+                Aggregation::Individual => "individual",
+                Aggregation::Object => "object",
+            };
+
+            return Err(ValidationError::Aggregation {
+                interface: interface_name.to_string(),
+                expected,
+            });
+        }
+
+        Ok(interface)
+    }
+
+    /// Validate a value sent on an individual interface.
+    pub fn validate_individual(
+        &self,
+        interface_name: &str,
+        path: &str,
+        value: &AstarteType,
+    ) -> Result<(), ValidationError> {
+        self.interface(interface_name, Aggregation::Individual)?
+            .validate_type(path, value)
+    }
+
+    /// Validate the fields of an object sent on an object aggregated interface.
+    pub fn validate_object(
+        &self,
+        interface_name: &str,
+        path: &str,
+        data: &HashMap<String, AstarteType>,
+    ) -> Result<(), ValidationError> {
+        let interface = self.interface(interface_name, Aggregation::Object)?;
+
+        let path = path.trim_end_matches('/');
+        for (field, value) in data {
+            interface.validate_type(&format!("{path}/{field}"), value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Publisher dropping the data that doesn't match the interfaces.
+#[derive(Debug, Clone)]
+pub struct ValidatingPublisher<P> {
+    publisher: P,
+    interfaces: Arc<Interfaces>,
+}
+
+impl<P> ValidatingPublisher<P> {
+    pub fn new(publisher: P, interfaces: Interfaces) -> Self {
+        Self {
+            publisher,
+            interfaces: Arc::new(interfaces),
+        }
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for ValidatingPublisher<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let data = data.astarte_aggregate()?;
+
+        if let Err(err) = self
+            .interfaces
+            .validate_object(interface_name, interface_path, &data)
+        {
+            error!("invalid data not sent: {err}");
+
+            return Ok(());
+        }
+
+        self.publisher
+            .send_object(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let data = data.astarte_aggregate()?;
+
+        if let Err(err) = self
+            .interfaces
+            .validate_object(interface_name, interface_path, &data)
+        {
+            error!("invalid data not sent: {err}");
+
+            return Ok(());
+        }
+
+        self.publisher
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        if let Err(err) = self
+            .interfaces
+            .validate_individual(interface_name, interface_path, &data)
+        {
+            error!("invalid data not sent: {err}");
+
+            return Ok(());
+        }
+
+        self.publisher
+            .send(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        if let Err(err) = self
+            .interfaces
+            .validate_individual(interface_name, interface_path, &data)
+        {
+            error!("invalid data not sent: {err}");
+
+            return Ok(());
+        }
+
+        self.publisher
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.publisher.interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        if let Err(err) =
+            self.interfaces
+                .validate_individual(interface_name, interface_path, &AstarteType::Unset)
+        {
+            error!("invalid unset not sent: {err}");
+
+            return Ok(());
+        }
+
+        self.publisher.unset(interface_name, interface_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    const OS_INFO: &str = r#"{
+        "interface_name": "io.edgehog.devicemanager.OSInfo",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "properties",
+        "ownership": "device",
+        "mappings": [
+            {"endpoint": "/osName", "type": "string"},
+            {"endpoint": "/osVersion", "type": "string"}
+        ]
+    }"#;
+
+    const STORAGE_USAGE: &str = r#"{
+        "interface_name": "io.edgehog.devicemanager.StorageUsage",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "device",
+        "aggregation": "object",
+        "mappings": [
+            {"endpoint": "/%{label}/totalBytes", "type": "longinteger"},
+            {"endpoint": "/%{label}/freeBytes", "type": "longinteger"}
+        ]
+    }"#;
+
+    const COMMANDS: &str = r#"{
+        "interface_name": "io.edgehog.devicemanager.Commands",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "server",
+        "mappings": [{"endpoint": "/request", "type": "string"}]
+    }"#;
+
+    fn interfaces() -> (TempDir, Interfaces) {
+        let dir = TempDir::new("edgehog-interfaces").unwrap();
+        for (name, content) in [
+            ("os_info.json", OS_INFO),
+            ("storage_usage.json", STORAGE_USAGE),
+            ("commands.json", COMMANDS),
+        ] {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+
+        let interfaces = Interfaces::load([dir.path()]).unwrap();
+
+        (dir, interfaces)
+    }
+
+    #[test]
+    fn mapping_matches_path() {
+        let mapping = Mapping {
+            endpoint: "/%{label}/totalBytes".to_string(),
+            mapping_type: "longinteger".to_string(),
+        };
+
+        assert!(mapping.matches("/root/totalBytes"));
+        assert!(!mapping.matches("/root/freeBytes"));
+        assert!(!mapping.matches("//totalBytes"));
+        assert!(!mapping.matches("/root/totalBytes/other"));
+    }
+
+    #[test]
+    fn validate_individual() {
+        let (_dir, interfaces) = interfaces();
+        let os_info = "io.edgehog.devicemanager.OSInfo";
+
+        assert_eq!(
+            interfaces.validate_individual(
+                os_info,
+                "/osName",
+                &AstarteType::String("Linux".to_string())
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            interfaces.validate_individual(os_info, "/osName", &AstarteType::Unset),
+            Ok(())
+        );
+        assert!(matches!(
+            interfaces.validate_individual(os_info, "/osName", &AstarteType::Integer(1)),
+            Err(ValidationError::Type { .. })
+        ));
+        assert!(matches!(
+            interfaces.validate_individual(os_info, "/kernel", &AstarteType::Integer(1)),
+            Err(ValidationError::PathNotFound { .. })
+        ));
+        assert!(matches!(
+            interfaces.validate_individual(
+                "io.edgehog.devicemanager.Commands",
+                "/request",
+                &AstarteType::String("Reboot".to_string())
+            ),
+            Err(ValidationError::ServerOwned(_))
+        ));
+        assert!(matches!(
+            interfaces.validate_individual("missing", "/path", &AstarteType::Integer(1)),
+            Err(ValidationError::InterfaceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn validate_object() {
+        let (_dir, interfaces) = interfaces();
+        let storage_usage = "io.edgehog.devicemanager.StorageUsage";
+
+        let mut data = HashMap::from([
+            ("totalBytes".to_string(), AstarteType::LongInteger(100)),
+            ("freeBytes".to_string(), AstarteType::Integer(10)),
+        ]);
+        assert_eq!(
+            interfaces.validate_object(storage_usage, "/root", &data),
+            Ok(())
+        );
+
+        data.insert("usedBytes".to_string(), AstarteType::LongInteger(90));
+        assert!(matches!(
+            interfaces.validate_object(storage_usage, "/root", &data),
+            Err(ValidationError::PathNotFound { .. })
+        ));
+
+        assert!(matches!(
+            interfaces.validate_individual(
+                storage_usage,
+                "/root/totalBytes",
+                &AstarteType::LongInteger(1)
+            ),
+            Err(ValidationError::Aggregation { .. })
+        ));
+    }
+
+    #[test]
+    fn runtime_interfaces() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("interfaces");
+        let interfaces = Interfaces::load([directory.as_path()]).unwrap();
+
+        assert_eq!(
+            interfaces.validate_individual(
+                "io.edgehog.devicemanager.RuntimeDiagnostics",
+                "/storage/store/readOnly",
+                &AstarteType::Boolean(false)
+            ),
+            Ok(())
+        );
+
+        for (path, value) in crate::telemetry::hardware_info::get_board_info() {
+            assert_eq!(
+                interfaces.validate_individual("io.edgehog.devicemanager.BoardInfo", &path, &value),
+                Ok(()),
+                "invalid BoardInfo path {path}"
+            );
+        }
+
+        for (path, value) in crate::telemetry::runtime_info::get_runtime_details().unwrap() {
+            assert_eq!(
+                interfaces.validate_individual(
+                    "io.edgehog.devicemanager.RuntimeDetails",
+                    &path,
+                    &value
+                ),
+                Ok(()),
+                "invalid RuntimeDetails path {path}"
+            );
+        }
+
+        let timing = crate::timing::StartupTiming {
+            runtime_version: "0.7.1".to_string(),
+            phases: vec!["connect".to_string()],
+            durations_ms: vec![10],
+            total_ms: 10,
+        };
+        assert_eq!(
+            interfaces.validate_object(
+                "io.edgehog.devicemanager.StartupTiming",
+                "/startup",
+                &timing.astarte_aggregate().unwrap()
+            ),
+            Ok(())
+        );
+    }
+}
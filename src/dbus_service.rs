@@ -0,0 +1,191 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! D-Bus service exposing the state of the runtime to the other services on the device.
+//!
+//! Local UIs can read the runtime status and the progress of an OTA update from the
+//! `io.edgehog.DeviceRuntime1` interface, without talking to the cloud.
+
+use std::collections::HashMap;
+
+use log::info;
+use serde::Deserialize;
+use tokio::sync::watch;
+use zbus::{dbus_interface, ConnectionBuilder, SignalContext};
+
+use crate::error::DeviceManagerError;
+use crate::state::State;
+
+pub const SERVICE_NAME: &str = "io.edgehog.DeviceRuntime1";
+pub const OBJECT_PATH: &str = "/io/edgehog/DeviceRuntime1";
+
+/// Bus the service is registered on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    #[default]
+    System,
+    Session,
+}
+
+/// Configuration of the D-Bus service.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct DbusServiceConfig {
+    #[serde(default)]
+    pub bus: Bus,
+}
+
+struct DeviceRuntime {
+    state: State,
+}
+
+#[dbus_interface(name = "io.edgehog.DeviceRuntime1")]
+impl DeviceRuntime {
+    #[dbus_interface(property)]
+    fn status(&self) -> String {
+        self.state.status.clone()
+    }
+
+    /// UUID of the last OTA request.
+    #[dbus_interface(property)]
+    fn ota_request(&self) -> String {
+        self.state.ota.request_uuid.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn ota_status(&self) -> String {
+        self.state.ota.status.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn ota_progress(&self) -> i32 {
+        self.state.ota.progress
+    }
+
+    /// Connected forwarder sessions, as token, host, Unix timestamp of the connection, bytes sent
+    /// and bytes received.
+    #[dbus_interface(property)]
+    fn forwarder_sessions(&self) -> Vec<(String, String, u64, u64, u64)> {
+        self.state
+            .forwarder_sessions
+            .iter()
+            .map(|session| {
+                (
+                    session.token.clone(),
+                    session.host.clone(),
+                    session.connected_since,
+                    session.bytes_sent,
+                    session.bytes_received,
+                )
+            })
+            .collect()
+    }
+
+    /// Random delay in milliseconds of the first sample of each telemetry interface.
+    #[dbus_interface(property)]
+    fn telemetry_offsets(&self) -> HashMap<String, u64> {
+        self.state.telemetry_offsets.clone()
+    }
+
+    /// Emitted on every transition of the runtime or OTA state.
+    #[dbus_interface(signal)]
+    async fn state_changed(
+        ctxt: &SignalContext<'_>,
+        status: &str,
+        ota_status: &str,
+        ota_progress: i32,
+    ) -> zbus::Result<()>;
+}
+
+/// Register the service and publish the state changes until the runtime exits.
+pub(crate) async fn serve(
+    config: DbusServiceConfig,
+    mut state: watch::Receiver<State>,
+) -> Result<(), DeviceManagerError> {
+    let builder = match config.bus {
+        Bus::System => ConnectionBuilder::system()?,
+        Bus::Session => ConnectionBuilder::session()?,
+    };
+
+    let runtime = DeviceRuntime {
+        state: state.borrow_and_update().clone(),
+    };
+
+    let connection = builder
+        .name(SERVICE_NAME)?
+        .serve_at(OBJECT_PATH, runtime)?
+        .build()
+        .await?;
+
+    info!("serving {SERVICE_NAME} on D-Bus");
+
+    let iface = connection
+        .object_server()
+        .interface::<_, DeviceRuntime>(OBJECT_PATH)
+        .await?;
+
+    while state.changed().await.is_ok() {
+        let new = state.borrow_and_update().clone();
+        let old = std::mem::replace(&mut iface.get_mut().await.state, new);
+
+        let runtime = iface.get().await;
+        let ctxt = iface.signal_context();
+
+        if old.status != runtime.state.status {
+            runtime.status_changed(ctxt).await?;
+        }
+
+        if old.ota != runtime.state.ota {
+            runtime.ota_request_changed(ctxt).await?;
+            runtime.ota_status_changed(ctxt).await?;
+            runtime.ota_progress_changed(ctxt).await?;
+        }
+
+        if old.forwarder_sessions != runtime.state.forwarder_sessions {
+            runtime.forwarder_sessions_changed(ctxt).await?;
+        }
+
+        if old.telemetry_offsets != runtime.state.telemetry_offsets {
+            runtime.telemetry_offsets_changed(ctxt).await?;
+        }
+
+        DeviceRuntime::state_changed(
+            ctxt,
+            &runtime.state.status,
+            &runtime.state.ota.status,
+            runtime.state.ota.progress,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_defaults() {
+        let config: DbusServiceConfig = toml::from_str("").unwrap();
+        assert_eq!(config.bus, Bus::System);
+
+        let config: DbusServiceConfig = toml::from_str(r#"bus = "session""#).unwrap();
+        assert_eq!(config.bus, Bus::Session);
+    }
+}
@@ -63,6 +63,12 @@ pub enum DeviceManagerError {
     #[error("the connection was closed")]
     Disconnected,
 
+    #[error("couldn't execute external program")]
+    Executor(#[from] crate::executor::ExecutorError),
+
+    #[error("couldn't read the custom telemetry")]
+    TelemetryScript(#[from] crate::telemetry::script::ScriptError),
+
     #[error("couldn't connect to the store")]
     Store(#[from] crate::data::StoreError),
 
@@ -0,0 +1,281 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bounded queues of the events dispatched to the subsystems.
+//!
+//! Each subsystem receives its events from its own queue, so a slow handler can't stall the
+//! others. When a queue is full the dispatcher either waits for it, or drops the oldest event.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+use tokio::sync::{mpsc, Notify};
+
+/// the {0} event queue is closed
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub(crate) struct QueueClosed(&'static str);
+
+/// What to do with a new event when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OverflowPolicy {
+    /// Wait for the handler to receive an event.
+    Block,
+    /// Drop the oldest event in the queue.
+    DropOldest,
+}
+
+#[derive(Debug, Default)]
+struct QueueStats {
+    dropped: AtomicU64,
+    blocked: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Ring<T> {
+    capacity: usize,
+    events: Mutex<VecDeque<T>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl<T> Ring<T> {
+    /// Push the event, returning true if the oldest one was dropped.
+    fn push(&self, event: T) -> bool {
+        let mut events = self.events.lock().unwrap_or_else(|err| err.into_inner());
+
+        let dropped = events.len() >= self.capacity && events.pop_front().is_some();
+        events.push_back(event);
+        drop(events);
+
+        self.notify.notify_one();
+
+        dropped
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.events
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .pop_front()
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+}
+
+#[derive(Debug)]
+enum SenderKind<T> {
+    Block(mpsc::Sender<T>),
+    DropOldest(Arc<Ring<T>>),
+}
+
+#[derive(Debug)]
+enum ReceiverKind<T> {
+    Block(mpsc::Receiver<T>),
+    DropOldest(Arc<Ring<T>>),
+}
+
+/// Sending half of an event queue.
+#[derive(Debug)]
+pub(crate) struct EventSender<T> {
+    name: &'static str,
+    kind: SenderKind<T>,
+    stats: Arc<QueueStats>,
+}
+
+/// Receiving half of an event queue.
+#[derive(Debug)]
+pub(crate) struct EventReceiver<T> {
+    kind: ReceiverKind<T>,
+}
+
+/// Create a queue of the given capacity.
+pub(crate) fn channel<T>(
+    name: &'static str,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (EventSender<T>, EventReceiver<T>) {
+    let (sender, receiver) = match policy {
+        OverflowPolicy::Block => {
+            let (tx, rx) = mpsc::channel(capacity);
+
+            (SenderKind::Block(tx), ReceiverKind::Block(rx))
+        }
+        OverflowPolicy::DropOldest => {
+            let ring = Arc::new(Ring {
+                capacity: capacity.max(1),
+                events: Mutex::new(VecDeque::with_capacity(capacity)),
+                notify: Notify::new(),
+                closed: AtomicBool::new(false),
+            });
+
+            (
+                SenderKind::DropOldest(ring.clone()),
+                ReceiverKind::DropOldest(ring),
+            )
+        }
+    };
+
+    (
+        EventSender {
+            name,
+            kind: sender,
+            stats: Arc::default(),
+        },
+        EventReceiver { kind: receiver },
+    )
+}
+
+impl<T> EventSender<T> {
+    /// Queue the event, applying the overflow policy if the queue is full.
+    pub(crate) async fn send(&self, event: T) -> Result<(), QueueClosed> {
+        match &self.kind {
+            SenderKind::Block(tx) => {
+                let event = match tx.try_send(event) {
+                    Ok(()) => return Ok(()),
+                    Err(mpsc::error::TrySendError::Closed(_)) => {
+                        return Err(QueueClosed(self.name))
+                    }
+                    Err(mpsc::error::TrySendError::Full(event)) => event,
+                };
+
+                let blocked = self.stats.blocked.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!("{} queue full, waiting ({blocked} times)", self.name);
+
+                #[cfg(feature = "metrics")]
+                crate::metrics::event_queue_blocked(self.name);
+
+                tx.send(event).await.map_err(|_| QueueClosed(self.name))
+            }
+            SenderKind::DropOldest(ring) => {
+                if Arc::strong_count(ring) == 1 {
+                    return Err(QueueClosed(self.name));
+                }
+
+                if ring.push(event) {
+                    let dropped = self.stats.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                    warn!(
+                        "{} queue full, dropped the oldest event ({dropped} dropped)",
+                        self.name
+                    );
+
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::event_queue_dropped(self.name);
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T> Drop for EventSender<T> {
+    fn drop(&mut self) {
+        if let SenderKind::DropOldest(ring) = &self.kind {
+            ring.close();
+        }
+    }
+}
+
+impl<T> EventReceiver<T> {
+    /// Receive the next event, or [`None`] once the sender is dropped and the queue is empty.
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        match &mut self.kind {
+            ReceiverKind::Block(rx) => rx.recv().await,
+            ReceiverKind::DropOldest(ring) => loop {
+                if let Some(event) = ring.pop() {
+                    return Some(event);
+                }
+
+                if ring.closed.load(Ordering::Acquire) {
+                    return None;
+                }
+
+                ring.notify.notified().await;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn drop_oldest() {
+        let (tx, mut rx) = channel("test", 2, OverflowPolicy::DropOldest);
+
+        for i in 0..4 {
+            tx.send(i).await.unwrap();
+        }
+
+        assert_eq!(tx.stats.dropped.load(Ordering::Relaxed), 2);
+
+        drop(tx);
+
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_wakes_receiver() {
+        let (tx, mut rx) = channel("test", 2, OverflowPolicy::DropOldest);
+
+        let handle = tokio::spawn(async move { rx.recv().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        tx.send(42).await.unwrap();
+
+        assert_eq!(handle.await.unwrap(), Some(42));
+    }
+
+    #[tokio::test]
+    async fn block_when_full() {
+        let (tx, mut rx) = channel("test", 1, OverflowPolicy::Block);
+
+        tx.send(1).await.unwrap();
+
+        let send = tokio::time::timeout(Duration::from_millis(10), tx.send(2)).await;
+        assert!(send.is_err(), "send should wait for the receiver");
+        assert_eq!(tx.stats.blocked.load(Ordering::Relaxed), 1);
+
+        assert_eq!(rx.recv().await, Some(1));
+
+        tx.send(3).await.unwrap();
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(tx.stats.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn closed_queue() {
+        let (tx, rx) = channel::<i32>("test", 1, OverflowPolicy::DropOldest);
+        drop(rx);
+        assert!(tx.send(1).await.is_err());
+
+        let (tx, rx) = channel::<i32>("test", 1, OverflowPolicy::Block);
+        drop(rx);
+        assert!(tx.send(1).await.is_err());
+    }
+}
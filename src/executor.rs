@@ -0,0 +1,524 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hardened execution of external programs.
+//!
+//! Every external program spawned by the runtime (hooks, scripts, commands) should go through the
+//! [`Executor`], so they all share the same timeout, output cap and environment scrubbing.
+//!
+//! The program is started in its own process group, and the whole group is killed on timeout so
+//! the processes it spawned don't outlive it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use log::{debug, warn};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use uuid::Uuid;
+
+/// Default maximum time a program is allowed to run.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Default maximum number of bytes captured from each of stdout and stderr.
+const DEFAULT_MAX_OUTPUT: usize = 64 * 1024;
+/// Environment variables that are kept from the runtime environment.
+const DEFAULT_ENV_ALLOWLIST: &[&str] = &["PATH", "LANG", "LC_ALL", "TZ"];
+/// Program used to run the command in a transient scope.
+const SYSTEMD_RUN: &str = "systemd-run";
+/// Database of the local users.
+const PASSWD_PATH: &str = "/etc/passwd";
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ExecutorError {
+    /// couldn't spawn {program}
+    Spawn {
+        #[source]
+        backtrace: std::io::Error,
+        program: String,
+    },
+    /// couldn't wait for {program}
+    Wait {
+        #[source]
+        backtrace: std::io::Error,
+        program: String,
+    },
+    /// {program} didn't exit in {timeout:?}
+    Timeout { program: String, timeout: Duration },
+    /// couldn't find the user {user}
+    UnknownUser {
+        #[source]
+        backtrace: Option<std::io::Error>,
+        user: String,
+    },
+}
+
+/// Resource limits applied to the transient systemd scope the program runs in.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ScopeLimits {
+    /// Value for the `MemoryMax` property, e.g. `64M`.
+    pub memory_max: Option<String>,
+    /// Value for the `CPUQuota` property, e.g. `20%`.
+    pub cpu_quota: Option<String>,
+    /// Value for the `TasksMax` property.
+    pub tasks_max: Option<u64>,
+}
+
+impl ScopeLimits {
+    fn properties(&self) -> Vec<String> {
+        let memory = self.memory_max.as_ref().map(|v| format!("MemoryMax={v}"));
+        let cpu = self.cpu_quota.as_ref().map(|v| format!("CPUQuota={v}"));
+        let tasks = self.tasks_max.map(|v| format!("TasksMax={v}"));
+
+        [memory, cpu, tasks].into_iter().flatten().collect()
+    }
+}
+
+/// Output of a program run through the [`Executor`].
+#[derive(Debug)]
+pub struct ExecOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Whether stdout or stderr exceeded the maximum output size.
+    pub truncated: bool,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
+/// Builder to run an external program with a scrubbed environment, a timeout and capped output.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+    max_output: usize,
+    working_dir: PathBuf,
+    env_allowlist: Vec<String>,
+    env: HashMap<String, String>,
+    scope: Option<ScopeLimits>,
+    user: Option<String>,
+}
+
+impl Executor {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            max_output: DEFAULT_MAX_OUTPUT,
+            working_dir: PathBuf::from("/"),
+            env_allowlist: DEFAULT_ENV_ALLOWLIST
+                .iter()
+                .map(|var| var.to_string())
+                .collect(),
+            env: HashMap::new(),
+            scope: None,
+            user: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, A>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Maximum time the program can run before being killed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Maximum number of bytes captured from each of stdout and stderr.
+    pub fn max_output(mut self, max_output: usize) -> Self {
+        self.max_output = max_output;
+        self
+    }
+
+    /// Directory the program is started in, defaults to `/`.
+    pub fn working_dir(mut self, working_dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = working_dir.into();
+        self
+    }
+
+    /// Keep the variable from the runtime environment, if set.
+    pub fn keep_env(mut self, name: impl Into<String>) -> Self {
+        self.env_allowlist.push(name.into());
+        self
+    }
+
+    /// Set an environment variable for the program.
+    pub fn env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(name.into(), value.into());
+        self
+    }
+
+    /// Run the program inside a transient systemd scope with the given limits.
+    pub fn scope(mut self, limits: ScopeLimits) -> Self {
+        self.scope = Some(limits);
+        self
+    }
+
+    /// Run the program as the given local user, instead of the user of the runtime.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Name of the transient scope, if the program runs in one.
+    fn unit(&self) -> Option<String> {
+        self.scope
+            .as_ref()
+            .map(|_| format!("edgehog-exec-{}", Uuid::new_v4().simple()))
+    }
+
+    fn command(&self, unit: Option<&str>) -> Result<Command, ExecutorError> {
+        let mut cmd = match (&self.scope, unit) {
+            (Some(limits), Some(unit)) => {
+                let mut cmd = Command::new(SYSTEMD_RUN);
+                cmd.args(["--scope", "--quiet", "--collect"])
+                    .arg(format!("--unit={unit}"));
+                for prop in limits.properties() {
+                    cmd.arg("-p").arg(prop);
+                }
+                cmd.arg("--").arg(&self.program);
+
+                cmd
+            }
+            _ => Command::new(&self.program),
+        };
+
+        cmd.args(&self.args)
+            .env_clear()
+            .envs(
+                self.env_allowlist
+                    .iter()
+                    .filter_map(|name| std::env::var(name).ok().map(|value| (name, value))),
+            )
+            .envs(&self.env)
+            .current_dir(&self.working_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true);
+
+        if let Some(user) = &self.user {
+            let (uid, gid) = lookup_user(Path::new(PASSWD_PATH), user)?;
+
+            cmd.uid(uid).gid(gid);
+        }
+
+        Ok(cmd)
+    }
+
+    /// Run the program to completion, collecting its output.
+    pub async fn output(&self) -> Result<ExecOutput, ExecutorError> {
+        debug!("executing {} {:?}", self.program, self.args);
+
+        let unit = self.unit();
+        let mut child = self
+            .command(unit.as_deref())?
+            .spawn()
+            .map_err(|backtrace| ExecutorError::Spawn {
+                backtrace,
+                program: self.program.clone(),
+            })?;
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let max_output = self.max_output;
+
+        let res = tokio::time::timeout(self.timeout, async {
+            tokio::try_join!(
+                child.wait(),
+                read_capped(stdout, max_output),
+                read_capped(stderr, max_output)
+            )
+        })
+        .await;
+
+        match res {
+            Ok(Ok((status, (stdout, stdout_truncated), (stderr, stderr_truncated)))) => {
+                Ok(ExecOutput {
+                    status,
+                    stdout,
+                    stderr,
+                    truncated: stdout_truncated || stderr_truncated,
+                })
+            }
+            Ok(Err(backtrace)) => Err(ExecutorError::Wait {
+                backtrace,
+                program: self.program.clone(),
+            }),
+            Err(_) => {
+                self.kill(&mut child, unit.as_deref()).await;
+
+                Err(ExecutorError::Timeout {
+                    program: self.program.clone(),
+                    timeout: self.timeout,
+                })
+            }
+        }
+    }
+}
+
+impl Executor {
+    /// Kill the process group of the child and stop its scope, if any.
+    async fn kill(&self, child: &mut Child, unit: Option<&str>) {
+        // the child is the leader of its process group, the pgid is the same as the pid
+        if let Some(pgid) = child.id().and_then(|pid| i32::try_from(pid).ok()) {
+            if let Err(err) = killpg(Pid::from_raw(pgid), Signal::SIGKILL) {
+                warn!("couldn't kill the process group of {}: {err}", self.program);
+            }
+        }
+
+        // the processes that left the group are still in the cgroup of the scope
+        if let Some(unit) = unit {
+            let res = Command::new("systemctl")
+                .args(["stop", &format!("{unit}.scope")])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await;
+
+            match res {
+                Ok(status) if status.success() => {}
+                Ok(status) => warn!("couldn't stop the scope {unit}: {status}"),
+                Err(err) => warn!("couldn't stop the scope {unit}: {err}"),
+            }
+        }
+
+        // reap the child
+        if let Err(err) = child.kill().await {
+            warn!("couldn't kill {}: {err}", self.program);
+        }
+    }
+}
+
+/// Find the user and primary group id of a user in the passwd file.
+fn lookup_user(passwd: &Path, user: &str) -> Result<(u32, u32), ExecutorError> {
+    let unknown = |backtrace| ExecutorError::UnknownUser {
+        backtrace,
+        user: user.to_string(),
+    };
+
+    let content = std::fs::read_to_string(passwd).map_err(|err| unknown(Some(err)))?;
+
+    content
+        .lines()
+        .find_map(|line| {
+            // name:password:uid:gid:gecos:home:shell
+            let mut fields = line.split(':');
+            if fields.next()? != user {
+                return None;
+            }
+
+            let uid = fields.nth(1)?.parse().ok()?;
+            let gid = fields.next()?.parse().ok()?;
+
+            Some((uid, gid))
+        })
+        .ok_or_else(|| unknown(None))
+}
+
+/// Read the whole stream, keeping at most `max` bytes.
+///
+/// The rest of the stream is drained so the child doesn't block on a full pipe.
+async fn read_capped<R>(mut reader: R, max: usize) -> std::io::Result<(Vec<u8>, bool)>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let mut truncated = false;
+
+    loop {
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+
+        let remaining = max.saturating_sub(buf.len());
+        if n > remaining {
+            truncated = true;
+        }
+
+        buf.extend_from_slice(&chunk[..n.min(remaining)]);
+    }
+
+    Ok((buf, truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn execute_success() {
+        let output = Executor::new("echo").arg("hello").output().await.unwrap();
+
+        assert!(output.success());
+        assert_eq!(output.stdout, b"hello\n");
+        assert!(!output.truncated);
+    }
+
+    #[tokio::test]
+    async fn execute_missing_program() {
+        let res = Executor::new("/this/program/does/not/exist").output().await;
+
+        assert!(matches!(res, Err(ExecutorError::Spawn { .. })));
+    }
+
+    #[tokio::test]
+    async fn execute_timeout() {
+        let res = Executor::new("sleep")
+            .arg("10")
+            .timeout(Duration::from_millis(100))
+            .output()
+            .await;
+
+        assert!(matches!(res, Err(ExecutorError::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn execute_timeout_kills_process_group() {
+        let dir = tempdir::TempDir::new("edgehog-executor").unwrap();
+        let pid_file = dir.path().join("pid");
+
+        let res = Executor::new("sh")
+            .args(["-c", "sleep 30 & echo $! > \"$1\"; wait", "sh"])
+            .arg(pid_file.to_string_lossy())
+            .timeout(Duration::from_millis(500))
+            .output()
+            .await;
+        assert!(matches!(res, Err(ExecutorError::Timeout { .. })));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let stat = Path::new("/proc").join(pid.trim()).join("stat");
+
+        // the killed process could be a zombie until it's reaped
+        let mut killed = false;
+        for _ in 0..50 {
+            killed = std::fs::read_to_string(&stat)
+                .map(|stat| stat.contains(") Z"))
+                .unwrap_or(true);
+            if killed {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert!(killed, "the background process is still running");
+    }
+
+    #[tokio::test]
+    async fn execute_truncates_output() {
+        let output = Executor::new("sh")
+            .args(["-c", "printf '%0100d' 0"])
+            .max_output(10)
+            .output()
+            .await
+            .unwrap();
+
+        assert!(output.success());
+        assert_eq!(output.stdout.len(), 10);
+        assert!(output.truncated);
+    }
+
+    #[tokio::test]
+    async fn execute_scrubs_environment() {
+        let output = Executor::new("env")
+            .env("EDGEHOG_TEST", "value")
+            .output()
+            .await
+            .unwrap();
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        for line in stdout.lines() {
+            let (name, _) = line.split_once('=').unwrap();
+
+            assert!(
+                DEFAULT_ENV_ALLOWLIST.contains(&name) || name == "EDGEHOG_TEST",
+                "unexpected variable {name}"
+            );
+        }
+        assert!(stdout.contains("EDGEHOG_TEST=value"));
+    }
+
+    #[tokio::test]
+    async fn execute_in_working_dir() {
+        let output = Executor::new("pwd")
+            .working_dir("/tmp")
+            .output()
+            .await
+            .unwrap();
+
+        assert_eq!(output.stdout, b"/tmp\n");
+    }
+
+    #[test]
+    fn lookup_user_in_passwd() {
+        let dir = tempdir::TempDir::new("edgehog-executor").unwrap();
+        let passwd = dir.path().join("passwd");
+        std::fs::write(
+            &passwd,
+            "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1001::/home/app:/bin/sh\n",
+        )
+        .unwrap();
+
+        assert_eq!(lookup_user(&passwd, "app").unwrap(), (1000, 1001));
+        assert!(matches!(
+            lookup_user(&passwd, "missing"),
+            Err(ExecutorError::UnknownUser {
+                backtrace: None,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn scope_properties() {
+        let limits = ScopeLimits {
+            memory_max: Some("64M".to_string()),
+            cpu_quota: None,
+            tasks_max: Some(16),
+        };
+
+        assert_eq!(limits.properties(), ["MemoryMax=64M", "TasksMax=16"]);
+    }
+}
@@ -20,20 +20,41 @@
 
 //! Manage the device forwarder operation.
 
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::data::Publisher;
+use crate::rate_limit::RateLimiter;
+use crate::repository::file_state_repository::FileStateRepository;
+use crate::repository::StateRepository;
+use crate::state::{ForwarderSession, RuntimeState};
 use astarte_device_sdk::types::AstarteType;
 use astarte_device_sdk::{AstarteDeviceDataEvent, FromEvent};
 use edgehog_forwarder::astarte::SessionInfo;
-use edgehog_forwarder::connections_manager::{ConnectionsManager, Disconnected};
-use log::{debug, error, info};
+use edgehog_forwarder::collection::{SessionTraffic, Traffic};
+use edgehog_forwarder::connections_manager::{
+    ConnectionsManager, Disconnected, Keepalive, SessionLimits,
+};
+use edgehog_forwarder::proxy::ProxyConfig;
+use edgehog_forwarder::tls::{ClientAuth, TlsConfig};
+use log::{debug, error, info, warn};
 use reqwest::Url;
+use serde::Deserialize;
+use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 const FORWARDER_SESSION_STATE_INTERFACE: &str = "io.edgehog.devicemanager.ForwarderSessionState";
 
+/// Default number of pings without a response after which the session reconnects.
+const DEFAULT_KEEPALIVE_MAX_MISSED: u32 = 3;
+
+/// Interval between the updates of the session traffic in the runtime state.
+const TRAFFIC_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Forwarder errors
 #[derive(displaydoc::Display, thiserror::Error, Debug)]
 pub enum ForwarderError {
@@ -47,11 +68,142 @@ pub enum ForwarderError {
     ConnectionsManager(#[from] edgehog_forwarder::connections_manager::Error),
 }
 
+/// Configuration of the forwarder sessions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ForwarderConfig {
+    /// Edgehog instances allowed to open a session, all are allowed if missing.
+    pub allowed_origins: Option<Vec<AllowedOrigin>>,
+    /// Maximum number of concurrent sessions from the same origin.
+    pub max_sessions_per_origin: Option<usize>,
+    /// Maximum number of concurrent sessions on the device.
+    pub max_sessions: Option<usize>,
+    /// Seconds without traffic after which a session is closed.
+    pub idle_timeout: Option<u64>,
+    /// Maximum duration of a session in seconds.
+    pub max_session_duration: Option<u64>,
+    /// Maximum bytes per second exchanged by a session.
+    pub max_bandwidth: Option<u64>,
+    /// Seconds between the pings sent to Edgehog, disabled if missing.
+    pub keepalive_interval: Option<u64>,
+    /// Pings without a response after which the session reconnects, defaults to 3.
+    pub keepalive_max_missed: Option<u32>,
+    /// Certificates for the connection with the Edgehog forwarder endpoint.
+    #[serde(default)]
+    pub tls: ForwarderTlsConfig,
+}
+
+/// Certificates for the TLS connection with Edgehog.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ForwarderTlsConfig {
+    /// Additional CA bundle used to verify the forwarder endpoint.
+    pub ca_bundle: Option<PathBuf>,
+    /// Client certificate used to authenticate the device with mutual TLS.
+    pub client_auth: Option<ClientAuthConfig>,
+}
+
+/// Paths to the PEM client certificate chain and private key.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ClientAuthConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl From<&ForwarderTlsConfig> for TlsConfig {
+    fn from(value: &ForwarderTlsConfig) -> Self {
+        Self {
+            ca_bundle: value.ca_bundle.clone(),
+            client_auth: value.client_auth.as_ref().map(|auth| ClientAuth {
+                cert: auth.cert.clone(),
+                key: auth.key.clone(),
+            }),
+        }
+    }
+}
+
+/// Edgehog instance allowed to open a session, any port is allowed if missing.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AllowedOrigin {
+    pub host: String,
+    pub port: Option<i32>,
+}
+
+/// Edgehog instance a session is opened from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Origin {
+    host: String,
+    port: i32,
+}
+
+impl From<&SessionInfo> for Origin {
+    fn from(value: &SessionInfo) -> Self {
+        Self {
+            host: value.host.clone(),
+            port: value.port,
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+impl ForwarderConfig {
+    fn session_limits(&self) -> SessionLimits {
+        SessionLimits {
+            idle_timeout: self.idle_timeout.map(Duration::from_secs),
+            max_duration: self.max_session_duration.map(Duration::from_secs),
+            bandwidth: self.max_bandwidth,
+        }
+    }
+
+    fn keepalive(&self) -> Option<Keepalive> {
+        self.keepalive_interval.map(|interval| Keepalive {
+            interval: Duration::from_secs(interval),
+            max_missed: self
+                .keepalive_max_missed
+                .unwrap_or(DEFAULT_KEEPALIVE_MAX_MISSED),
+        })
+    }
+
+    fn is_allowed(&self, origin: &Origin) -> bool {
+        let Some(allowed) = &self.allowed_origins else {
+            return true;
+        };
+
+        allowed.iter().any(|allowed| {
+            allowed.host == origin.host && allowed.port.map_or(true, |port| port == origin.port)
+        })
+    }
+}
+
+/// Reason a session request was rejected.
+#[derive(displaydoc::Display, Debug, Clone, PartialEq, Eq)]
+enum SessionRejected {
+    /// origin {0} is not allowed
+    OriginNotAllowed(Origin),
+    /// session token already used by a different origin
+    TokenInUse,
+    /// maximum number of sessions reached for origin {0}
+    TooManySessions(Origin),
+    /// maximum number of concurrent sessions reached
+    MaxSessions,
+}
+
+impl SessionRejected {
+    /// The request was valid, but a session limit was reached.
+    fn is_limit(&self) -> bool {
+        matches!(self, Self::TooManySessions(_) | Self::MaxSessions)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum SessionStatus {
     Connecting,
     Connected,
     Disconnected,
+    Rejected,
 }
 
 impl Display for SessionStatus {
@@ -60,6 +212,7 @@ impl Display for SessionStatus {
             Self::Connecting => write!(f, "Connecting"),
             Self::Connected => write!(f, "Connected"),
             Self::Disconnected => write!(f, "Disconnected"),
+            Self::Rejected => write!(f, "Rejected"),
         }
     }
 }
@@ -92,12 +245,19 @@ impl SessionState {
             status: SessionStatus::Disconnected,
         }
     }
+
+    fn rejected(token: String) -> Self {
+        Self {
+            token,
+            status: SessionStatus::Rejected,
+        }
+    }
 }
 
 impl From<SessionState> for AstarteType {
     fn from(value: SessionState) -> Self {
         match value.status {
-            SessionStatus::Connecting | SessionStatus::Connected => {
+            SessionStatus::Connecting | SessionStatus::Connected | SessionStatus::Rejected => {
                 Self::String(value.status.to_string())
             }
             SessionStatus::Disconnected => Self::Unset,
@@ -120,6 +280,80 @@ impl SessionState {
     }
 }
 
+/// Tokens of the open sessions, stored to clean up their state after a crash.
+#[derive(Debug)]
+struct SessionStore {
+    repository: FileStateRepository<Vec<String>>,
+    /// Serializes the updates of the stored tokens.
+    lock: Mutex<()>,
+}
+
+impl SessionStore {
+    fn new(store_directory: &Path) -> Self {
+        Self {
+            repository: FileStateRepository::new(store_directory, "forwarder_sessions.json"),
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn load(&self) -> Vec<String> {
+        if !self.repository.exists().await {
+            return Vec::new();
+        }
+
+        self.repository.read().await.unwrap_or_else(|err| {
+            error!("couldn't read the forwarder sessions: {err}");
+
+            Vec::new()
+        })
+    }
+
+    async fn store(&self, tokens: Vec<String>) {
+        if let Err(err) = self.repository.write(&tokens).await {
+            error!("couldn't store the forwarder sessions: {err}");
+        }
+    }
+
+    /// Record a session before its state is sent.
+    async fn add(&self, token: &str) {
+        let _guard = self.lock.lock().await;
+
+        let mut tokens = self.load().await;
+        if !tokens.iter().any(|stored| stored == token) {
+            tokens.push(token.to_string());
+            self.store(tokens).await;
+        }
+    }
+
+    /// Remove a session once its state is unset.
+    async fn remove(&self, token: &str) {
+        let _guard = self.lock.lock().await;
+
+        let mut tokens = self.load().await;
+        tokens.retain(|stored| stored != token);
+        self.store(tokens).await;
+    }
+
+    async fn clear(&self) {
+        let _guard = self.lock.lock().await;
+
+        if self.repository.exists().await {
+            if let Err(err) = self.repository.clear().await {
+                warn!("couldn't clear the forwarder sessions: {err}");
+            }
+        }
+    }
+}
+
+/// Settings of the connection of a session with Edgehog.
+#[derive(Debug, Clone)]
+struct SessionOptions {
+    limits: SessionLimits,
+    keepalive: Option<Keepalive>,
+    tls: TlsConfig,
+    proxy: ProxyConfig,
+}
+
 /// Device forwarder.
 ///
 /// It maintains a collection of tokio task handles, each one identified by a [`Key`] containing
@@ -128,16 +362,32 @@ impl SessionState {
 #[derive(Debug)]
 pub struct Forwarder<P> {
     publisher: P,
+    config: ForwarderConfig,
+    proxy: ProxyConfig,
     tasks: HashMap<SessionInfo, JoinHandle<()>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    state: RuntimeState,
+    sessions: Arc<SessionStore>,
 }
 
 impl<P> Forwarder<P> {
-    pub async fn init(publisher: P) -> Result<Self, ForwarderError>
+    pub async fn init(
+        publisher: P,
+        config: ForwarderConfig,
+        proxy: ProxyConfig,
+        rate_limiter: Option<RateLimiter>,
+        state: RuntimeState,
+        store_directory: &Path,
+    ) -> Result<Self, ForwarderError>
     where
         P: Publisher + 'static + Send + Sync,
     {
+        // The session tasks don't survive a restart, so the sessions recorded by a previous run
+        // are stale
+        let sessions = SessionStore::new(store_directory);
+        let mut stale: HashSet<String> = sessions.load().await.into_iter().collect();
+
         // unset all the existing sessions
-        // TODO: the following snippet assumes that the property has been stored, which is not the case until the [issue #346](https://github.com/edgehog-device-manager/edgehog-device-runtime/issues/346) is solved
         debug!("unsetting ForwarderSessionState property");
         for prop in publisher
             .interface_props(FORWARDER_SESSION_STATE_INTERFACE)
@@ -147,11 +397,35 @@ impl<P> Forwarder<P> {
             publisher
                 .unset(FORWARDER_SESSION_STATE_INTERFACE, &prop.path)
                 .await?;
+
+            if let Some(token) = prop
+                .path
+                .strip_prefix('/')
+                .and_then(|path| path.strip_suffix("/status"))
+            {
+                stale.remove(token);
+            }
+        }
+
+        // the properties could be missing from the SDK store, until the
+        // [issue #346](https://github.com/edgehog-device-manager/edgehog-device-runtime/issues/346)
+        // is solved, so also unset the sessions recorded in the local store
+        for token in stale {
+            info!("unsetting the stale session {token}");
+
+            SessionState::disconnected(token).send(&publisher).await?;
         }
 
+        sessions.clear().await;
+
         Ok(Self {
             publisher,
+            config,
+            proxy,
             tasks: HashMap::default(),
+            rate_limiter: rate_limiter.map(Arc::new),
+            state,
+            sessions: Arc::new(sessions),
         })
     }
 
@@ -178,18 +452,43 @@ impl<P> Forwarder<P> {
             }
         };
 
+        if let Err(err) = self.validate_session(&sinfo) {
+            error!("session rejected, {err}");
+
+            if err.is_limit() {
+                self.reject(sinfo.session_token);
+            }
+
+            return;
+        }
+
         // check if the remote terminal task is already running. if not, spawn a new task and add it
         // to the collection
-        // flag indicating whether the connection should use TLS, i.e. 'ws' or 'wss' scheme.
-        let secure = sinfo.secure;
-        let session_token = sinfo.session_token.clone();
+        let session = sinfo.clone();
         let publisher = self.publisher.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let options = SessionOptions {
+            limits: self.config.session_limits(),
+            keepalive: self.config.keepalive(),
+            tls: TlsConfig::from(&self.config.tls),
+            proxy: self.proxy.clone(),
+        };
+        let state = self.state.clone();
+        let sessions = self.sessions.clone();
         self.get_running(sinfo).or_insert_with(|| {
             info!("opening a new session");
             // spawn a new task responsible for handling the remote terminal operations
             tokio::spawn(async move {
+                if let Some(rate_limiter) = rate_limiter {
+                    if let Err(exceeded) = rate_limiter.try_acquire().await {
+                        exceeded.report(&publisher).await;
+                        return;
+                    }
+                }
+
                 if let Err(err) =
-                    Self::handle_session(edgehog_url, session_token, secure, publisher).await
+                    Self::handle_session(edgehog_url, session, options, state, sessions, publisher)
+                        .await
                 {
                     error!("session failed, {err}");
                 }
@@ -197,6 +496,67 @@ impl<P> Forwarder<P> {
         });
     }
 
+    /// Check the session can be opened from its origin.
+    ///
+    /// Sessions from different origins are isolated, so a token can only be used by one origin at
+    /// a time.
+    fn validate_session(&mut self, sinfo: &SessionInfo) -> Result<(), SessionRejected> {
+        let origin = Origin::from(sinfo);
+
+        if !self.config.is_allowed(&origin) {
+            return Err(SessionRejected::OriginNotAllowed(origin));
+        }
+
+        // remove all finished tasks
+        self.tasks.retain(|_, jh| !jh.is_finished());
+
+        if self.tasks.contains_key(sinfo) {
+            return Ok(());
+        }
+
+        if self.tasks.keys().any(|running| {
+            running.session_token == sinfo.session_token && Origin::from(running) != origin
+        }) {
+            return Err(SessionRejected::TokenInUse);
+        }
+
+        if self
+            .config
+            .max_sessions
+            .is_some_and(|max| self.tasks.len() >= max)
+        {
+            return Err(SessionRejected::MaxSessions);
+        }
+
+        if let Some(max) = self.config.max_sessions_per_origin {
+            let count = self
+                .tasks
+                .keys()
+                .filter(|running| Origin::from(*running) == origin)
+                .count();
+
+            if count >= max {
+                return Err(SessionRejected::TooManySessions(origin));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Notify Edgehog that the session won't be opened.
+    fn reject(&self, session_token: String)
+    where
+        P: Publisher + 'static + Send + Sync,
+    {
+        let publisher = self.publisher.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = SessionState::rejected(session_token).send(&publisher).await {
+                error!("couldn't send the rejected session state, {err}");
+            }
+        });
+    }
+
     /// Remove terminated sessions and return the searched one.
     fn get_running(&mut self, sinfo: SessionInfo) -> Entry<SessionInfo, JoinHandle<()>> {
         // remove all finished tasks
@@ -208,29 +568,43 @@ impl<P> Forwarder<P> {
     /// Handle remote session connection, operations and disconnection.
     async fn handle_session(
         edgehog_url: Url,
-        session_token: String,
-        secure: bool,
+        sinfo: SessionInfo,
+        options: SessionOptions,
+        state: RuntimeState,
+        sessions: Arc<SessionStore>,
         publisher: P,
     ) -> Result<(), ForwarderError>
     where
         P: Publisher + 'static + Send + Sync,
     {
+        let session_token = sinfo.session_token.clone();
+
+        sessions.add(&session_token).await;
+
         // update the session state to "Connecting"
         SessionState::connecting(session_token.clone())
             .send(&publisher)
             .await?;
 
-        if let Err(err) =
-            Self::connect(edgehog_url, session_token.clone(), secure, &publisher).await
-        {
+        #[cfg(feature = "metrics")]
+        crate::metrics::forwarder_session_opened();
+
+        if let Err(err) = Self::connect(edgehog_url, &sinfo, options, &state, &publisher).await {
             error!("failed to connect, {err}");
         }
 
+        #[cfg(feature = "metrics")]
+        crate::metrics::forwarder_session_closed();
+
+        state.remove_forwarder_session(&session_token);
+
         // unset the session state, meaning that the device correctly disconnected itself
         SessionState::disconnected(session_token.clone())
             .send(&publisher)
             .await?;
 
+        sessions.remove(&session_token).await;
+
         info!("forwarder correctly disconnected");
 
         Ok(())
@@ -238,14 +612,53 @@ impl<P> Forwarder<P> {
 
     async fn connect(
         edgehog_url: Url,
-        session_token: String,
-        secure: bool,
+        sinfo: &SessionInfo,
+        options: SessionOptions,
+        state: &RuntimeState,
         publisher: &P,
     ) -> Result<(), ForwarderError>
     where
         P: Publisher + 'static + Send + Sync,
     {
-        let mut con_manager = ConnectionsManager::connect(edgehog_url.clone(), secure).await?;
+        let session_token = &sinfo.session_token;
+
+        // the secure flag indicates whether the connection should use TLS, i.e. 'ws' or 'wss' scheme
+        let SessionOptions {
+            limits,
+            keepalive,
+            tls,
+            proxy,
+        } = options;
+
+        let mut con_manager =
+            ConnectionsManager::connect_with_proxy(edgehog_url.clone(), sinfo.secure, tls, proxy)
+                .await?
+                .with_limits(limits);
+
+        if let Some(keepalive) = keepalive {
+            con_manager = con_manager.with_keepalive(keepalive);
+        }
+
+        state.add_forwarder_session(ForwarderSession {
+            token: session_token.clone(),
+            host: sinfo.host.clone(),
+            connected_since: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            bytes_sent: 0,
+            bytes_received: 0,
+        });
+
+        // report the traffic until the session is closed
+        let cancel = CancellationToken::new();
+        let _report_guard = cancel.clone().drop_guard();
+        tokio::spawn(Self::report_traffic(
+            con_manager.traffic(),
+            session_token.clone(),
+            state.clone(),
+            cancel,
+        ));
 
         // update the session state to "Connected"
         SessionState::connected(session_token.clone())
@@ -262,6 +675,9 @@ impl<P> Forwarder<P> {
                 .send(publisher)
                 .await?;
 
+            #[cfg(feature = "metrics")]
+            crate::metrics::forwarder_reconnect();
+
             con_manager
                 .reconnect()
                 .await
@@ -275,6 +691,31 @@ impl<P> Forwarder<P> {
 
         Ok(())
     }
+
+    /// Periodically update the traffic of the session in the runtime state.
+    async fn report_traffic(
+        traffic: SessionTraffic,
+        session_token: String,
+        state: RuntimeState,
+        cancel: CancellationToken,
+    ) {
+        let mut interval = tokio::time::interval(TRAFFIC_REPORT_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+
+            let Traffic { sent, received } = traffic.get();
+
+            state.set_forwarder_traffic(&session_token, sent, received);
+        }
+
+        let Traffic { sent, received } = traffic.get();
+
+        info!("session sent {sent} and received {received} bytes");
+    }
 }
 
 #[cfg(test)]
@@ -284,6 +725,7 @@ mod tests {
     use astarte_device_sdk::store::StoredProp;
     use astarte_device_sdk::{interface::def::Ownership, Aggregation};
     use std::net::Ipv4Addr;
+    use tempdir::TempDir;
 
     #[test]
     fn test_session_status() {
@@ -291,9 +733,10 @@ mod tests {
             SessionStatus::Connected,
             SessionStatus::Connecting,
             SessionStatus::Disconnected,
+            SessionStatus::Rejected,
         ]
         .map(|ss| ss.to_string());
-        let exp_res = ["Connected", "Connecting", "Disconnected"];
+        let exp_res = ["Connected", "Connecting", "Disconnected", "Rejected"];
 
         // test display
         for (idx, el) in sstatus.into_iter().enumerate() {
@@ -334,12 +777,14 @@ mod tests {
             SessionState::connected("abcd".to_string()),
             SessionState::connecting("abcd".to_string()),
             SessionState::disconnected("abcd".to_string()),
+            SessionState::rejected("abcd".to_string()),
         ]
         .map(AstarteType::from);
         let exp_res = [
             AstarteType::String("Connected".to_string()),
             AstarteType::String("Connecting".to_string()),
             AstarteType::Unset,
+            AstarteType::String("Rejected".to_string()),
         ];
 
         for (idx, el) in sstates.into_iter().enumerate() {
@@ -368,9 +813,19 @@ mod tests {
 
     #[tokio::test]
     async fn test_init_forwarder() {
+        let dir = TempDir::new("edgehog-forwarder").unwrap();
+
         let mut publisher = MockPublisher::new();
         mock_forwarder_init(&mut publisher);
-        let f = Forwarder::init(publisher).await;
+        let f = Forwarder::init(
+            publisher,
+            ForwarderConfig::default(),
+            ProxyConfig::default(),
+            None,
+            RuntimeState::default(),
+            dir.path(),
+        )
+        .await;
 
         assert!(f.is_ok());
 
@@ -385,7 +840,15 @@ mod tests {
                 Err(astarte_device_sdk::error::Error::ConnectionTimeout)
             });
 
-        let f = Forwarder::init(publisher).await;
+        let f = Forwarder::init(
+            publisher,
+            ForwarderConfig::default(),
+            ProxyConfig::default(),
+            None,
+            RuntimeState::default(),
+            dir.path(),
+        )
+        .await;
 
         assert!(f.is_err());
 
@@ -412,7 +875,15 @@ mod tests {
             // the returned error is irrelevant, it is only necessary to the test
             .returning(|_, _| Err(astarte_device_sdk::error::Error::ConnectionTimeout));
 
-        let f = Forwarder::init(publisher).await;
+        let f = Forwarder::init(
+            publisher,
+            ForwarderConfig::default(),
+            ProxyConfig::default(),
+            None,
+            RuntimeState::default(),
+            dir.path(),
+        )
+        .await;
 
         assert!(f.is_err());
     }
@@ -439,14 +910,57 @@ mod tests {
             .returning(|_, _| Ok(()));
     }
 
+    #[tokio::test]
+    async fn test_init_stale_sessions() {
+        let dir = TempDir::new("edgehog-forwarder").unwrap();
+
+        let store = SessionStore::new(dir.path());
+        store.add("abcd").await;
+        store.add("efgh").await;
+        store.add("ijkl").await;
+        store.remove("ijkl").await;
+        assert_eq!(store.load().await, ["abcd", "efgh"]);
+
+        let mut publisher = MockPublisher::new();
+        mock_forwarder_init(&mut publisher);
+
+        // the session missing from the stored properties is unset too
+        publisher
+            .expect_send()
+            .withf(move |iface, ipath, idata| {
+                iface == FORWARDER_SESSION_STATE_INTERFACE
+                    && ipath == "/efgh/status"
+                    && idata == &AstarteType::Unset
+            })
+            .once()
+            .returning(|_, _, _| Ok(()));
+
+        let f = Forwarder::init(
+            publisher,
+            ForwarderConfig::default(),
+            ProxyConfig::default(),
+            None,
+            RuntimeState::default(),
+            dir.path(),
+        )
+        .await;
+
+        assert!(f.is_ok());
+        assert!(store.load().await.is_empty());
+    }
+
     #[tokio::test]
     async fn test_handle_sessions() {
+        let dir = TempDir::new("edgehog-forwarder").unwrap();
+
         let mut publisher = MockPublisher::new();
 
         publisher.expect_clone().returning(MockPublisher::new);
 
         let mut f = Forwarder {
             publisher,
+            config: ForwarderConfig::default(),
+            proxy: ProxyConfig::default(),
             tasks: HashMap::from([(
                 SessionInfo {
                     host: Ipv4Addr::LOCALHOST.to_string(),
@@ -456,6 +970,9 @@ mod tests {
                 },
                 tokio::spawn(async {}),
             )]),
+            rate_limiter: None,
+            state: RuntimeState::default(),
+            sessions: Arc::new(SessionStore::new(dir.path())),
         };
 
         let astarte_event = AstarteDeviceDataEvent {
@@ -478,4 +995,180 @@ mod tests {
         // the test is successful once handle_sessions terminates
         f.handle_sessions(astarte_event);
     }
+
+    fn session(host: &str, port: i32, token: &str) -> SessionInfo {
+        SessionInfo {
+            host: host.to_string(),
+            port,
+            session_token: token.to_string(),
+            secure: false,
+        }
+    }
+
+    fn forwarder_with_sessions(
+        config: ForwarderConfig,
+        sessions: &[SessionInfo],
+    ) -> Forwarder<MockPublisher> {
+        let tasks = sessions
+            .iter()
+            .map(|sinfo| (sinfo.clone(), tokio::spawn(std::future::pending())))
+            .collect();
+
+        Forwarder {
+            publisher: MockPublisher::new(),
+            config,
+            proxy: ProxyConfig::default(),
+            tasks,
+            rate_limiter: None,
+            state: RuntimeState::default(),
+            sessions: Arc::new(SessionStore::new(&std::env::temp_dir())),
+        }
+    }
+
+    #[test]
+    fn test_session_limits() {
+        let config: ForwarderConfig = toml::from_str(
+            "idle_timeout = 300\nmax_session_duration = 3600\nmax_bandwidth = 65536",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.session_limits(),
+            SessionLimits {
+                idle_timeout: Some(Duration::from_secs(300)),
+                max_duration: Some(Duration::from_secs(3600)),
+                bandwidth: Some(65536),
+            }
+        );
+        assert_eq!(
+            ForwarderConfig::default().session_limits(),
+            SessionLimits::default()
+        );
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let config: ForwarderConfig = toml::from_str("keepalive_interval = 30").unwrap();
+
+        assert_eq!(
+            config.keepalive(),
+            Some(Keepalive {
+                interval: Duration::from_secs(30),
+                max_missed: 3,
+            })
+        );
+        assert_eq!(ForwarderConfig::default().keepalive(), None);
+    }
+
+    #[test]
+    fn test_tls_config() {
+        let config: ForwarderConfig = toml::from_str(
+            r#"
+            [tls]
+            ca_bundle = "/etc/edgehog/forwarder-ca.pem"
+            [tls.client_auth]
+            cert = "/etc/edgehog/device.crt"
+            key = "/etc/edgehog/device.key"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            TlsConfig::from(&config.tls),
+            TlsConfig {
+                ca_bundle: Some(PathBuf::from("/etc/edgehog/forwarder-ca.pem")),
+                client_auth: Some(ClientAuth {
+                    cert: PathBuf::from("/etc/edgehog/device.crt"),
+                    key: PathBuf::from("/etc/edgehog/device.key"),
+                }),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_session_origin() {
+        let config = ForwarderConfig {
+            allowed_origins: Some(vec![
+                AllowedOrigin {
+                    host: "edgehog.example.com".to_string(),
+                    port: Some(443),
+                },
+                AllowedOrigin {
+                    host: "staging.example.com".to_string(),
+                    port: None,
+                },
+            ]),
+            max_sessions_per_origin: None,
+            ..Default::default()
+        };
+        let mut f = forwarder_with_sessions(config, &[]);
+
+        assert!(f
+            .validate_session(&session("edgehog.example.com", 443, "abcd"))
+            .is_ok());
+        assert!(f
+            .validate_session(&session("staging.example.com", 4000, "abcd"))
+            .is_ok());
+        assert_eq!(
+            f.validate_session(&session("edgehog.example.com", 80, "abcd")),
+            Err(SessionRejected::OriginNotAllowed(Origin {
+                host: "edgehog.example.com".to_string(),
+                port: 80
+            }))
+        );
+        assert!(f
+            .validate_session(&session("other.example.com", 443, "abcd"))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_session_isolation() {
+        let config = ForwarderConfig {
+            allowed_origins: None,
+            max_sessions_per_origin: Some(2),
+            ..Default::default()
+        };
+        let running = [
+            session("edgehog.example.com", 443, "abcd"),
+            session("edgehog.example.com", 443, "efgh"),
+            session("staging.example.com", 443, "ijkl"),
+        ];
+        let mut f = forwarder_with_sessions(config, &running);
+
+        // already running sessions are accepted
+        assert!(f.validate_session(&running[0]).is_ok());
+        // the token is used by another origin
+        assert_eq!(
+            f.validate_session(&session("staging.example.com", 443, "abcd")),
+            Err(SessionRejected::TokenInUse)
+        );
+        // too many sessions for the origin
+        assert!(matches!(
+            f.validate_session(&session("edgehog.example.com", 443, "mnop")),
+            Err(SessionRejected::TooManySessions(_))
+        ));
+        // the other origin has its own sessions count
+        assert!(f
+            .validate_session(&session("staging.example.com", 443, "mnop"))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_max_sessions() {
+        let config = ForwarderConfig {
+            max_sessions: Some(2),
+            ..Default::default()
+        };
+        let running = [
+            session("edgehog.example.com", 443, "abcd"),
+            session("staging.example.com", 443, "efgh"),
+        ];
+        let mut f = forwarder_with_sessions(config, &running);
+
+        assert!(f.validate_session(&running[1]).is_ok());
+
+        let res = f.validate_session(&session("edgehog.example.com", 443, "ijkl"));
+        assert_eq!(res, Err(SessionRejected::MaxSessions));
+        assert!(res.unwrap_err().is_limit());
+    }
 }
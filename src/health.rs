@@ -0,0 +1,295 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP endpoint reporting the health of the runtime.
+//!
+//! Liveness probes, like a systemd health check or a local monitoring agent, can `GET /health` to
+//! know if the runtime is connected to Astarte, can write its store and if the async runtime is
+//! still scheduling tasks. The endpoint responds with `200 OK` when every check passes, or with
+//! `503 Service Unavailable` otherwise, and the result of each check in a JSON body.
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::state::State;
+
+/// Maximum size of the request headers.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// Time to receive the request before the connection is closed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Response to the requests for unknown paths.
+const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Last time the runtime probe was woken up.
+static LAST_TICK: Mutex<Option<Instant>> = Mutex::new(None);
+
+const fn default_address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9110))
+}
+
+const fn default_max_tick_age() -> u64 {
+    30
+}
+
+/// Configuration of the health endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HealthConfig {
+    /// Address the endpoint listens on.
+    #[serde(default = "default_address")]
+    pub address: SocketAddr,
+    /// Seconds since the last tick of the runtime after which it's considered stalled.
+    #[serde(default = "default_max_tick_age")]
+    pub max_tick_age: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            address: default_address(),
+            max_tick_age: default_max_tick_age(),
+        }
+    }
+}
+
+/// Record a tick of the runtime, called periodically by the watchdog probe.
+pub(crate) fn tick() {
+    *LAST_TICK.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+}
+
+fn last_tick() -> Option<Instant> {
+    *LAST_TICK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Result of the health checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Health {
+    healthy: bool,
+    astarte_connected: bool,
+    store_writable: bool,
+    runtime_ticking: bool,
+}
+
+impl Health {
+    fn new(astarte_connected: bool, store_writable: bool, runtime_ticking: bool) -> Self {
+        Self {
+            healthy: astarte_connected && store_writable && runtime_ticking,
+            astarte_connected,
+            store_writable,
+            runtime_ticking,
+        }
+    }
+}
+
+/// Checks run for each request.
+struct Checks {
+    state: watch::Receiver<State>,
+    store_directory: PathBuf,
+    max_tick_age: Duration,
+}
+
+impl Checks {
+    async fn run(&self) -> Health {
+        let astarte_connected = self.state.borrow().status != "Disconnected";
+        let store_writable = is_writable(&self.store_directory).await;
+        let runtime_ticking = last_tick().is_some_and(|tick| tick.elapsed() <= self.max_tick_age);
+
+        Health::new(astarte_connected, store_writable, runtime_ticking)
+    }
+}
+
+/// Write and remove a file to check the directory is writable.
+async fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(".health");
+
+    if let Err(err) = tokio::fs::write(&probe, b"").await {
+        warn!("store directory {} not writable: {err}", dir.display());
+
+        return false;
+    }
+
+    if let Err(err) = tokio::fs::remove_file(&probe).await {
+        warn!("couldn't remove {}: {err}", probe.display());
+    }
+
+    true
+}
+
+/// Serve the health endpoint until an error occurs.
+pub(crate) async fn serve(
+    config: HealthConfig,
+    state: watch::Receiver<State>,
+    store_directory: PathBuf,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(config.address).await?;
+
+    info!("health endpoint listening on {}", config.address);
+
+    let checks = Checks {
+        state,
+        store_directory,
+        max_tick_age: Duration::from_secs(config.max_tick_age),
+    };
+
+    serve_listener(listener, checks).await
+}
+
+async fn serve_listener(listener: TcpListener, checks: Checks) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+
+        debug!("health request from {addr}");
+
+        // Requests are handled one at a time, the checks are cheap
+        if let Err(err) = handle(stream, &checks).await {
+            debug!("couldn't respond to {addr}: {err}");
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, checks: &Checks) -> std::io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"))??;
+
+    let mut parts = request.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/health")) => {
+            let health = checks.run().await;
+            let body = serde_json::to_string(&health).unwrap_or_default();
+            let status = if health.healthy {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+
+            format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => NOT_FOUND.to_string(),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read the request headers and return the request line.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "request too long",
+            ));
+        }
+
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+
+    Ok(request.lines().next().unwrap_or_default().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::state::RuntimeState;
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        response
+    }
+
+    #[test]
+    fn health_result() {
+        assert!(Health::new(true, true, true).healthy);
+        assert!(!Health::new(false, true, true).healthy);
+        assert!(!Health::new(true, false, true).healthy);
+        assert!(!Health::new(true, true, false).healthy);
+
+        assert_eq!(
+            serde_json::to_string(&Health::new(true, true, false)).unwrap(),
+            r#"{"healthy":false,"astarte_connected":true,"store_writable":true,"runtime_ticking":false}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_health() {
+        let dir = TempDir::new("edgehog-health").unwrap();
+        let state = RuntimeState::default();
+        state.set_status("Running");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let checks = Checks {
+            state: state.subscribe(),
+            store_directory: dir.path().to_path_buf(),
+            max_tick_age: Duration::from_secs(30),
+        };
+
+        tokio::spawn(serve_listener(listener, checks));
+
+        tick();
+
+        let response = get(addr, "/health").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with(r#""runtime_ticking":true}"#));
+
+        state.set_status("Disconnected");
+
+        let response = get(addr, "/health").await;
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.contains(r#""astarte_connected":false"#));
+
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,307 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Monitor the integrity of critical files on the device.
+//!
+//! The SHA-256 hashes of the configured files, or of the files inside the configured directories,
+//! are compared periodically with the ones stored in the store directory. An alert is published
+//! for every file that was changed, added or removed, then the new hashes are stored.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::data::Publisher;
+use crate::repository::file_state_repository::FileStateRepository;
+use crate::repository::StateRepository;
+
+const INTEGRITY_ALERT_INTERFACE: &str = "io.edgehog.devicemanager.IntegrityAlert";
+
+const fn default_interval() -> u64 {
+    3600
+}
+
+/// Configuration of the integrity monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct IntegrityConfig {
+    /// Files and directories to monitor, directories are walked recursively.
+    pub paths: Vec<PathBuf>,
+    /// Seconds between two checks.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+/// Hex encoded hash of each monitored file.
+type Hashes = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct IntegrityAlert {
+    pub path: String,
+    /// Hash stored by the previous check, empty if the file was added.
+    pub expected_hash: String,
+    /// Current hash, empty if the file was removed.
+    pub actual_hash: String,
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn hash_path(path: &Path, hashes: &mut Hashes) {
+    let res = if path.is_dir() {
+        std::fs::read_dir(path).map(|entries| {
+            for entry in entries.flatten() {
+                let path = entry.path();
+
+                // don't follow the links to directories, they could create loops
+                if path.is_symlink() && path.is_dir() {
+                    continue;
+                }
+
+                hash_path(&path, hashes);
+            }
+        })
+    } else {
+        hash_file(path).map(|hash| {
+            hashes.insert(path.display().to_string(), hash);
+        })
+    };
+
+    if let Err(err) = res {
+        // a missing file is reported as removed
+        debug!("couldn't hash {}: {err}", path.display());
+    }
+}
+
+/// Hash all the files in the paths.
+fn hash_paths(paths: &[PathBuf]) -> Hashes {
+    let mut hashes = Hashes::new();
+
+    for path in paths {
+        hash_path(path, &mut hashes);
+    }
+
+    hashes
+}
+
+/// Compare the hashes, returning an alert for every file that differs.
+fn compare(expected: &Hashes, actual: &Hashes) -> Vec<IntegrityAlert> {
+    let changed = expected.iter().filter_map(|(path, expected_hash)| {
+        let actual_hash = actual.get(path).cloned().unwrap_or_default();
+
+        (*expected_hash != actual_hash).then(|| IntegrityAlert {
+            path: path.clone(),
+            expected_hash: expected_hash.clone(),
+            actual_hash,
+        })
+    });
+
+    let added = actual
+        .iter()
+        .filter(|(path, _)| !expected.contains_key(*path))
+        .map(|(path, actual_hash)| IntegrityAlert {
+            path: path.clone(),
+            expected_hash: String::new(),
+            actual_hash: actual_hash.clone(),
+        });
+
+    changed.chain(added).collect()
+}
+
+/// Periodically checks the hashes of the monitored files.
+pub(crate) struct IntegrityMonitor<P> {
+    config: IntegrityConfig,
+    publisher: P,
+    /// Hashes of the previous check.
+    hashes: FileStateRepository<Hashes>,
+}
+
+impl<P> IntegrityMonitor<P>
+where
+    P: Publisher + Send + Sync + 'static,
+{
+    pub(crate) fn new(config: IntegrityConfig, store_directory: &Path, publisher: P) -> Self {
+        Self {
+            config,
+            publisher,
+            hashes: FileStateRepository::new(store_directory, "integrity.json"),
+        }
+    }
+
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+
+        loop {
+            interval.tick().await;
+
+            self.check().await;
+        }
+    }
+
+    async fn check(&self) {
+        let paths = self.config.paths.clone();
+        let actual = match tokio::task::spawn_blocking(move || hash_paths(&paths)).await {
+            Ok(hashes) => hashes,
+            Err(err) => {
+                error!("couldn't hash the monitored files: {err}");
+
+                return;
+            }
+        };
+
+        if !self.hashes.exists().await {
+            info!("storing the hashes of {} monitored files", actual.len());
+
+            self.store(&actual).await;
+
+            return;
+        }
+
+        let expected = match self.hashes.read().await {
+            Ok(hashes) => hashes,
+            Err(err) => {
+                error!("couldn't read the stored hashes: {err}");
+
+                return;
+            }
+        };
+
+        let alerts = compare(&expected, &actual);
+        if alerts.is_empty() {
+            debug!("monitored files unchanged");
+
+            return;
+        }
+
+        for alert in alerts {
+            warn!("integrity of {} changed", alert.path);
+
+            if let Err(err) = self
+                .publisher
+                .send_object(INTEGRITY_ALERT_INTERFACE, "/alert", alert)
+                .await
+            {
+                error!("couldn't send integrity alert: {err}");
+            }
+        }
+
+        self.store(&actual).await;
+    }
+
+    async fn store(&self, hashes: &Hashes) {
+        if let Err(err) = self.hashes.write(hashes).await {
+            error!("couldn't store the hashes of the monitored files: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::data::tests::MockPublisher;
+
+    #[test]
+    fn compare_hashes() {
+        let expected = Hashes::from([
+            ("/etc/a".to_string(), "aaaa".to_string()),
+            ("/etc/b".to_string(), "bbbb".to_string()),
+            ("/etc/c".to_string(), "cccc".to_string()),
+        ]);
+        let actual = Hashes::from([
+            ("/etc/a".to_string(), "aaaa".to_string()),
+            ("/etc/b".to_string(), "ffff".to_string()),
+            ("/etc/d".to_string(), "dddd".to_string()),
+        ]);
+
+        let alerts = compare(&expected, &actual);
+
+        assert_eq!(
+            alerts,
+            [
+                IntegrityAlert {
+                    path: "/etc/b".to_string(),
+                    expected_hash: "bbbb".to_string(),
+                    actual_hash: "ffff".to_string(),
+                },
+                IntegrityAlert {
+                    path: "/etc/c".to_string(),
+                    expected_hash: "cccc".to_string(),
+                    actual_hash: String::new(),
+                },
+                IntegrityAlert {
+                    path: "/etc/d".to_string(),
+                    expected_hash: String::new(),
+                    actual_hash: "dddd".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn alert_on_change() {
+        let dir = TempDir::new("edgehog-integrity").unwrap();
+        let store = TempDir::new("edgehog-integrity-store").unwrap();
+        let file = dir.path().join("config.toml");
+        tokio::fs::write(&file, "a = 1").await.unwrap();
+
+        let config = IntegrityConfig {
+            paths: vec![dir.path().to_path_buf()],
+            interval: default_interval(),
+        };
+
+        // the first check stores the hashes
+        let monitor = IntegrityMonitor::new(config.clone(), store.path(), MockPublisher::new());
+        monitor.check().await;
+
+        tokio::fs::write(&file, "a = 2").await.unwrap();
+
+        let mut publisher = MockPublisher::new();
+        let path = file.display().to_string();
+        publisher
+            .expect_send_object()
+            .withf(move |iface: &str, ipath: &str, alert: &IntegrityAlert| {
+                iface == INTEGRITY_ALERT_INTERFACE
+                    && ipath == "/alert"
+                    && alert.path == path
+                    && !alert.expected_hash.is_empty()
+                    && alert.expected_hash != alert.actual_hash
+            })
+            .times(1)
+            .returning(|_: &str, _: &str, _: IntegrityAlert| Ok(()));
+
+        let monitor = IntegrityMonitor::new(config, store.path(), publisher);
+        monitor.check().await;
+        // the new hashes are stored, so the change is reported once
+        monitor.check().await;
+    }
+}
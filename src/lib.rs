@@ -18,6 +18,7 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -25,27 +26,55 @@ use astarte_device_sdk::types::AstarteType;
 use astarte_device_sdk::{Aggregation, AstarteDeviceDataEvent};
 use log::{debug, error, info, warn};
 use serde::Deserialize;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::mpsc::{channel, Receiver};
 use tokio::sync::RwLock;
+use tokio_util::task::TaskTracker;
 
+use crate::data::connection::ConnectionState;
 use crate::data::{Publisher, Subscriber};
 use crate::error::DeviceManagerError;
+use crate::event_queue::{EventReceiver, EventSender, OverflowPolicy};
 use crate::ota::ota_handler::OtaHandler;
+use crate::ota::update_polling::UpdatePoller;
+use crate::supervisor::RestartPolicy;
+use crate::telemetry::overrides::{OverridePublisher, TelemetryOverrides, DIAGNOSTICS_INTERFACE};
 use crate::telemetry::{TelemetryMessage, TelemetryPayload};
 
 mod commands;
+mod connectivity;
+pub mod crash_report;
 pub mod data;
+mod dbus_service;
 mod device;
 pub mod error;
+mod event_queue;
+pub mod executor;
 #[cfg(feature = "forwarder")]
 mod forwarder;
+mod health;
+mod integrity;
 mod led_behavior;
+mod log_forwarding;
+pub mod logging;
+#[cfg(feature = "systemd")]
+mod logs;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod ota;
 mod power_management;
+pub mod proxy;
+mod rate_limit;
 pub mod repository;
+mod scheduler;
+mod shutdown;
+mod state;
+mod storage_check;
+mod supervisor;
 #[cfg(feature = "systemd")]
 pub mod systemd_wrapper;
 mod telemetry;
+pub mod timing;
+mod watchdog;
 
 const MAX_OTA_OPERATION: usize = 2;
 
@@ -64,10 +93,54 @@ pub struct DeviceManagerOptions {
     pub astarte_device_sdk: Option<data::astarte_device_sdk_lib::AstarteDeviceSdkConfigOptions>,
     #[cfg(feature = "message-hub")]
     pub astarte_message_hub: Option<data::astarte_message_hub_node::AstarteMessageHubOptions>,
-    pub interfaces_directory: PathBuf,
+    pub interfaces_directory: data::interface_directories::InterfaceDirectories,
     pub store_directory: PathBuf,
     pub download_directory: PathBuf,
     pub telemetry_config: Option<Vec<telemetry::TelemetryInterfaceConfig>>,
+    pub telemetry_overrides_file: Option<PathBuf>,
+    #[serde(default)]
+    pub attributes: HashMap<String, telemetry::device_attributes::AttributeValue>,
+    #[serde(default)]
+    pub rate_limits: rate_limit::RateLimitsConfig,
+    pub update_polling: Option<ota::update_polling::UpdatePollingConfig>,
+    pub ota_self_test: Option<ota::self_test::SelfTestConfig>,
+    #[serde(default)]
+    pub ota_download: ota::DownloadConfig,
+    pub geolocation: Option<telemetry::geolocation::GeolocationConfig>,
+    #[serde(default)]
+    pub property_cache: data::property_cache::PropertyCacheConfig,
+    #[serde(default)]
+    pub offline_queue: data::offline_queue::OfflineQueueConfig,
+    #[serde(default)]
+    pub decimation: Vec<data::decimation::DecimationConfig>,
+    #[serde(default)]
+    pub watchdog: watchdog::WatchdogConfig,
+    pub health: Option<health::HealthConfig>,
+    #[serde(default)]
+    pub logging: logging::LoggingConfig,
+    #[serde(default)]
+    pub secondary_connections: Vec<data::multi_realm::SecondaryConnectionConfig>,
+    #[serde(default)]
+    pub custom_commands: HashMap<String, commands::CustomCommandConfig>,
+    #[serde(default)]
+    pub factory_reset: commands::FactoryResetConfig,
+    #[serde(default)]
+    pub shutdown: shutdown::ShutdownConfig,
+    pub dbus_service: Option<dbus_service::DbusServiceConfig>,
+    pub connectivity: Option<connectivity::ConnectivityConfig>,
+    #[serde(default)]
+    pub proxy: proxy::ProxyConfig,
+    pub integrity: Option<integrity::IntegrityConfig>,
+    pub log_forwarding: Option<log_forwarding::LogForwardingConfig>,
+    #[serde(default)]
+    pub crash_reports: crash_report::CrashReportConfig,
+    #[cfg(feature = "systemd")]
+    pub logs: Option<logs::LogsConfig>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<metrics::MetricsConfig>,
+    #[cfg(feature = "forwarder")]
+    #[serde(default)]
+    pub forwarder: forwarder::ForwarderConfig,
 }
 
 #[derive(Debug)]
@@ -75,11 +148,24 @@ pub struct DeviceManager<T: Publisher + Clone, U: Subscriber> {
     publisher: T,
     subscriber: U,
     // We pass all Astarte event through a channel, to avoid blocking the main loop
-    ota_event_channel: Sender<AstarteDeviceDataEvent>,
-    data_event_channel: Sender<AstarteDeviceDataEvent>,
-    telemetry: Arc<RwLock<telemetry::Telemetry>>,
+    ota_event_channel: EventSender<AstarteDeviceDataEvent>,
+    data_event_channel: EventSender<AstarteDeviceDataEvent>,
+    telemetry_config_channel: EventSender<AstarteDeviceDataEvent>,
     #[cfg(feature = "forwarder")]
-    forwarder: forwarder::Forwarder<T>,
+    forwarder_channel: EventSender<AstarteDeviceDataEvent>,
+    telemetry: Arc<RwLock<telemetry::Telemetry>>,
+    telemetry_overrides: Arc<TelemetryOverrides>,
+    connection: ConnectionState,
+    watchdog: watchdog::Watchdog,
+    ota_handler: Arc<OtaHandler>,
+    scheduler: Arc<scheduler::Scheduler<T>>,
+    supervisor: supervisor::Supervisor<T>,
+    state: state::RuntimeState,
+    storage_status: Vec<(&'static str, storage_check::StorageStatus)>,
+    attributes: HashMap<String, telemetry::device_attributes::AttributeValue>,
+    // In-flight operations awaited on shutdown
+    tasks: TaskTracker,
+    shutdown: shutdown::ShutdownConfig,
 }
 
 impl<P, S> DeviceManager<P, S>
@@ -91,18 +177,163 @@ where
         opts: DeviceManagerOptions,
         publisher: P,
         subscriber: S,
+        connection: ConnectionState,
     ) -> Result<Self, DeviceManagerError> {
         #[cfg(feature = "systemd")]
         systemd_wrapper::systemd_notify_status("Initializing");
 
         info!("Starting");
 
-        let ota_handler = OtaHandler::new(&opts).await?;
+        let state = state::RuntimeState::default();
+
+        if let Some(config) = opts.dbus_service.clone() {
+            let rx = state.subscribe();
+
+            tokio::spawn(async move {
+                if let Err(err) = dbus_service::serve(config, rx).await {
+                    error!("D-Bus service error: {err}");
+                }
+            });
+        }
+
+        if let Some(config) = opts.health.clone() {
+            let rx = state.subscribe();
+            let store_directory = opts.store_directory.clone();
+
+            tokio::spawn(async move {
+                if let Err(err) = health::serve(config, rx, store_directory).await {
+                    error!("health endpoint error: {err}");
+                }
+            });
+        }
+
+        let storage_status = storage_check::check_directories(&opts);
+
+        let connectivity = match opts.connectivity.clone() {
+            Some(config) => {
+                let monitor = connectivity::ConnectivityMonitor::new(config, publisher.clone());
+                let watch = monitor.watch();
+
+                tokio::spawn(monitor.run());
+
+                watch
+            }
+            None => connectivity::ConnectivityWatch::default(),
+        };
+
+        let ota_handler = Arc::new(OtaHandler::new(&opts, state.clone(), connectivity).await?);
+
+        let http_client = opts.proxy.client()?;
 
         ota_handler.ensure_pending_ota_is_done(&publisher).await?;
 
-        let (ota_tx, ota_rx) = channel(MAX_OTA_OPERATION);
-        let (data_tx, data_rx) = channel(32);
+        let telemetry_overrides = match &opts.telemetry_overrides_file {
+            Some(path) => TelemetryOverrides::read(path).await?,
+            None => TelemetryOverrides::default(),
+        };
+        let telemetry_overrides = Arc::new(telemetry_overrides);
+
+        let supervisor = supervisor::Supervisor::new(publisher.clone());
+
+        if let Some(config) = opts.update_polling.clone() {
+            let store_directory = opts.store_directory.clone();
+            let publisher = publisher.clone();
+            let ota_handler = ota_handler.clone();
+            let connection = connection.clone();
+            let client = http_client.clone();
+
+            supervisor.spawn("update_polling", RestartPolicy::OnFailure, move || {
+                UpdatePoller::new(
+                    config.clone(),
+                    &store_directory,
+                    publisher.clone(),
+                    ota_handler.clone(),
+                    connection.clone(),
+                    client.clone(),
+                )
+                .run()
+            });
+        }
+
+        let scheduler = Arc::new(scheduler::Scheduler::new(
+            &opts.store_directory,
+            publisher.clone(),
+        ));
+
+        {
+            let scheduler = scheduler.clone();
+            let ota_handler = ota_handler.clone();
+
+            supervisor.spawn("scheduler", RestartPolicy::Always, move || {
+                scheduler.clone().run(ota_handler.clone())
+            });
+        }
+
+        if let Some(config) = opts.integrity.clone() {
+            let store_directory = opts.store_directory.clone();
+            let publisher = publisher.clone();
+
+            supervisor.spawn("integrity", RestartPolicy::OnFailure, move || {
+                integrity::IntegrityMonitor::new(
+                    config.clone(),
+                    &store_directory,
+                    publisher.clone(),
+                )
+                .run()
+            });
+        }
+
+        if let Some(config) = opts.log_forwarding.clone() {
+            let publisher = publisher.clone();
+
+            // the captured records can be received only once
+            supervisor.spawn("log_forwarding", RestartPolicy::Never, move || {
+                log_forwarding::run(config.clone(), publisher.clone())
+            });
+        }
+
+        #[cfg(feature = "systemd")]
+        if let Some(config) = opts.logs.clone() {
+            let store_directory = opts.store_directory.clone();
+            let publisher = publisher.clone();
+            let client = http_client.clone();
+
+            supervisor.spawn("logs", RestartPolicy::OnFailure, move || {
+                logs::LogsCollector::new(
+                    config.clone(),
+                    &store_directory,
+                    client.clone(),
+                    publisher.clone(),
+                )
+                .run()
+            });
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(config) = opts.metrics.clone() {
+            supervisor.spawn("metrics", RestartPolicy::OnFailure, move || {
+                metrics::run(config.clone())
+            });
+        }
+
+        if let Some(config) = opts.geolocation.clone() {
+            let publisher = OverridePublisher::new(publisher.clone(), telemetry_overrides.clone());
+            let client = http_client.clone();
+
+            supervisor.spawn("geolocation", RestartPolicy::OnFailure, move || {
+                telemetry::geolocation::Geolocator::new(&config, client.clone(), publisher.clone())
+                    .run()
+            });
+        }
+
+        let (ota_tx, ota_rx) =
+            event_queue::channel("ota", MAX_OTA_OPERATION, OverflowPolicy::Block);
+        let (data_tx, data_rx) = event_queue::channel("commands", 32, OverflowPolicy::Block);
+        let (telemetry_config_tx, telemetry_config_rx) =
+            event_queue::channel("telemetry", 32, OverflowPolicy::DropOldest);
+        #[cfg(feature = "forwarder")]
+        let (forwarder_tx, forwarder_rx) =
+            event_queue::channel("forwarder", 32, OverflowPolicy::Block);
 
         let (telemetry_tx, telemetry_rx) = channel(32);
 
@@ -110,39 +341,136 @@ where
             opts.telemetry_config,
             telemetry_tx,
             opts.store_directory.clone(),
+            state.clone(),
         )
         .await;
 
         #[cfg(feature = "forwarder")]
         // Initialize the forwarder instance
-        let forwarder = forwarder::Forwarder::init(publisher.clone()).await?;
+        let forwarder = forwarder::Forwarder::init(
+            publisher.clone(),
+            opts.forwarder,
+            opts.proxy.forwarder(),
+            opts.rate_limits.forwarder.map(|config| {
+                rate_limit::RateLimiter::new(&opts.store_directory, "forwarder", config)
+            }),
+            state.clone(),
+            &opts.store_directory,
+        )
+        .await?;
+
+        let watchdog = watchdog::Watchdog::new(&opts.watchdog);
+        let custom_commands = Arc::new(commands::CustomCommands::new(opts.custom_commands));
+        let factory_reset = Arc::new(commands::FactoryReset::new(
+            opts.factory_reset,
+            &opts.store_directory,
+            &opts.download_directory,
+        ));
 
         let device_runtime = Self {
             publisher,
             subscriber,
             ota_event_channel: ota_tx,
             data_event_channel: data_tx,
-            telemetry: Arc::new(RwLock::new(tel)),
+            telemetry_config_channel: telemetry_config_tx,
             #[cfg(feature = "forwarder")]
-            forwarder,
+            forwarder_channel: forwarder_tx,
+            telemetry: Arc::new(RwLock::new(tel)),
+            telemetry_overrides,
+            connection,
+            watchdog,
+            ota_handler,
+            scheduler,
+            supervisor,
+            state,
+            storage_status,
+            attributes: opts.attributes,
+            tasks: TaskTracker::new(),
+            shutdown: opts.shutdown,
         };
 
-        device_runtime.init_ota_event(ota_handler, ota_rx);
-        device_runtime.init_data_event(data_rx);
+        device_runtime.init_ota_event(ota_rx);
+        device_runtime.init_data_event(data_rx, custom_commands, factory_reset);
+        device_runtime.init_telemetry_config_event(telemetry_config_rx);
         device_runtime.init_telemetry_event(telemetry_rx);
+        #[cfg(feature = "forwarder")]
+        device_runtime.init_forwarder_event(forwarder_rx, forwarder);
         Ok(device_runtime)
     }
 
-    fn init_ota_event(
+    fn init_ota_event(&self, ota_rx: EventReceiver<AstarteDeviceDataEvent>) {
+        let publisher = self.publisher.clone();
+        let ota_handler = self.ota_handler.clone();
+        let scheduler = self.scheduler.clone();
+        let tasks = self.tasks.clone();
+        // The receiver is shared with the restarted loops
+        let ota_rx = Arc::new(tokio::sync::Mutex::new(ota_rx));
+
+        self.supervisor
+            .spawn("ota", RestartPolicy::OnFailure, move || {
+                tasks.track_future(Self::handle_ota_events(
+                    ota_rx.clone(),
+                    publisher.clone(),
+                    ota_handler.clone(),
+                    scheduler.clone(),
+                    tasks.clone(),
+                ))
+            });
+    }
+
+    async fn handle_ota_events(
+        ota_rx: Arc<tokio::sync::Mutex<EventReceiver<AstarteDeviceDataEvent>>>,
+        publisher: P,
+        ota_handler: Arc<OtaHandler>,
+        scheduler: Arc<scheduler::Scheduler<P>>,
+        tasks: TaskTracker,
+    ) {
+        let mut ota_rx = ota_rx.lock().await;
+
+        while let Some(data_event) = ota_rx.recv().await {
+            match (
+                data_event
+                    .path
+                    .trim_matches('/')
+                    .split('/')
+                    .collect::<Vec<&str>>()
+                    .as_slice(),
+                &data_event.data,
+            ) {
+                (["request"], Aggregation::Object(data)) => {
+                    if scheduler.handle_ota_request(data).await {
+                        continue;
+                    }
+
+                    let publisher = publisher.clone();
+                    let data = data.clone();
+                    let ota_handler = ota_handler.clone();
+                    tasks.spawn(async move {
+                        if let Err(err) = ota_handler.ota_event(&publisher, data).await {
+                            error!("ota error {err}");
+                        }
+                    });
+                }
+                _ => {
+                    warn!("Receiving data from an unknown path/interface: {data_event:?}");
+                }
+            }
+        }
+    }
+
+    fn init_data_event(
         &self,
-        ota_handler: OtaHandler,
-        mut ota_rx: Receiver<AstarteDeviceDataEvent>,
+        mut data_rx: EventReceiver<AstarteDeviceDataEvent>,
+        custom_commands: Arc<commands::CustomCommands>,
+        factory_reset: Arc<commands::FactoryReset>,
     ) {
         let publisher = self.publisher.clone();
-        let ota_handler = Arc::new(ota_handler);
-        tokio::spawn(async move {
-            while let Some(data_event) = ota_rx.recv().await {
+        let scheduler = self.scheduler.clone();
+        let tasks = self.tasks.clone();
+        self.tasks.spawn(async move {
+            while let Some(data_event) = data_rx.recv().await {
                 match (
+                    data_event.interface.as_str(),
                     data_event
                         .path
                         .trim_matches('/')
@@ -151,16 +479,55 @@ where
                         .as_slice(),
                     &data_event.data,
                 ) {
-                    (["request"], Aggregation::Object(data)) => {
+                    (
+                        "io.edgehog.devicemanager.Commands",
+                        ["request"],
+                        Aggregation::Individual(AstarteType::String(command)),
+                    ) => commands::execute_command(&publisher, command).await,
+                    (
+                        "io.edgehog.devicemanager.ScheduledCommandRequest",
+                        ["request"],
+                        Aggregation::Object(data),
+                    ) => scheduler.handle_command_request(data.clone()).await,
+                    (
+                        "io.edgehog.devicemanager.CustomCommandRequest",
+                        ["request"],
+                        Aggregation::Object(data),
+                    ) => {
                         let publisher = publisher.clone();
+                        let custom_commands = custom_commands.clone();
                         let data = data.clone();
-                        let ota_handler = ota_handler.clone();
-                        tokio::spawn(async move {
-                            if let Err(err) = ota_handler.ota_event(&publisher, data).await {
-                                error!("ota error {err}");
-                            }
+                        tasks.spawn(async move {
+                            custom_commands.handle_request(&publisher, data).await;
                         });
                     }
+                    (
+                        "io.edgehog.devicemanager.FactoryResetRequest",
+                        ["request"],
+                        Aggregation::Object(data),
+                    ) => {
+                        let publisher = publisher.clone();
+                        let factory_reset = factory_reset.clone();
+                        let data = data.clone();
+                        tasks.spawn(async move {
+                            factory_reset.handle_request(&publisher, data).await;
+                        });
+                    }
+                    (
+                        "io.edgehog.devicemanager.LogLevelRequest",
+                        ["request"],
+                        Aggregation::Object(data),
+                    ) => logging::handle_request(data.clone()),
+                    (
+                        "io.edgehog.devicemanager.LedBehavior",
+                        [led_id, "behavior"],
+                        Aggregation::Individual(AstarteType::String(behavior)),
+                    ) => {
+                        tokio::spawn(led_behavior::set_behavior(
+                            led_id.to_string(),
+                            behavior.clone(),
+                        ));
+                    }
                     _ => {
                         warn!("Receiving data from an unknown path/interface: {data_event:?}");
                     }
@@ -169,12 +536,14 @@ where
         });
     }
 
-    fn init_data_event(&self, mut data_rx: Receiver<AstarteDeviceDataEvent>) {
+    fn init_telemetry_config_event(
+        &self,
+        mut telemetry_config_rx: EventReceiver<AstarteDeviceDataEvent>,
+    ) {
         let self_telemetry = self.telemetry.clone();
-        tokio::spawn(async move {
-            while let Some(data_event) = data_rx.recv().await {
+        self.tasks.spawn(async move {
+            while let Some(data_event) = telemetry_config_rx.recv().await {
                 match (
-                    data_event.interface.as_str(),
                     data_event
                         .path
                         .trim_matches('/')
@@ -183,32 +552,13 @@ where
                         .as_slice(),
                     &data_event.data,
                 ) {
-                    (
-                        "io.edgehog.devicemanager.Commands",
-                        ["request"],
-                        Aggregation::Individual(AstarteType::String(command)),
-                    ) => commands::execute_command(command).await,
-                    (
-                        "io.edgehog.devicemanager.config.Telemetry",
-                        ["request", interface_name, endpoint],
-                        Aggregation::Individual(data),
-                    ) => {
+                    (["request", interface_name, endpoint], Aggregation::Individual(data)) => {
                         self_telemetry
                             .write()
                             .await
                             .telemetry_config_event(interface_name, endpoint, data)
                             .await;
                     }
-                    (
-                        "io.edgehog.devicemanager.LedBehavior",
-                        [led_id, "behavior"],
-                        Aggregation::Individual(AstarteType::String(behavior)),
-                    ) => {
-                        tokio::spawn(led_behavior::set_behavior(
-                            led_id.to_string(),
-                            behavior.clone(),
-                        ));
-                    }
                     _ => {
                         warn!("Receiving data from an unknown path/interface: {data_event:?}");
                     }
@@ -217,8 +567,34 @@ where
         });
     }
 
+    #[cfg(feature = "forwarder")]
+    fn init_forwarder_event(
+        &self,
+        forwarder_rx: EventReceiver<AstarteDeviceDataEvent>,
+        forwarder: forwarder::Forwarder<P>,
+    ) {
+        // The receiver and the running sessions are shared with the restarted loops
+        let forwarder_rx = Arc::new(tokio::sync::Mutex::new(forwarder_rx));
+        let forwarder = Arc::new(tokio::sync::Mutex::new(forwarder));
+
+        self.supervisor
+            .spawn("forwarder", RestartPolicy::OnFailure, move || {
+                let forwarder_rx = forwarder_rx.clone();
+                let forwarder = forwarder.clone();
+
+                async move {
+                    let mut forwarder_rx = forwarder_rx.lock().await;
+
+                    while let Some(data_event) = forwarder_rx.recv().await {
+                        forwarder.lock().await.handle_sessions(data_event);
+                    }
+                }
+            });
+    }
+
     fn init_telemetry_event(&self, mut telemetry_rx: Receiver<TelemetryMessage>) {
-        let publisher = self.publisher.clone();
+        let publisher =
+            OverridePublisher::new(self.publisher.clone(), self.telemetry_overrides.clone());
         tokio::spawn(async move {
             while let Some(msg) = telemetry_rx.recv().await {
                 Self::send_telemetry(&publisher, msg).await;
@@ -228,48 +604,148 @@ where
 
     pub async fn run(mut self) -> Result<(), DeviceManagerError> {
         #[cfg(feature = "systemd")]
-        systemd_wrapper::systemd_notify_status("Running");
+        systemd_wrapper::systemd_notify_ready_status("Running");
+        self.state.set_status("Running");
 
-        let tel_clone = self.telemetry.clone();
-        tokio::task::spawn(async move {
-            tel_clone.write().await.run_telemetry().await;
-        });
+        let telemetry = self.telemetry.clone();
+        self.supervisor
+            .spawn("telemetry", RestartPolicy::OnFailure, move || {
+                let telemetry = telemetry.clone();
+
+                async move {
+                    telemetry.write().await.run_telemetry().await;
+                }
+            });
+
+        self.watchdog.spawn_probe();
+
+        let mut signals = shutdown::Signals::new()?;
+        let mut heartbeat = watchdog::Heartbeat::new();
+
+        loop {
+            // The subscribers read the events from a channel, so waiting on them is cancel safe
+            let data_event = tokio::select! {
+                _ = signals.recv() => break,
+                _ = heartbeat.tick() => {
+                    heartbeat.notify();
+
+                    continue;
+                }
+                data_event = self.subscriber.on_event() => data_event,
+            };
+
+            let Some(data_event) = data_event else {
+                error!("publisher closed, device disconnected");
+
+                #[cfg(feature = "systemd")]
+                systemd_wrapper::systemd_notify_status("Disconnected");
+                self.state.set_status("Disconnected");
+
+                self.subscriber.exit().await?;
+
+                return Err(DeviceManagerError::Disconnected);
+            };
 
-        while let Some(data_event) = self.subscriber.on_event().await {
             match data_event {
                 Ok(data_event) => {
                     debug!("incoming: {:?}", data_event);
 
-                    match data_event.interface.as_str() {
+                    self.connection.set_connected();
+
+                    let res = match data_event.interface.as_str() {
                         "io.edgehog.devicemanager.OTARequest" => {
-                            self.ota_event_channel.send(data_event).await.unwrap()
+                            self.ota_event_channel.send(data_event).await
+                        }
+                        "io.edgehog.devicemanager.config.Telemetry" => {
+                            self.telemetry_config_channel.send(data_event).await
                         }
                         #[cfg(feature = "forwarder")]
                         "io.edgehog.devicemanager.ForwarderSessionRequest" => {
-                            self.forwarder.handle_sessions(data_event)
-                        }
-                        _ => {
-                            self.data_event_channel.send(data_event).await.unwrap();
+                            self.forwarder_channel.send(data_event).await
                         }
+                        _ => self.data_event_channel.send(data_event).await,
+                    };
+
+                    if let Err(err) = res {
+                        error!("couldn't dispatch the event: {err}");
                     }
                 }
-                Err(err) => error!("{:?}", err),
+                Err(err) => {
+                    // The errors are returned when the connection to Astarte is lost
+                    self.connection.set_disconnected();
+
+                    error!("{:?}", err);
+                }
             }
         }
 
-        error!("publisher closed, device disconnected");
+        self.graceful_shutdown().await
+    }
+
+    /// Stop handling new events and wait for the in-flight operations before disconnecting.
+    async fn graceful_shutdown(self) -> Result<(), DeviceManagerError> {
+        #[cfg(feature = "systemd")]
+        systemd_wrapper::systemd_notify_stopping_status("Shutting down");
+        self.state.set_status("Shutting down");
 
-        self.subscriber.exit().await?;
+        info!("shutting down");
 
-        Err(DeviceManagerError::Disconnected)
+        let start = tokio::time::Instant::now();
+
+        let Self {
+            subscriber,
+            ota_event_channel,
+            data_event_channel,
+            telemetry_config_channel,
+            ota_handler,
+            publisher,
+            tasks,
+            shutdown,
+            ..
+        } = self;
+
+        // Closing the channels ends the event loops once the queued events are handled
+        drop(ota_event_channel);
+        drop(data_event_channel);
+        drop(telemetry_config_channel);
+
+        // The cancelled update is reported before the drain, since it sends no further events
+        if let Err(err) = ota_handler.cancel_on_shutdown(&publisher).await {
+            warn!("couldn't publish the cancellation of the OTA update: {err}");
+        }
+
+        shutdown::drain(&tasks, &shutdown).await;
+
+        subscriber.exit().await?;
+
+        info!("shutdown completed in {}ms", start.elapsed().as_millis());
+
+        Ok(())
+    }
+
+    /// Log and publish the timing of the startup phases.
+    pub async fn report_startup(&self, timer: &timing::StartupTimer) {
+        timer.report(&self.publisher).await;
     }
 
     pub async fn init(&self) -> Result<(), DeviceManagerError> {
         #[cfg(feature = "systemd")]
         systemd_wrapper::systemd_notify_status("Sending initial telemetry");
+        self.state.set_status("Sending initial telemetry");
 
         self.send_initial_telemetry().await?;
 
+        crash_report::send_pending_reports(&self.publisher).await;
+
+        match self.ota_handler.slot_status().await {
+            Ok(slots) => {
+                if let Err(err) = ota::slots::send_slot_status(&self.publisher, &slots).await {
+                    warn!("couldn't send the status of the slots: {err}");
+                }
+            }
+            Err(err) => warn!("couldn't get the status of the slots: {err}"),
+        }
+
         Ok(())
     }
 
@@ -285,10 +761,18 @@ where
                 "io.edgehog.devicemanager.HardwareInfo",
                 telemetry::hardware_info::get_hardware_info()?,
             ),
+            (
+                "io.edgehog.devicemanager.BoardInfo",
+                telemetry::hardware_info::get_board_info(),
+            ),
             (
                 "io.edgehog.devicemanager.RuntimeInfo",
                 telemetry::runtime_info::get_runtime_info()?,
             ),
+            (
+                "io.edgehog.devicemanager.RuntimeDetails",
+                telemetry::runtime_info::get_runtime_details()?,
+            ),
             (
                 "io.edgehog.devicemanager.NetworkInterfaceProperties",
                 telemetry::net_if_properties::get_network_interface_properties().await?,
@@ -309,6 +793,12 @@ where
             }
         }
 
+        if let Err(err) =
+            telemetry::device_attributes::send_device_attributes(device, &self.attributes).await
+        {
+            warn!("couldn't send the device attributes: {err}");
+        }
+
         let disks = telemetry::storage_usage::get_storage_usage();
         for (disk_name, storage) in disks {
             device
@@ -320,7 +810,8 @@ where
                 .await?;
         }
 
-        for wifi_scan_result in telemetry::wifi_scan::get_wifi_scan_results()? {
+        #[cfg(feature = "wifi")]
+        for wifi_scan_result in telemetry::wifi_scan::get_wifi_scan_results().await? {
             device
                 .send_object(
                     "io.edgehog.devicemanager.WiFiScanResults",
@@ -330,10 +821,44 @@ where
                 .await?;
         }
 
+        #[cfg(feature = "cellular")]
+        match telemetry::cellular_connection::get_cellular_properties().await {
+            Ok(properties) => {
+                for (path, data) in properties {
+                    device
+                        .send(
+                            "io.edgehog.devicemanager.CellularConnectionProperties",
+                            &path,
+                            data,
+                        )
+                        .await?;
+                }
+            }
+            Err(err) => warn!("couldn't get the cellular connection properties: {err}"),
+        }
+
+        if let Err(err) = device
+            .send(
+                DIAGNOSTICS_INTERFACE,
+                "/telemetryOverridesActive",
+                AstarteType::Boolean(self.telemetry_overrides.is_active()),
+            )
+            .await
+        {
+            warn!("couldn't send the telemetry overrides diagnostics: {err}");
+        }
+
+        if let Err(err) = storage_check::send_storage_status(device, &self.storage_status).await {
+            warn!("couldn't send the storage diagnostics: {err}");
+        }
+
         Ok(())
     }
 
-    async fn send_telemetry(publisher: &P, msg: TelemetryMessage) {
+    async fn send_telemetry<T>(publisher: &T, msg: TelemetryMessage)
+    where
+        T: Publisher + Send + Sync,
+    {
         match msg.payload {
             TelemetryPayload::SystemStatus(data) => {
                 let _ = publisher
@@ -362,6 +887,60 @@ where
                     )
                     .await;
             }
+            TelemetryPayload::LoadAverage(data) => {
+                let _ = publisher
+                    .send_object("io.edgehog.devicemanager.LoadAverage", "/loadAverage", data)
+                    .await;
+            }
+            TelemetryPayload::SchedulerStatus(data) => {
+                let _ = publisher
+                    .send_object(
+                        "io.edgehog.devicemanager.SchedulerStatus",
+                        "/schedulerStatus",
+                        data,
+                    )
+                    .await;
+            }
+            TelemetryPayload::ThermalZone(data) => {
+                let _ = publisher
+                    .send_object(
+                        "io.edgehog.devicemanager.ThermalZones",
+                        format!("/{}", msg.path).as_str(),
+                        data,
+                    )
+                    .await;
+            }
+            TelemetryPayload::PressureStall(data) => {
+                let _ = publisher
+                    .send_object(
+                        "io.edgehog.devicemanager.PressureStall",
+                        format!("/{}", msg.path).as_str(),
+                        data,
+                    )
+                    .await;
+            }
+            #[cfg(feature = "cellular")]
+            TelemetryPayload::CellularConnectionStatus(data) => {
+                let _ = publisher
+                    .send_object(
+                        "io.edgehog.devicemanager.CellularConnectionStatus",
+                        format!("/{}", msg.path).as_str(),
+                        data,
+                    )
+                    .await;
+            }
+            #[cfg(feature = "wifi")]
+            TelemetryPayload::WiFiScanResult(data) => {
+                let _ = publisher
+                    .send_object("io.edgehog.devicemanager.WiFiScanResults", "/ap", data)
+                    .await;
+            }
+            TelemetryPayload::Custom {
+                interface_name,
+                data,
+            } => {
+                let _ = publisher.send(&interface_name, &msg.path, data).await;
+            }
         };
     }
 }
@@ -388,19 +967,22 @@ pub mod e2e_test {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::path::PathBuf;
 
     use astarte_device_sdk::types::AstarteType;
 
     use crate::data::astarte_device_sdk_lib::AstarteDeviceSdkConfigOptions;
-    use crate::data::tests::MockSubscriber;
+    use crate::data::connection::ConnectionState;
     use crate::data::tests::__mock_MockPublisher_Clone::__clone::Expectation;
+    use crate::data::tests::MockSubscriber;
     use crate::data::tests::{create_tmp_store, MockPublisher};
     use crate::telemetry::base_image::get_base_image;
     use crate::telemetry::battery_status::{get_battery_status, BatteryStatus};
-    use crate::telemetry::hardware_info::get_hardware_info;
+    use crate::telemetry::hardware_info::{get_board_info, get_hardware_info};
     use crate::telemetry::net_if_properties::get_network_interface_properties;
     use crate::telemetry::os_info::get_os_info;
+    use crate::telemetry::overrides::DIAGNOSTICS_INTERFACE;
     use crate::telemetry::runtime_info::get_runtime_info;
     use crate::telemetry::storage_usage::{get_storage_usage, DiskUsage};
     use crate::telemetry::system_info::get_system_info;
@@ -409,12 +991,21 @@ mod tests {
         AstarteLibrary, DeviceManager, DeviceManagerOptions, TelemetryMessage, TelemetryPayload,
     };
 
+    /// Publisher cloned by the supervised tasks.
+    fn cloneable_publisher() -> MockPublisher {
+        let mut publisher = MockPublisher::new();
+
+        publisher.expect_clone().returning(cloneable_publisher);
+
+        publisher
+    }
+
     #[cfg(feature = "forwarder")]
     fn mock_forwarder(publisher: &mut MockPublisher) -> &mut Expectation {
         // define an expectation for the cloned MockPublisher due to the `init` method of the
         // Forwarder struct
         publisher.expect_clone().returning(move || {
-            let mut publisher_clone = MockPublisher::new();
+            let mut publisher_clone = cloneable_publisher();
 
             publisher_clone
                 .expect_interface_props()
@@ -439,27 +1030,54 @@ mod tests {
                 pairing_url: "".to_string(),
                 pairing_token: None,
                 ignore_ssl: false,
+                ca_bundle: None,
             }),
             #[cfg(feature = "message-hub")]
             astarte_message_hub: None,
-            interfaces_directory: PathBuf::new(),
+            interfaces_directory: PathBuf::new().into(),
             store_directory: store_dir.path().to_owned(),
             download_directory: PathBuf::new(),
             telemetry_config: Some(vec![]),
+            telemetry_overrides_file: None,
+            attributes: HashMap::new(),
+            rate_limits: Default::default(),
+            update_polling: None,
+            ota_self_test: None,
+            ota_download: Default::default(),
+            geolocation: None,
+            property_cache: Default::default(),
+            offline_queue: Default::default(),
+            decimation: Vec::new(),
+            watchdog: Default::default(),
+            health: None,
+            logging: Default::default(),
+            secondary_connections: Vec::new(),
+            custom_commands: Default::default(),
+            factory_reset: Default::default(),
+            shutdown: Default::default(),
+            dbus_service: None,
+            connectivity: None,
+            proxy: Default::default(),
+            integrity: None,
+            log_forwarding: None,
+            crash_reports: Default::default(),
+            #[cfg(feature = "systemd")]
+            logs: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "forwarder")]
+            forwarder: Default::default(),
         };
 
         let (publisher, subscriber) = options
             .astarte_device_sdk
             .as_ref()
             .unwrap()
-            .connect(
-                store,
-                &options.store_directory,
-                &options.interfaces_directory,
-            )
+            .connect(store, &options.store_directory, &PathBuf::new())
             .await
             .unwrap();
-        let dm = DeviceManager::new(options, publisher, subscriber).await;
+        let dm =
+            DeviceManager::new(options, publisher, subscriber, ConnectionState::default()).await;
 
         assert!(dm.is_ok());
     }
@@ -475,13 +1093,43 @@ mod tests {
                 pairing_url: "".to_string(),
                 pairing_token: None,
                 ignore_ssl: false,
+                ca_bundle: None,
             }),
             #[cfg(feature = "message-hub")]
             astarte_message_hub: None,
-            interfaces_directory: PathBuf::new(),
+            interfaces_directory: PathBuf::new().into(),
             store_directory: PathBuf::new(),
             download_directory: PathBuf::new(),
             telemetry_config: Some(vec![]),
+            telemetry_overrides_file: None,
+            attributes: HashMap::new(),
+            rate_limits: Default::default(),
+            update_polling: None,
+            ota_self_test: None,
+            ota_download: Default::default(),
+            geolocation: None,
+            property_cache: Default::default(),
+            offline_queue: Default::default(),
+            decimation: Vec::new(),
+            watchdog: Default::default(),
+            health: None,
+            logging: Default::default(),
+            secondary_connections: Vec::new(),
+            custom_commands: Default::default(),
+            factory_reset: Default::default(),
+            shutdown: Default::default(),
+            dbus_service: None,
+            connectivity: None,
+            proxy: Default::default(),
+            integrity: None,
+            log_forwarding: None,
+            crash_reports: Default::default(),
+            #[cfg(feature = "systemd")]
+            logs: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "forwarder")]
+            forwarder: Default::default(),
         };
 
         let mut publisher = MockPublisher::new();
@@ -489,11 +1137,12 @@ mod tests {
         #[cfg(feature = "forwarder")]
         mock_forwarder(&mut publisher);
 
-        publisher.expect_clone().returning(MockPublisher::new);
+        publisher.expect_clone().returning(cloneable_publisher);
 
         let subscriber = MockSubscriber::new();
 
-        let dm = DeviceManager::new(options, publisher, subscriber).await;
+        let dm =
+            DeviceManager::new(options, publisher, subscriber, ConnectionState::default()).await;
         assert!(dm.is_ok(), "error {}", dm.err().unwrap());
     }
 
@@ -508,13 +1157,43 @@ mod tests {
                 pairing_url: "".to_string(),
                 pairing_token: None,
                 ignore_ssl: false,
+                ca_bundle: None,
             }),
             #[cfg(feature = "message-hub")]
             astarte_message_hub: None,
-            interfaces_directory: PathBuf::new(),
+            interfaces_directory: PathBuf::new().into(),
             store_directory: PathBuf::new(),
             download_directory: PathBuf::new(),
             telemetry_config: Some(vec![]),
+            telemetry_overrides_file: None,
+            attributes: HashMap::new(),
+            rate_limits: Default::default(),
+            update_polling: None,
+            ota_self_test: None,
+            ota_download: Default::default(),
+            geolocation: None,
+            property_cache: Default::default(),
+            offline_queue: Default::default(),
+            decimation: Vec::new(),
+            watchdog: Default::default(),
+            health: None,
+            logging: Default::default(),
+            secondary_connections: Vec::new(),
+            custom_commands: Default::default(),
+            factory_reset: Default::default(),
+            shutdown: Default::default(),
+            dbus_service: None,
+            connectivity: None,
+            proxy: Default::default(),
+            integrity: None,
+            log_forwarding: None,
+            crash_reports: Default::default(),
+            #[cfg(feature = "systemd")]
+            logs: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "forwarder")]
+            forwarder: Default::default(),
         };
 
         let os_info = get_os_info().await.expect("failed to get os info");
@@ -523,7 +1202,7 @@ mod tests {
         #[cfg(feature = "forwarder")]
         mock_forwarder(&mut publisher);
 
-        publisher.expect_clone().returning(MockPublisher::new);
+        publisher.expect_clone().returning(cloneable_publisher);
 
         publisher
             .expect_send()
@@ -546,6 +1225,17 @@ mod tests {
             )
             .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
 
+        let board_info = get_board_info();
+        publisher
+            .expect_send()
+            .withf(
+                move |interface_name: &str, interface_path: &str, data: &AstarteType| {
+                    interface_name == "io.edgehog.devicemanager.BoardInfo"
+                        && board_info.get(interface_path).unwrap() == data
+                },
+            )
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
         let runtime_info = get_runtime_info().unwrap();
         publisher
             .expect_send()
@@ -557,6 +1247,16 @@ mod tests {
             )
             .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
 
+        publisher
+            .expect_send()
+            .withf(
+                move |interface_name: &str, interface_path: &str, _: &AstarteType| {
+                    interface_name == "io.edgehog.devicemanager.RuntimeDetails"
+                        && ["/architecture", "/features", "/startTime"].contains(&interface_path)
+                },
+            )
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
         let storage_usage = get_storage_usage();
         publisher
             .expect_send_object()
@@ -601,7 +1301,39 @@ mod tests {
             )
             .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
 
-        let dm = DeviceManager::new(options, publisher, MockSubscriber::new()).await;
+        publisher
+            .expect_interface_props()
+            .withf(|iface: &str| iface == "io.edgehog.devicemanager.DeviceAttributes")
+            .returning(|_: &str| Ok(Vec::new()));
+
+        publisher
+            .expect_send()
+            .withf(
+                move |interface_name: &str, interface_path: &str, data: &AstarteType| {
+                    interface_name == DIAGNOSTICS_INTERFACE
+                        && interface_path == "/telemetryOverridesActive"
+                        && *data == AstarteType::Boolean(false)
+                },
+            )
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        publisher
+            .expect_send()
+            .withf(
+                move |interface_name: &str, interface_path: &str, _: &AstarteType| {
+                    interface_name == DIAGNOSTICS_INTERFACE
+                        && interface_path.starts_with("/storage/")
+                },
+            )
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        let dm = DeviceManager::new(
+            options,
+            publisher,
+            MockSubscriber::new(),
+            ConnectionState::default(),
+        )
+        .await;
         assert!(dm.is_ok());
 
         let telemetry_result = dm.unwrap().send_initial_telemetry().await;
@@ -644,7 +1376,7 @@ mod tests {
             )
             .returning(|_: &str, _: &str, _: BatteryStatus| Ok(()));
 
-        DeviceManager::<_, MockSubscriber>::send_telemetry(
+        DeviceManager::<MockPublisher, MockSubscriber>::send_telemetry(
             &publisher,
             TelemetryMessage {
                 path: "".to_string(),
@@ -653,7 +1385,7 @@ mod tests {
         )
         .await;
         for (path, payload) in get_storage_usage() {
-            DeviceManager::<_, MockSubscriber>::send_telemetry(
+            DeviceManager::<MockPublisher, MockSubscriber>::send_telemetry(
                 &publisher,
                 TelemetryMessage {
                     path,
@@ -663,7 +1395,7 @@ mod tests {
             .await;
         }
         for (path, payload) in get_battery_status().await.unwrap() {
-            DeviceManager::<_, MockSubscriber>::send_telemetry(
+            DeviceManager::<MockPublisher, MockSubscriber>::send_telemetry(
                 &publisher,
                 TelemetryMessage {
                     path,
@@ -0,0 +1,227 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Forwarding of the runtime warnings and errors to Astarte.
+//!
+//! Fleet operators can see the errors of a device without accessing it. The records are buffered in
+//! a bounded channel, dropping the new ones when it's full, and published in rate limited batches
+//! so a burst of errors doesn't flood a metered link.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use astarte_device_sdk::AstarteAggregate;
+use log::{error, Level, Record};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::commands::now_millis;
+use crate::data::Publisher;
+
+const RUNTIME_LOG_INTERFACE: &str = "io.edgehog.devicemanager.RuntimeLog";
+
+static FORWARDER: OnceLock<Forwarder> = OnceLock::new();
+
+const fn default_buffer_size() -> usize {
+    100
+}
+
+const fn default_batch_size() -> usize {
+    20
+}
+
+const fn default_interval() -> u64 {
+    60
+}
+
+/// Configuration of the log forwarding.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LogForwardingConfig {
+    /// Records buffered while waiting to be sent.
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// Maximum number of records sent every interval.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Seconds between two batches.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, AstarteAggregate)]
+pub struct RuntimeLog {
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    /// Unix time in milliseconds of the record.
+    pub timestamp: i64,
+}
+
+impl RuntimeLog {
+    fn from_record(record: &Record) -> Self {
+        Self {
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            timestamp: now_millis(),
+        }
+    }
+}
+
+struct Forwarder {
+    tx: mpsc::Sender<RuntimeLog>,
+    dropped: AtomicU64,
+}
+
+/// Buffer the warnings and errors, if the forwarding is enabled.
+pub(crate) fn capture(record: &Record) {
+    if record.level() > Level::Warn || record.target().starts_with(module_path!()) {
+        return;
+    }
+
+    let Some(forwarder) = FORWARDER.get() else {
+        return;
+    };
+
+    if forwarder
+        .tx
+        .try_send(RuntimeLog::from_record(record))
+        .is_err()
+    {
+        forwarder.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Start buffering the records, returns [`None`] if the forwarding was already started.
+fn start(buffer_size: usize) -> Option<mpsc::Receiver<RuntimeLog>> {
+    let (tx, rx) = mpsc::channel(buffer_size.max(1));
+
+    let forwarder = Forwarder {
+        tx,
+        dropped: AtomicU64::new(0),
+    };
+
+    FORWARDER.set(forwarder).ok().map(|()| rx)
+}
+
+/// Record reporting the records dropped since the last batch.
+fn dropped_records() -> Option<RuntimeLog> {
+    let dropped = FORWARDER.get()?.dropped.swap(0, Ordering::Relaxed);
+
+    (dropped > 0).then(|| RuntimeLog {
+        level: Level::Warn.to_string(),
+        target: module_path!().to_string(),
+        message: format!("dropped {dropped} log records"),
+        timestamp: now_millis(),
+    })
+}
+
+/// Take the next batch of records, without waiting.
+fn next_batch(rx: &mut mpsc::Receiver<RuntimeLog>, batch_size: usize) -> Vec<RuntimeLog> {
+    let mut batch = Vec::new();
+
+    while batch.len() < batch_size {
+        match rx.try_recv() {
+            Ok(record) => batch.push(record),
+            Err(_) => break,
+        }
+    }
+
+    batch
+}
+
+/// Publish the buffered records in batches.
+pub(crate) async fn run<P>(config: LogForwardingConfig, publisher: P)
+where
+    P: Publisher + Send + Sync,
+{
+    let Some(mut rx) = start(config.buffer_size) else {
+        error!("log forwarding already started");
+
+        return;
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+
+    loop {
+        interval.tick().await;
+
+        let batch = next_batch(&mut rx, config.batch_size)
+            .into_iter()
+            .chain(dropped_records());
+
+        for record in batch {
+            if let Err(err) = publisher
+                .send_object(RUNTIME_LOG_INTERFACE, "/record", record)
+                .await
+            {
+                error!("couldn't send runtime log record: {err}");
+
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_size() {
+        let (tx, mut rx) = mpsc::channel(10);
+
+        for i in 0..5 {
+            tx.try_send(RuntimeLog {
+                level: "ERROR".to_string(),
+                target: "test".to_string(),
+                message: i.to_string(),
+                timestamp: 0,
+            })
+            .unwrap();
+        }
+
+        let batch = next_batch(&mut rx, 3);
+        assert_eq!(
+            batch
+                .iter()
+                .map(|record| record.message.as_str())
+                .collect::<Vec<_>>(),
+            ["0", "1", "2"]
+        );
+
+        assert_eq!(next_batch(&mut rx, 3).len(), 2);
+        assert!(next_batch(&mut rx, 3).is_empty());
+    }
+
+    #[test]
+    fn record_levels() {
+        let log = RuntimeLog::from_record(
+            &Record::builder()
+                .level(Level::Error)
+                .target("edgehog_device_runtime::ota")
+                .args(format_args!("update failed"))
+                .build(),
+        );
+
+        assert_eq!(log.level, "ERROR");
+        assert_eq!(log.target, "edgehog_device_runtime::ota");
+        assert_eq!(log.message, "update failed");
+    }
+}
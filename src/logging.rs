@@ -0,0 +1,414 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Logger whose verbosity can be raised from Astarte for a bounded time.
+//!
+//! The filters in `RUST_LOG` are always applied. An override received on
+//! `io.edgehog.devicemanager.LogLevelRequest` enables additional filters, like
+//! `edgehog_forwarder=trace`, that are removed once the requested duration expires. This avoids
+//! leaving a device in verbose logging mode, wearing out its flash.
+//!
+//! The records are written in the `env_logger` text format, or as JSON lines for the log
+//! collectors when configured.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use astarte_device_sdk::types::AstarteType;
+use chrono::{SecondsFormat, Utc};
+use log::{error, info, Log, Metadata, Record};
+use serde::Deserialize;
+
+/// Maximum duration of an override.
+pub const MAX_OVERRIDE_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+static LOGGER: OnceLock<RuntimeLogger> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LoggingError {
+    /// invalid request, {0}
+    InvalidRequest(&'static str),
+    /// the runtime logger is not installed
+    NotInstalled,
+    /// the log format was already configured
+    AlreadyConfigured,
+}
+
+/// Format of the log records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Configuration of the logging subsystem.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+/// Writes the records as JSON lines.
+#[derive(Debug)]
+struct JsonFormat {
+    device_id: Option<String>,
+}
+
+impl JsonFormat {
+    fn line(&self, record: &Record) -> String {
+        let line = serde_json::json!({
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "module": record.module_path(),
+            "file": record.file(),
+            "line": record.line(),
+            "device_id": self.device_id,
+            "message": record.args().to_string(),
+        });
+
+        line.to_string()
+    }
+
+    fn write(&self, record: &Record) {
+        let line = self.line(record);
+
+        // like env_logger, the errors writing to stderr are ignored
+        let _ = writeln!(std::io::stderr().lock(), "{line}");
+    }
+}
+
+/// Request received on `io.edgehog.devicemanager.LogLevelRequest`.
+#[derive(Debug, PartialEq, Eq)]
+struct LogLevelRequest {
+    /// Filters in the `RUST_LOG` format, an empty string removes the override.
+    filter: String,
+    duration: Duration,
+}
+
+impl TryFrom<HashMap<String, AstarteType>> for LogLevelRequest {
+    type Error = LoggingError;
+
+    fn try_from(mut value: HashMap<String, AstarteType>) -> Result<Self, Self::Error> {
+        let Some(AstarteType::String(filter)) = value.remove("filter") else {
+            return Err(LoggingError::InvalidRequest("missing filter"));
+        };
+
+        let duration = match value.remove("duration") {
+            Some(AstarteType::Integer(secs)) => i64::from(secs),
+            Some(AstarteType::LongInteger(secs)) => secs,
+            _ => return Err(LoggingError::InvalidRequest("missing duration")),
+        };
+
+        let duration = u64::try_from(duration)
+            .map_err(|_| LoggingError::InvalidRequest("negative duration"))?;
+
+        Ok(Self {
+            filter: filter.trim().to_string(),
+            duration: Duration::from_secs(duration).min(MAX_OVERRIDE_DURATION),
+        })
+    }
+}
+
+struct Override {
+    id: u64,
+    logger: env_logger::Logger,
+    expires_at: Instant,
+}
+
+impl Override {
+    fn is_active(&self) -> bool {
+        Instant::now() < self.expires_at
+    }
+}
+
+/// Logger applying the `RUST_LOG` filters and the active override.
+struct RuntimeLogger {
+    base: env_logger::Logger,
+    over: RwLock<Option<Override>>,
+    next_id: AtomicU64,
+    /// Set once the configuration is read, the records are written as text until then.
+    json: OnceLock<JsonFormat>,
+}
+
+impl RuntimeLogger {
+    fn new(base: env_logger::Logger) -> Self {
+        Self {
+            base,
+            over: RwLock::new(None),
+            next_id: AtomicU64::new(0),
+            json: OnceLock::new(),
+        }
+    }
+
+    /// Write the record with the format of the passed logger, or as JSON if configured.
+    fn write(&self, logger: &env_logger::Logger, record: &Record) {
+        match self.json.get() {
+            Some(json) => json.write(record),
+            None => logger.log(record),
+        }
+    }
+
+    fn set(&self, filter: &str, duration: Duration) -> u64 {
+        let logger = env_logger::Builder::new().parse_filters(filter).build();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        log::set_max_level(self.base.filter().max(logger.filter()));
+
+        let mut over = self.over.write().unwrap_or_else(|err| err.into_inner());
+        *over = Some(Override {
+            id,
+            logger,
+            expires_at: Instant::now() + duration,
+        });
+
+        id
+    }
+
+    /// Remove the override, only if it's the one with the given id when passed.
+    fn clear(&self, id: Option<u64>) -> bool {
+        let mut over = self.over.write().unwrap_or_else(|err| err.into_inner());
+
+        let matches = over
+            .as_ref()
+            .is_some_and(|over| id.map_or(true, |id| id == over.id));
+
+        if matches {
+            *over = None;
+            log::set_max_level(self.base.filter());
+        }
+
+        matches
+    }
+}
+
+impl Log for RuntimeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if self.base.enabled(metadata) {
+            return true;
+        }
+
+        let over = self.over.read().unwrap_or_else(|err| err.into_inner());
+
+        over.as_ref()
+            .is_some_and(|over| over.is_active() && over.logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.base.matches(record) {
+            self.write(&self.base, record);
+            crate::log_forwarding::capture(record);
+            crate::crash_report::record(record);
+
+            return;
+        }
+
+        let over = self.over.read().unwrap_or_else(|err| err.into_inner());
+
+        if let Some(over) = over.as_ref().filter(|over| over.is_active()) {
+            if over.logger.matches(record) {
+                self.write(&over.logger, record);
+                crate::log_forwarding::capture(record);
+                crate::crash_report::record(record);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.base.flush();
+    }
+}
+
+/// Install the runtime logger, reading the filters from `RUST_LOG`.
+///
+/// Panics if a logger was already installed, like [`env_logger::init`].
+pub fn init() {
+    let base = env_logger::Builder::from_default_env().build();
+    let max_level = base.filter();
+
+    let logger = LOGGER.get_or_init(|| RuntimeLogger::new(base));
+
+    log::set_logger(logger).expect("logger already initialized");
+    log::set_max_level(max_level);
+}
+
+/// Apply the logging configuration, once it has been read.
+///
+/// The device ID is added to the JSON records, since the logs of many devices can be collected in
+/// the same place.
+pub fn configure(config: &LoggingConfig, device_id: Option<String>) -> Result<(), LoggingError> {
+    if config.format == LogFormat::Text {
+        return Ok(());
+    }
+
+    let logger = LOGGER.get().ok_or(LoggingError::NotInstalled)?;
+
+    logger
+        .json
+        .set(JsonFormat { device_id })
+        .map_err(|_| LoggingError::AlreadyConfigured)
+}
+
+/// Enable the filters until the duration expires, replacing the previous override.
+fn set_override(filter: &str, duration: Duration) -> Result<(), LoggingError> {
+    let logger = LOGGER.get().ok_or(LoggingError::NotInstalled)?;
+
+    let id = logger.set(filter, duration);
+
+    info!("log filter {filter} enabled for {}s", duration.as_secs());
+
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+
+        if logger.clear(Some(id)) {
+            info!("log filter override expired");
+        }
+    });
+
+    Ok(())
+}
+
+fn clear_override() -> Result<(), LoggingError> {
+    let logger = LOGGER.get().ok_or(LoggingError::NotInstalled)?;
+
+    if logger.clear(None) {
+        info!("log filter override removed");
+    }
+
+    Ok(())
+}
+
+/// handle io.edgehog.devicemanager.LogLevelRequest
+pub(crate) fn handle_request(data: HashMap<String, AstarteType>) {
+    let res = LogLevelRequest::try_from(data).and_then(|request| {
+        if request.filter.is_empty() || request.duration.is_zero() {
+            clear_override()
+        } else {
+            set_override(&request.filter, request.duration)
+        }
+    });
+
+    if let Err(err) = res {
+        error!("couldn't change the log filter: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use log::LevelFilter;
+
+    fn request(filter: &str, duration: AstarteType) -> HashMap<String, AstarteType> {
+        HashMap::from([
+            (
+                "filter".to_string(),
+                AstarteType::String(filter.to_string()),
+            ),
+            ("duration".to_string(), duration),
+        ])
+    }
+
+    #[test]
+    fn parse_request() {
+        let req = LogLevelRequest::try_from(request(
+            "edgehog_forwarder=trace ",
+            AstarteType::Integer(600),
+        ))
+        .unwrap();
+        assert_eq!(
+            req,
+            LogLevelRequest {
+                filter: "edgehog_forwarder=trace".to_string(),
+                duration: Duration::from_secs(600),
+            }
+        );
+
+        let req = LogLevelRequest::try_from(request(
+            "edgehog_forwarder=trace",
+            AstarteType::LongInteger(i64::MAX),
+        ))
+        .unwrap();
+        assert_eq!(req.duration, MAX_OVERRIDE_DURATION);
+
+        let res = LogLevelRequest::try_from(request("trace", AstarteType::Integer(-1)));
+        assert!(matches!(res, Err(LoggingError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn override_expires() {
+        let logger = RuntimeLogger::new(
+            env_logger::Builder::new()
+                .filter_level(LevelFilter::Info)
+                .build(),
+        );
+        let metadata = Metadata::builder()
+            .target("edgehog_forwarder")
+            .level(log::Level::Trace)
+            .build();
+
+        assert!(!logger.enabled(&metadata));
+
+        let id = logger.set("edgehog_forwarder=trace", Duration::from_secs(60));
+        assert!(logger.enabled(&metadata));
+
+        // an old timer doesn't remove a newer override
+        let new_id = logger.set("edgehog_forwarder=trace", Duration::from_secs(60));
+        assert!(!logger.clear(Some(id)));
+        assert!(logger.enabled(&metadata));
+
+        assert!(logger.clear(Some(new_id)));
+        assert!(!logger.enabled(&metadata));
+
+        logger.set("edgehog_forwarder=trace", Duration::ZERO);
+        assert!(!logger.enabled(&metadata));
+    }
+
+    #[test]
+    fn json_record() {
+        let config: LoggingConfig = toml::from_str(r#"format = "json""#).unwrap();
+        assert_eq!(config.format, LogFormat::Json);
+
+        let json = JsonFormat {
+            device_id: Some("2TBn-jNESuuHamE2Zo1anA".to_string()),
+        };
+        let line = json.line(
+            &Record::builder()
+                .args(format_args!("session opened"))
+                .level(log::Level::Info)
+                .target("edgehog_device_runtime::forwarder")
+                .module_path_static(Some("edgehog_device_runtime::forwarder"))
+                .line(Some(42))
+                .build(),
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "edgehog_device_runtime::forwarder");
+        assert_eq!(value["module"], "edgehog_device_runtime::forwarder");
+        assert_eq!(value["line"], 42);
+        assert_eq!(value["device_id"], "2TBn-jNESuuHamE2Zo1anA");
+        assert_eq!(value["message"], "session opened");
+        assert!(value["timestamp"].is_string());
+    }
+}
@@ -0,0 +1,396 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Collection of the journal entries of selected units.
+//!
+//! The entries of the configured units, up to a priority, are read from systemd-journald and shipped
+//! in batches to Astarte, to an HTTP endpoint or to a syslog server. The cursor of the last entry
+//! shipped is persisted in the store, so reading resumes from it after a restart and no entry is
+//! lost. While the destination is unreachable the reader blocks, instead of dropping the entries.
+
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use astarte_device_sdk::AstarteAggregate;
+use chrono::{TimeZone, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use systemd::journal::{Journal, OpenOptions};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+
+use crate::data::Publisher;
+use crate::repository::file_state_repository::FileStateRepository;
+use crate::repository::StateRepository;
+
+const JOURNAL_ENTRY_INTERFACE: &str = "io.edgehog.devicemanager.JournalEntry";
+
+/// Wait for new entries before checking if the shipper stopped.
+const JOURNAL_WAIT: Duration = Duration::from_secs(5);
+
+const fn default_priority() -> u8 {
+    // warning
+    4
+}
+
+const fn default_batch_size() -> usize {
+    50
+}
+
+const fn default_interval() -> u64 {
+    10
+}
+
+/// Configuration of the journal collection.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LogsConfig {
+    /// Units to collect the entries of.
+    pub units: Vec<String>,
+    /// Maximum priority of the entries, from 0 (emerg) to 7 (debug).
+    #[serde(default = "default_priority")]
+    pub priority: u8,
+    #[serde(default)]
+    pub destination: LogsDestination,
+    /// Maximum number of entries shipped every interval.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Seconds between two batches.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+/// Where the entries are shipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogsDestination {
+    /// The `io.edgehog.devicemanager.JournalEntry` interface.
+    #[default]
+    Astarte,
+    /// JSON array of the entries, posted to the URL.
+    Http { url: String },
+    /// RFC 5424 messages over UDP.
+    Syslog { address: String },
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LogsError {
+    /// couldn't read the journal
+    Journal(#[source] std::io::Error),
+    /// couldn't send the entries to Astarte
+    Astarte(#[from] astarte_device_sdk::error::Error),
+    /// couldn't serialize the entries
+    Serialize(#[from] serde_json::Error),
+    /// couldn't post the entries
+    Http(#[from] reqwest::Error),
+    /// couldn't send the entries to the syslog server
+    Syslog(#[source] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, AstarteAggregate)]
+#[serde(rename_all = "camelCase")]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub unit: String,
+    pub priority: i32,
+    pub message: String,
+    /// Unix time in milliseconds of the entry.
+    pub timestamp: i64,
+}
+
+impl JournalEntry {
+    /// Format the entry as an RFC 5424 message, with the user facility.
+    fn to_syslog(&self, hostname: &str) -> String {
+        let timestamp = Utc
+            .timestamp_millis_opt(self.timestamp)
+            .single()
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_else(|| "-".to_string());
+
+        format!(
+            "<{}>1 {timestamp} {hostname} {} - - - {}",
+            8 + self.priority.clamp(0, 7),
+            self.unit,
+            self.message
+        )
+    }
+}
+
+/// Entry read from the journal, with its cursor.
+#[derive(Debug)]
+struct CursorEntry {
+    cursor: String,
+    entry: JournalEntry,
+}
+
+fn open_journal(config: &LogsConfig, cursor: Option<&str>) -> Result<Journal, std::io::Error> {
+    let mut journal = OpenOptions::default()
+        .system(true)
+        .local_only(true)
+        .open()?;
+
+    // matches on the same field are OR-ed, while the ones on different fields are AND-ed
+    for unit in &config.units {
+        journal.match_add("_SYSTEMD_UNIT", unit.as_str())?;
+    }
+    for priority in 0..=config.priority.min(7) {
+        journal.match_add("PRIORITY", priority.to_string())?;
+    }
+
+    match cursor {
+        Some(cursor) => {
+            journal.seek_cursor(cursor)?;
+            // skip the entry at the cursor, it was already shipped
+            journal.next()?;
+        }
+        None => {
+            journal.seek_tail()?;
+            journal.previous()?;
+        }
+    }
+
+    Ok(journal)
+}
+
+/// Read the entries, blocking while the channel is full.
+fn read_journal(
+    config: &LogsConfig,
+    cursor: Option<&str>,
+    tx: &mpsc::Sender<CursorEntry>,
+) -> Result<(), std::io::Error> {
+    let mut journal = open_journal(config, cursor)?;
+
+    loop {
+        let Some(record) = journal.next_entry()? else {
+            if tx.is_closed() {
+                return Ok(());
+            }
+
+            journal.wait(Some(JOURNAL_WAIT))?;
+
+            continue;
+        };
+
+        let timestamp = journal
+            .timestamp()?
+            .duration_since(UNIX_EPOCH)
+            .map(|time| i64::try_from(time.as_millis()).unwrap_or(i64::MAX))
+            .unwrap_or_default();
+
+        let entry = CursorEntry {
+            cursor: journal.cursor()?,
+            entry: JournalEntry {
+                unit: record.get("_SYSTEMD_UNIT").cloned().unwrap_or_default(),
+                priority: record
+                    .get("PRIORITY")
+                    .and_then(|priority| priority.parse().ok())
+                    .unwrap_or(6),
+                message: record.get("MESSAGE").cloned().unwrap_or_default(),
+                timestamp,
+            },
+        };
+
+        if tx.blocking_send(entry).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Ships the journal entries to the destination.
+pub(crate) struct LogsCollector<P> {
+    config: LogsConfig,
+    publisher: P,
+    client: reqwest::Client,
+    cursor: FileStateRepository<String>,
+}
+
+impl<P> LogsCollector<P>
+where
+    P: Publisher + Send + Sync + 'static,
+{
+    pub(crate) fn new(
+        config: LogsConfig,
+        store_directory: &Path,
+        client: reqwest::Client,
+        publisher: P,
+    ) -> Self {
+        Self {
+            config,
+            publisher,
+            client,
+            cursor: FileStateRepository::new(store_directory, "journal_cursor.json"),
+        }
+    }
+
+    async fn read_cursor(&self) -> Option<String> {
+        if !self.cursor.exists().await {
+            return None;
+        }
+
+        self.cursor
+            .read()
+            .await
+            .map_err(|err| error!("couldn't read the journal cursor: {err}"))
+            .ok()
+    }
+
+    async fn ship(&self, entries: &[JournalEntry]) -> Result<(), LogsError> {
+        match &self.config.destination {
+            LogsDestination::Astarte => {
+                for entry in entries {
+                    self.publisher
+                        .send_object(JOURNAL_ENTRY_INTERFACE, "/entry", entry.clone())
+                        .await?;
+                }
+            }
+            LogsDestination::Http { url } => {
+                self.client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(entries)?)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            LogsDestination::Syslog { address } => {
+                let hostname = tokio::fs::read_to_string("/proc/sys/kernel/hostname")
+                    .await
+                    .map(|hostname| hostname.trim().to_string())
+                    .unwrap_or_else(|_| "-".to_string());
+
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .await
+                    .map_err(LogsError::Syslog)?;
+                for entry in entries {
+                    socket
+                        .send_to(entry.to_syslog(&hostname).as_bytes(), address)
+                        .await
+                        .map_err(LogsError::Syslog)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn run(self) {
+        let cursor = self.read_cursor().await;
+
+        let (tx, mut rx) = mpsc::channel(self.config.batch_size.max(1));
+
+        let config = self.config.clone();
+        let reader = tokio::task::spawn_blocking(move || {
+            read_journal(&config, cursor.as_deref(), &tx).map_err(LogsError::Journal)
+        });
+
+        info!(
+            "collecting the journal of {} units",
+            self.config.units.len()
+        );
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval.max(1)));
+        let mut pending: Vec<CursorEntry> = Vec::new();
+        let mut closed = false;
+
+        while !closed || !pending.is_empty() {
+            interval.tick().await;
+
+            while pending.len() < self.config.batch_size.max(1) {
+                match rx.try_recv() {
+                    Ok(entry) => pending.push(entry),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        closed = true;
+
+                        break;
+                    }
+                }
+            }
+
+            let Some(last) = pending.last() else {
+                continue;
+            };
+
+            let entries: Vec<JournalEntry> =
+                pending.iter().map(|entry| entry.entry.clone()).collect();
+            if let Err(err) = self.ship(&entries).await {
+                warn!("couldn't ship {} journal entries: {err}", entries.len());
+
+                continue;
+            }
+
+            debug!("shipped {} journal entries", entries.len());
+
+            if let Err(err) = self.cursor.write(&last.cursor).await {
+                error!("couldn't store the journal cursor: {err}");
+            }
+
+            pending.clear();
+        }
+
+        match reader.await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("journal collection stopped: {err}"),
+            Err(err) => error!("journal reader panicked: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_config() {
+        let config: LogsConfig = toml::from_str(
+            r#"
+            units = ["edgehog-device-runtime.service"]
+            destination = { type = "syslog", address = "10.0.0.1:514" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            LogsConfig {
+                units: vec!["edgehog-device-runtime.service".to_string()],
+                priority: default_priority(),
+                destination: LogsDestination::Syslog {
+                    address: "10.0.0.1:514".to_string(),
+                },
+                batch_size: default_batch_size(),
+                interval: default_interval(),
+            }
+        );
+    }
+
+    #[test]
+    fn syslog_message() {
+        let entry = JournalEntry {
+            unit: "app.service".to_string(),
+            priority: 3,
+            message: "failed to start".to_string(),
+            timestamp: 0,
+        };
+
+        assert_eq!(
+            entry.to_syslog("device"),
+            "<11>1 1970-01-01T00:00:00+00:00 device app.service - - - failed to start"
+        );
+    }
+}
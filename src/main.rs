@@ -24,9 +24,18 @@ use std::panic::{self, PanicInfo};
 use std::path::Path;
 
 use config::read_options;
+use edgehog_device_runtime::crash_report;
 use edgehog_device_runtime::data::connect_store;
+use edgehog_device_runtime::data::connection::{ConnectionState, ConnectionTracker};
+use edgehog_device_runtime::data::decimation::Decimation;
+use edgehog_device_runtime::data::interface_directories;
+use edgehog_device_runtime::data::multi_realm;
+use edgehog_device_runtime::data::offline_queue::OfflineQueue;
+use edgehog_device_runtime::data::property_cache::PropertyCache;
+use edgehog_device_runtime::data::validation::{Interfaces, ValidatingPublisher};
 use edgehog_device_runtime::error::DeviceManagerError;
-use edgehog_device_runtime::AstarteLibrary;
+use edgehog_device_runtime::timing::StartupTimer;
+use edgehog_device_runtime::{AstarteLibrary, DeviceManagerOptions};
 
 mod config;
 
@@ -43,7 +52,7 @@ struct Cli {
 
 #[tokio::main]
 async fn main() -> Result<(), DeviceManagerError> {
-    env_logger::init();
+    edgehog_device_runtime::logging::init();
     #[cfg(feature = "systemd")]
     {
         let default_panic_hook = panic::take_hook();
@@ -52,12 +61,38 @@ async fn main() -> Result<(), DeviceManagerError> {
             systemd_panic_hook(panic_info);
         }));
     }
+    let mut timer = StartupTimer::start();
+
     let Cli {
         configuration_file: config_file_path,
     } = Parser::parse();
 
     let options = read_options(config_file_path).await?;
 
+    let device_id = options
+        .astarte_device_sdk
+        .as_ref()
+        .and_then(|sdk| sdk.device_id.clone());
+    if let Err(err) = edgehog_device_runtime::logging::configure(&options.logging, device_id) {
+        log::error!("couldn't configure the logging: {err}");
+    }
+
+    timer.phase("config_load");
+
+    crash_report::install(&options.crash_reports, &options.store_directory);
+
+    let res = run(options, timer).await;
+    if let Err(err) = &res {
+        crash_report::write_error_report(err);
+    }
+
+    res
+}
+
+async fn run(
+    options: DeviceManagerOptions,
+    mut timer: StartupTimer,
+) -> Result<(), DeviceManagerError> {
     if !Path::new(&options.download_directory).exists() {
         tokio::fs::create_dir_all(&options.download_directory)
             .await
@@ -80,6 +115,11 @@ async fn main() -> Result<(), DeviceManagerError> {
 
     let store = connect_store(&options.store_directory).await?;
 
+    let interfaces_directory = interface_directories::merged_directory(&options.store_directory);
+    interface_directories::merge(&options.interfaces_directory, &interfaces_directory)?;
+
+    timer.phase("store_open");
+
     match &options.astarte_library {
         AstarteLibrary::AstarteDeviceSDK => {
             let astarte_sdk_options = options
@@ -87,18 +127,61 @@ async fn main() -> Result<(), DeviceManagerError> {
                 .as_ref()
                 .expect("couldn't find astarte options");
             let (publisher, subscriber) = astarte_sdk_options
-                .connect(
-                    store,
-                    &options.store_directory,
-                    &options.interfaces_directory,
-                )
+                .connect(store, &options.store_directory, &interfaces_directory)
                 .await?;
 
-            let dm =
-                edgehog_device_runtime::DeviceManager::new(options, publisher, subscriber).await?;
+            let (publisher, subscriber) = multi_realm::connect(
+                (publisher, subscriber),
+                &options.secondary_connections,
+                &options.store_directory,
+            )
+            .await?;
+
+            #[cfg(feature = "metrics")]
+            let publisher = edgehog_device_runtime::metrics::MetricsPublisher::new(publisher);
+
+            let connection = ConnectionState::default();
+            let publisher = ConnectionTracker::new(publisher, connection.clone());
+
+            timer.phase("astarte_connect");
+
+            let interfaces = Interfaces::load(
+                std::iter::once(interfaces_directory.as_path()).chain(
+                    options
+                        .secondary_connections
+                        .iter()
+                        .map(|config| config.interfaces_directory.as_path()),
+                ),
+            )?;
+            let publisher = OfflineQueue::new(
+                publisher,
+                interfaces.clone(),
+                &options.offline_queue,
+                &options.store_directory,
+            );
+
+            let queue = publisher.clone();
+            let reconnected = connection.subscribe();
+            tokio::spawn(async move { queue.flush_on_reconnect(reconnected).await });
+
+            let publisher = ValidatingPublisher::new(publisher, interfaces);
+            let publisher = PropertyCache::new(publisher, &options.property_cache);
+            let publisher = Decimation::new(publisher, &options.decimation);
+
+            timer.phase("interfaces_load");
+
+            let dm = edgehog_device_runtime::DeviceManager::new(
+                options, publisher, subscriber, connection,
+            )
+            .await?;
+
+            timer.phase("subsystem_init");
 
             dm.init().await?;
 
+            timer.phase("initial_telemetry");
+            dm.report_startup(&timer).await;
+
             dm.run().await?;
         }
         #[cfg(feature = "message-hub")]
@@ -108,15 +191,57 @@ async fn main() -> Result<(), DeviceManagerError> {
                 .as_ref()
                 .expect("Unable to find MessageHub options");
 
+            if !options.secondary_connections.is_empty() {
+                return Err(DeviceManagerError::FatalError(
+                    "secondary connections require the astarte-device-sdk library".to_string(),
+                ));
+            }
+
             let (publisher, subscriber) = astarte_message_hub_options
-                .connect(store, &options.interfaces_directory)
+                .connect(store, &interfaces_directory)
                 .await?;
 
-            let dm =
-                edgehog_device_runtime::DeviceManager::new(options, publisher, subscriber).await?;
+            let node_state = publisher.node_state();
+
+            #[cfg(feature = "metrics")]
+            let publisher = edgehog_device_runtime::metrics::MetricsPublisher::new(publisher);
+
+            let connection = ConnectionState::default();
+            let publisher = ConnectionTracker::new(publisher, connection.clone());
+            tokio::spawn(connection.clone().follow_node(node_state));
+
+            timer.phase("astarte_connect");
+
+            let interfaces = Interfaces::load([interfaces_directory.as_path()])?;
+            let publisher = OfflineQueue::new(
+                publisher,
+                interfaces.clone(),
+                &options.offline_queue,
+                &options.store_directory,
+            );
+
+            let queue = publisher.clone();
+            let reconnected = connection.subscribe();
+            tokio::spawn(async move { queue.flush_on_reconnect(reconnected).await });
+
+            let publisher = ValidatingPublisher::new(publisher, interfaces);
+            let publisher = PropertyCache::new(publisher, &options.property_cache);
+            let publisher = Decimation::new(publisher, &options.decimation);
+
+            timer.phase("interfaces_load");
+
+            let dm = edgehog_device_runtime::DeviceManager::new(
+                options, publisher, subscriber, connection,
+            )
+            .await?;
+
+            timer.phase("subsystem_init");
 
             dm.init().await?;
 
+            timer.phase("initial_telemetry");
+            dm.report_startup(&timer).await;
+
             dm.run().await?;
         }
     };
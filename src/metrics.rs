@@ -0,0 +1,464 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prometheus exporter of the runtime internals.
+//!
+//! The metrics are served in the text exposition format on `/metrics`, to scrape the device from
+//! a local monitoring system.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::data::Publisher;
+
+static METRICS: Metrics = Metrics::new();
+
+fn default_address() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 9100))
+}
+
+/// Configuration of the metrics endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MetricsConfig {
+    /// Address to listen on, defaults to `127.0.0.1:9100`.
+    #[serde(default = "default_address")]
+    pub address: SocketAddr,
+}
+
+struct Metrics {
+    publish_success: AtomicU64,
+    publish_failure: AtomicU64,
+    store_queries: AtomicU64,
+    store_query_micros: AtomicU64,
+    ota_phases: Mutex<BTreeMap<String, u64>>,
+    forwarder_sessions: AtomicI64,
+    forwarder_sessions_total: AtomicU64,
+    forwarder_reconnects: AtomicU64,
+    event_queue_dropped: Mutex<BTreeMap<&'static str, u64>>,
+    event_queue_blocked: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            publish_success: AtomicU64::new(0),
+            publish_failure: AtomicU64::new(0),
+            store_queries: AtomicU64::new(0),
+            store_query_micros: AtomicU64::new(0),
+            ota_phases: Mutex::new(BTreeMap::new()),
+            forwarder_sessions: AtomicI64::new(0),
+            forwarder_sessions_total: AtomicU64::new(0),
+            forwarder_reconnects: AtomicU64::new(0),
+            event_queue_dropped: Mutex::new(BTreeMap::new()),
+            event_queue_blocked: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        let publish_success = self.publish_success.load(Ordering::Relaxed);
+        let publish_failure = self.publish_failure.load(Ordering::Relaxed);
+        let store_queries = self.store_queries.load(Ordering::Relaxed);
+        let store_query_micros = self.store_query_micros.load(Ordering::Relaxed);
+        let forwarder_sessions = self.forwarder_sessions.load(Ordering::Relaxed);
+        let forwarder_sessions_total = self.forwarder_sessions_total.load(Ordering::Relaxed);
+        let forwarder_reconnects = self.forwarder_reconnects.load(Ordering::Relaxed);
+
+        // writing to a String can't fail
+        let _ = writeln!(
+            out,
+            "# HELP edgehog_astarte_publish_total Data published to Astarte.\n\
+             # TYPE edgehog_astarte_publish_total counter\n\
+             edgehog_astarte_publish_total{{result=\"success\"}} {publish_success}\n\
+             edgehog_astarte_publish_total{{result=\"failure\"}} {publish_failure}"
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP edgehog_store_query_seconds Duration of the queries to the properties store.\n\
+             # TYPE edgehog_store_query_seconds summary\n\
+             edgehog_store_query_seconds_sum {}\n\
+             edgehog_store_query_seconds_count {store_queries}",
+            store_query_micros as f64 / 1_000_000.0
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP edgehog_ota_phase_total OTA update events sent for each phase.\n\
+             # TYPE edgehog_ota_phase_total counter"
+        );
+        if let Ok(phases) = self.ota_phases.lock() {
+            for (phase, count) in phases.iter() {
+                let _ = writeln!(out, "edgehog_ota_phase_total{{phase=\"{phase}\"}} {count}");
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP edgehog_forwarder_sessions Forwarder sessions currently open.\n\
+             # TYPE edgehog_forwarder_sessions gauge\n\
+             edgehog_forwarder_sessions {forwarder_sessions}\n\
+             # HELP edgehog_forwarder_sessions_total Forwarder sessions opened.\n\
+             # TYPE edgehog_forwarder_sessions_total counter\n\
+             edgehog_forwarder_sessions_total {forwarder_sessions_total}\n\
+             # HELP edgehog_forwarder_reconnects_total Reconnections of the forwarder sessions.\n\
+             # TYPE edgehog_forwarder_reconnects_total counter\n\
+             edgehog_forwarder_reconnects_total {forwarder_reconnects}"
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP edgehog_event_queue_dropped_total Events dropped because their queue was full.\n\
+             # TYPE edgehog_event_queue_dropped_total counter"
+        );
+        if let Ok(dropped) = self.event_queue_dropped.lock() {
+            for (queue, count) in dropped.iter() {
+                let _ = writeln!(
+                    out,
+                    "edgehog_event_queue_dropped_total{{queue=\"{queue}\"}} {count}"
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP edgehog_event_queue_blocked_total Events that waited for a full queue.\n\
+             # TYPE edgehog_event_queue_blocked_total counter"
+        );
+        if let Ok(blocked) = self.event_queue_blocked.lock() {
+            for (queue, count) in blocked.iter() {
+                let _ = writeln!(
+                    out,
+                    "edgehog_event_queue_blocked_total{{queue=\"{queue}\"}} {count}"
+                );
+            }
+        }
+
+        out
+    }
+}
+
+/// Count an OTA update event sent for the phase.
+pub(crate) fn ota_phase(phase: &str) {
+    if phase.is_empty() {
+        return;
+    }
+
+    if let Ok(mut phases) = METRICS.ota_phases.lock() {
+        *phases.entry(phase.to_string()).or_default() += 1;
+    }
+}
+
+/// Count an event dropped from the full queue.
+pub(crate) fn event_queue_dropped(queue: &'static str) {
+    if let Ok(mut dropped) = METRICS.event_queue_dropped.lock() {
+        *dropped.entry(queue).or_default() += 1;
+    }
+}
+
+/// Count an event waiting for the full queue.
+pub(crate) fn event_queue_blocked(queue: &'static str) {
+    if let Ok(mut blocked) = METRICS.event_queue_blocked.lock() {
+        *blocked.entry(queue).or_default() += 1;
+    }
+}
+
+/// Count a forwarder session being opened.
+#[cfg(feature = "forwarder")]
+pub(crate) fn forwarder_session_opened() {
+    METRICS.forwarder_sessions.fetch_add(1, Ordering::Relaxed);
+    METRICS
+        .forwarder_sessions_total
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a forwarder session being closed.
+#[cfg(feature = "forwarder")]
+pub(crate) fn forwarder_session_closed() {
+    METRICS.forwarder_sessions.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Count a reconnection of a forwarder session.
+#[cfg(feature = "forwarder")]
+pub(crate) fn forwarder_reconnect() {
+    METRICS.forwarder_reconnects.fetch_add(1, Ordering::Relaxed);
+}
+
+fn count_publish<T, E>(res: &Result<T, E>) {
+    match res {
+        Ok(_) => METRICS.publish_success.fetch_add(1, Ordering::Relaxed),
+        Err(_) => METRICS.publish_failure.fetch_add(1, Ordering::Relaxed),
+    };
+}
+
+/// Publisher counting the data published to Astarte and timing the queries to the store.
+#[derive(Debug, Clone)]
+pub struct MetricsPublisher<P> {
+    publisher: P,
+}
+
+impl<P> MetricsPublisher<P> {
+    pub fn new(publisher: P) -> Self {
+        Self { publisher }
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for MetricsPublisher<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let res = self
+            .publisher
+            .send_object(interface_name, interface_path, data)
+            .await;
+
+        count_publish(&res);
+
+        res
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        let res = self
+            .publisher
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await;
+
+        count_publish(&res);
+
+        res
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        let res = self
+            .publisher
+            .send(interface_name, interface_path, data)
+            .await;
+
+        count_publish(&res);
+
+        res
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        let res = self
+            .publisher
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await;
+
+        count_publish(&res);
+
+        res
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        let start = Instant::now();
+
+        let res = self.publisher.interface_props(interface).await;
+
+        let micros = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        METRICS.store_queries.fetch_add(1, Ordering::Relaxed);
+        METRICS
+            .store_query_micros
+            .fetch_add(micros, Ordering::Relaxed);
+
+        res
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        let res = self.publisher.unset(interface_name, interface_path).await;
+
+        count_publish(&res);
+
+        res
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream) -> io::Result<()> {
+    let mut buf = [0; 1024];
+    let read = stream.read(&mut buf).await?;
+
+    let request = String::from_utf8_lossy(&buf[..read]);
+    let mut request_line = request.split_whitespace();
+
+    let response = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = METRICS.render();
+
+            format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn serve(listener: TcpListener) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, addr)) => {
+                debug!("metrics requested from {addr}");
+
+                stream
+            }
+            Err(err) => {
+                error!("couldn't accept the metrics connection: {err}");
+
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream).await {
+                error!("couldn't send the metrics: {err}");
+            }
+        });
+    }
+}
+
+/// Serve the metrics on the configured address.
+pub(crate) async fn run(config: MetricsConfig) {
+    let listener = match TcpListener::bind(config.address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("couldn't listen for metrics on {}: {err}", config.address);
+
+            return;
+        }
+    };
+
+    info!("serving metrics on {}", config.address);
+
+    serve(listener).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = Metrics::new();
+        metrics.publish_success.fetch_add(3, Ordering::Relaxed);
+        metrics.store_queries.fetch_add(2, Ordering::Relaxed);
+        metrics
+            .store_query_micros
+            .fetch_add(1_500_000, Ordering::Relaxed);
+        metrics
+            .ota_phases
+            .lock()
+            .unwrap()
+            .insert("Downloading".to_string(), 1);
+
+        let out = metrics.render();
+
+        assert!(out.contains("edgehog_astarte_publish_total{result=\"success\"} 3\n"));
+        assert!(out.contains("edgehog_astarte_publish_total{result=\"failure\"} 0\n"));
+        assert!(out.contains("edgehog_store_query_seconds_sum 1.5\n"));
+        assert!(out.contains("edgehog_store_query_seconds_count 2\n"));
+        assert!(out.contains("edgehog_ota_phase_total{phase=\"Downloading\"} 1\n"));
+        assert!(out.contains("edgehog_forwarder_sessions 0\n"));
+    }
+
+    #[tokio::test]
+    async fn serve_metrics() {
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        MetricsPublisher::new(publisher)
+            .send(
+                "io.edgehog.devicemanager.OSInfo",
+                "/osName",
+                AstarteType::String("Linux".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+
+        let res = reqwest::get(format!("http://{addr}/metrics"))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+
+        let body = res.text().await.unwrap();
+        assert!(body.contains("# TYPE edgehog_astarte_publish_total counter"));
+        assert!(!body.contains("edgehog_astarte_publish_total{result=\"success\"} 0\n"));
+
+        let res = reqwest::get(format!("http://{addr}/other")).await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
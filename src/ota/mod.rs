@@ -22,15 +22,28 @@ use async_trait::async_trait;
 use futures::stream::BoxStream;
 #[cfg(test)]
 use mockall::automock;
+use serde::Deserialize;
 
 use crate::error::DeviceManagerError;
-use crate::ota::rauc::BundleInfo;
+use crate::ota::rauc::{BundleInfo, Slot};
 
 mod ota_handle;
 pub(crate) mod ota_handler;
 #[cfg(test)]
 mod ota_handler_test;
 pub(crate) mod rauc;
+pub(crate) mod self_test;
+pub(crate) mod slots;
+pub(crate) mod update_polling;
+
+/// Configuration of the download of the update bundle.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct DownloadConfig {
+    /// Keep the partially downloaded bundle when the update is cancelled, and resume it with an
+    /// HTTP range request when the same update is requested again.
+    #[serde(default)]
+    pub resume: bool,
+}
 
 /// Provides deploying progress information.
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -61,6 +74,7 @@ pub trait SystemUpdate: Send + Sync {
     async fn boot_slot(&self) -> Result<String, DeviceManagerError>;
     async fn receive_completed(&self) -> Result<ProgressStream, DeviceManagerError>;
     async fn get_primary(&self) -> Result<String, DeviceManagerError>;
+    async fn slot_status(&self) -> Result<Vec<Slot>, DeviceManagerError>;
     async fn mark(
         &self,
         state: &str,
@@ -97,6 +111,12 @@ pub enum OtaError {
     /// OTA update aborted by Edgehog half way during the procedure
     #[error("Canceled")]
     Canceled,
+    /// Too many OTA requests received in the configured period
+    #[error("RateLimited: {0}")]
+    RateLimited(String),
+    /// The update can't be cancelled anymore, it keeps going
+    #[error("CancelRejected: {0}")]
+    CancelRejected(&'static str),
 }
 
 impl Default for DeployStatus {
@@ -26,16 +26,23 @@ use std::sync::Arc;
 use astarte_device_sdk::types::AstarteType;
 use futures::TryStreamExt;
 use log::{debug, error, info, warn};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use crate::connectivity::ConnectivityWatch;
 use crate::error::DeviceManagerError;
+use crate::ota::rauc::Slot;
+use crate::ota::self_test::SelfTestConfig;
 use crate::ota::{DeployProgress, DeployStatus, OtaError, SystemUpdate};
 use crate::repository::StateRepository;
 
 const DOWNLOAD_PERC_ROUNDING_STEP: f64 = 10.0;
+/// Message of the rollback caused by a failed post update self-test.
+pub(crate) const SELF_TEST_FAILED: &str = "Post update self-test failed";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PersistentState {
@@ -53,8 +60,8 @@ pub enum OtaStatus {
     NoPendingOta,
     /// The device received a valid OTA Request
     Acknowledged(OtaRequest),
-    /// The device is in downloading process, the i32 identify the progress percentage
-    Downloading(OtaRequest, i32),
+    /// The device is in downloading process
+    Downloading(OtaRequest, DownloadProgress),
     /// The device is in the process of deploying the update
     Deploying(OtaRequest, DeployProgress),
     /// The device deployed the update
@@ -71,10 +78,23 @@ pub enum OtaStatus {
     Failure(OtaError, Option<OtaRequest>),
 }
 
+/// Progress of the download of the update bundle.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct DownloadProgress {
+    /// Percentage of the bundle downloaded.
+    pub percentage: i32,
+    /// Bytes downloaded so far.
+    pub downloaded: u64,
+    /// Size of the bundle in bytes.
+    pub total: u64,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct OtaRequest {
     pub uuid: Uuid,
     pub url: String,
+    /// Hex encoded SHA-256 digest the bundle is checked against before installing it.
+    pub sha256: Option<String>,
 }
 
 /// An enum that defines the kind of messages we can send to the Ota handle.
@@ -82,6 +102,9 @@ pub enum OtaMessage {
     GetOtaStatus {
         respond_to: oneshot::Sender<OtaStatus>,
     },
+    GetSlotStatus {
+        respond_to: oneshot::Sender<Result<Vec<Slot>, DeviceManagerError>>,
+    },
     EnsurePendingOta {
         respond_to: mpsc::Sender<OtaStatus>,
     },
@@ -117,7 +140,13 @@ where
     pub system_update: T,
     pub state_repository: U,
     pub download_file_path: PathBuf,
+    /// Keep the partial download of a cancelled update to resume it.
+    pub resume_download: bool,
     pub ota_status: Arc<RwLock<OtaStatus>>,
+    pub self_test: Option<SelfTestConfig>,
+    pub connectivity: ConnectivityWatch,
+    /// Client downloading the bundles, through the configured proxy.
+    pub client: reqwest::Client,
 }
 
 impl<T, U> Ota<T, U>
@@ -129,12 +158,17 @@ where
         opts: &crate::DeviceManagerOptions,
         system_update: T,
         state_repository: U,
+        connectivity: ConnectivityWatch,
     ) -> Result<Self, DeviceManagerError> {
         Ok(Ota {
             system_update,
             state_repository,
             download_file_path: opts.download_directory.clone(),
+            resume_download: opts.ota_download.resume,
             ota_status: Arc::new(RwLock::new(OtaStatus::Idle)),
+            self_test: opts.ota_self_test.clone(),
+            connectivity,
+            client: opts.proxy.client()?,
         })
     }
 
@@ -145,15 +179,28 @@ where
                 cancel_token,
                 respond_to,
             } => {
-                tokio::select! {
-                    _ = cancel_token.cancelled() => {
-                        debug!("OTA update channel closed by handle");
-                        self.clear().await;
-                    }
-                    ota_status = self.handle_ota_event(OtaStatus::Idle, &respond_to, data) => {
-                        let _ = respond_to.send(ota_status).await;
+                // The update can be cancelled until the bundle is installed, RAUC cannot abort an
+                // installation once it's started.
+                let ota_status = tokio::select! {
+                    _ = cancel_token.cancelled() => None,
+                    ota_status = self.prepare(&respond_to, data) => Some(ota_status),
+                };
+
+                // The cancel could have raced with the last transition
+                let ota_status = match ota_status {
+                    Some(ota_status) if !cancel_token.is_cancelled() => ota_status,
+                    _ => {
+                        info!("OTA update cancelled");
+                        self.cancel().await;
+
+                        return;
                     }
-                }
+                };
+
+                let ota_status = self
+                    .handle_ota_event(ota_status, &respond_to, HashMap::new())
+                    .await;
+                let _ = respond_to.send(ota_status).await;
             }
             OtaMessage::EnsurePendingOta { respond_to } => {
                 let ota_status = self
@@ -164,6 +211,9 @@ where
             OtaMessage::GetOtaStatus { respond_to } => {
                 let _ = respond_to.send(self.ota_status.read().await.clone());
             }
+            OtaMessage::GetSlotStatus { respond_to } => {
+                let _ = respond_to.send(self.system_update.slot_status().await);
+            }
         }
     }
 
@@ -175,6 +225,62 @@ where
         self.download_file_path.join("update.bin")
     }
 
+    /// File with the UUID of the update the partial download belongs to.
+    fn get_partial_marker_path(&self) -> PathBuf {
+        self.download_file_path.join("update.uuid")
+    }
+
+    /// Download the bundle, resuming the partial download of the same update if enabled.
+    async fn download(
+        &self,
+        ota_request: &OtaRequest,
+        ota_status_publisher: &mpsc::Sender<OtaStatus>,
+    ) -> Result<(), OtaError> {
+        let download_file_path = self.get_update_file_path();
+
+        let offset = if self.resume_download {
+            self.resume_offset(&ota_request.uuid, &download_file_path)
+                .await
+        } else {
+            0
+        };
+
+        wget_from(
+            &self.client,
+            &ota_request.url,
+            &download_file_path,
+            &ota_request.uuid,
+            ota_status_publisher,
+            offset,
+        )
+        .await
+    }
+
+    /// Size of the partial download of the update, or 0 to start the download from scratch.
+    async fn resume_offset(&self, uuid: &Uuid, file_path: &Path) -> u64 {
+        let marker_path = self.get_partial_marker_path();
+
+        let partial = tokio::fs::read_to_string(&marker_path)
+            .await
+            .ok()
+            .and_then(|content| Uuid::parse_str(content.trim()).ok());
+
+        if partial.as_ref() == Some(uuid) {
+            if let Ok(metadata) = tokio::fs::metadata(file_path).await {
+                return metadata.len();
+            }
+        }
+
+        if let Err(err) = tokio::fs::write(&marker_path, uuid.to_string()).await {
+            warn!(
+                "couldn't write the partial download marker {}: {err}",
+                marker_path.display()
+            );
+        }
+
+        0
+    }
+
     /// Handle the transition to the acknowledged status.
     pub async fn acknowledged(
         &self,
@@ -201,9 +307,15 @@ where
                 }
             };
 
+            let sha256 = match data.get("sha256") {
+                Some(AstarteType::String(sha256)) => Some(sha256.clone()),
+                _ => None,
+            };
+
             let ota_request = OtaRequest {
                 uuid: request_uuid,
                 url: request_url.to_string(),
+                sha256,
             };
 
             let ack_status = OtaStatus::Acknowledged(ota_request);
@@ -224,7 +336,7 @@ where
         ota_request: OtaRequest,
         ota_status_publisher: &mpsc::Sender<OtaStatus>,
     ) -> OtaStatus {
-        let downloading_status = OtaStatus::Downloading(ota_request, 0);
+        let downloading_status = OtaStatus::Downloading(ota_request, DownloadProgress::default());
         if ota_status_publisher
             .send(downloading_status.clone())
             .await
@@ -250,13 +362,9 @@ where
             );
         };
 
-        let mut ota_download_result = wget(
-            &ota_request.url,
-            &download_file_path,
-            &ota_request.uuid,
-            ota_status_publisher,
-        )
-        .await;
+        self.connectivity.wait_for_download().await;
+
+        let mut ota_download_result = self.download(&ota_request, ota_status_publisher).await;
         for i in 1..5 {
             if let Err(error) = ota_download_result {
                 let wait = u64::pow(2, i);
@@ -273,13 +381,9 @@ where
                 }
 
                 tokio::time::sleep(tokio::time::Duration::from_secs(wait)).await;
-                ota_download_result = wget(
-                    &ota_request.url,
-                    &download_file_path,
-                    &ota_request.uuid,
-                    ota_status_publisher,
-                )
-                .await;
+                self.connectivity.wait_for_download().await;
+
+                ota_download_result = self.download(&ota_request, ota_status_publisher).await;
             } else {
                 break;
             }
@@ -288,6 +392,14 @@ where
         if let Err(error) = ota_download_result {
             OtaStatus::Failure(error, Some(ota_request.clone()))
         } else {
+            if let Some(sha256) = &ota_request.sha256 {
+                if let Err(error) = verify_sha256(&download_file_path, sha256).await {
+                    error!("{error}");
+
+                    return OtaStatus::Failure(error, Some(ota_request.clone()));
+                }
+            }
+
             let bundle_info = self.system_update.info(download_file_str).await;
             if bundle_info.is_err() {
                 let message = format!(
@@ -348,8 +460,13 @@ where
                 return OtaStatus::Failure(OtaError::IO(message), Some(ota_request.clone()));
             };
 
-            let deploying_state =
-                OtaStatus::Deploying(ota_request.clone(), DeployProgress::default());
+            let deploying_state = OtaStatus::Deploying(
+                ota_request.clone(),
+                DeployProgress {
+                    percentage: 0,
+                    message: "Installing the update".to_string(),
+                },
+            );
             if ota_status_publisher
                 .send(deploying_state.clone())
                 .await
@@ -505,6 +622,7 @@ where
         let ota_request = OtaRequest {
             uuid: request_uuid,
             url: "".to_string(),
+            sha256: None,
         };
 
         if let Err(error) = self.do_pending_ota(&ota_state).await {
@@ -516,6 +634,7 @@ where
 
     pub async fn do_pending_ota(&self, state: &PersistentState) -> Result<(), OtaError> {
         const GOOD_STATE: &str = "good";
+        const BAD_STATE: &str = "bad";
 
         let booted_slot = self.system_update.boot_slot().await.map_err(|error| {
             let message = "Unable to identify the booted slot";
@@ -534,6 +653,23 @@ where
             OtaError::Internal(message)
         })?;
 
+        if let Some(self_test) = &self.self_test {
+            if let Err(error) = self_test.run().await {
+                error!("{SELF_TEST_FAILED}: {error}");
+
+                self.system_update
+                    .mark(BAD_STATE, &primary_slot)
+                    .await
+                    .map_err(|error| {
+                        let message = "Unable to mark the slot as bad";
+                        error!("{message}: {error}");
+                        OtaError::Internal(message)
+                    })?;
+
+                return Err(OtaError::SystemRollback(SELF_TEST_FAILED));
+            }
+        }
+
         let (marked_slot, _) = self
             .system_update
             .mark(GOOD_STATE, &primary_slot)
@@ -552,14 +688,40 @@ where
         }
     }
 
+    /// Advance the update until the bundle needs to be installed, or the update ends.
+    async fn prepare(
+        &self,
+        ota_status_publisher: &mpsc::Sender<OtaStatus>,
+        data: HashMap<String, AstarteType>,
+    ) -> OtaStatus {
+        self.advance(OtaStatus::Idle, ota_status_publisher, data, |ota_status| {
+            matches!(ota_status, OtaStatus::Deploying(_, _))
+        })
+        .await
+    }
+
     pub async fn handle_ota_event(
         &self,
         ota_status: OtaStatus,
         ota_status_publisher: &mpsc::Sender<OtaStatus>,
         data: HashMap<String, AstarteType>,
     ) -> OtaStatus {
-        let mut ota_status = ota_status.clone();
+        let ota_status = self
+            .advance(ota_status, ota_status_publisher, data, |_| false)
+            .await;
 
+        self.clear().await;
+        ota_status
+    }
+
+    /// Advance the update until it ends or `stop` returns true for the current status.
+    async fn advance(
+        &self,
+        mut ota_status: OtaStatus,
+        ota_status_publisher: &mpsc::Sender<OtaStatus>,
+        data: HashMap<String, AstarteType>,
+        stop: fn(&OtaStatus) -> bool,
+    ) -> OtaStatus {
         loop {
             ota_status = match ota_status {
                 OtaStatus::Idle => OtaStatus::Init,
@@ -587,28 +749,49 @@ where
             };
 
             *self.ota_status.write().await = ota_status.clone();
+
+            if stop(&ota_status) {
+                break;
+            }
         }
 
-        self.clear().await;
         ota_status
     }
 
+    /// Restore the state after the update was cancelled.
+    async fn cancel(&self) {
+        if self.resume_download {
+            info!("keeping the partial download to resume it");
+        } else {
+            self.remove_download().await;
+        }
+
+        self.clear_state().await;
+    }
+
     async fn clear(&self) {
+        self.remove_download().await;
+        self.clear_state().await;
+    }
+
+    async fn clear_state(&self) {
         if self.state_repository.exists().await {
             let _ = self.state_repository.clear().await.map_err(|error| {
                 warn!("Error during clear of state repository-> {:?}", error);
             });
         }
 
-        if let Some(path) = self.get_update_file_path().to_str() {
-            if std::path::Path::new(&path).exists() {
-                if let Err(e) = tokio::fs::remove_file(path).await {
-                    error!("Unable to remove {}: {}", path, e);
+        *self.ota_status.write().await = OtaStatus::Idle;
+    }
+
+    async fn remove_download(&self) {
+        for path in [self.get_update_file_path(), self.get_partial_marker_path()] {
+            if path.exists() {
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    error!("Unable to remove {}: {}", path.display(), e);
                 }
             }
         }
-
-        *self.ota_status.write().await = OtaStatus::Idle;
     }
 }
 
@@ -629,14 +812,59 @@ where
 }
 
 pub async fn wget(
+    client: &reqwest::Client,
     url: &str,
     file_path: &Path,
     request_uuid: &Uuid,
     ota_status_publisher: &mpsc::Sender<OtaStatus>,
+) -> Result<(), OtaError> {
+    wget_from(
+        client,
+        url,
+        file_path,
+        request_uuid,
+        ota_status_publisher,
+        0,
+    )
+    .await
+}
+
+/// Check the SHA-256 digest of the downloaded bundle.
+async fn verify_sha256(file_path: &Path, expected: &str) -> Result<(), OtaError> {
+    let path = file_path.to_owned();
+    let digest = tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+
+        std::io::copy(&mut file, &mut hasher)?;
+
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await
+    .map_err(|_| OtaError::Internal("couldn't compute the digest of the bundle"))?
+    .map_err(|err| OtaError::IO(format!("couldn't read the bundle: {err}")))?;
+
+    if !digest.eq_ignore_ascii_case(expected) {
+        return Err(OtaError::InvalidBaseImage(format!(
+            "the bundle digest {digest} doesn't match the expected {expected}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Download the file, resuming it from the offset if the server supports range requests.
+pub async fn wget_from(
+    client: &reqwest::Client,
+    url: &str,
+    file_path: &Path,
+    request_uuid: &Uuid,
+    ota_status_publisher: &mpsc::Sender<OtaStatus>,
+    offset: u64,
 ) -> Result<(), OtaError> {
     use tokio_stream::StreamExt;
 
-    if file_path.exists() {
+    if offset == 0 && file_path.exists() {
         tokio::fs::remove_file(file_path).await.map_err(|err| {
             error!(
                 "failed to remove old file '{}': {}",
@@ -650,7 +878,14 @@ pub async fn wget(
 
     info!("Downloading {:?}", url);
 
-    let result_response = reqwest::get(url).await;
+    let mut request = client.get(url);
+    if offset > 0 {
+        info!("Resuming the download from byte {offset}");
+
+        request = request.header(reqwest::header::RANGE, format!("bytes={offset}-"));
+    }
+
+    let result_response = request.send().await;
 
     match result_response {
         Err(err) => {
@@ -658,21 +893,50 @@ pub async fn wget(
             error!("{message}: {err:?}");
             Err(OtaError::Network(message))
         }
+        Ok(response) if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE => {
+            // Start from scratch on the next attempt
+            if let Err(err) = tokio::fs::remove_file(file_path).await {
+                error!(
+                    "failed to remove partial file '{}': {err}",
+                    file_path.display()
+                );
+            }
+
+            Err(OtaError::Network(format!(
+                "Unable to resume the download from byte {offset}"
+            )))
+        }
         Ok(response) => {
             debug!("Writing {}", file_path.display());
 
+            // The server could ignore the range and send the whole file
+            let offset = if response.status() == StatusCode::PARTIAL_CONTENT {
+                offset
+            } else {
+                0
+            };
+
             let total_size = response
                 .content_length()
                 .and_then(|size| if size == 0 { None } else { Some(size) })
+                .map(|size| size + offset)
                 .ok_or_else(|| {
                     OtaError::Network(format!("Unable to get content length from: {url}"))
-                })? as f64;
+                })?;
 
-            let mut downloaded: f64 = 0.0;
+            let mut downloaded: u64 = offset;
             let mut last_percentage_sent = 0.0;
             let mut stream = response.bytes_stream();
 
-            let mut os_file = tokio::fs::File::create(file_path).await.map_err(|error| {
+            let os_file = if offset > 0 {
+                tokio::fs::OpenOptions::new()
+                    .append(true)
+                    .open(file_path)
+                    .await
+            } else {
+                tokio::fs::File::create(file_path).await
+            };
+            let mut os_file = os_file.map_err(|error| {
                 let message = format!("Unable to create ota_file in {file_path:?}");
                 error!("{message} : {error:?}");
                 OtaError::IO(message)
@@ -699,8 +963,8 @@ pub async fn wget(
                         OtaError::IO(message)
                     })?;
 
-                downloaded += chunk.len() as f64;
-                let progress_percentage = (downloaded / total_size) * 100.0;
+                downloaded += chunk.len() as u64;
+                let progress_percentage = (downloaded as f64 / total_size as f64) * 100.0;
                 if progress_percentage == 100.0
                     || (progress_percentage - last_percentage_sent) >= DOWNLOAD_PERC_ROUNDING_STEP
                 {
@@ -710,8 +974,13 @@ pub async fn wget(
                             OtaRequest {
                                 uuid: *request_uuid,
                                 url: "".to_string(),
+                                sha256: None,
+                            },
+                            DownloadProgress {
+                                percentage: progress_percentage as i32,
+                                downloaded,
+                                total: total_size,
                             },
-                            progress_percentage as i32,
                         ))
                         .await
                         .is_err()
@@ -744,13 +1013,18 @@ mod tests {
     use futures::StreamExt;
     use httpmock::prelude::*;
     use tempdir::TempDir;
-    use tokio::sync::{mpsc, RwLock};
+    use tokio::sync::{mpsc, oneshot, RwLock};
     use uuid::Uuid;
 
+    use crate::connectivity::ConnectivityWatch;
     use crate::error::DeviceManagerError;
-    use crate::ota::ota_handle::{wget, Ota, OtaRequest, OtaStatus, PersistentState};
+    use crate::ota::ota_handle::{
+        wget, wget_from, DownloadProgress, Ota, OtaMessage, OtaRequest, OtaStatus, PersistentState,
+        SELF_TEST_FAILED,
+    };
     use crate::ota::ota_handler_test::deploy_status_stream;
-    use crate::ota::rauc::BundleInfo;
+    use crate::ota::rauc::{BundleInfo, Slot, SlotStatus};
+    use crate::ota::self_test::SelfTestConfig;
     use crate::ota::{DeployProgress, DeployStatus, MockSystemUpdate, OtaError, SystemUpdate};
     use crate::repository::file_state_repository::FileStateError;
     use crate::repository::{MockStateRepository, StateRepository};
@@ -774,7 +1048,11 @@ mod tests {
                 system_update,
                 state_repository,
                 download_file_path: PathBuf::from("/dev/null"),
+                resume_download: false,
                 ota_status: Arc::new(RwLock::new(OtaStatus::Idle)),
+                self_test: None,
+                connectivity: ConnectivityWatch::default(),
+                client: reqwest::Client::new(),
             }
         }
 
@@ -789,7 +1067,11 @@ mod tests {
                 system_update,
                 state_repository,
                 download_file_path: path,
+                resume_download: false,
                 ota_status: Arc::new(RwLock::new(OtaStatus::Idle)),
+                self_test: None,
+                connectivity: ConnectivityWatch::default(),
+                client: reqwest::Client::new(),
             };
 
             (mock, dir)
@@ -813,6 +1095,38 @@ mod tests {
         assert_eq!("Unable to deploy image", last_error_result.unwrap());
     }
 
+    #[tokio::test]
+    async fn get_slot_status() {
+        let mut system_update = MockSystemUpdate::new();
+        let state_mock = MockStateRepository::<PersistentState>::new();
+
+        system_update.expect_slot_status().returning(|| {
+            Ok(vec![Slot {
+                name: "rootfs.0".to_string(),
+                data: SlotStatus {
+                    boot_status: Some("good".to_string()),
+                    bootname: Some("A".to_string()),
+                    class: "rootfs".to_string(),
+                    device: "/dev/mmcblk0p2".to_string(),
+                    state: "booted".to_string(),
+                    type_: "ext4".to_string(),
+                    bundle_version: Some("1.2.0".to_string()),
+                },
+            }])
+        });
+
+        let ota = Ota::mock_new(system_update, state_mock);
+
+        let (respond_to, receiver) = oneshot::channel();
+        ota.handle_message(OtaMessage::GetSlotStatus { respond_to })
+            .await;
+
+        let slots = receiver.await.unwrap().unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].name, "rootfs.0");
+        assert_eq!(slots[0].data.state, "booted");
+    }
+
     #[tokio::test]
     async fn last_error_fail() {
         let mut system_update = MockSystemUpdate::new();
@@ -994,7 +1308,10 @@ mod tests {
         let receive_result = ota_status_receiver.try_recv();
         assert!(receive_result.is_ok());
         let ota_status_received = receive_result.unwrap();
-        assert!(matches!(ota_status_received, OtaStatus::Downloading(_, 0)));
+        assert!(matches!(
+            ota_status_received,
+            OtaStatus::Downloading(_, DownloadProgress { percentage: 0, .. })
+        ));
 
         let receive_result = ota_status_receiver.try_recv();
         assert!(receive_result.is_err());
@@ -1040,7 +1357,13 @@ mod tests {
         let ota_status_received = receive_result.unwrap();
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(
+                _,
+                DownloadProgress {
+                    percentage: 100,
+                    ..
+                }
+            )
         ));
 
         let receive_result = ota_status_receiver.try_recv();
@@ -1145,8 +1468,49 @@ mod tests {
         let ota_status_received = receive_result.unwrap();
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(
+                _,
+                DownloadProgress {
+                    percentage: 100,
+                    ..
+                }
+            )
+        ));
+
+        assert!(matches!(
+            ota_status,
+            OtaStatus::Failure(OtaError::InvalidBaseImage(_), _),
         ));
+    }
+
+    #[tokio::test]
+    async fn try_to_deploying_fail_sha256() {
+        let state_mock = MockStateRepository::<PersistentState>::new();
+        let mut system_update = MockSystemUpdate::new();
+
+        system_update.expect_info().never();
+
+        let mut ota_request = OtaRequest::default();
+        let binary_content = b"\x80\x02\x03";
+        let binary_size = binary_content.len();
+
+        let server = MockServer::start_async().await;
+        ota_request.url = server.url("/ota.bin");
+        ota_request.sha256 = Some(hex::encode(Sha256::digest(b"other bundle")));
+        let mock_ota_file_request = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/ota.bin");
+                then.status(200)
+                    .header("content-Length", binary_size.to_string())
+                    .body(binary_content);
+            })
+            .await;
+
+        let (ota, _dir) = Ota::mock_new_with_path(system_update, state_mock, "fail_sha256");
+        let (ota_status_publisher, _ota_status_receiver) = mpsc::channel(1);
+
+        let ota_status = ota.deploying(ota_request, &ota_status_publisher).await;
+        mock_ota_file_request.assert_async().await;
 
         assert!(matches!(
             ota_status,
@@ -1197,7 +1561,13 @@ mod tests {
         let ota_status_received = receive_result.unwrap();
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(
+                _,
+                DownloadProgress {
+                    percentage: 100,
+                    ..
+                }
+            )
         ));
 
         let receive_result = ota_status_receiver.try_recv();
@@ -1252,7 +1622,13 @@ mod tests {
         let ota_status_received = receive_result.unwrap();
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(
+                _,
+                DownloadProgress {
+                    percentage: 100,
+                    ..
+                }
+            )
         ));
 
         let receive_result = ota_status_receiver.try_recv();
@@ -1313,7 +1689,13 @@ mod tests {
         let ota_status_received = receive_result.unwrap();
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(
+                _,
+                DownloadProgress {
+                    percentage: 100,
+                    ..
+                }
+            )
         ));
 
         let receive_result = ota_status_receiver.try_recv();
@@ -1433,7 +1815,13 @@ mod tests {
         let ota_status_received = receive_result.unwrap();
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(
+                _,
+                DownloadProgress {
+                    percentage: 100,
+                    ..
+                }
+            )
         ));
 
         let receive_result = ota_status_receiver.try_recv();
@@ -1932,6 +2320,49 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn do_pending_ota_self_test_rollback() {
+        let uuid = Uuid::new_v4();
+
+        let state_mock = MockStateRepository::<PersistentState>::new();
+
+        let mut system_update = MockSystemUpdate::new();
+        system_update
+            .expect_boot_slot()
+            .returning(|| Ok("B".to_owned()));
+        system_update
+            .expect_get_primary()
+            .returning(|| Ok("rootfs.0".to_owned()));
+        system_update
+            .expect_mark()
+            .withf(|state: &str, slot: &str| state == "bad" && slot == "rootfs.0")
+            .once()
+            .returning(|_: &str, _: &str| {
+                Ok((
+                    "rootfs.0".to_owned(),
+                    "marked slot rootfs.0 as bad".to_owned(),
+                ))
+            });
+
+        let mut ota = Ota::mock_new(system_update, state_mock);
+        ota.self_test = Some(SelfTestConfig {
+            timeout: 0,
+            units: Vec::new(),
+            http_probes: Vec::new(),
+            scripts: vec![PathBuf::from("false")],
+        });
+
+        let state = PersistentState {
+            uuid,
+            slot: "A".to_owned(),
+        };
+        let result = ota.do_pending_ota(&state).await;
+        assert!(matches!(
+            result,
+            Err(OtaError::SystemRollback(SELF_TEST_FAILED))
+        ));
+    }
+
     #[tokio::test]
     async fn wget_failed() {
         let (_dir, t_dir) = temp_dir("wget_failed");
@@ -1948,6 +2379,7 @@ mod tests {
         let (ota_status_publisher, _) = mpsc::channel(1);
 
         let result = wget(
+            &reqwest::Client::new(),
             server.url("/ota.bin").as_str(),
             &ota_file,
             &Uuid::new_v4(),
@@ -1983,6 +2415,7 @@ mod tests {
         let (ota_status_publisher, _) = mpsc::channel(1);
 
         let result = wget(
+            &reqwest::Client::new(),
             ota_url.as_str(),
             &ota_file,
             &uuid_request,
@@ -2011,6 +2444,7 @@ mod tests {
         let (ota_status_publisher, _) = mpsc::channel(1);
 
         let result = wget(
+            &reqwest::Client::new(),
             server.url("/ota.bin").as_str(),
             &ota_file,
             &Uuid::new_v4(),
@@ -2047,6 +2481,7 @@ mod tests {
         let (ota_status_publisher, mut ota_status_receiver) = mpsc::channel(1);
 
         let result = wget(
+            &reqwest::Client::new(),
             ota_url.as_str(),
             &ota_file,
             &uuid_request,
@@ -2058,9 +2493,14 @@ mod tests {
         let receive_result = ota_status_receiver.try_recv();
         assert!(receive_result.is_ok());
         let ota_status_received = receive_result.unwrap();
+        let expected_progress = DownloadProgress {
+            percentage: 100,
+            downloaded: binary_size as u64,
+            total: binary_size as u64,
+        };
         assert!(matches!(
             ota_status_received,
-            OtaStatus::Downloading(_, 100)
+            OtaStatus::Downloading(_, progress) if progress == expected_progress
         ));
 
         let receive_result = ota_status_receiver.try_recv();
@@ -2068,4 +2508,89 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn wget_from_resume() {
+        let (_dir, t_dir) = temp_dir("wget_from_resume");
+
+        let server = MockServer::start_async().await;
+        let ota_url = server.url("/ota.bin");
+        let mock_ota_file_request = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/ota.bin")
+                    .header("range", "bytes=2-");
+                then.status(206).header("content-Length", "1").body(b"\x03");
+            })
+            .await;
+
+        let ota_file = t_dir.join("ota.bin");
+        tokio::fs::write(&ota_file, b"\x80\x02").await.unwrap();
+
+        let (ota_status_publisher, mut ota_status_receiver) = mpsc::channel(1);
+
+        let result = wget_from(
+            &reqwest::Client::new(),
+            ota_url.as_str(),
+            &ota_file,
+            &Uuid::new_v4(),
+            &ota_status_publisher,
+            2,
+        )
+        .await;
+        mock_ota_file_request.assert_async().await;
+
+        assert!(result.is_ok());
+
+        let expected_progress = DownloadProgress {
+            percentage: 100,
+            downloaded: 3,
+            total: 3,
+        };
+        assert!(matches!(
+            ota_status_receiver.try_recv(),
+            Ok(OtaStatus::Downloading(_, progress)) if progress == expected_progress
+        ));
+
+        let content = tokio::fs::read(&ota_file).await.unwrap();
+        assert_eq!(content, b"\x80\x02\x03");
+    }
+
+    #[tokio::test]
+    async fn cancel_partial_download() {
+        let uuid = Uuid::new_v4();
+
+        for resume in [true, false] {
+            let mut state_mock = MockStateRepository::<PersistentState>::new();
+            state_mock.expect_exists().returning(|| false);
+
+            let (mut ota, _dir) =
+                Ota::mock_new_with_path(MockSystemUpdate::new(), state_mock, "cancel_partial");
+            ota.resume_download = resume;
+
+            let file_path = ota.get_update_file_path();
+            tokio::fs::write(&file_path, b"\x80\x02").await.unwrap();
+            assert_eq!(ota.resume_offset(&uuid, &file_path).await, 0);
+
+            *ota.ota_status.write().await = OtaStatus::Downloading(
+                OtaRequest {
+                    uuid,
+                    url: "".to_string(),
+                    sha256: None,
+                },
+                DownloadProgress::default(),
+            );
+
+            ota.cancel().await;
+
+            assert_eq!(*ota.ota_status.read().await, OtaStatus::Idle);
+            assert_eq!(file_path.exists(), resume);
+            assert_eq!(ota.get_partial_marker_path().exists(), resume);
+
+            if resume {
+                assert_eq!(ota.resume_offset(&uuid, &file_path).await, 2);
+                assert_eq!(ota.resume_offset(&Uuid::new_v4(), &file_path).await, 0);
+            }
+        }
+    }
 }
@@ -25,17 +25,20 @@ use std::sync::Arc;
 
 use astarte_device_sdk::types::AstarteType;
 use astarte_device_sdk::AstarteAggregate;
-use log::{debug, error};
+use log::{debug, error, info, warn};
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use crate::connectivity::ConnectivityWatch;
 use crate::data::Publisher;
 use crate::error::DeviceManagerError;
-use crate::ota::ota_handle::{Ota, OtaMessage, OtaRequest, OtaStatus};
-use crate::ota::rauc::OTARauc;
+use crate::ota::ota_handle::{Ota, OtaMessage, OtaRequest, OtaStatus, SELF_TEST_FAILED};
+use crate::ota::rauc::{OTARauc, Slot};
 use crate::ota::OtaError;
+use crate::rate_limit::RateLimiter;
 use crate::repository::file_state_repository::FileStateRepository;
+use crate::state::{OtaState, RuntimeState};
 
 use super::ota_handle::PersistentState;
 
@@ -54,6 +57,16 @@ pub struct OtaEvent {
     pub message: String,
 }
 
+impl From<OtaEvent> for OtaState {
+    fn from(value: OtaEvent) -> Self {
+        Self {
+            request_uuid: value.requestUUID,
+            status: value.status,
+            progress: value.statusProgress,
+        }
+    }
+}
+
 struct OtaStatusMessage {
     status_code: String,
     message: String,
@@ -64,6 +77,10 @@ struct OtaStatusMessage {
 pub struct OtaHandler {
     pub sender: mpsc::Sender<OtaMessage>,
     pub ota_cancellation: Arc<RwLock<Option<CancellationToken>>>,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub state: RuntimeState,
+    /// Reason the updates are refused, if the store or download directories aren't persistent.
+    pub persistence_error: Option<String>,
 }
 
 impl FromStr for OtaOperation {
@@ -79,7 +96,11 @@ impl FromStr for OtaOperation {
 }
 
 impl OtaHandler {
-    pub async fn new(opts: &crate::DeviceManagerOptions) -> Result<Self, DeviceManagerError> {
+    pub async fn new(
+        opts: &crate::DeviceManagerOptions,
+        state: RuntimeState,
+        connectivity: ConnectivityWatch,
+    ) -> Result<Self, DeviceManagerError> {
         let (sender, receiver) = mpsc::channel(8);
         let system_update = OTARauc::new().await?;
 
@@ -89,13 +110,22 @@ impl OtaHandler {
             opts,
             system_update,
             state_repository,
+            connectivity,
         )
         .await?;
         tokio::spawn(crate::ota::ota_handle::run_ota(ota, receiver));
 
+        let rate_limiter = opts
+            .rate_limits
+            .ota
+            .map(|config| Arc::new(RateLimiter::new(&opts.store_directory, "ota", config)));
+
         Ok(Self {
             sender,
             ota_cancellation: Arc::new(RwLock::new(None)),
+            rate_limiter,
+            state,
+            persistence_error: crate::storage_check::ota_persistence_error(opts),
         })
     }
 
@@ -115,7 +145,19 @@ impl OtaHandler {
         }
 
         while let Some(ota_status) = ota_status_receiver.recv().await {
-            send_ota_event(sdk, &ota_status).await?;
+            self.update_state(&ota_status);
+            let sent = send_ota_event(sdk, &ota_status).await;
+
+            // The slot was marked bad, reboot to fall back to the previous one only after the
+            // failure was published, since the rolled back runtime doesn't know about it
+            if matches!(
+                ota_status,
+                OtaStatus::Failure(OtaError::SystemRollback(SELF_TEST_FAILED), _)
+            ) {
+                Self::reboot_to_rollback().await;
+            }
+
+            sent?;
 
             if let OtaStatus::Failure(ota_error, _) = ota_status {
                 return Err(DeviceManagerError::OtaError(ota_error));
@@ -125,6 +167,41 @@ impl OtaHandler {
         Ok(())
     }
 
+    #[cfg(not(test))]
+    async fn reboot_to_rollback() {
+        info!("Rebooting the device to roll back the update");
+
+        if let Err(error) = crate::power_management::reboot().await {
+            error!("Unable to run reboot command : {error}");
+        }
+    }
+
+    #[cfg(test)]
+    async fn reboot_to_rollback() {}
+
+    /// Status of the RAUC slots.
+    pub async fn slot_status(&self) -> Result<Vec<Slot>, DeviceManagerError> {
+        let (respond_to, receiver) = oneshot::channel();
+        let msg = OtaMessage::GetSlotStatus { respond_to };
+
+        self.sender.send(msg).await.map_err(|_| {
+            DeviceManagerError::OtaError(OtaError::Internal(
+                "Unable to get the slot status, receiver channel dropped",
+            ))
+        })?;
+
+        receiver.await.map_err(|_| {
+            DeviceManagerError::OtaError(OtaError::Internal("Unable to get the slot status"))
+        })?
+    }
+
+    /// Share the phase of the OTA with the local services.
+    fn update_state(&self, ota_status: &OtaStatus) {
+        if ota_status.ota_request().is_some() {
+            self.state.set_ota(OtaEvent::from(ota_status).into());
+        }
+    }
+
     async fn get_ota_status(&self) -> Result<OtaStatus, DeviceManagerError> {
         let (ota_status_publisher, ota_status_receiver) = oneshot::channel();
         let msg = OtaMessage::GetOtaStatus {
@@ -191,9 +268,25 @@ impl OtaHandler {
 
         self.check_update_already_in_progress(uuid, sdk).await?;
 
+        self.check_rate_limit(uuid, sdk).await?;
+
+        self.check_persistence(uuid, sdk).await?;
+
         let mut ota_status_receiver = self.start_ota_update(data).await?;
+        let cancel_token = self.ota_cancellation.read().await.clone();
 
         while let Some(ota_status) = ota_status_receiver.recv().await {
+            // The Canceled event is the last one sent for a cancelled update
+            if cancel_token
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                debug!("OTA update cancelled, ignoring status {ota_status:?}");
+
+                break;
+            }
+
+            self.update_state(&ota_status);
             send_ota_event(sdk, &ota_status).await?;
 
             //After entering in Deploying state the OTA cannot be stopped.
@@ -207,6 +300,38 @@ impl OtaHandler {
         Ok(())
     }
 
+    /// Cancels the update in progress on shutdown, publishing the cancellation as its last event.
+    ///
+    /// The update can be cancelled only until it enters the deploying state, after that it's
+    /// awaited to not leave the partition half written.
+    pub async fn cancel_on_shutdown<P>(&self, sdk: &P) -> Result<(), DeviceManagerError>
+    where
+        P: Publisher + Send + Sync,
+    {
+        let mut ota_cancellation = self.ota_cancellation.write().await;
+        let Some(ota_token) = ota_cancellation.take() else {
+            return Ok(());
+        };
+
+        let ota_request = match self.get_ota_status().await {
+            Ok(ota_status) => ota_status.ota_request().cloned(),
+            Err(err) => {
+                warn!("couldn't get the status of the cancelled OTA: {err}");
+
+                None
+            }
+        };
+
+        info!("cancelling the OTA update");
+        ota_token.cancel();
+
+        let canceled = OtaStatus::Failure(OtaError::Canceled, ota_request);
+        self.update_state(&canceled);
+        send_ota_event(sdk, &canceled).await?;
+
+        Ok(())
+    }
+
     /// Sends the cancellation token and channel to start the update process.
     pub(crate) async fn start_ota_update(
         &self,
@@ -257,6 +382,7 @@ impl OtaHandler {
                                 Some(OtaRequest {
                                     uuid,
                                     url: "".to_string(),
+                                    sha256: None,
                                 }),
                             ),
                         )
@@ -270,6 +396,64 @@ impl OtaHandler {
         }
     }
 
+    async fn check_rate_limit<P>(&self, uuid: Uuid, sdk: &P) -> Result<(), DeviceManagerError>
+    where
+        P: Publisher + Send + Sync,
+    {
+        let Some(rate_limiter) = &self.rate_limiter else {
+            return Ok(());
+        };
+
+        if let Err(exceeded) = rate_limiter.try_acquire().await {
+            exceeded.report(sdk).await;
+
+            let ota_error = OtaError::RateLimited(exceeded.to_string());
+            send_ota_event(
+                sdk,
+                &OtaStatus::Failure(
+                    ota_error.clone(),
+                    Some(OtaRequest {
+                        uuid,
+                        url: "".to_string(),
+                        sha256: None,
+                    }),
+                ),
+            )
+            .await?;
+
+            return Err(DeviceManagerError::OtaError(ota_error));
+        }
+
+        Ok(())
+    }
+
+    async fn check_persistence<P>(&self, uuid: Uuid, sdk: &P) -> Result<(), DeviceManagerError>
+    where
+        P: Publisher + Send + Sync,
+    {
+        let Some(message) = &self.persistence_error else {
+            return Ok(());
+        };
+
+        error!("refusing the update: {message}");
+
+        let ota_error = OtaError::IO(message.clone());
+        send_ota_event(
+            sdk,
+            &OtaStatus::Failure(
+                ota_error.clone(),
+                Some(OtaRequest {
+                    uuid,
+                    url: "".to_string(),
+                    sha256: None,
+                }),
+            ),
+        )
+        .await?;
+
+        Err(DeviceManagerError::OtaError(ota_error))
+    }
+
     async fn handle_cancel<P>(
         &self,
         sdk: &P,
@@ -291,6 +475,7 @@ impl OtaHandler {
         let cancel_ota_request = OtaRequest {
             uuid: request_uuid,
             url: "".to_string(),
+            sha256: None,
         };
 
         let ota_status = match self.get_ota_status().await {
@@ -333,15 +518,31 @@ impl OtaHandler {
                 )
                 .await?;
             }
+            // RAUC can't abort an installation, so the update can't be rolled back once the bundle
+            // starts to be installed. The Error status doesn't end the update, it keeps going.
+            Some(_)
+                if matches!(
+                    ota_status,
+                    OtaStatus::Deploying(_, _) | OtaStatus::Deployed(_) | OtaStatus::Rebooting(_)
+                ) =>
+            {
+                send_ota_event(
+                    sdk,
+                    &OtaStatus::Error(
+                        OtaError::CancelRejected("the update is already being deployed"),
+                        cancel_ota_request,
+                    ),
+                )
+                .await?;
+            }
             _ => {
                 let mut ota_cancellation = self.ota_cancellation.write().await;
                 if let Some(ota_token) = ota_cancellation.take() {
                     ota_token.cancel();
-                    send_ota_event(
-                        sdk,
-                        &OtaStatus::Failure(OtaError::Canceled, Some(cancel_ota_request)),
-                    )
-                    .await?;
+
+                    let canceled = OtaStatus::Failure(OtaError::Canceled, Some(cancel_ota_request));
+                    self.update_state(&canceled);
+                    send_ota_event(sdk, &canceled).await?;
                 } else {
                     send_ota_event(
                         sdk,
@@ -376,8 +577,11 @@ impl From<&OtaStatus> for OtaEvent {
             }
             OtaStatus::Downloading(ota_request, progress) => {
                 ota_event.requestUUID = ota_request.uuid.to_string();
-                ota_event.statusProgress = *progress;
+                ota_event.statusProgress = progress.percentage;
                 ota_event.status = "Downloading".to_string();
+                if progress.total > 0 {
+                    ota_event.message = format!("{}/{} bytes", progress.downloaded, progress.total);
+                }
             }
             OtaStatus::Deploying(ota_request, deploying_progress) => {
                 ota_event.requestUUID = ota_request.uuid.to_string();
@@ -455,6 +659,14 @@ impl From<&OtaError> for OtaStatusMessage {
                 ota_status_message.message = message.to_string()
             }
             OtaError::Canceled => ota_status_message.status_code = "Canceled".to_string(),
+            OtaError::RateLimited(message) => {
+                ota_status_message.status_code = "RateLimited".to_string();
+                ota_status_message.message = message.to_string()
+            }
+            OtaError::CancelRejected(message) => {
+                ota_status_message.status_code = "CancelRejected".to_string();
+                ota_status_message.message = message.to_string()
+            }
         }
 
         ota_status_message
@@ -478,6 +690,9 @@ where
         ));
     }
 
+    #[cfg(feature = "metrics")]
+    crate::metrics::ota_phase(&ota_event.status);
+
     sdk.send_object("io.edgehog.devicemanager.OTAEvent", "/event", ota_event)
         .await
         .map_err(|error| {
@@ -491,7 +706,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::ota::ota_handle::{OtaRequest, OtaStatus};
+    use crate::ota::ota_handle::{DownloadProgress, OtaRequest, OtaStatus};
     use crate::ota::ota_handler::OtaEvent;
     use crate::ota::{DeployProgress, OtaError};
     use uuid::Uuid;
@@ -501,6 +716,7 @@ mod tests {
             OtaRequest {
                 uuid: Uuid::new_v4(),
                 url: "http://ota.bin".to_string(),
+                sha256: None,
             }
         }
     }
@@ -553,7 +769,13 @@ mod tests {
             message: "".to_string(),
         };
 
-        let ota_event = OtaEvent::from(&OtaStatus::Downloading(ota_request, 100));
+        let ota_event = OtaEvent::from(&OtaStatus::Downloading(
+            ota_request,
+            DownloadProgress {
+                percentage: 100,
+                ..Default::default()
+            },
+        ));
         assert_eq!(expected_ota_event.status, ota_event.status);
         assert_eq!(expected_ota_event.statusCode, ota_event.statusCode);
         assert_eq!(expected_ota_event.message, ota_event.message);
@@ -561,6 +783,22 @@ mod tests {
         assert_eq!(expected_ota_event.statusProgress, ota_event.statusProgress);
     }
 
+    #[test]
+    fn convert_ota_status_downloading_with_bytes() {
+        let ota_event = OtaEvent::from(&OtaStatus::Downloading(
+            OtaRequest::default(),
+            DownloadProgress {
+                percentage: 50,
+                downloaded: 1024,
+                total: 2048,
+            },
+        ));
+
+        assert_eq!(ota_event.status, "Downloading");
+        assert_eq!(ota_event.statusProgress, 50);
+        assert_eq!(ota_event.message, "1024/2048 bytes");
+    }
+
     #[test]
     #[allow(non_snake_case)]
     fn convert_ota_status_Deploying_to_OtaStatusMessage() {
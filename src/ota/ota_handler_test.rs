@@ -30,10 +30,12 @@ use uuid::Uuid;
 
 use crate::data::tests::MockPublisher;
 use crate::error::DeviceManagerError;
-use crate::ota::ota_handle::{run_ota, Ota, OtaRequest, OtaStatus, PersistentState};
+use crate::ota::ota_handle::{
+    run_ota, DownloadProgress, Ota, OtaRequest, OtaStatus, PersistentState,
+};
 use crate::ota::ota_handler::{OtaEvent, OtaHandler};
 use crate::ota::rauc::BundleInfo;
-use crate::ota::{DeployStatus, MockSystemUpdate, OtaError, ProgressStream};
+use crate::ota::{DeployProgress, DeployStatus, MockSystemUpdate, OtaError, ProgressStream};
 use crate::repository::MockStateRepository;
 
 pub(crate) fn deploy_status_stream<I>(iter: I) -> Result<ProgressStream, DeviceManagerError>
@@ -73,6 +75,9 @@ impl OtaHandler {
         Self {
             sender,
             ota_cancellation: Arc::new(RwLock::new(None)),
+            rate_limiter: None,
+            state: Default::default(),
+            persistence_error: None,
         }
     }
 }
@@ -712,7 +717,11 @@ async fn ota_event_update_already_in_progress_same_uuid() {
 
     let ota = Ota::mock_new(system_update, state_mock);
     // Fake another update is happening state != idle
-    *ota.ota_status.write().await = OtaStatus::Acknowledged(OtaRequest { uuid, url: ota_url });
+    *ota.ota_status.write().await = OtaStatus::Acknowledged(OtaRequest {
+        uuid,
+        url: ota_url,
+        sha256: None,
+    });
 
     let ota_handler = OtaHandler::mock_new_with_ota(ota);
 
@@ -771,6 +780,7 @@ async fn ota_event_update_already_in_progress_different_uuid() {
     *ota.ota_status.write().await = OtaStatus::Acknowledged(OtaRequest {
         uuid: uuid_2,
         url: ota_url,
+        sha256: None,
     });
 
     let ota_handler = OtaHandler::mock_new_with_ota(ota);
@@ -790,6 +800,52 @@ async fn ota_event_update_already_in_progress_different_uuid() {
     );
 }
 
+#[tokio::test]
+async fn ota_event_update_not_persistent() {
+    let uuid = Uuid::new_v4();
+
+    let mut ota_req_map = HashMap::new();
+    ota_req_map.insert(
+        "url".to_owned(),
+        AstarteType::String("http://localhost".to_string()),
+    );
+    ota_req_map.insert("uuid".to_owned(), AstarteType::String(uuid.to_string()));
+    ota_req_map.insert(
+        "operation".to_string(),
+        AstarteType::String("Update".to_string()),
+    );
+
+    let message = "the store directory /var/lib/edgehog is on a volatile tmpfs filesystem";
+
+    let mut publisher = MockPublisher::new();
+    publisher
+        .expect_send_object()
+        .withf(move |_: &str, _: &str, ota_event: &OtaEvent| {
+            ota_event.status.eq("Failure")
+                && ota_event.statusCode.eq("IOError")
+                && ota_event.requestUUID == uuid.to_string()
+                && ota_event.message.eq(message)
+        })
+        .once()
+        .returning(|_: &str, _: &str, _: OtaEvent| Ok(()));
+
+    let mut ota_handler = OtaHandler::mock_new(
+        MockSystemUpdate::new(),
+        MockStateRepository::<PersistentState>::new(),
+    );
+    ota_handler.persistence_error = Some(message.to_string());
+
+    let err = ota_handler
+        .ota_event(&publisher, ota_req_map)
+        .await
+        .expect_err("expected the update to be refused");
+
+    assert!(matches!(
+        err,
+        DeviceManagerError::OtaError(OtaError::IO(msg)) if msg == message
+    ));
+}
+
 #[tokio::test]
 async fn ota_event_canceled() {
     let uuid = Uuid::new_v4();
@@ -802,6 +858,7 @@ async fn ota_event_canceled() {
     *ota.ota_status.write().await = OtaStatus::Acknowledged(OtaRequest {
         uuid,
         url: "".to_string(),
+        sha256: None,
     });
 
     let ota_handler = OtaHandler::mock_new_with_ota(ota);
@@ -925,7 +982,8 @@ async fn ota_event_success_after_canceled_event() {
         ack,
         OtaStatus::Acknowledged(OtaRequest {
             uuid,
-            url: ota_url.clone()
+            url: ota_url.clone(),
+            sha256: None
         })
     );
 
@@ -961,9 +1019,10 @@ async fn ota_event_success_after_canceled_event() {
         OtaStatus::Downloading(
             OtaRequest {
                 uuid,
-                url: ota_url.clone()
+                url: ota_url.clone(),
+                sha256: None
             },
-            0
+            DownloadProgress::default()
         )
     );
     let status = rx_update.recv().await;
@@ -1145,6 +1204,7 @@ async fn ota_event_not_canceled() {
     *ota.ota_status.write().await = OtaStatus::Success(OtaRequest {
         uuid,
         url: "".to_string(),
+        sha256: None,
     });
     let ota_handler = OtaHandler::mock_new_with_ota(ota);
 
@@ -1242,6 +1302,7 @@ async fn ota_event_not_canceled_different_uuid() {
     *ota.ota_status.write().await = OtaStatus::Deployed(OtaRequest {
         uuid: uuid_2,
         url: "".to_string(),
+        sha256: None,
     });
     let ota_handler = OtaHandler::mock_new_with_ota(ota);
 
@@ -1254,6 +1315,57 @@ async fn ota_event_not_canceled_different_uuid() {
     );
 }
 
+/// Try to cancel an OTA that is already installing the bundle
+#[tokio::test]
+async fn ota_event_not_canceled_deploying() {
+    let uuid = Uuid::new_v4();
+    let cancel_token = CancellationToken::new();
+
+    let state_mock = MockStateRepository::<PersistentState>::new();
+    let system_update = MockSystemUpdate::new();
+
+    let mut ota_req_map = HashMap::new();
+    ota_req_map.insert("uuid".to_owned(), AstarteType::String(uuid.to_string()));
+    ota_req_map.insert(
+        "operation".to_string(),
+        AstarteType::String("Cancel".to_string()),
+    );
+
+    let mut publisher = MockPublisher::new();
+
+    publisher
+        .expect_send_object()
+        .withf(move |_: &str, _: &str, ota_event: &OtaEvent| {
+            ota_event.status.eq("Error")
+                && ota_event.statusCode.eq("CancelRejected")
+                && ota_event.requestUUID == uuid.to_string()
+                && ota_event.message.eq("the update is already being deployed")
+        })
+        .once()
+        .returning(|_: &str, _: &str, _: OtaEvent| Ok(()));
+
+    let (ota, _dir) = Ota::mock_new_with_path(system_update, state_mock, "cancel_deploying");
+    *ota.ota_status.write().await = OtaStatus::Deploying(
+        OtaRequest {
+            uuid,
+            url: "".to_string(),
+            sha256: None,
+        },
+        DeployProgress::default(),
+    );
+    let ota_handler = OtaHandler::mock_new_with_ota(ota);
+    *ota_handler.ota_cancellation.write().await = Some(cancel_token.clone());
+
+    let result = ota_handler.ota_event(&publisher, ota_req_map).await;
+
+    assert!(
+        result.is_ok(),
+        "expected ota event to be Ok, but got: {}",
+        result.unwrap_err()
+    );
+    assert!(!cancel_token.is_cancelled());
+}
+
 #[tokio::test]
 async fn ensure_pending_ota_ota_is_done_fail() {
     let uuid = Uuid::new_v4();
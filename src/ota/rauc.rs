@@ -33,24 +33,29 @@ use crate::DeviceManagerError;
 
 use super::ProgressStream;
 
-#[derive(DeserializeDict, SerializeDict, Type, Debug)]
+#[derive(DeserializeDict, SerializeDict, Type, Debug, Clone, PartialEq, Eq)]
 #[zvariant(signature = "dict")]
 pub struct SlotStatus {
+    /// Mark of the slot, `good` or `bad`.
     #[zvariant(rename = "boot-status")]
-    boot_status: Option<String>,
-    bootname: Option<String>,
-    class: String,
-    device: String,
-    state: String,
+    pub boot_status: Option<String>,
+    pub bootname: Option<String>,
+    pub class: String,
+    pub device: String,
+    /// State of the slot, `booted`, `active` or `inactive`.
+    pub state: String,
     #[zvariant(rename = "type")]
-    type_: String,
+    pub type_: String,
+    /// Version of the bundle installed in the slot.
+    #[zvariant(rename = "bundle.version")]
+    pub bundle_version: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Type)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Type)]
 #[zvariant(signature = "(sa{sv})")]
 pub struct Slot {
-    name: String,
-    data: SlotStatus,
+    pub name: String,
+    pub data: SlotStatus,
 }
 
 #[derive(Debug, Deserialize, Serialize, Type)]
@@ -201,6 +206,13 @@ impl SystemUpdate for OTARauc<'static> {
             .map_err(DeviceManagerError::ZbusError)
     }
 
+    async fn slot_status(&self) -> Result<Vec<Slot>, DeviceManagerError> {
+        self.rauc
+            .get_slot_status()
+            .await
+            .map_err(DeviceManagerError::ZbusError)
+    }
+
     async fn mark(
         &self,
         state: &str,
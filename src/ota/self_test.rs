@@ -0,0 +1,247 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Health checks run after booting the updated slot.
+//!
+//! The slot is marked good only if all the checks pass within the timeout, otherwise it's marked
+//! bad so the bootloader falls back to the previous slot on the next boot.
+//!
+//! The checks run before the runtime notifies systemd it's ready, so with the `systemd` feature the
+//! start timeout of the unit is extended while they run.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{debug, info};
+use serde::Deserialize;
+use tokio::time::Instant;
+
+use crate::executor::{Executor, ExecutorError};
+
+/// Interval between two attempts of the checks.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Timeout of a single HTTP probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Interval between two extensions of the start timeout of the unit.
+#[cfg(feature = "systemd")]
+const EXTEND_TIMEOUT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Below the default start timeout of systemd units of 90 seconds.
+const fn default_timeout() -> u64 {
+    60
+}
+
+/// Checks to run after booting the updated slot.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SelfTestConfig {
+    /// Seconds to wait for all the checks to pass.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Systemd units that must be active.
+    #[serde(default)]
+    pub units: Vec<String>,
+    /// URLs that must respond with a success status.
+    #[serde(default)]
+    pub http_probes: Vec<String>,
+    /// Scripts that must exit successfully.
+    #[serde(default)]
+    pub scripts: Vec<PathBuf>,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SelfTestError {
+    /// unit {0} is not active
+    UnitInactive(String),
+    /// HTTP probe {url} failed
+    HttpProbe {
+        #[source]
+        backtrace: reqwest::Error,
+        url: String,
+    },
+    /// script {0} failed
+    Script(String),
+    /// couldn't execute the check
+    Executor(#[from] ExecutorError),
+}
+
+impl SelfTestConfig {
+    /// Run the checks until they all pass or the timeout expires.
+    ///
+    /// Returns the error of the last attempt on timeout.
+    pub async fn run(&self) -> Result<(), SelfTestError> {
+        #[cfg(feature = "systemd")]
+        let keep_alive = tokio::spawn(async {
+            let mut interval = tokio::time::interval(EXTEND_TIMEOUT_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                crate::systemd_wrapper::systemd_notify_extend_timeout(EXTEND_TIMEOUT_INTERVAL * 3);
+            }
+        });
+
+        let res = self.run_checks().await;
+
+        #[cfg(feature = "systemd")]
+        keep_alive.abort();
+
+        res
+    }
+
+    async fn run_checks(&self) -> Result<(), SelfTestError> {
+        let deadline = Instant::now() + Duration::from_secs(self.timeout);
+        let client = reqwest::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        loop {
+            let err = match self.check(&client).await {
+                Ok(()) => {
+                    info!("post update self-test passed");
+
+                    return Ok(());
+                }
+                Err(err) => err,
+            };
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(err);
+            }
+
+            debug!("self-test not passed yet: {err}");
+
+            tokio::time::sleep(RETRY_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    async fn check(&self, client: &reqwest::Client) -> Result<(), SelfTestError> {
+        for unit in &self.units {
+            let output = Executor::new("systemctl")
+                .args(["is-active", "--quiet", unit.as_str()])
+                .output()
+                .await?;
+
+            if !output.success() {
+                return Err(SelfTestError::UnitInactive(unit.clone()));
+            }
+        }
+
+        for url in &self.http_probes {
+            client
+                .get(url)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status())
+                .map_err(|err| SelfTestError::HttpProbe {
+                    backtrace: err,
+                    url: url.clone(),
+                })?;
+        }
+
+        for script in &self.scripts {
+            let output = Executor::new(script.to_string_lossy()).output().await?;
+
+            if !output.success() {
+                return Err(SelfTestError::Script(script.display().to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use httpmock::prelude::*;
+
+    fn config() -> SelfTestConfig {
+        SelfTestConfig {
+            timeout: 0,
+            units: Vec::new(),
+            http_probes: Vec::new(),
+            scripts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: SelfTestConfig = toml::from_str(r#"units = ["edgehog.service"]"#).unwrap();
+
+        assert_eq!(config.timeout, default_timeout());
+        assert_eq!(config.units, ["edgehog.service"]);
+        assert!(config.http_probes.is_empty());
+        assert!(config.scripts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn self_test_success() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(200);
+            })
+            .await;
+
+        let config = SelfTestConfig {
+            http_probes: vec![server.url("/health")],
+            scripts: vec![PathBuf::from("true")],
+            ..config()
+        };
+
+        config.run().await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn self_test_probe_failed() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/health");
+                then.status(503);
+            })
+            .await;
+
+        let config = SelfTestConfig {
+            http_probes: vec![server.url("/health")],
+            ..config()
+        };
+
+        let res = config.run().await;
+
+        assert!(matches!(res, Err(SelfTestError::HttpProbe { .. })));
+    }
+
+    #[tokio::test]
+    async fn self_test_script_failed() {
+        let config = SelfTestConfig {
+            scripts: vec![PathBuf::from("false")],
+            ..config()
+        };
+
+        let res = config.run().await;
+
+        assert!(matches!(res, Err(SelfTestError::Script(script)) if script == "false"));
+    }
+}
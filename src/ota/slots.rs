@@ -0,0 +1,144 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Status of the RAUC slots, published on the `io.edgehog.devicemanager.OTASlots` interface.
+//!
+//! The properties are sent at startup, after a pending update was completed or rolled back, so the
+//! fleet can show the slot each device is running and the bundle installed in every slot.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::types::AstarteType;
+
+use crate::data::Publisher;
+use crate::error::DeviceManagerError;
+use crate::ota::rauc::Slot;
+
+const OTA_SLOTS_INTERFACE: &str = "io.edgehog.devicemanager.OTASlots";
+
+/// Properties of the slots, with the slot name as the first segment of the path.
+fn slot_properties(slots: &[Slot]) -> HashMap<String, AstarteType> {
+    let mut properties = HashMap::new();
+
+    for slot in slots {
+        let name = &slot.name;
+
+        properties.insert(format!("/{name}/state"), slot.data.state.clone().into());
+        properties.insert(format!("/{name}/class"), slot.data.class.clone().into());
+
+        if let Some(boot_status) = &slot.data.boot_status {
+            properties.insert(format!("/{name}/bootStatus"), boot_status.clone().into());
+        }
+
+        if let Some(version) = &slot.data.bundle_version {
+            properties.insert(format!("/{name}/bundleVersion"), version.clone().into());
+        }
+    }
+
+    properties
+}
+
+/// Publish the status of the slots.
+pub(crate) async fn send_slot_status<P>(
+    publisher: &P,
+    slots: &[Slot],
+) -> Result<(), DeviceManagerError>
+where
+    P: Publisher + Send + Sync,
+{
+    for (path, data) in slot_properties(slots) {
+        publisher.send(OTA_SLOTS_INTERFACE, &path, data).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+    use crate::ota::rauc::SlotStatus;
+
+    fn slots() -> Vec<Slot> {
+        vec![
+            Slot {
+                name: "rootfs.0".to_string(),
+                data: SlotStatus {
+                    boot_status: Some("good".to_string()),
+                    bootname: Some("A".to_string()),
+                    class: "rootfs".to_string(),
+                    device: "/dev/mmcblk0p2".to_string(),
+                    state: "booted".to_string(),
+                    type_: "ext4".to_string(),
+                    bundle_version: Some("1.2.0".to_string()),
+                },
+            },
+            Slot {
+                name: "rootfs.1".to_string(),
+                data: SlotStatus {
+                    boot_status: Some("bad".to_string()),
+                    bootname: Some("B".to_string()),
+                    class: "rootfs".to_string(),
+                    device: "/dev/mmcblk0p3".to_string(),
+                    state: "inactive".to_string(),
+                    type_: "ext4".to_string(),
+                    bundle_version: None,
+                },
+            },
+        ]
+    }
+
+    #[test]
+    fn slots_to_properties() {
+        let properties = slot_properties(&slots());
+
+        let expected = HashMap::from([
+            ("/rootfs.0/state".to_string(), "booted".to_string().into()),
+            ("/rootfs.0/class".to_string(), "rootfs".to_string().into()),
+            (
+                "/rootfs.0/bootStatus".to_string(),
+                "good".to_string().into(),
+            ),
+            (
+                "/rootfs.0/bundleVersion".to_string(),
+                "1.2.0".to_string().into(),
+            ),
+            ("/rootfs.1/state".to_string(), "inactive".to_string().into()),
+            ("/rootfs.1/class".to_string(), "rootfs".to_string().into()),
+            ("/rootfs.1/bootStatus".to_string(), "bad".to_string().into()),
+        ]);
+
+        assert_eq!(properties, expected);
+    }
+
+    #[tokio::test]
+    async fn send_slots() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send()
+            .withf(|iface: &str, path: &str, _: &AstarteType| {
+                iface == OTA_SLOTS_INTERFACE && path.starts_with("/rootfs.")
+            })
+            .times(7)
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        send_slot_status(&publisher, &slots()).await.unwrap();
+    }
+}
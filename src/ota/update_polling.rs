@@ -0,0 +1,342 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Poll an update server when Astarte is unreachable for a long period.
+//!
+//! Astarte is considered unreachable while the [`ConnectionState`] is disconnected.
+//!
+//! The server exposes a static JSON manifest with the UUID, URL and SHA-256 digest of the latest
+//! update. A new update is applied through the same [`OtaHandler`] pipeline used for the Astarte
+//! requests, so the OTA events are published once the connection is re-established.
+//!
+//! The manifest isn't authenticated by Astarte, so both the manifest and the bundle must be served
+//! over HTTPS and the bundle is checked against the digest before being installed.
+
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use astarte_device_sdk::types::AstarteType;
+use log::{debug, error, info};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::data::connection::ConnectionState;
+use crate::data::Publisher;
+use crate::error::DeviceManagerError;
+use crate::ota::ota_handler::OtaHandler;
+use crate::ota::OtaError;
+use crate::repository::file_state_repository::FileStateRepository;
+use crate::repository::StateRepository;
+
+fn default_interval() -> NonZeroU64 {
+    NonZeroU64::new(3600).expect("the default interval is not zero")
+}
+
+const fn default_offline_threshold() -> u64 {
+    86400
+}
+
+/// Configuration of the update polling.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UpdatePollingConfig {
+    /// URL of the JSON manifest describing the latest update.
+    pub manifest_url: String,
+    /// Seconds between two checks of the manifest, zero is rejected.
+    #[serde(default = "default_interval")]
+    pub interval: NonZeroU64,
+    /// Seconds disconnected from Astarte after which the manifest is polled.
+    #[serde(default = "default_offline_threshold")]
+    pub offline_threshold: u64,
+}
+
+/// Latest update advertised by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UpdateManifest {
+    pub uuid: Uuid,
+    pub url: String,
+    /// Hex encoded SHA-256 digest of the bundle.
+    pub sha256: String,
+}
+
+impl UpdateManifest {
+    /// Convert the manifest into the data of an `io.edgehog.devicemanager.OTARequest`.
+    fn into_request(self) -> HashMap<String, AstarteType> {
+        HashMap::from([
+            (
+                "operation".to_string(),
+                AstarteType::String("Update".to_string()),
+            ),
+            (
+                "uuid".to_string(),
+                AstarteType::String(self.uuid.to_string()),
+            ),
+            ("url".to_string(), AstarteType::String(self.url)),
+            ("sha256".to_string(), AstarteType::String(self.sha256)),
+        ])
+    }
+}
+
+/// Reject the URLs that are not served over HTTPS.
+fn require_https(url: &str) -> Result<(), OtaError> {
+    let url = url::Url::parse(url).map_err(|_| OtaError::Request("invalid url"))?;
+
+    if url.scheme() != "https" {
+        return Err(OtaError::Request("the url must use https"));
+    }
+
+    Ok(())
+}
+
+async fn fetch_manifest(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<UpdateManifest, DeviceManagerError> {
+    let body = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    let manifest = serde_json::from_slice(&body)?;
+
+    Ok(manifest)
+}
+
+/// Periodically checks the manifest while Astarte is unreachable.
+pub(crate) struct UpdatePoller<P> {
+    config: UpdatePollingConfig,
+    client: reqwest::Client,
+    publisher: P,
+    ota_handler: Arc<OtaHandler>,
+    connection: ConnectionState,
+    /// UUID of the last update applied from the manifest.
+    last_update: FileStateRepository<Uuid>,
+}
+
+impl<P> UpdatePoller<P>
+where
+    P: Publisher + Send + Sync + 'static,
+{
+    pub(crate) fn new(
+        config: UpdatePollingConfig,
+        store_directory: &Path,
+        publisher: P,
+        ota_handler: Arc<OtaHandler>,
+        connection: ConnectionState,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            config,
+            client,
+            publisher,
+            ota_handler,
+            connection,
+            last_update: FileStateRepository::new(store_directory, "update_polling.json"),
+        }
+    }
+
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval.get()));
+        let offline_threshold = Duration::from_secs(self.config.offline_threshold);
+
+        loop {
+            interval.tick().await;
+
+            match self.connection.disconnected_for() {
+                None => {
+                    debug!("astarte connected, skipping update polling");
+
+                    continue;
+                }
+                Some(disconnected) if disconnected < offline_threshold => {
+                    debug!("astarte disconnected for {disconnected:?}, skipping update polling");
+
+                    continue;
+                }
+                Some(_) => {}
+            }
+
+            if let Err(err) = self.poll().await {
+                error!("couldn't poll the update manifest: {err}");
+            }
+        }
+    }
+
+    async fn poll(&self) -> Result<(), DeviceManagerError> {
+        require_https(&self.config.manifest_url)?;
+
+        let manifest = fetch_manifest(&self.client, &self.config.manifest_url).await?;
+
+        require_https(&manifest.url)?;
+
+        if self.is_last_update(&manifest.uuid).await {
+            debug!("update {} already applied", manifest.uuid);
+
+            return Ok(());
+        }
+
+        info!("found update {} in the manifest", manifest.uuid);
+
+        // Stored before applying it, since a successful update reboots the device
+        if let Err(err) = self.last_update.write(&manifest.uuid).await {
+            error!("couldn't store the polled update: {err}");
+        }
+
+        let res = self
+            .ota_handler
+            .ota_event(&self.publisher, manifest.into_request())
+            .await;
+
+        // Forget the failed update, so it's retried on the next poll
+        if res.is_err() {
+            if let Err(err) = self.last_update.clear().await {
+                error!("couldn't clear the polled update: {err}");
+            }
+        }
+
+        res
+    }
+
+    async fn is_last_update(&self, uuid: &Uuid) -> bool {
+        if !self.last_update.exists().await {
+            return false;
+        }
+
+        match self.last_update.read().await {
+            Ok(last) => last == *uuid,
+            Err(err) => {
+                error!("couldn't read the last polled update: {err}");
+
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use httpmock::prelude::*;
+
+    #[test]
+    fn manifest_into_request() {
+        let uuid = Uuid::new_v4();
+        let manifest = UpdateManifest {
+            uuid,
+            url: "https://example.com/update.bin".to_string(),
+            sha256: "ab".repeat(32),
+        };
+
+        let request = manifest.into_request();
+
+        assert_eq!(
+            request["operation"],
+            AstarteType::String("Update".to_string())
+        );
+        assert_eq!(request["uuid"], AstarteType::String(uuid.to_string()));
+        assert_eq!(
+            request["url"],
+            AstarteType::String("https://example.com/update.bin".to_string())
+        );
+        assert_eq!(request["sha256"], AstarteType::String("ab".repeat(32)));
+    }
+
+    #[test]
+    fn require_https_url() {
+        assert!(require_https("https://example.com/manifest.json").is_ok());
+        assert!(matches!(
+            require_https("http://example.com/manifest.json"),
+            Err(OtaError::Request(_))
+        ));
+        assert!(matches!(
+            require_https("example.com/manifest.json"),
+            Err(OtaError::Request(_))
+        ));
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: UpdatePollingConfig =
+            toml::from_str(r#"manifest_url = "https://example.com/manifest.json""#).unwrap();
+
+        assert_eq!(config.interval, default_interval());
+        assert_eq!(config.offline_threshold, default_offline_threshold());
+    }
+
+    #[test]
+    fn config_zero_interval() {
+        let res = toml::from_str::<UpdatePollingConfig>(
+            r#"
+            manifest_url = "https://example.com/manifest.json"
+            interval = 0
+            "#,
+        );
+
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_manifest_success() {
+        let uuid = Uuid::new_v4();
+        let sha256 = "ab".repeat(32);
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/manifest.json");
+                then.status(200).body(format!(
+                    r#"{{"uuid": "{uuid}", "url": "https://example.com/update.bin", "sha256": "{sha256}"}}"#
+                ));
+            })
+            .await;
+
+        let manifest = fetch_manifest(&reqwest::Client::new(), &server.url("/manifest.json"))
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(
+            manifest,
+            UpdateManifest {
+                uuid,
+                url: "https://example.com/update.bin".to_string(),
+                sha256,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_manifest_not_found() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/manifest.json");
+                then.status(404);
+            })
+            .await;
+
+        let res = fetch_manifest(&reqwest::Client::new(), &server.url("/manifest.json")).await;
+
+        assert!(matches!(res, Err(DeviceManagerError::ReqwestError(_))));
+    }
+}
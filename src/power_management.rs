@@ -23,6 +23,10 @@ use std::time::Duration;
 use log::{debug, error, info};
 
 use crate::error::DeviceManagerError;
+use crate::executor::Executor;
+
+/// Maximum time to wait for the shutdown command to return.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub async fn reboot() -> Result<(), DeviceManagerError> {
     debug!("waiting 5 secs before reboot");
@@ -36,8 +40,9 @@ pub async fn reboot() -> Result<(), DeviceManagerError> {
     }
 
     // TODO: use systemd api
-    let output = tokio::process::Command::new("shutdown")
+    let output = Executor::new("shutdown")
         .args(["-r", "now"])
+        .timeout(SHUTDOWN_TIMEOUT)
         .output()
         .await?;
 
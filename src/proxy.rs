@@ -0,0 +1,109 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Proxy of the outbound HTTP(S) and WebSocket connections.
+//!
+//! The configured proxies are set on the HTTP clients of the runtime and passed to the forwarder.
+//! When none is configured, the proxies in the environment are used. The local connections, like
+//! the ones of the forwarder to the device services, never go through a proxy.
+
+use reqwest::{NoProxy, Proxy};
+use serde::Deserialize;
+
+/// Proxies from the configuration, they override the ones in the environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ProxyConfig {
+    /// Proxy for the `http` and `ws` connections.
+    pub http: Option<String>,
+    /// Proxy for the `https` and `wss` connections.
+    pub https: Option<String>,
+    /// Comma separated list of hosts and domains connected to directly.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    /// HTTP client for the remote connections of the runtime.
+    pub fn client(&self) -> Result<reqwest::Client, reqwest::Error> {
+        let mut builder = reqwest::Client::builder();
+
+        // Setting a proxy disables the ones from the environment
+        if let Some(http) = &self.http {
+            builder = builder.proxy(Proxy::http(http)?.no_proxy(self.no_proxy()));
+        }
+
+        if let Some(https) = &self.https {
+            builder = builder.proxy(Proxy::https(https)?.no_proxy(self.no_proxy()));
+        }
+
+        builder.build()
+    }
+
+    fn no_proxy(&self) -> Option<NoProxy> {
+        self.no_proxy.as_deref().and_then(NoProxy::from_string)
+    }
+
+    /// Proxies of the forwarder WebSocket connections.
+    #[cfg(feature = "forwarder")]
+    pub fn forwarder(&self) -> edgehog_forwarder::proxy::ProxyConfig {
+        if self.http.is_none() && self.https.is_none() {
+            return edgehog_forwarder::proxy::ProxyConfig::from_env();
+        }
+
+        edgehog_forwarder::proxy::ProxyConfig {
+            http: self.http.clone(),
+            https: self.https.clone(),
+            no_proxy: self.no_proxy.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_client() {
+        let config = ProxyConfig {
+            http: None,
+            https: Some("http://proxy.local:3128".to_string()),
+            no_proxy: Some("localhost,.local".to_string()),
+        };
+
+        assert!(config.client().is_ok());
+        assert!(ProxyConfig::default().client().is_ok());
+    }
+
+    #[cfg(feature = "forwarder")]
+    #[test]
+    fn forwarder_proxy() {
+        let config = ProxyConfig {
+            http: Some("http://proxy.local:3128".to_string()),
+            https: None,
+            no_proxy: None,
+        };
+
+        assert_eq!(
+            config.forwarder(),
+            edgehog_forwarder::proxy::ProxyConfig {
+                http: Some("http://proxy.local:3128".to_string()),
+                https: None,
+                no_proxy: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent rate limiting of the operations triggered from the cloud.
+//!
+//! The timestamps of the accepted operations are stored in the store directory, so a restart of
+//! the runtime doesn't reset the counters.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{error, warn};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::data::Publisher;
+use crate::repository::file_state_repository::FileStateRepository;
+use crate::repository::StateRepository;
+
+const RATE_LIMIT_EVENT_INTERFACE: &str = "io.edgehog.devicemanager.RateLimitEvent";
+
+/// Limit of operations in a sliding window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct RateLimitConfig {
+    /// Maximum number of operations accepted in the period.
+    pub max_operations: u32,
+    /// Length of the period in seconds.
+    pub period: u64,
+}
+
+/// Rate limits for each kind of operation, no limit is applied if missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct RateLimitsConfig {
+    pub ota: Option<RateLimitConfig>,
+    pub forwarder: Option<RateLimitConfig>,
+}
+
+/// {operation} exceeded the limit of {max_operations} operations every {period} seconds
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub struct RateLimitExceeded {
+    pub operation: String,
+    pub max_operations: u32,
+    pub period: u64,
+}
+
+impl RateLimitExceeded {
+    /// Report the violation to Astarte.
+    pub async fn report<P>(&self, publisher: &P)
+    where
+        P: Publisher + Send + Sync,
+    {
+        let event = RateLimitEvent::from(self);
+
+        if let Err(err) = publisher
+            .send_object(RATE_LIMIT_EVENT_INTERFACE, "/event", event)
+            .await
+        {
+            error!("couldn't send rate limit event: {err}");
+        }
+    }
+}
+
+#[derive(Debug, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct RateLimitEvent {
+    pub operation: String,
+    pub max_operations: i32,
+    pub period_seconds: i64,
+}
+
+impl From<&RateLimitExceeded> for RateLimitEvent {
+    fn from(value: &RateLimitExceeded) -> Self {
+        Self {
+            operation: value.operation.clone(),
+            max_operations: value.max_operations.try_into().unwrap_or(i32::MAX),
+            period_seconds: value.period.try_into().unwrap_or(i64::MAX),
+        }
+    }
+}
+
+/// Rate limiter for a single kind of operation.
+#[derive(Debug)]
+pub struct RateLimiter<S = FileStateRepository<Vec<u64>>> {
+    operation: String,
+    config: RateLimitConfig,
+    repository: S,
+    /// Timestamps in seconds of the accepted operations, lazily loaded from the repository.
+    history: Mutex<Option<Vec<u64>>>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter persisting its state in the store directory.
+    pub fn new(store_directory: &Path, operation: &str, config: RateLimitConfig) -> Self {
+        let repository =
+            FileStateRepository::new(store_directory, format!("rate_limit_{operation}.json"));
+
+        Self::with_repository(operation, config, repository)
+    }
+}
+
+impl<S> RateLimiter<S>
+where
+    S: StateRepository<Vec<u64>>,
+{
+    fn with_repository(
+        operation: impl Into<String>,
+        config: RateLimitConfig,
+        repository: S,
+    ) -> Self {
+        Self {
+            operation: operation.into(),
+            config,
+            repository,
+            history: Mutex::new(None),
+        }
+    }
+
+    async fn load(&self) -> Vec<u64> {
+        if !self.repository.exists().await {
+            return Vec::new();
+        }
+
+        self.repository.read().await.unwrap_or_else(|err| {
+            error!(
+                "couldn't read the rate limit state for {}: {err}",
+                self.operation
+            );
+
+            Vec::new()
+        })
+    }
+
+    /// Account a new operation, returning an error if the limit was exceeded.
+    pub async fn try_acquire(&self) -> Result<(), RateLimitExceeded> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        self.try_acquire_at(now).await
+    }
+
+    async fn try_acquire_at(&self, now: u64) -> Result<(), RateLimitExceeded> {
+        let mut history = self.history.lock().await;
+        if history.is_none() {
+            *history = Some(self.load().await);
+        }
+        let history = history.get_or_insert_with(Vec::new);
+
+        let window_start = now.saturating_sub(self.config.period);
+        history.retain(|timestamp| *timestamp > window_start);
+
+        if history.len() >= self.config.max_operations as usize {
+            let exceeded = RateLimitExceeded {
+                operation: self.operation.clone(),
+                max_operations: self.config.max_operations,
+                period: self.config.period,
+            };
+
+            warn!("{exceeded}");
+
+            return Err(exceeded);
+        }
+
+        history.push(now);
+
+        if let Err(err) = self.repository.write(history).await {
+            error!(
+                "couldn't persist the rate limit state for {}: {err}",
+                self.operation
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::data::tests::MockPublisher;
+
+    const CONFIG: RateLimitConfig = RateLimitConfig {
+        max_operations: 2,
+        period: 60,
+    };
+
+    #[tokio::test]
+    async fn rate_limit_exceeded() {
+        let dir = TempDir::new("edgehog-rate-limit").unwrap();
+        let limiter = RateLimiter::new(dir.path(), "ota", CONFIG);
+
+        assert!(limiter.try_acquire_at(100).await.is_ok());
+        assert!(limiter.try_acquire_at(110).await.is_ok());
+
+        let err = limiter.try_acquire_at(120).await.unwrap_err();
+        assert_eq!(
+            err,
+            RateLimitExceeded {
+                operation: "ota".to_string(),
+                max_operations: 2,
+                period: 60,
+            }
+        );
+
+        // the first operation exits the window
+        assert!(limiter.try_acquire_at(161).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rate_limit_persisted() {
+        let dir = TempDir::new("edgehog-rate-limit").unwrap();
+
+        let limiter = RateLimiter::new(dir.path(), "forwarder", CONFIG);
+        assert!(limiter.try_acquire_at(100).await.is_ok());
+        assert!(limiter.try_acquire_at(101).await.is_ok());
+
+        let limiter = RateLimiter::new(dir.path(), "forwarder", CONFIG);
+        assert!(limiter.try_acquire_at(102).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn report_rate_limit_exceeded() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, path: &str, event: &RateLimitEvent| {
+                iface == RATE_LIMIT_EVENT_INTERFACE
+                    && path == "/event"
+                    && event.operation == "ota"
+                    && event.max_operations == 2
+                    && event.period_seconds == 60
+            })
+            .returning(|_: &str, _: &str, _: RateLimitEvent| Ok(()));
+
+        RateLimitExceeded {
+            operation: "ota".to_string(),
+            max_operations: 2,
+            period: 60,
+        }
+        .report(&publisher)
+        .await;
+    }
+}
@@ -21,6 +21,7 @@
 use std::{
     io,
     marker::PhantomData,
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
 };
 
@@ -28,6 +29,7 @@ use crate::repository::StateRepository;
 use async_trait::async_trait;
 use log::{debug, error};
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::AsyncWriteExt;
 
 #[derive(thiserror::Error, displaydoc::Display, Debug)]
 pub enum FileStateError {
@@ -55,8 +57,10 @@ pub enum FileStateError {
     },
 }
 
+#[derive(Debug)]
 pub struct FileStateRepository<T> {
     pub path: PathBuf,
+    mode: Option<u32>,
     _marker: PhantomData<T>,
 }
 
@@ -64,9 +68,33 @@ impl<T> FileStateRepository<T> {
     pub fn new(path: &Path, name: impl AsRef<Path>) -> Self {
         FileStateRepository {
             path: path.join(name),
+            mode: None,
             _marker: PhantomData,
         }
     }
+
+    /// Restrict the permissions of the file to the given mode when writing it.
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    async fn write_with_mode(&self, data: &[u8], mode: u32) -> io::Result<()> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(&self.path)
+            .await?;
+
+        // the mode is only used when creating the file
+        file.set_permissions(std::fs::Permissions::from_mode(mode))
+            .await?;
+
+        file.write_all(data).await?;
+        file.flush().await
+    }
 }
 
 #[async_trait]
@@ -79,12 +107,15 @@ where
     async fn write(&self, value: &T) -> Result<(), Self::Err> {
         let data_json = serde_json::to_string(value).map_err(FileStateError::Serialize)?;
 
-        tokio::fs::write(&self.path, &data_json)
-            .await
-            .map_err(|err| FileStateError::Write {
-                backtrace: err,
-                path: self.path.display().to_string(),
-            })?;
+        let res = match self.mode {
+            Some(mode) => self.write_with_mode(data_json.as_bytes(), mode).await,
+            None => tokio::fs::write(&self.path, &data_json).await,
+        };
+
+        res.map_err(|err| FileStateError::Write {
+            backtrace: err,
+            path: self.path.display().to_string(),
+        })?;
 
         Ok(())
     }
@@ -139,6 +170,7 @@ where
 #[cfg(test)]
 mod tests {
     use std::marker::PhantomData;
+    use std::os::unix::fs::PermissionsExt;
     use std::path::Path;
 
     use crate::repository::file_state_repository::FileStateRepository;
@@ -151,6 +183,7 @@ mod tests {
 
         let repository = FileStateRepository {
             path,
+            mode: None,
             _marker: PhantomData,
         };
 
@@ -161,6 +194,24 @@ mod tests {
         repository.clear().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn file_state_with_mode() {
+        let dir = tempdir::TempDir::new("edgehog").expect("failed to create temp dir");
+        let path = dir.path().join("secret.json");
+
+        // already existing files are restricted too
+        std::fs::write(&path, "\"old\"").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let repository = FileStateRepository::new(dir.path(), "secret.json").with_mode(0o600);
+
+        repository.write(&"secret".to_string()).await.unwrap();
+        assert_eq!(repository.read().await.unwrap(), "secret");
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
     #[test]
     fn file_repository_new_end_without_slash() {
         let file = FileStateRepository::<()>::new(Path::new("/tmp/path"), "state.json");
@@ -0,0 +1,561 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deferred execution of the commands and OTA updates requested with an `executeAt` timestamp.
+//!
+//! The scheduled actions are stored in the store directory, so they are executed also after a
+//! restart of the runtime. Actions whose time passed while the runtime was stopped are executed on
+//! startup.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, Notify};
+
+use crate::commands::now_millis;
+use crate::data::Publisher;
+use crate::ota::ota_handler::OtaHandler;
+use crate::repository::file_state_repository::FileStateRepository;
+use crate::repository::StateRepository;
+
+const SCHEDULED_ACTION_EVENT_INTERFACE: &str = "io.edgehog.devicemanager.ScheduledActionEvent";
+
+/// Wait when no action is scheduled, the scheduler is woken up by a new action.
+const IDLE_WAIT: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ScheduleError {
+    /// invalid scheduled request, {0}
+    InvalidRequest(&'static str),
+    /// action {0} is already scheduled
+    AlreadyScheduled(String),
+}
+
+/// Operation executed at the scheduled time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Command of the `io.edgehog.devicemanager.Commands` interface.
+    Command { command: String },
+    /// Update of the `io.edgehog.devicemanager.OTARequest` interface.
+    OtaUpdate { url: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledAction {
+    /// Id of the request, the uuid for the OTA updates.
+    pub id: String,
+    /// Unix timestamp in milliseconds.
+    pub execute_at: i64,
+    pub action: Action,
+}
+
+impl ScheduledAction {
+    /// Parse an OTA update request with an `executeAt` in the future.
+    pub(crate) fn from_ota_request(data: &HashMap<String, AstarteType>, now: i64) -> Option<Self> {
+        let Some(AstarteType::LongInteger(execute_at)) = data.get("executeAt") else {
+            return None;
+        };
+
+        if *execute_at <= now {
+            return None;
+        }
+
+        match (data.get("operation"), data.get("uuid"), data.get("url")) {
+            (
+                Some(AstarteType::String(operation)),
+                Some(AstarteType::String(uuid)),
+                Some(AstarteType::String(url)),
+            ) if operation == "Update" => Some(Self {
+                id: uuid.clone(),
+                execute_at: *execute_at,
+                action: Action::OtaUpdate { url: url.clone() },
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Request received on `io.edgehog.devicemanager.ScheduledCommandRequest`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ScheduledCommandRequest {
+    Schedule(ScheduledAction),
+    Cancel(String),
+}
+
+impl TryFrom<HashMap<String, AstarteType>> for ScheduledCommandRequest {
+    type Error = ScheduleError;
+
+    fn try_from(mut value: HashMap<String, AstarteType>) -> Result<Self, Self::Error> {
+        let Some(AstarteType::String(id)) = value.remove("id") else {
+            return Err(ScheduleError::InvalidRequest("missing id"));
+        };
+
+        let Some(AstarteType::String(operation)) = value.remove("operation") else {
+            return Err(ScheduleError::InvalidRequest("missing operation"));
+        };
+
+        match operation.as_str() {
+            "Schedule" => {
+                let Some(AstarteType::String(command)) = value.remove("command") else {
+                    return Err(ScheduleError::InvalidRequest("missing command"));
+                };
+
+                let Some(AstarteType::LongInteger(execute_at)) = value.remove("executeAt") else {
+                    return Err(ScheduleError::InvalidRequest("missing executeAt"));
+                };
+
+                Ok(Self::Schedule(ScheduledAction {
+                    id,
+                    execute_at,
+                    action: Action::Command { command },
+                }))
+            }
+            "Cancel" => Ok(Self::Cancel(id)),
+            _ => Err(ScheduleError::InvalidRequest("unknown operation")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScheduleStatus {
+    Scheduled,
+    Executed,
+    Cancelled,
+}
+
+impl ScheduleStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleStatus::Scheduled => "Scheduled",
+            ScheduleStatus::Executed => "Executed",
+            ScheduleStatus::Cancelled => "Cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct ScheduledActionEvent {
+    pub id: String,
+    /// Any of: Scheduled, Executed, Cancelled
+    pub status: String,
+    /// Unix timestamp in milliseconds the action is scheduled at.
+    pub execute_at: i64,
+}
+
+/// Persistent queue of the scheduled actions.
+#[derive(Debug)]
+pub struct Scheduler<P, S = FileStateRepository<Vec<ScheduledAction>>> {
+    publisher: P,
+    repository: S,
+    /// Scheduled actions, lazily loaded from the repository.
+    actions: Mutex<Option<Vec<ScheduledAction>>>,
+    notify: Notify,
+}
+
+impl<P> Scheduler<P> {
+    /// Create a scheduler persisting the actions in the store directory.
+    pub fn new(store_directory: &Path, publisher: P) -> Self {
+        let repository = FileStateRepository::new(store_directory, "scheduled_actions.json");
+
+        Self::with_repository(publisher, repository)
+    }
+}
+
+impl<P, S> Scheduler<P, S>
+where
+    P: Publisher + Send + Sync,
+    S: StateRepository<Vec<ScheduledAction>>,
+{
+    fn with_repository(publisher: P, repository: S) -> Self {
+        Self {
+            publisher,
+            repository,
+            actions: Mutex::new(None),
+            notify: Notify::new(),
+        }
+    }
+
+    async fn load(&self) -> Vec<ScheduledAction> {
+        if !self.repository.exists().await {
+            return Vec::new();
+        }
+
+        self.repository.read().await.unwrap_or_else(|err| {
+            error!("couldn't read the scheduled actions: {err}");
+
+            Vec::new()
+        })
+    }
+
+    /// Run a function on the scheduled actions, persisting them if changed.
+    async fn update<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Vec<ScheduledAction>) -> T,
+    {
+        let mut actions = self.actions.lock().await;
+        if actions.is_none() {
+            *actions = Some(self.load().await);
+        }
+        let actions = actions.get_or_insert_with(Vec::new);
+
+        let before = actions.clone();
+        let res = f(actions);
+
+        if *actions != before {
+            if let Err(err) = self.repository.write(actions).await {
+                error!("couldn't persist the scheduled actions: {err}");
+            }
+        }
+
+        res
+    }
+
+    async fn send_event(&self, action: &ScheduledAction, status: ScheduleStatus) {
+        let event = ScheduledActionEvent {
+            id: action.id.clone(),
+            status: status.as_str().to_string(),
+            execute_at: action.execute_at,
+        };
+
+        if let Err(err) = self
+            .publisher
+            .send_object(SCHEDULED_ACTION_EVENT_INTERFACE, "/event", event)
+            .await
+        {
+            error!("couldn't send scheduled action event: {err}");
+        }
+    }
+
+    /// Add an action to the queue.
+    pub(crate) async fn schedule(&self, action: ScheduledAction) -> Result<(), ScheduleError> {
+        let added = self
+            .update(|actions| {
+                if actions.iter().any(|scheduled| scheduled.id == action.id) {
+                    return false;
+                }
+
+                actions.push(action.clone());
+
+                true
+            })
+            .await;
+
+        if !added {
+            return Err(ScheduleError::AlreadyScheduled(action.id));
+        }
+
+        info!("scheduled {} at {}", action.id, action.execute_at);
+
+        self.send_event(&action, ScheduleStatus::Scheduled).await;
+        self.notify.notify_one();
+
+        Ok(())
+    }
+
+    /// Remove an action from the queue, returns false if no action has the id.
+    pub(crate) async fn cancel(&self, id: &str) -> bool {
+        let removed = self
+            .update(|actions| {
+                let idx = actions.iter().position(|action| action.id == id)?;
+
+                Some(actions.remove(idx))
+            })
+            .await;
+
+        let Some(action) = removed else {
+            return false;
+        };
+
+        info!("cancelled scheduled action {id}");
+
+        self.send_event(&action, ScheduleStatus::Cancelled).await;
+        self.notify.notify_one();
+
+        true
+    }
+
+    /// Schedule or cancel an OTA request, returns false if it must be handled immediately.
+    pub(crate) async fn handle_ota_request(&self, data: &HashMap<String, AstarteType>) -> bool {
+        if let Some(action) = ScheduledAction::from_ota_request(data, now_millis()) {
+            if let Err(err) = self.schedule(action).await {
+                error!("couldn't schedule the ota request: {err}");
+            }
+
+            return true;
+        }
+
+        match (data.get("operation"), data.get("uuid")) {
+            (Some(AstarteType::String(operation)), Some(AstarteType::String(uuid)))
+                if operation == "Cancel" =>
+            {
+                self.cancel(uuid).await
+            }
+            _ => false,
+        }
+    }
+
+    /// handle io.edgehog.devicemanager.ScheduledCommandRequest
+    pub(crate) async fn handle_command_request(&self, data: HashMap<String, AstarteType>) {
+        match ScheduledCommandRequest::try_from(data) {
+            Ok(ScheduledCommandRequest::Schedule(action)) => {
+                if let Err(err) = self.schedule(action).await {
+                    error!("couldn't schedule the command: {err}");
+                }
+            }
+            Ok(ScheduledCommandRequest::Cancel(id)) => {
+                if !self.cancel(&id).await {
+                    warn!("no scheduled action {id} to cancel");
+                }
+            }
+            Err(err) => error!("{err}"),
+        }
+    }
+
+    /// Time of the first scheduled action.
+    async fn next_execute_at(&self) -> Option<i64> {
+        self.update(|actions| actions.iter().map(|action| action.execute_at).min())
+            .await
+    }
+
+    /// Remove the actions scheduled before or at the given time.
+    async fn take_due(&self, now: i64) -> Vec<ScheduledAction> {
+        self.update(|actions| {
+            let (due, pending): (Vec<_>, Vec<_>) = actions
+                .drain(..)
+                .partition(|action| action.execute_at <= now);
+
+            *actions = pending;
+
+            due
+        })
+        .await
+    }
+
+    /// Execute the actions at their scheduled time.
+    pub(crate) async fn run(self: Arc<Self>, ota_handler: Arc<OtaHandler>)
+    where
+        P: Clone + 'static,
+    {
+        loop {
+            let wait = match self.next_execute_at().await {
+                Some(execute_at) => {
+                    let delay = execute_at.saturating_sub(now_millis()).max(0);
+
+                    Duration::from_millis(u64::try_from(delay).unwrap_or_default())
+                }
+                None => IDLE_WAIT,
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.notify.notified() => continue,
+            }
+
+            for action in self.take_due(now_millis()).await {
+                self.execute(&action, &ota_handler).await;
+            }
+        }
+    }
+
+    async fn execute(&self, action: &ScheduledAction, ota_handler: &Arc<OtaHandler>)
+    where
+        P: Clone + 'static,
+    {
+        info!("executing scheduled action {}", action.id);
+
+        self.send_event(action, ScheduleStatus::Executed).await;
+
+        match &action.action {
+            Action::Command { command } => {
+                crate::commands::execute_command(&self.publisher, command).await;
+            }
+            Action::OtaUpdate { url } => {
+                let data = HashMap::from([
+                    (
+                        "operation".to_string(),
+                        AstarteType::String("Update".to_string()),
+                    ),
+                    ("uuid".to_string(), AstarteType::String(action.id.clone())),
+                    ("url".to_string(), AstarteType::String(url.clone())),
+                ]);
+
+                let publisher = self.publisher.clone();
+                let ota_handler = ota_handler.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = ota_handler.ota_event(&publisher, data).await {
+                        warn!("scheduled ota error {err}");
+                    }
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::data::tests::MockPublisher;
+
+    fn action(id: &str, execute_at: i64) -> ScheduledAction {
+        ScheduledAction {
+            id: id.to_string(),
+            execute_at,
+            action: Action::Command {
+                command: "Reboot".to_string(),
+            },
+        }
+    }
+
+    fn expect_events(publisher: &mut MockPublisher, status: &'static str, times: usize) {
+        publisher
+            .expect_send_object()
+            .withf(
+                move |iface: &str, path: &str, event: &ScheduledActionEvent| {
+                    iface == SCHEDULED_ACTION_EVENT_INTERFACE
+                        && path == "/event"
+                        && event.status == status
+                },
+            )
+            .times(times)
+            .returning(|_: &str, _: &str, _: ScheduledActionEvent| Ok(()));
+    }
+
+    #[test]
+    fn parse_ota_request() {
+        let mut data = HashMap::from([
+            (
+                "operation".to_string(),
+                AstarteType::String("Update".to_string()),
+            ),
+            (
+                "uuid".to_string(),
+                AstarteType::String("04bf491c-af94-4e9d-813f-ebeebfb856a6".to_string()),
+            ),
+            (
+                "url".to_string(),
+                AstarteType::String("https://example.com/update.bin".to_string()),
+            ),
+        ]);
+
+        assert_eq!(ScheduledAction::from_ota_request(&data, 100), None);
+
+        data.insert("executeAt".to_string(), AstarteType::LongInteger(50));
+        assert_eq!(ScheduledAction::from_ota_request(&data, 100), None);
+
+        data.insert("executeAt".to_string(), AstarteType::LongInteger(200));
+        assert_eq!(
+            ScheduledAction::from_ota_request(&data, 100),
+            Some(ScheduledAction {
+                id: "04bf491c-af94-4e9d-813f-ebeebfb856a6".to_string(),
+                execute_at: 200,
+                action: Action::OtaUpdate {
+                    url: "https://example.com/update.bin".to_string()
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_command_request() {
+        let request = ScheduledCommandRequest::try_from(HashMap::from([
+            ("id".to_string(), AstarteType::String("42".to_string())),
+            (
+                "operation".to_string(),
+                AstarteType::String("Schedule".to_string()),
+            ),
+            (
+                "command".to_string(),
+                AstarteType::String("Reboot".to_string()),
+            ),
+            ("executeAt".to_string(), AstarteType::LongInteger(200)),
+        ]))
+        .unwrap();
+        assert_eq!(
+            request,
+            ScheduledCommandRequest::Schedule(action("42", 200))
+        );
+
+        let request = ScheduledCommandRequest::try_from(HashMap::from([
+            ("id".to_string(), AstarteType::String("42".to_string())),
+            (
+                "operation".to_string(),
+                AstarteType::String("Cancel".to_string()),
+            ),
+        ]))
+        .unwrap();
+        assert_eq!(request, ScheduledCommandRequest::Cancel("42".to_string()));
+
+        let res = ScheduledCommandRequest::try_from(HashMap::from([(
+            "id".to_string(),
+            AstarteType::String("42".to_string()),
+        )]));
+        assert!(matches!(res, Err(ScheduleError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn schedule_and_cancel() {
+        let dir = TempDir::new("edgehog-scheduler").unwrap();
+        let mut publisher = MockPublisher::new();
+        expect_events(&mut publisher, "Scheduled", 2);
+        expect_events(&mut publisher, "Cancelled", 1);
+
+        let scheduler = Scheduler::new(dir.path(), publisher);
+
+        scheduler.schedule(action("a", 200)).await.unwrap();
+        scheduler.schedule(action("b", 100)).await.unwrap();
+
+        let res = scheduler.schedule(action("a", 300)).await;
+        assert!(matches!(res, Err(ScheduleError::AlreadyScheduled(id)) if id == "a"));
+
+        assert_eq!(scheduler.next_execute_at().await, Some(100));
+
+        assert!(scheduler.cancel("b").await);
+        assert!(!scheduler.cancel("b").await);
+
+        assert_eq!(scheduler.next_execute_at().await, Some(200));
+    }
+
+    #[tokio::test]
+    async fn persisted_actions() {
+        let dir = TempDir::new("edgehog-scheduler").unwrap();
+        let mut publisher = MockPublisher::new();
+        expect_events(&mut publisher, "Scheduled", 2);
+
+        let scheduler = Scheduler::new(dir.path(), publisher);
+        scheduler.schedule(action("a", 100)).await.unwrap();
+        scheduler.schedule(action("b", 200)).await.unwrap();
+
+        // a restart reloads the scheduled actions
+        let scheduler = Scheduler::new(dir.path(), MockPublisher::new());
+
+        assert_eq!(scheduler.take_due(150).await, [action("a", 100)]);
+        assert!(scheduler.take_due(150).await.is_empty());
+        assert_eq!(scheduler.next_execute_at().await, Some(200));
+    }
+}
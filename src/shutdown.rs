@@ -0,0 +1,126 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Graceful shutdown of the runtime.
+//!
+//! On SIGTERM or SIGINT the runtime stops handling new Astarte events and waits, up to a deadline,
+//! for the operations already started to complete before disconnecting.
+
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Deserialize;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio_util::task::TaskTracker;
+
+use crate::error::DeviceManagerError;
+
+const fn default_timeout() -> u64 {
+    30
+}
+
+/// Configuration of the graceful shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct ShutdownConfig {
+    /// Seconds to wait for the in-flight operations before exiting.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_timeout(),
+        }
+    }
+}
+
+/// Termination signals handled by the runtime.
+#[derive(Debug)]
+pub(crate) struct Signals {
+    terminate: Signal,
+    interrupt: Signal,
+}
+
+impl Signals {
+    pub(crate) fn new() -> Result<Self, DeviceManagerError> {
+        Ok(Self {
+            terminate: signal(SignalKind::terminate())?,
+            interrupt: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    /// Wait for the first termination signal.
+    pub(crate) async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.terminate.recv() => info!("received SIGTERM"),
+            _ = self.interrupt.recv() => info!("received SIGINT"),
+        }
+    }
+}
+
+/// Wait for the tracked tasks to complete, returns `false` if the timeout elapsed.
+pub(crate) async fn drain(tasks: &TaskTracker, config: &ShutdownConfig) -> bool {
+    tasks.close();
+
+    let timeout = Duration::from_secs(config.timeout);
+    match tokio::time::timeout(timeout, tasks.wait()).await {
+        Ok(()) => {
+            info!("in-flight operations completed");
+
+            true
+        }
+        Err(_) => {
+            warn!(
+                "shutdown timeout elapsed with {} operations still running",
+                tasks.len()
+            );
+
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_defaults() {
+        let config: ShutdownConfig = toml::from_str("").unwrap();
+
+        assert_eq!(config, ShutdownConfig::default());
+        assert_eq!(config.timeout, 30);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_completed() {
+        let tasks = TaskTracker::new();
+        tasks.spawn(tokio::time::sleep(Duration::from_secs(5)));
+
+        assert!(drain(&tasks, &ShutdownConfig { timeout: 10 }).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_timeout() {
+        let tasks = TaskTracker::new();
+        tasks.spawn(tokio::time::sleep(Duration::from_secs(60)));
+
+        assert!(!drain(&tasks, &ShutdownConfig { timeout: 10 }).await);
+    }
+}
@@ -0,0 +1,230 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! State of the runtime shared with the local services.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Phase of the last OTA update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OtaState {
+    pub request_uuid: String,
+    pub status: String,
+    pub progress: i32,
+}
+
+/// Forwarder session connected to Edgehog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwarderSession {
+    pub token: String,
+    pub host: String,
+    /// Unix timestamp in seconds of the connection.
+    pub connected_since: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    /// Status of the runtime, the same reported to systemd.
+    pub status: String,
+    pub ota: OtaState,
+    pub forwarder_sessions: Vec<ForwarderSession>,
+    /// Random delay in milliseconds applied to the first sample of each telemetry interface.
+    pub telemetry_offsets: HashMap<String, u64>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            status: "Initializing".to_string(),
+            ota: OtaState::default(),
+            forwarder_sessions: Vec::new(),
+            telemetry_offsets: HashMap::new(),
+        }
+    }
+}
+
+/// Handle to update the state, notifying the subscribers only on changes.
+#[derive(Debug, Clone)]
+pub struct RuntimeState(Arc<watch::Sender<State>>);
+
+impl RuntimeState {
+    pub fn set_status(&self, status: &str) {
+        self.0.send_if_modified(|state| {
+            if state.status == status {
+                return false;
+            }
+
+            state.status = status.to_string();
+
+            true
+        });
+    }
+
+    pub fn set_ota(&self, ota: OtaState) {
+        self.0.send_if_modified(|state| {
+            if state.ota == ota {
+                return false;
+            }
+
+            state.ota = ota;
+
+            true
+        });
+    }
+
+    /// Add or replace the session with the same token.
+    #[cfg(feature = "forwarder")]
+    pub fn add_forwarder_session(&self, session: ForwarderSession) {
+        self.0.send_modify(|state| {
+            state
+                .forwarder_sessions
+                .retain(|running| running.token != session.token);
+            state.forwarder_sessions.push(session);
+        });
+    }
+
+    #[cfg(feature = "forwarder")]
+    pub fn remove_forwarder_session(&self, token: &str) {
+        self.0.send_if_modified(|state| {
+            let len = state.forwarder_sessions.len();
+
+            state
+                .forwarder_sessions
+                .retain(|running| running.token != token);
+
+            state.forwarder_sessions.len() != len
+        });
+    }
+
+    #[cfg(feature = "forwarder")]
+    pub fn set_forwarder_traffic(&self, token: &str, bytes_sent: u64, bytes_received: u64) {
+        self.0.send_if_modified(|state| {
+            let Some(session) = state
+                .forwarder_sessions
+                .iter_mut()
+                .find(|session| session.token == token)
+            else {
+                return false;
+            };
+
+            if session.bytes_sent == bytes_sent && session.bytes_received == bytes_received {
+                return false;
+            }
+
+            session.bytes_sent = bytes_sent;
+            session.bytes_received = bytes_received;
+
+            true
+        });
+    }
+
+    pub fn set_telemetry_offset(&self, interface: &str, offset: u64) {
+        self.0.send_if_modified(|state| {
+            state
+                .telemetry_offsets
+                .insert(interface.to_string(), offset)
+                .map_or(true, |old| old != offset)
+        });
+    }
+
+    pub fn remove_telemetry_offset(&self, interface: &str) {
+        self.0
+            .send_if_modified(|state| state.telemetry_offsets.remove(interface).is_some());
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<State> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for RuntimeState {
+    fn default() -> Self {
+        let (tx, _rx) = watch::channel(State::default());
+
+        Self(Arc::new(tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn notify_only_changes() {
+        let state = RuntimeState::default();
+        let mut rx = state.subscribe();
+
+        state.set_status("Initializing");
+        assert!(!rx.has_changed().unwrap());
+
+        state.set_status("Running");
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(rx.borrow_and_update().status, "Running");
+
+        let ota = OtaState {
+            request_uuid: "uuid".to_string(),
+            status: "Downloading".to_string(),
+            progress: 50,
+        };
+        state.set_ota(ota.clone());
+        assert_eq!(rx.borrow_and_update().ota, ota);
+
+        state.set_ota(ota);
+        assert!(!rx.has_changed().unwrap());
+    }
+
+    #[cfg(feature = "forwarder")]
+    #[tokio::test]
+    async fn forwarder_sessions() {
+        let state = RuntimeState::default();
+        let mut rx = state.subscribe();
+
+        let session = ForwarderSession {
+            token: "abcd".to_string(),
+            host: "edgehog.example.com".to_string(),
+            connected_since: 1700000000,
+            bytes_sent: 0,
+            bytes_received: 0,
+        };
+        state.add_forwarder_session(session.clone());
+        // a reconnection replaces the session
+        state.add_forwarder_session(session.clone());
+        assert_eq!(rx.borrow_and_update().forwarder_sessions, [session]);
+
+        state.remove_forwarder_session("efgh");
+        assert!(!rx.has_changed().unwrap());
+
+        state.set_forwarder_traffic("abcd", 10, 20);
+        let sessions = rx.borrow_and_update().forwarder_sessions.clone();
+        assert_eq!(
+            (sessions[0].bytes_sent, sessions[0].bytes_received),
+            (10, 20)
+        );
+
+        state.set_forwarder_traffic("abcd", 10, 20);
+        assert!(!rx.has_changed().unwrap());
+
+        state.remove_forwarder_session("abcd");
+        assert!(rx.borrow_and_update().forwarder_sessions.is_empty());
+    }
+}
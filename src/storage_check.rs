@@ -0,0 +1,246 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Detection of the store and download directories on read-only or volatile filesystems.
+//!
+//! Misconfigured images often leave them on the read-only root filesystem or on a tmpfs, and the
+//! operations relying on them would fail later in confusing ways.
+
+use std::path::{Path, PathBuf};
+
+use astarte_device_sdk::types::AstarteType;
+use log::warn;
+use procfs::process::{MountInfo, Process};
+
+use crate::data::Publisher;
+use crate::error::DeviceManagerError;
+use crate::telemetry::overrides::DIAGNOSTICS_INTERFACE;
+
+/// Filesystems whose content is lost on reboot.
+const VOLATILE_FS: &[&str] = &["tmpfs", "ramfs"];
+
+/// Filesystem of a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StorageStatus {
+    pub(crate) fs_type: String,
+    pub(crate) read_only: bool,
+    pub(crate) volatile: bool,
+}
+
+impl StorageStatus {
+    pub(crate) fn is_persistent(&self) -> bool {
+        !self.read_only && !self.volatile
+    }
+
+    fn describe(&self) -> &'static str {
+        if self.read_only {
+            "read-only"
+        } else {
+            "volatile"
+        }
+    }
+}
+
+/// Mount containing the path, the last one mounted on the longest mount point.
+fn find_mount<'a>(mounts: &'a [MountInfo], path: &Path) -> Option<&'a MountInfo> {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.as_os_str().len())
+}
+
+fn status_of(mounts: &[MountInfo], path: &Path) -> Option<StorageStatus> {
+    let mount = find_mount(mounts, path)?;
+
+    let mut read_only = mount.mount_options.contains_key("ro");
+    let volatile = match mount.fs_type.as_str() {
+        "overlay" => match mount.super_options.get("upperdir").cloned().flatten() {
+            // the overlay is persistent only if its upper directory is
+            Some(upper) => find_mount(mounts, Path::new(&upper))
+                .is_some_and(|upper| VOLATILE_FS.contains(&upper.fs_type.as_str())),
+            // an overlay without an upper directory can't be written
+            None => {
+                read_only = true;
+
+                false
+            }
+        },
+        fs_type => VOLATILE_FS.contains(&fs_type),
+    };
+
+    Some(StorageStatus {
+        fs_type: mount.fs_type.clone(),
+        read_only,
+        volatile,
+    })
+}
+
+/// Resolve the path, or its first existing ancestor if it wasn't created yet.
+fn resolve(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|ancestor| ancestor.canonicalize().ok())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Status of the filesystem of a directory, [`None`] if the mounts couldn't be read.
+pub(crate) fn check(path: &Path) -> Option<StorageStatus> {
+    let mounts = Process::myself()
+        .and_then(|process| process.mountinfo())
+        .map_err(|err| warn!("couldn't read the mounts: {err}"))
+        .ok()?;
+
+    status_of(&mounts.0, &resolve(path))
+}
+
+/// Check the store and download directories, warning if they aren't persistent.
+pub(crate) fn check_directories(
+    opts: &crate::DeviceManagerOptions,
+) -> Vec<(&'static str, StorageStatus)> {
+    let directories = [
+        ("storeDirectory", &opts.store_directory),
+        ("downloadDirectory", &opts.download_directory),
+    ];
+
+    directories
+        .into_iter()
+        .filter_map(|(name, path)| {
+            let status = check(path)?;
+
+            if !status.is_persistent() {
+                warn!(
+                    "{} is on a {} {} filesystem",
+                    path.display(),
+                    status.describe(),
+                    status.fs_type
+                );
+            }
+
+            Some((name, status))
+        })
+        .collect()
+}
+
+/// Reason an OTA update can't be performed, the download must be written and the state must
+/// survive the reboot.
+pub(crate) fn ota_persistence_error(opts: &crate::DeviceManagerOptions) -> Option<String> {
+    let store = check(&opts.store_directory).filter(|status| !status.is_persistent());
+    if let Some(status) = store {
+        return Some(format!(
+            "the store directory {} is on a {} {} filesystem, the update state wouldn't survive the reboot",
+            opts.store_directory.display(),
+            status.describe(),
+            status.fs_type
+        ));
+    }
+
+    let download = check(&opts.download_directory).filter(|status| status.read_only);
+    download.map(|status| {
+        format!(
+            "the download directory {} is on a read-only {} filesystem",
+            opts.download_directory.display(),
+            status.fs_type
+        )
+    })
+}
+
+/// Send the status of the directories on the `io.edgehog.devicemanager.RuntimeDiagnostics`
+/// interface.
+pub(crate) async fn send_storage_status<P>(
+    publisher: &P,
+    directories: &[(&'static str, StorageStatus)],
+) -> Result<(), DeviceManagerError>
+where
+    P: Publisher + Send + Sync,
+{
+    for (name, status) in directories {
+        let values = [
+            ("fsType", AstarteType::String(status.fs_type.clone())),
+            ("readOnly", AstarteType::Boolean(status.read_only)),
+            ("volatile", AstarteType::Boolean(status.volatile)),
+        ];
+
+        for (field, value) in values {
+            publisher
+                .send(
+                    DIAGNOSTICS_INTERFACE,
+                    &format!("/storage/{name}/{field}"),
+                    value,
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mounts(lines: &[&str]) -> Vec<MountInfo> {
+        lines
+            .iter()
+            .map(|line| MountInfo::from_line(line).unwrap())
+            .collect()
+    }
+
+    const ROOT_RO: &str = "20 1 179:2 / / ro,relatime shared:1 - ext4 /dev/mmcblk0p2 ro";
+    const DATA: &str = "30 20 179:4 / /data rw,relatime shared:2 - ext4 /dev/mmcblk0p4 rw";
+    const TMP: &str = "40 20 0:30 / /var/volatile rw,nosuid,nodev shared:3 - tmpfs tmpfs rw";
+
+    #[test]
+    fn read_only_root() {
+        let mounts = mounts(&[ROOT_RO, DATA, TMP]);
+
+        let status = status_of(&mounts, Path::new("/var/lib/edgehog")).unwrap();
+        assert_eq!(
+            status,
+            StorageStatus {
+                fs_type: "ext4".to_string(),
+                read_only: true,
+                volatile: false,
+            }
+        );
+        assert!(!status.is_persistent());
+
+        let status = status_of(&mounts, Path::new("/data/edgehog")).unwrap();
+        assert!(status.is_persistent());
+
+        let status = status_of(&mounts, Path::new("/var/volatile/edgehog")).unwrap();
+        assert!(status.volatile);
+    }
+
+    #[test]
+    fn overlay_upper_directory() {
+        let volatile = mounts(&[
+            ROOT_RO,
+            TMP,
+            "50 20 0:40 / /var/lib rw shared:4 - overlay overlay rw,lowerdir=/var/lib,upperdir=/var/volatile/upper,workdir=/var/volatile/work",
+        ]);
+        let status = status_of(&volatile, Path::new("/var/lib/edgehog")).unwrap();
+        assert!(status.volatile);
+
+        let persistent = mounts(&[
+            ROOT_RO,
+            DATA,
+            "50 20 0:40 / /var/lib rw shared:4 - overlay overlay rw,lowerdir=/var/lib,upperdir=/data/upper,workdir=/data/work",
+        ]);
+        let status = status_of(&persistent, Path::new("/var/lib/edgehog")).unwrap();
+        assert!(status.is_persistent());
+    }
+}
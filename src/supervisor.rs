@@ -0,0 +1,316 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Supervision of the background tasks of the runtime.
+//!
+//! Each task is restarted according to its [`RestartPolicy`], with an exponential backoff, instead
+//! of silently stopping when it panics. The failures of a task and its state are sent to Astarte.
+
+use std::future::Future;
+use std::time::Duration;
+
+use astarte_device_sdk::types::AstarteType;
+use log::{error, info, warn};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::data::Publisher;
+
+const SUBSYSTEM_STATUS_INTERFACE: &str = "io.edgehog.devicemanager.SubsystemStatus";
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Time after which a running task is healthy again, and the backoff is reset.
+const HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+/// When a supervised task is restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RestartPolicy {
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    fn restart(&self, panicked: bool) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => panicked,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+/// Spawns the background tasks and restarts them.
+#[derive(Debug, Clone)]
+pub(crate) struct Supervisor<P> {
+    publisher: P,
+}
+
+impl<P> Supervisor<P>
+where
+    P: Publisher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(publisher: P) -> Self {
+        Self { publisher }
+    }
+
+    /// Spawn the task created by the factory, creating a new one on restart.
+    pub(crate) fn spawn<F, Fut>(
+        &self,
+        name: &'static str,
+        policy: RestartPolicy,
+        factory: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let publisher = self.publisher.clone();
+
+        tokio::spawn(async move {
+            let mut failures: i32 = 0;
+            let mut backoff = MIN_BACKOFF;
+
+            loop {
+                let started = Instant::now();
+
+                let mut task = AbortOnDrop(tokio::spawn(factory()));
+
+                let panicked = match (&mut task.0).await {
+                    Ok(()) => {
+                        info!("task {name} completed");
+
+                        false
+                    }
+                    Err(err) if err.is_panic() => {
+                        failures = failures.saturating_add(1);
+
+                        error!("task {name} panicked, {failures} failures");
+
+                        true
+                    }
+                    Err(err) => {
+                        warn!("task {name} cancelled: {err}");
+
+                        return;
+                    }
+                };
+
+                let restart = policy.restart(panicked);
+
+                if panicked {
+                    let status = if restart { "degraded" } else { "stopped" };
+
+                    send_status(&publisher, name, status, failures).await;
+                }
+
+                if !restart {
+                    return;
+                }
+
+                if started.elapsed() >= HEALTHY_AFTER {
+                    backoff = MIN_BACKOFF;
+                }
+
+                info!("restarting task {name} in {}s", backoff.as_secs());
+
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+
+                if panicked {
+                    send_status(&publisher, name, "running", failures).await;
+                }
+            }
+        })
+    }
+}
+
+/// Aborts the supervised task when dropped, so it doesn't outlive an aborted supervisor.
+#[derive(Debug)]
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn send_status<P>(publisher: &P, name: &str, status: &str, failures: i32)
+where
+    P: Publisher + Send + Sync,
+{
+    let res = publisher
+        .send(
+            SUBSYSTEM_STATUS_INTERFACE,
+            &format!("/{name}/status"),
+            AstarteType::String(status.to_string()),
+        )
+        .await;
+
+    if let Err(err) = res {
+        error!("couldn't send the status of {name}: {err}");
+
+        return;
+    }
+
+    if let Err(err) = publisher
+        .send(
+            SUBSYSTEM_STATUS_INTERFACE,
+            &format!("/{name}/failures"),
+            AstarteType::Integer(failures),
+        )
+        .await
+    {
+        error!("couldn't send the failures of {name}: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use mockall::Sequence;
+
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    /// Publisher expecting the statuses to be sent by the supervised task.
+    fn expect_statuses(statuses: &'static [(&'static str, i32)]) -> MockPublisher {
+        let mut task_publisher = MockPublisher::new();
+        let mut seq = Sequence::new();
+
+        for (status, failures) in statuses {
+            task_publisher
+                .expect_send()
+                .withf(move |iface: &str, path: &str, data: &AstarteType| {
+                    iface == SUBSYSTEM_STATUS_INTERFACE
+                        && path == "/task/status"
+                        && *data == AstarteType::String(status.to_string())
+                })
+                .once()
+                .in_sequence(&mut seq)
+                .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+            task_publisher
+                .expect_send()
+                .withf(move |iface: &str, path: &str, data: &AstarteType| {
+                    iface == SUBSYSTEM_STATUS_INTERFACE
+                        && path == "/task/failures"
+                        && *data == AstarteType::Integer(*failures)
+                })
+                .once()
+                .in_sequence(&mut seq)
+                .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+        }
+
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_clone()
+            .once()
+            .return_once(move || task_publisher);
+
+        publisher
+    }
+
+    #[test]
+    fn restart_policy() {
+        assert!(!RestartPolicy::Never.restart(true));
+        assert!(RestartPolicy::OnFailure.restart(true));
+        assert!(!RestartPolicy::OnFailure.restart(false));
+        assert!(RestartPolicy::Always.restart(false));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_on_failure() {
+        let publisher = expect_statuses(&[("degraded", 1), ("running", 1)]);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_cl = runs.clone();
+
+        let handle = Supervisor { publisher }.spawn("task", RestartPolicy::OnFailure, move || {
+            let runs = runs_cl.clone();
+
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first run fails");
+                }
+            }
+        });
+
+        handle.await.unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn never_restart() {
+        let publisher = expect_statuses(&[("stopped", 1)]);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_cl = runs.clone();
+
+        let handle = Supervisor { publisher }.spawn("task", RestartPolicy::Never, move || {
+            let runs = runs_cl.clone();
+
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+
+                panic!("always fails");
+            }
+        });
+
+        handle.await.unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn abort_supervised_task() {
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_clone()
+            .once()
+            .returning(MockPublisher::new);
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let tx = Arc::new(std::sync::Mutex::new(Some(tx)));
+
+        let handle = Supervisor { publisher }.spawn("task", RestartPolicy::Always, move || {
+            // dropped when the task is aborted
+            let tx = tx.lock().unwrap().take();
+
+            async move {
+                let _tx = tx;
+
+                std::future::pending::<()>().await;
+            }
+        });
+
+        tokio::task::yield_now().await;
+        handle.abort();
+
+        let res = tokio::time::timeout(Duration::from_secs(5), rx)
+            .await
+            .expect("supervised task not aborted");
+        assert!(res.is_err());
+    }
+}
@@ -19,10 +19,11 @@
 //! Wrapper to notify systemd for the status
 
 use std::io;
+use std::time::Duration;
 
 use log::error;
 use systemd::daemon;
-use systemd::daemon::{STATE_ERRNO, STATE_READY, STATE_STATUS};
+use systemd::daemon::{STATE_ERRNO, STATE_READY, STATE_STATUS, STATE_STOPPING, STATE_WATCHDOG};
 
 /// Check the result of the call to [`daemon::notify`].
 ///
@@ -59,6 +60,52 @@ pub fn systemd_notify_ready_status(service_status: &str) {
     check_notify_result(notify);
 }
 
+pub fn systemd_notify_stopping_status(service_status: &str) {
+    let systemd_state_pairs = [(STATE_STOPPING, "1"), (STATE_STATUS, service_status)];
+    let notify = daemon::notify(false, systemd_state_pairs.iter());
+
+    check_notify_result(notify);
+}
+
+/// Keep-alive ping for the systemd watchdog.
+pub fn systemd_notify_watchdog() {
+    let systemd_state_pairs = [(STATE_WATCHDOG, "1")];
+    let notify = daemon::notify(false, systemd_state_pairs.iter());
+
+    check_notify_result(notify);
+}
+
+/// Extend the start or stop timeout of the unit to the given duration from now.
+pub fn systemd_notify_extend_timeout(timeout: Duration) {
+    let usec = timeout.as_micros().to_string();
+    let systemd_state_pairs = [("EXTEND_TIMEOUT_USEC", usec.as_str())];
+    let notify = daemon::notify(false, systemd_state_pairs.iter());
+
+    check_notify_result(notify);
+}
+
+/// Interval of the watchdog pings, half of the `WatchdogSec` configured in the unit.
+///
+/// Returns [`None`] if the watchdog is disabled or it's configured for another process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok()?;
+    let pid = std::env::var("WATCHDOG_PID").ok();
+
+    parse_watchdog_interval(&usec, pid.as_deref(), std::process::id())
+}
+
+fn parse_watchdog_interval(usec: &str, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    let usec: u64 = usec.parse().ok()?;
+
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
 pub fn systemd_notify_errno_status(err_no: i32, service_status: &str) {
     let systemd_state_pairs = [
         (STATE_ERRNO, err_no.to_string()),
@@ -68,3 +115,23 @@ pub fn systemd_notify_errno_status(err_no: i32, service_status: &str) {
 
     check_notify_result(notify);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_interval_from_env() {
+        assert_eq!(
+            parse_watchdog_interval("10000000", None, 42),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            parse_watchdog_interval("10000000", Some("42"), 42),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(parse_watchdog_interval("10000000", Some("7"), 42), None);
+        assert_eq!(parse_watchdog_interval("0", None, 42), None);
+        assert_eq!(parse_watchdog_interval("invalid", None, 42), None);
+    }
+}
@@ -19,15 +19,73 @@
  */
 
 use std::collections::HashMap;
+use std::path::Path;
 
 use astarte_device_sdk::types::AstarteType;
+use log::error;
+use serde::Deserialize;
 
 use crate::DeviceManagerError;
 
+const BASE_IMAGE_METADATA_PATH: &str = "/etc/edgehog/base-image.toml";
+
+/// Metadata of the base image, overrides the values of the os-release file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct BaseImageMetadata {
+    name: Option<String>,
+    version: Option<String>,
+    build_id: Option<String>,
+}
+
+impl BaseImageMetadata {
+    fn apply(self, ret: &mut HashMap<String, AstarteType>) {
+        let values = [
+            ("/name", self.name),
+            ("/version", self.version),
+            ("/buildId", self.build_id),
+        ];
+
+        for (path, value) in values {
+            if let Some(value) = value {
+                ret.insert(path.to_owned(), AstarteType::String(value));
+            }
+        }
+    }
+}
+
+/// get structured data for `io.edgehog.devicemanager.BaseImage` interface
 pub async fn get_base_image() -> Result<HashMap<String, AstarteType>, DeviceManagerError> {
-    let file = tokio::fs::read_to_string("/etc/os-release").await?;
+    let paths = ["/etc/os-release", "/usr/lib/os-release"];
+
+    let path = paths
+        .iter()
+        .find(|f| Path::new(f).exists())
+        .ok_or_else(|| DeviceManagerError::FatalError("No os-release file found".to_owned()))?;
+
+    let file = tokio::fs::read_to_string(path).await?;
+
+    let mut ret = file.lines().fold(HashMap::new(), get_from_iter);
+
+    if let Some(metadata) = read_base_image_metadata(Path::new(BASE_IMAGE_METADATA_PATH)).await {
+        metadata.apply(&mut ret);
+    }
+
+    Ok(ret)
+}
+
+async fn read_base_image_metadata(path: &Path) -> Option<BaseImageMetadata> {
+    if !path.exists() {
+        return None;
+    }
+
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|err| error!("couldn't read {}: {err}", path.display()))
+        .ok()?;
 
-    Ok(file.lines().fold(HashMap::new(), get_from_iter))
+    toml::from_str(&content)
+        .map_err(|err| error!("invalid base image metadata {}: {err}", path.display()))
+        .ok()
 }
 
 fn get_from_iter(
@@ -63,9 +121,12 @@ fn get_from_iter(
 
 #[cfg(test)]
 mod tests {
-    use crate::telemetry::base_image::{get_base_image, get_from_iter};
+    use crate::telemetry::base_image::{
+        get_base_image, get_from_iter, read_base_image_metadata, BaseImageMetadata,
+    };
     use astarte_device_sdk::types::AstarteType;
     use std::collections::HashMap;
+    use tempdir::TempDir;
 
     #[tokio::test]
     async fn get_base_image_test() {
@@ -126,4 +187,33 @@ mod tests {
             &AstarteType::String("20220922".to_string())
         );
     }
+
+    #[tokio::test]
+    async fn base_image_metadata() {
+        let dir = TempDir::new("edgehog-base-image").unwrap();
+        let path = dir.path().join("base-image.toml");
+
+        assert!(read_base_image_metadata(&path).await.is_none());
+
+        std::fs::write(&path, "name = \"edgehog-os\"\nbuild_id = \"42\"\n").unwrap();
+
+        let metadata = read_base_image_metadata(&path).await.unwrap();
+        assert_eq!(
+            metadata,
+            BaseImageMetadata {
+                name: Some("edgehog-os".to_string()),
+                version: None,
+                build_id: Some("42".to_string()),
+            }
+        );
+
+        let mut map = "IMAGE_VERSION=\"1.0.0+20220922\""
+            .lines()
+            .fold(HashMap::new(), get_from_iter);
+        metadata.apply(&mut map);
+
+        assert_eq!(map["/name"], AstarteType::String("edgehog-os".to_string()));
+        assert_eq!(map["/version"], AstarteType::String("1.0.0".to_string()));
+        assert_eq!(map["/buildId"], AstarteType::String("42".to_string()));
+    }
 }
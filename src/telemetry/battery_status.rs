@@ -19,7 +19,9 @@
  */
 
 use astarte_device_sdk::AstarteAggregate;
-use std::collections::HashMap;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
 use crate::error::DeviceManagerError;
 use crate::telemetry::upower::device::{BatteryState, DeviceProxy, PowerDeviceType};
@@ -47,7 +49,78 @@ impl BatteryStatus {
     }
 }
 
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Values of the sysfs `health` attribute reporting a faulty battery.
+const FAILURE_HEALTH: &[&str] = &[
+    "Dead",
+    "Over voltage",
+    "Unspecified failure",
+    "Overheat",
+    "Cold",
+    "Over current",
+    "Watchdog timer expire",
+    "Safety timer expire",
+];
+
+impl BatteryStatus {
+    fn removed() -> Self {
+        BatteryStatus {
+            levelPercentage: 0.0,
+            levelAbsoluteError: 100.0,
+            status: "Removed".to_string(),
+        }
+    }
+}
+
+/// Keeps track of the batteries to detect the ones connected or removed between two reads.
+#[derive(Debug, Default)]
+pub struct BatteryMonitor {
+    known: HashSet<String>,
+}
+
+impl BatteryMonitor {
+    /// Status of the batteries, with the removed ones reported as `Removed` once.
+    pub async fn battery_status(
+        &mut self,
+    ) -> Result<HashMap<String, BatteryStatus>, DeviceManagerError> {
+        let mut batteries = get_battery_status().await?;
+
+        self.track(&mut batteries);
+
+        Ok(batteries)
+    }
+
+    fn track(&mut self, batteries: &mut HashMap<String, BatteryStatus>) {
+        let current: HashSet<String> = batteries.keys().cloned().collect();
+
+        for added in current.difference(&self.known) {
+            info!("battery {added} connected");
+        }
+
+        for removed in self.known.difference(&current) {
+            info!("battery {removed} removed");
+
+            batteries.insert(removed.clone(), BatteryStatus::removed());
+        }
+
+        self.known = current;
+    }
+}
+
+/// Status of the batteries from UPower, or from sysfs if UPower is not available.
 pub async fn get_battery_status() -> Result<HashMap<String, BatteryStatus>, DeviceManagerError> {
+    match get_upower_battery_status().await {
+        Ok(batteries) => Ok(batteries),
+        Err(err) => {
+            debug!("couldn't get battery status from UPower, reading sysfs: {err}");
+
+            read_power_supplies(Path::new(POWER_SUPPLY_PATH))
+        }
+    }
+}
+
+async fn get_upower_battery_status() -> Result<HashMap<String, BatteryStatus>, DeviceManagerError> {
     let connection = zbus::Connection::system().await?;
     let upower = UPowerProxy::new(&connection).await?;
     let devices = upower.enumerate_devices().await?;
@@ -76,6 +149,92 @@ pub async fn get_battery_status() -> Result<HashMap<String, BatteryStatus>, Devi
     Ok(result)
 }
 
+fn read_power_supplies(base: &Path) -> Result<HashMap<String, BatteryStatus>, DeviceManagerError> {
+    let mut batteries = HashMap::new();
+
+    let entries = match std::fs::read_dir(base) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            debug!("no power supply class in sysfs");
+
+            return Ok(batteries);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+
+        if read_attribute(&path, "type").as_deref() != Some("Battery") {
+            continue;
+        }
+
+        // Same identifier used by UPower, falling back to the name of the supply
+        let id = read_attribute(&path, "serial_number")
+            .filter(|serial| !serial.is_empty())
+            .unwrap_or_else(|| name.clone());
+
+        match read_power_supply(&path) {
+            Ok(battery) => {
+                batteries.insert(id, battery);
+            }
+            Err(err) => warn!("couldn't read power supply {name}: {err}"),
+        }
+    }
+
+    Ok(batteries)
+}
+
+fn read_power_supply(path: &Path) -> Result<BatteryStatus, DeviceManagerError> {
+    let present = read_attribute(path, "present").map_or(true, |present| present == "1");
+    if !present {
+        return Ok(BatteryStatus::removed());
+    }
+
+    // The capacity is an integer percentage
+    let capacity = read_attribute(path, "capacity")
+        .map(|capacity| capacity.parse::<u8>().map(f64::from))
+        .transpose()?;
+    let status = read_attribute(path, "status").unwrap_or_default();
+    let health = read_attribute(path, "health").unwrap_or_default();
+
+    let status = get_sysfs_status(&status, &health);
+    let level_absolute_error = match (capacity, status.as_str()) {
+        (Some(_), "Charging" | "Discharging" | "Idle") => 0.0,
+        _ => 100.0,
+    };
+
+    Ok(BatteryStatus {
+        levelPercentage: capacity.unwrap_or_default(),
+        levelAbsoluteError: level_absolute_error,
+        status,
+    })
+}
+
+fn read_attribute(path: &Path, attribute: &str) -> Option<String> {
+    std::fs::read_to_string(path.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+fn get_sysfs_status(status: &str, health: &str) -> String {
+    if FAILURE_HEALTH.contains(&health) {
+        return "Failure".to_string();
+    }
+
+    match status {
+        "Charging" => "Charging".to_string(),
+        "Discharging" => "Discharging".to_string(),
+        "Not charging" | "Full" => "Idle".to_string(),
+        _ => "Unknown".to_string(),
+    }
+}
+
 fn get_status(device_state: BatteryState, is_present: bool) -> String {
     match device_state {
         BatteryState::Charging => "Charging".to_string(),
@@ -102,9 +261,21 @@ fn get_error_level(device_state: BatteryState) -> f64 {
 #[cfg(test)]
 mod tests {
     use crate::telemetry::battery_status::{
-        get_battery_status, get_error_level, get_status, BatteryStatus,
+        get_battery_status, get_error_level, get_status, get_sysfs_status, read_power_supplies,
+        BatteryMonitor, BatteryStatus,
     };
     use crate::telemetry::upower::device::BatteryState;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    fn create_supply(base: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let dir = base.join(name);
+        std::fs::create_dir(&dir).unwrap();
+        for (attribute, value) in attributes {
+            std::fs::write(dir.join(attribute), format!("{value}\n")).unwrap();
+        }
+    }
 
     #[tokio::test]
     async fn battery_unknown_new_test() {
@@ -232,4 +403,74 @@ mod tests {
         assert_eq!(get_error_level(BatteryState::Charging), 0_f64);
         assert_eq!(get_error_level(BatteryState::Unknown), 100_f64);
     }
+
+    #[test]
+    fn get_sysfs_status_test() {
+        assert_eq!(get_sysfs_status("Charging", "Good"), "Charging");
+        assert_eq!(get_sysfs_status("Discharging", ""), "Discharging");
+        assert_eq!(get_sysfs_status("Not charging", "Good"), "Idle");
+        assert_eq!(get_sysfs_status("Full", "Good"), "Idle");
+        assert_eq!(get_sysfs_status("Discharging", "Dead"), "Failure");
+        assert_eq!(get_sysfs_status("", ""), "Unknown");
+    }
+
+    #[test]
+    fn read_power_supplies_test() {
+        let dir = TempDir::new("edgehog-power-supply").unwrap();
+        create_supply(
+            dir.path(),
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("present", "1"),
+                ("capacity", "87"),
+                ("status", "Discharging"),
+                ("health", "Good"),
+                ("serial_number", "SN123"),
+            ],
+        );
+        create_supply(dir.path(), "BAT1", &[("type", "Battery"), ("present", "0")]);
+        create_supply(dir.path(), "AC", &[("type", "Mains"), ("online", "1")]);
+
+        let batteries = read_power_supplies(dir.path()).unwrap();
+
+        assert_eq!(batteries.len(), 2);
+        assert_eq!(
+            batteries["SN123"],
+            BatteryStatus {
+                levelPercentage: 87.0,
+                levelAbsoluteError: 0.0,
+                status: "Discharging".to_string()
+            }
+        );
+        assert_eq!(batteries["BAT1"], BatteryStatus::removed());
+    }
+
+    #[test]
+    fn read_power_supplies_missing() {
+        let batteries = read_power_supplies(Path::new("/this/path/does/not/exist")).unwrap();
+
+        assert!(batteries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn battery_monitor_hotplug() {
+        let mut monitor = BatteryMonitor::default();
+
+        let mut batteries = HashMap::from([(
+            "BAT0".to_string(),
+            BatteryStatus::new(50.0, BatteryState::Discharging, true).await,
+        )]);
+        monitor.track(&mut batteries);
+        assert_eq!(batteries.len(), 1);
+
+        let mut batteries = HashMap::new();
+        monitor.track(&mut batteries);
+        assert_eq!(batteries["BAT0"], BatteryStatus::removed());
+
+        // the removal is reported only once
+        let mut batteries = HashMap::new();
+        monitor.track(&mut batteries);
+        assert!(batteries.is_empty());
+    }
 }
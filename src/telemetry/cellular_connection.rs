@@ -0,0 +1,462 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Information on the cellular modems managed by ModemManager.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{debug, warn};
+use zbus::fdo::ObjectManagerProxy;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::Connection;
+
+use crate::error::DeviceManagerError;
+use crate::telemetry::modem_manager::{
+    BearerProxy, LocationProxy, Modem3gppProxy, ModemProxy, SignalProxy, SimProxy, MODEM_INTERFACE,
+    MODEM_MANAGER_PATH, MODEM_MANAGER_SERVICE,
+};
+
+/// Rate in seconds used to enable the extended signal information on the modem.
+const SIGNAL_REFRESH_RATE: u32 = 30;
+/// MMModemLocationSource of the serving cell.
+const LOCATION_SOURCE_3GPP_LAC_CI: u32 = 1 << 0;
+
+// MMModem3gppRegistrationState values
+const REGISTRATION_IDLE: u32 = 0;
+const REGISTRATION_HOME: u32 = 1;
+const REGISTRATION_SEARCHING: u32 = 2;
+const REGISTRATION_DENIED: u32 = 3;
+const REGISTRATION_ROAMING: u32 = 5;
+const REGISTRATION_HOME_SMS_ONLY: u32 = 6;
+const REGISTRATION_ROAMING_SMS_ONLY: u32 = 7;
+const REGISTRATION_HOME_CSFB_NOT_PREFERRED: u32 = 9;
+const REGISTRATION_ROAMING_CSFB_NOT_PREFERRED: u32 = 10;
+
+// MMModemAccessTechnology flags, ordered from the newest technology
+const ACCESS_TECHNOLOGIES: &[(u32, &str)] = &[
+    (1 << 15, "NR"),
+    (1 << 14 | 1 << 16 | 1 << 17, "EUTRAN"),
+    (1 << 8 | 1 << 9, "UTRANwHSDPAandHSUPA"),
+    (1 << 7, "UTRANwHSUPA"),
+    (1 << 6, "UTRANwHSDPA"),
+    (1 << 5, "UTRAN"),
+    (1 << 3 | 1 << 4, "GSMwEGPRS"),
+    (1 << 2, "GSMCompact"),
+    (1 << 1, "GSM"),
+];
+
+#[derive(Debug, Clone, PartialEq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct CellularConnectionStatus {
+    pub carrier: String,
+    /// Any of: NotRegistered, Registered, SearchingOperator, RegistrationDenied, Unknown,
+    /// RegisteredRoaming
+    pub registration_status: String,
+    pub technology: String,
+    /// Signal quality in percent.
+    pub signal_quality: i32,
+    /// RSSI in dBm, 0 if the modem doesn't report it.
+    pub rssi: f64,
+    /// RSRP in dBm, 0 if the modem doesn't report it.
+    pub rsrp: f64,
+}
+
+/// Serving cell of a modem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellLocation {
+    pub mobile_country_code: u16,
+    pub mobile_network_code: u16,
+    /// Location area code, or the tracking area code on LTE.
+    pub location_area_code: u32,
+    pub cell_id: u32,
+}
+
+/// get structured data for `io.edgehog.devicemanager.CellularConnectionProperties` interface
+pub async fn get_cellular_properties() -> Result<HashMap<String, AstarteType>, DeviceManagerError> {
+    let connection = Connection::system().await?;
+
+    let mut result = HashMap::new();
+    for path in modem_paths(&connection).await? {
+        let modem = ModemProxy::builder(&connection)
+            .path(path.clone())?
+            .build()
+            .await?;
+        let modem_3gpp = Modem3gppProxy::builder(&connection)
+            .path(path)?
+            .build()
+            .await?;
+
+        let id = modem.device_identifier().await?;
+
+        result.insert(
+            format!("/{id}/imei"),
+            AstarteType::String(modem_3gpp.imei().await?),
+        );
+
+        if let Some(imsi) = get_imsi(&connection, &modem).await? {
+            result.insert(format!("/{id}/imsi"), AstarteType::String(imsi));
+        }
+
+        if let Some(apn) = get_apn(&connection, &modem).await? {
+            result.insert(format!("/{id}/apn"), AstarteType::String(apn));
+        }
+    }
+
+    Ok(result)
+}
+
+/// get structured data for `io.edgehog.devicemanager.CellularConnectionStatus` interface
+pub async fn get_cellular_status(
+) -> Result<HashMap<String, CellularConnectionStatus>, DeviceManagerError> {
+    let connection = Connection::system().await?;
+
+    let mut result = HashMap::new();
+    for path in modem_paths(&connection).await? {
+        let modem = ModemProxy::builder(&connection)
+            .path(path.clone())?
+            .build()
+            .await?;
+        let modem_3gpp = Modem3gppProxy::builder(&connection)
+            .path(path.clone())?
+            .build()
+            .await?;
+        let signal = SignalProxy::builder(&connection)
+            .path(path)?
+            .build()
+            .await?;
+
+        let (quality, _recent) = modem.signal_quality().await?;
+        let (rssi, rsrp) = get_signal(&signal).await;
+
+        result.insert(
+            modem.device_identifier().await?,
+            CellularConnectionStatus {
+                carrier: modem_3gpp.operator_name().await?,
+                registration_status: registration_status(modem_3gpp.registration_state().await?)
+                    .to_string(),
+                technology: technology(modem.access_technologies().await?).to_string(),
+                signal_quality: quality.try_into().unwrap_or(i32::MAX),
+                rssi: rssi.unwrap_or_default(),
+                rsrp: rsrp.unwrap_or_default(),
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+/// Serving cells of the modems, from the 3GPP location of ModemManager.
+///
+/// The location source is disabled by default, so it's enabled on the first read and will be
+/// available from the next one.
+pub async fn get_cell_locations() -> Result<Vec<CellLocation>, DeviceManagerError> {
+    let connection = Connection::system().await?;
+
+    let mut cells = Vec::new();
+    for path in modem_paths(&connection).await? {
+        let location = LocationProxy::builder(&connection)
+            .path(path)?
+            .build()
+            .await?;
+
+        if location.capabilities().await? & LOCATION_SOURCE_3GPP_LAC_CI == 0 {
+            debug!("no 3GPP location for modem {}", location.path());
+
+            continue;
+        }
+
+        let enabled = location.enabled().await?;
+        if enabled & LOCATION_SOURCE_3GPP_LAC_CI == 0 {
+            debug!("enabling the 3GPP location of modem {}", location.path());
+
+            let signals_location = location.signals_location().await?;
+            if let Err(err) = location
+                .setup(enabled | LOCATION_SOURCE_3GPP_LAC_CI, signals_location)
+                .await
+            {
+                warn!("couldn't enable the 3GPP location: {err}");
+            }
+
+            continue;
+        }
+
+        let cell = location
+            .get_location()
+            .await?
+            .get(&LOCATION_SOURCE_3GPP_LAC_CI)
+            .and_then(|value| <&str>::try_from(value).ok())
+            .and_then(parse_3gpp_location);
+
+        cells.extend(cell);
+    }
+
+    Ok(cells)
+}
+
+/// Parse the `MCC,MNC,LAC,CI,TAC` 3GPP location, with the LAC, CI and TAC in hexadecimal.
+fn parse_3gpp_location(value: &str) -> Option<CellLocation> {
+    let mut fields = value.split(',');
+
+    let mobile_country_code = fields.next()?.parse().ok()?;
+    let mobile_network_code = fields.next()?.parse().ok()?;
+    let lac = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let cell_id = u32::from_str_radix(fields.next()?, 16).ok()?;
+    // The LAC is 0 on LTE, where the TAC is used instead
+    let tac = fields
+        .next()
+        .and_then(|tac| u32::from_str_radix(tac, 16).ok())
+        .unwrap_or_default();
+
+    Some(CellLocation {
+        mobile_country_code,
+        mobile_network_code,
+        location_area_code: if lac == 0 { tac } else { lac },
+        cell_id,
+    })
+}
+
+/// Object paths of the modems exported by ModemManager.
+async fn modem_paths(connection: &Connection) -> Result<Vec<OwnedObjectPath>, DeviceManagerError> {
+    let object_manager = ObjectManagerProxy::builder(connection)
+        .destination(MODEM_MANAGER_SERVICE)?
+        .path(MODEM_MANAGER_PATH)?
+        .build()
+        .await?;
+
+    let objects = object_manager
+        .get_managed_objects()
+        .await
+        .map_err(zbus::Error::from)?;
+
+    let modems = objects
+        .into_iter()
+        .filter(|(_, interfaces)| {
+            interfaces
+                .keys()
+                .any(|interface| interface.as_str() == MODEM_INTERFACE)
+        })
+        .map(|(path, _)| path)
+        .collect();
+
+    Ok(modems)
+}
+
+async fn get_imsi(
+    connection: &Connection,
+    modem: &ModemProxy<'_>,
+) -> Result<Option<String>, DeviceManagerError> {
+    let path = modem.sim().await?;
+    if path.as_str() == "/" {
+        debug!("no SIM in modem {}", modem.path());
+
+        return Ok(None);
+    }
+
+    let sim = SimProxy::builder(connection).path(path)?.build().await?;
+    let imsi = sim.imsi().await?;
+
+    Ok((!imsi.is_empty()).then_some(imsi))
+}
+
+/// APN of the first connected bearer, or of the first one configured.
+async fn get_apn(
+    connection: &Connection,
+    modem: &ModemProxy<'_>,
+) -> Result<Option<String>, DeviceManagerError> {
+    let mut apn = None;
+
+    for path in modem.bearers().await? {
+        let bearer = BearerProxy::builder(connection).path(path)?.build().await?;
+
+        let Some(bearer_apn) = string_value(&bearer.bearer_properties().await?, "apn") else {
+            continue;
+        };
+
+        if bearer.connected().await? {
+            return Ok(Some(bearer_apn));
+        }
+
+        apn.get_or_insert(bearer_apn);
+    }
+
+    Ok(apn)
+}
+
+/// RSSI and RSRP from the extended signal information.
+///
+/// The extended information is disabled by default, so it's enabled on the first read and will be
+/// available from the next one.
+async fn get_signal(signal: &SignalProxy<'_>) -> (Option<f64>, Option<f64>) {
+    match signal.rate().await {
+        Ok(0) => {
+            debug!("enabling extended signal information");
+
+            if let Err(err) = signal.setup(SIGNAL_REFRESH_RATE).await {
+                warn!("couldn't enable the extended signal information: {err}");
+            }
+
+            return (None, None);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            debug!("extended signal information not supported: {err}");
+
+            return (None, None);
+        }
+    }
+
+    let technologies = [
+        signal.nr5g().await,
+        signal.lte().await,
+        signal.umts().await,
+        signal.gsm().await,
+    ];
+
+    let values: Vec<HashMap<String, OwnedValue>> =
+        technologies.into_iter().filter_map(Result::ok).collect();
+
+    signal_values(&values)
+}
+
+/// First RSSI and RSRP reported, the technologies are ordered by preference.
+fn signal_values(technologies: &[HashMap<String, OwnedValue>]) -> (Option<f64>, Option<f64>) {
+    let find = |key: &str| {
+        technologies
+            .iter()
+            .find_map(|values| values.get(key).and_then(|v| f64::try_from(v).ok()))
+    };
+
+    (find("rssi"), find("rsrp"))
+}
+
+fn string_value(values: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    values
+        .get(key)
+        .and_then(|value| <&str>::try_from(value).ok())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn registration_status(state: u32) -> &'static str {
+    match state {
+        REGISTRATION_IDLE => "NotRegistered",
+        REGISTRATION_HOME | REGISTRATION_HOME_SMS_ONLY | REGISTRATION_HOME_CSFB_NOT_PREFERRED => {
+            "Registered"
+        }
+        REGISTRATION_SEARCHING => "SearchingOperator",
+        REGISTRATION_DENIED => "RegistrationDenied",
+        REGISTRATION_ROAMING
+        | REGISTRATION_ROAMING_SMS_ONLY
+        | REGISTRATION_ROAMING_CSFB_NOT_PREFERRED => "RegisteredRoaming",
+        _ => "Unknown",
+    }
+}
+
+fn technology(access_technologies: u32) -> &'static str {
+    ACCESS_TECHNOLOGIES
+        .iter()
+        .find(|(flags, _)| access_technologies & flags != 0)
+        .map(|(_, name)| *name)
+        .unwrap_or("Unknown")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use zbus::zvariant::Value;
+
+    fn values(entries: &[(&str, Value<'static>)]) -> HashMap<String, OwnedValue> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), OwnedValue::from(value.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn registration_status_from_state() {
+        assert_eq!(registration_status(0), "NotRegistered");
+        assert_eq!(registration_status(1), "Registered");
+        assert_eq!(registration_status(2), "SearchingOperator");
+        assert_eq!(registration_status(3), "RegistrationDenied");
+        assert_eq!(registration_status(4), "Unknown");
+        assert_eq!(registration_status(5), "RegisteredRoaming");
+        assert_eq!(registration_status(42), "Unknown");
+    }
+
+    #[test]
+    fn technology_from_flags() {
+        assert_eq!(technology(0), "Unknown");
+        assert_eq!(technology(1 << 1), "GSM");
+        assert_eq!(technology(1 << 4), "GSMwEGPRS");
+        assert_eq!(technology(1 << 5 | 1 << 6), "UTRANwHSDPA");
+        assert_eq!(technology(1 << 1 | 1 << 14), "EUTRAN");
+        assert_eq!(technology(1 << 14 | 1 << 15), "NR");
+    }
+
+    #[test]
+    fn signal_values_by_preference() {
+        let lte = values(&[("rssi", Value::F64(-70.0)), ("rsrp", Value::F64(-95.0))]);
+        let umts = values(&[("rssi", Value::F64(-80.0))]);
+
+        assert_eq!(
+            signal_values(&[umts.clone(), lte.clone()]),
+            (Some(-80.0), Some(-95.0))
+        );
+        assert_eq!(signal_values(&[lte, umts]), (Some(-70.0), Some(-95.0)));
+        assert_eq!(signal_values(&[]), (None, None));
+    }
+
+    #[test]
+    fn cell_from_3gpp_location() {
+        assert_eq!(
+            parse_3gpp_location("222,01,1A2B,3C4D,0"),
+            Some(CellLocation {
+                mobile_country_code: 222,
+                mobile_network_code: 1,
+                location_area_code: 0x1a2b,
+                cell_id: 0x3c4d,
+            })
+        );
+        assert_eq!(
+            parse_3gpp_location("222,10,0,1F2E3D,5A"),
+            Some(CellLocation {
+                mobile_country_code: 222,
+                mobile_network_code: 10,
+                location_area_code: 0x5a,
+                cell_id: 0x1f2e3d,
+            })
+        );
+        assert_eq!(parse_3gpp_location("222,10"), None);
+        assert_eq!(parse_3gpp_location(""), None);
+    }
+
+    #[test]
+    fn string_value_empty() {
+        let properties = values(&[("apn", Value::from("internet")), ("user", Value::from(""))]);
+
+        assert_eq!(
+            string_value(&properties, "apn"),
+            Some("internet".to_string())
+        );
+        assert_eq!(string_value(&properties, "user"), None);
+        assert_eq!(string_value(&properties, "password"), None);
+    }
+}
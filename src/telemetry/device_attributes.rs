@@ -0,0 +1,206 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Custom attributes of the device, declared in the configuration.
+//!
+//! Integrators can attach metadata like the site or the hardware revision to the device without
+//! modifying the runtime. Each attribute is either a value or the content of a file.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use astarte_device_sdk::types::AstarteType;
+use log::warn;
+use serde::Deserialize;
+
+use crate::data::Publisher;
+use crate::error::DeviceManagerError;
+
+const DEVICE_ATTRIBUTES_INTERFACE: &str = "io.edgehog.devicemanager.DeviceAttributes";
+
+/// Value of an attribute, or the file to read it from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Value(String),
+    File { file: PathBuf },
+}
+
+/// Values of the attributes by path, skipping the invalid ones.
+async fn get_device_attributes(
+    attributes: &HashMap<String, AttributeValue>,
+) -> HashMap<String, AstarteType> {
+    let mut values = HashMap::new();
+
+    for (key, attribute) in attributes {
+        if key.is_empty() || key.contains('/') {
+            warn!("invalid device attribute name {key:?}");
+
+            continue;
+        }
+
+        let value = match attribute {
+            AttributeValue::Value(value) => value.clone(),
+            AttributeValue::File { file } => match tokio::fs::read_to_string(file).await {
+                Ok(content) => content.trim_end().to_string(),
+                Err(err) => {
+                    warn!(
+                        "couldn't read device attribute {key} from {}: {err}",
+                        file.display()
+                    );
+
+                    continue;
+                }
+            },
+        };
+
+        values.insert(format!("/{key}"), AstarteType::String(value));
+    }
+
+    values
+}
+
+/// Send the attributes, unsetting the ones no longer configured.
+pub(crate) async fn send_device_attributes<P>(
+    publisher: &P,
+    attributes: &HashMap<String, AttributeValue>,
+) -> Result<(), DeviceManagerError>
+where
+    P: Publisher + Send + Sync,
+{
+    let values = get_device_attributes(attributes).await;
+
+    let stored = publisher
+        .interface_props(DEVICE_ATTRIBUTES_INTERFACE)
+        .await?;
+    for prop in stored {
+        if !values.contains_key(&prop.path) {
+            publisher
+                .unset(DEVICE_ATTRIBUTES_INTERFACE, &prop.path)
+                .await?;
+        }
+    }
+
+    for (path, value) in values {
+        publisher
+            .send(DEVICE_ATTRIBUTES_INTERFACE, &path, value)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use astarte_device_sdk::interface::def::Ownership;
+    use astarte_device_sdk::store::StoredProp;
+    use tempdir::TempDir;
+
+    use crate::data::tests::MockPublisher;
+
+    #[tokio::test]
+    async fn send_attributes() {
+        let dir = TempDir::new("edgehog-attributes").unwrap();
+        let revision = dir.path().join("revision");
+        std::fs::write(&revision, "B2\n").unwrap();
+
+        let attributes = HashMap::from([
+            (
+                "siteId".to_string(),
+                AttributeValue::Value("turin-01".to_string()),
+            ),
+            (
+                "hardwareRevision".to_string(),
+                AttributeValue::File { file: revision },
+            ),
+            (
+                "missing".to_string(),
+                AttributeValue::File {
+                    file: dir.path().join("missing"),
+                },
+            ),
+        ]);
+
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_interface_props()
+            .withf(|iface: &str| iface == DEVICE_ATTRIBUTES_INTERFACE)
+            .returning(|_: &str| {
+                Ok(vec![StoredProp {
+                    interface: DEVICE_ATTRIBUTES_INTERFACE.to_string(),
+                    path: "/oldAttribute".to_string(),
+                    value: AstarteType::String("old".to_string()),
+                    interface_major: 0,
+                    ownership: Ownership::Device,
+                }])
+            });
+        publisher
+            .expect_unset()
+            .withf(|iface: &str, path: &str| {
+                iface == DEVICE_ATTRIBUTES_INTERFACE && path == "/oldAttribute"
+            })
+            .once()
+            .returning(|_: &str, _: &str| Ok(()));
+        publisher
+            .expect_send()
+            .withf(|iface: &str, path: &str, data: &AstarteType| {
+                iface == DEVICE_ATTRIBUTES_INTERFACE
+                    && path == "/siteId"
+                    && *data == AstarteType::String("turin-01".to_string())
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+        publisher
+            .expect_send()
+            .withf(|iface: &str, path: &str, data: &AstarteType| {
+                iface == DEVICE_ATTRIBUTES_INTERFACE
+                    && path == "/hardwareRevision"
+                    && *data == AstarteType::String("B2".to_string())
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: AstarteType| Ok(()));
+
+        send_device_attributes(&publisher, &attributes)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn deserialize_attributes() {
+        let attributes: HashMap<String, AttributeValue> = toml::from_str(
+            r#"
+            siteId = "turin-01"
+            hardwareRevision = { file = "/sys/firmware/revision" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            attributes["siteId"],
+            AttributeValue::Value("turin-01".to_string())
+        );
+        assert_eq!(
+            attributes["hardwareRevision"],
+            AttributeValue::File {
+                file: PathBuf::from("/sys/firmware/revision")
+            }
+        );
+    }
+}
@@ -0,0 +1,218 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Position from a GNSS receiver through [gpsd](https://gpsd.io/gpsd_json.html).
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::debug;
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use super::{Geolocation, GeolocationError, GeolocationProvider};
+
+/// Command to start the streaming of the reports in JSON.
+const WATCH_COMMAND: &[u8] = b"?WATCH={\"enable\":true,\"json\":true};\n";
+
+fn default_address() -> String {
+    "127.0.0.1:2947".to_string()
+}
+
+const fn default_timeout() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GpsdConfig {
+    /// Address of the gpsd daemon.
+    #[serde(default = "default_address")]
+    pub address: String,
+    /// Seconds to wait for a fix.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+/// Time-position-velocity report, the fields are missing if the fix doesn't provide them.
+#[derive(Debug, Deserialize)]
+struct Tpv {
+    class: String,
+    /// Fix mode: 0 and 1 no fix, 2 for 2D and 3 for 3D.
+    #[serde(default)]
+    mode: u8,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    /// Altitude, deprecated in favour of `altMSL` since gpsd 3.20.
+    alt: Option<f64>,
+    #[serde(rename = "altMSL")]
+    alt_msl: Option<f64>,
+    /// Longitude and latitude errors in meters.
+    epx: Option<f64>,
+    epy: Option<f64>,
+    /// Altitude error in meters.
+    epv: Option<f64>,
+    track: Option<f64>,
+    speed: Option<f64>,
+}
+
+/// Parse a gpsd report, returning the position if it's a TPV with a fix.
+fn parse_fix(line: &str) -> Result<Option<Geolocation>, GeolocationError> {
+    let tpv: Tpv = serde_json::from_str(line)?;
+
+    if tpv.class != "TPV" || tpv.mode < 2 {
+        return Ok(None);
+    }
+
+    let (Some(latitude), Some(longitude)) = (tpv.lat, tpv.lon) else {
+        return Ok(None);
+    };
+
+    let accuracy = match (tpv.epx, tpv.epy) {
+        (Some(epx), Some(epy)) => epx.max(epy),
+        (Some(error), None) | (None, Some(error)) => error,
+        (None, None) => 0.0,
+    };
+
+    Ok(Some(Geolocation {
+        latitude,
+        longitude,
+        altitude: tpv.alt_msl.or(tpv.alt).unwrap_or_default(),
+        accuracy,
+        altitude_accuracy: tpv.epv.unwrap_or_default(),
+        heading: tpv.track.unwrap_or_default(),
+        speed: tpv.speed.unwrap_or_default(),
+    }))
+}
+
+#[derive(Debug)]
+pub(crate) struct Gpsd {
+    address: String,
+    timeout: Duration,
+}
+
+impl Gpsd {
+    pub(crate) fn new(config: &GpsdConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            timeout: Duration::from_secs(config.timeout),
+        }
+    }
+
+    async fn read_fix(&self) -> Result<Option<Geolocation>, GeolocationError> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let (reader, mut writer) = stream.into_split();
+
+        writer.write_all(WATCH_COMMAND).await?;
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            match parse_fix(&line) {
+                Ok(Some(fix)) => return Ok(Some(fix)),
+                Ok(None) => {}
+                Err(err) => debug!("skipping gpsd report: {err}"),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl GeolocationProvider for Gpsd {
+    fn name(&self) -> &str {
+        "gpsd"
+    }
+
+    async fn locate(&self) -> Result<Option<Geolocation>, GeolocationError> {
+        tokio::time::timeout(self.timeout, self.read_fix())
+            .await
+            .map_err(|_| GeolocationError::Timeout(self.timeout))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    const TPV: &str = r#"{"class":"TPV","device":"/dev/ttyACM0","mode":3,"lat":45.07,"lon":7.68,"alt":250.0,"altMSL":240.0,"epx":3.5,"epy":4.5,"epv":8.0,"track":90.0,"speed":1.5}"#;
+
+    #[test]
+    fn parse_fix_tpv() {
+        let fix = parse_fix(TPV).unwrap().unwrap();
+
+        assert_eq!(
+            fix,
+            Geolocation {
+                latitude: 45.07,
+                longitude: 7.68,
+                altitude: 240.0,
+                accuracy: 4.5,
+                altitude_accuracy: 8.0,
+                heading: 90.0,
+                speed: 1.5,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_fix_no_fix() {
+        let no_fix = parse_fix(r#"{"class":"TPV","device":"/dev/ttyACM0","mode":1}"#).unwrap();
+        let version = parse_fix(r#"{"class":"VERSION","release":"3.25"}"#).unwrap();
+
+        assert!(no_fix.is_none());
+        assert!(version.is_none());
+    }
+
+    #[tokio::test]
+    async fn locate_from_gpsd() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            stream
+                .write_all(b"{\"class\":\"VERSION\",\"release\":\"3.25\"}\n")
+                .await
+                .unwrap();
+
+            let mut buf = [0; WATCH_COMMAND.len()];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, WATCH_COMMAND);
+
+            stream
+                .write_all(format!("{TPV}\n").as_bytes())
+                .await
+                .unwrap();
+        });
+
+        let gpsd = Gpsd::new(&GpsdConfig {
+            address,
+            timeout: default_timeout(),
+        });
+
+        let fix = gpsd.locate().await.unwrap().unwrap();
+        assert_eq!(fix.latitude, 45.07);
+
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,443 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Position from an HTTP geolocation service, using the visible WiFi access points and cell
+//! towers.
+//!
+//! The request and response follow the format of the
+//! [Geolocation API](https://developers.google.com/maps/documentation/geolocation/requests-geolocation)
+//! that is also supported by other services.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{Geolocation, GeolocationError, GeolocationProvider};
+use crate::executor::Executor;
+
+/// Program used to scan the WiFi access points.
+const IW: &str = "iw";
+/// Maximum time for a scan of the access points.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HttpConfig {
+    /// URL of the service, including the API key if needed.
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WifiAccessPoint {
+    mac_address: String,
+    signal_strength: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CellTower {
+    cell_id: u32,
+    location_area_code: u32,
+    mobile_country_code: u16,
+    mobile_network_code: u16,
+}
+
+#[cfg(feature = "cellular")]
+impl From<crate::telemetry::cellular_connection::CellLocation> for CellTower {
+    fn from(cell: crate::telemetry::cellular_connection::CellLocation) -> Self {
+        Self {
+            cell_id: cell.cell_id,
+            location_area_code: cell.location_area_code,
+            mobile_country_code: cell.mobile_country_code,
+            mobile_network_code: cell.mobile_network_code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LocateRequest {
+    consider_ip: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    wifi_access_points: Vec<WifiAccessPoint>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cell_towers: Vec<CellTower>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Location {
+    lat: f64,
+    lng: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocateResponse {
+    location: Location,
+    accuracy: f64,
+}
+
+impl From<LocateResponse> for Geolocation {
+    fn from(value: LocateResponse) -> Self {
+        Self {
+            latitude: value.location.lat,
+            longitude: value.location.lng,
+            altitude: 0.0,
+            accuracy: value.accuracy,
+            altitude_accuracy: 0.0,
+            heading: 0.0,
+            speed: 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct HttpGeolocation {
+    url: String,
+    client: reqwest::Client,
+    /// Runs `iw` to scan the access points.
+    iw: Executor,
+}
+
+impl HttpGeolocation {
+    pub(crate) fn new(config: &HttpConfig, client: reqwest::Client) -> Self {
+        Self::with_executor(config, client, Executor::new(IW).timeout(SCAN_TIMEOUT))
+    }
+
+    fn with_executor(config: &HttpConfig, client: reqwest::Client, iw: Executor) -> Self {
+        Self {
+            url: config.url.clone(),
+            client,
+            iw,
+        }
+    }
+
+    async fn request(
+        &self,
+        access_points: Vec<WifiAccessPoint>,
+        cell_towers: Vec<CellTower>,
+    ) -> Result<Geolocation, GeolocationError> {
+        let request = LocateRequest {
+            consider_ip: false,
+            wifi_access_points: access_points,
+            cell_towers,
+        };
+
+        let body = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&request)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let response: LocateResponse = serde_json::from_slice(&body)?;
+
+        Ok(response.into())
+    }
+
+    /// Run `iw` with the given arguments, returning its output.
+    async fn run_iw(&self, args: &[&str]) -> Option<String> {
+        let output = self
+            .iw
+            .clone()
+            .args(args.iter().copied())
+            .output()
+            .await
+            .map_err(|err| warn!("couldn't scan the WiFi access points: {err}"))
+            .ok()?;
+
+        if !output.success() {
+            warn!(
+                "couldn't scan the WiFi access points, iw exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Scan the visible access points from the first wireless interface.
+    async fn scan_access_points(&self) -> Vec<WifiAccessPoint> {
+        let Some(interface) = self
+            .run_iw(&["dev"])
+            .await
+            .and_then(|dev| parse_interface(&dev))
+        else {
+            debug!("no wireless interface");
+
+            return Vec::new();
+        };
+
+        self.run_iw(&["dev", &interface, "scan"])
+            .await
+            .map(|scan| parse_scan(&scan))
+            .unwrap_or_default()
+    }
+}
+
+/// First interface listed by `iw dev`.
+fn parse_interface(dev: &str) -> Option<String> {
+    dev.lines()
+        .find_map(|line| line.trim().strip_prefix("Interface "))
+        .map(|interface| interface.trim().to_string())
+}
+
+/// Access points in the output of `iw dev <interface> scan`.
+///
+/// Each access point starts with a `BSS <mac>(on <interface>)` line, the ones without a signal
+/// strength are skipped.
+fn parse_scan(scan: &str) -> Vec<WifiAccessPoint> {
+    let mut access_points = Vec::new();
+    let mut current: Option<(String, Option<i32>, Option<i32>)> = None;
+
+    for line in scan.lines() {
+        if let Some(bss) = line.strip_prefix("BSS ") {
+            access_points.extend(current.take().and_then(access_point));
+
+            let mac = bss
+                .split(|c: char| c == '(' || c.is_whitespace())
+                .next()
+                .unwrap_or_default();
+            current = Some((mac.to_string(), None, None));
+
+            continue;
+        }
+
+        let Some((_, signal, channel)) = current.as_mut() else {
+            continue;
+        };
+
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("signal: ") {
+            // The signal is like `-60.00 dBm`
+            *signal = value
+                .split_whitespace()
+                .next()
+                .and_then(|dbm| dbm.parse::<f64>().ok())
+                .map(|dbm| dbm.round() as i32);
+        } else if let Some(value) = line
+            .strip_prefix("DS Parameter set: channel ")
+            .or_else(|| line.strip_prefix("* primary channel: "))
+        {
+            *channel = value.trim().parse().ok();
+        }
+    }
+
+    access_points.extend(current.and_then(access_point));
+
+    access_points
+}
+
+fn access_point(
+    (mac_address, signal, channel): (String, Option<i32>, Option<i32>),
+) -> Option<WifiAccessPoint> {
+    Some(WifiAccessPoint {
+        mac_address,
+        signal_strength: signal?,
+        channel,
+    })
+}
+
+/// Serving cells of the cellular modems.
+#[cfg(feature = "cellular")]
+async fn cell_towers() -> Vec<CellTower> {
+    match crate::telemetry::cellular_connection::get_cell_locations().await {
+        Ok(cells) => cells.into_iter().map(CellTower::from).collect(),
+        Err(err) => {
+            warn!("couldn't read the cell towers: {err}");
+
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(not(feature = "cellular"))]
+async fn cell_towers() -> Vec<CellTower> {
+    Vec::new()
+}
+
+#[async_trait]
+impl GeolocationProvider for HttpGeolocation {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn locate(&self) -> Result<Option<Geolocation>, GeolocationError> {
+        let access_points = self.scan_access_points().await;
+        let cell_towers = cell_towers().await;
+        if access_points.is_empty() && cell_towers.is_empty() {
+            debug!("no visible WiFi access points or cell towers");
+
+            return Ok(None);
+        }
+
+        self.request(access_points, cell_towers).await.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use httpmock::prelude::*;
+
+    const IW_DEV: &str = "phy#0
+\tInterface wlan0
+\t\tifindex 3
+\t\ttype managed
+";
+
+    const IW_SCAN: &str = "BSS ab:cd:ef:01:23:45(on wlan0) -- associated
+\tlast seen: 120 ms ago
+\tfreq: 2437
+\tsignal: -60.00 dBm
+\tSSID: Edgehog
+\tDS Parameter set: channel 6
+BSS 01:23:45:67:89:ab(on wlan0)
+\tfreq: 5180
+\tsignal: -71.50 dBm
+\tSSID: Edgehog 5G
+\tHT operation:
+\t\t * primary channel: 36
+BSS 01:23:45:67:89:cd(on wlan0)
+\tSSID: no signal
+";
+
+    #[test]
+    fn interface_from_iw_dev() {
+        assert_eq!(parse_interface(IW_DEV).as_deref(), Some("wlan0"));
+        assert_eq!(parse_interface("phy#0\n"), None);
+    }
+
+    #[test]
+    fn access_points_from_iw_scan() {
+        assert_eq!(
+            parse_scan(IW_SCAN),
+            [
+                WifiAccessPoint {
+                    mac_address: "ab:cd:ef:01:23:45".to_string(),
+                    signal_strength: -60,
+                    channel: Some(6),
+                },
+                WifiAccessPoint {
+                    mac_address: "01:23:45:67:89:ab".to_string(),
+                    signal_strength: -72,
+                    channel: Some(36),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_with_executor() {
+        // Fake iw printing the interfaces or the scan depending on the arguments
+        let script = format!(
+            r#"if [ "$1" = dev ] && [ "$#" -eq 1 ]; then printf '{IW_DEV}'; elif [ "$2" = wlan0 ] && [ "$3" = scan ]; then printf '{IW_SCAN}'; else exit 1; fi"#
+        );
+        let iw = Executor::new("sh").args(["-c", script.as_str(), "iw"]);
+
+        let provider = HttpGeolocation::with_executor(
+            &HttpConfig {
+                url: "http://localhost".to_string(),
+            },
+            reqwest::Client::new(),
+            iw,
+        );
+
+        let access_points = provider.scan_access_points().await;
+
+        assert_eq!(access_points, parse_scan(IW_SCAN));
+        assert_eq!(access_points.len(), 2);
+
+        let failing = HttpGeolocation::with_executor(
+            &HttpConfig {
+                url: "http://localhost".to_string(),
+            },
+            reqwest::Client::new(),
+            Executor::new("false"),
+        );
+
+        assert!(failing.scan_access_points().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn request_position() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/v1/geolocate")
+                    .json_body(serde_json::json!({
+                        "considerIp": false,
+                        "wifiAccessPoints": [
+                            {"macAddress": "ab:cd:ef:01:23:45", "signalStrength": -60, "channel": 6}
+                        ],
+                        "cellTowers": [
+                            {
+                                "cellId": 42,
+                                "locationAreaCode": 415,
+                                "mobileCountryCode": 222,
+                                "mobileNetworkCode": 1
+                            }
+                        ]
+                    }));
+                then.status(200)
+                    .body(r#"{"location": {"lat": 45.07, "lng": 7.68}, "accuracy": 30.0}"#);
+            })
+            .await;
+
+        let provider = HttpGeolocation::new(
+            &HttpConfig {
+                url: server.url("/v1/geolocate"),
+            },
+            reqwest::Client::new(),
+        );
+
+        let position = provider
+            .request(
+                vec![WifiAccessPoint {
+                    mac_address: "ab:cd:ef:01:23:45".to_string(),
+                    signal_strength: -60,
+                    channel: Some(6),
+                }],
+                vec![CellTower {
+                    cell_id: 42,
+                    location_area_code: 415,
+                    mobile_country_code: 222,
+                    mobile_network_code: 1,
+                }],
+            )
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(position.latitude, 45.07);
+        assert_eq!(position.longitude, 7.68);
+        assert_eq!(position.accuracy, 30.0);
+    }
+}
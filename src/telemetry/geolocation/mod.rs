@@ -0,0 +1,233 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Position of the device from multiple geolocation providers.
+//!
+//! Each configured [`GeolocationProvider`] is queried periodically, and its position is published
+//! on the `io.edgehog.devicemanager.Geolocation` interface with the provider name as path.
+
+use std::time::Duration;
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use async_trait::async_trait;
+use log::{debug, error};
+use serde::Deserialize;
+
+use crate::data::Publisher;
+
+pub(crate) mod gpsd;
+pub(crate) mod http;
+
+const GEOLOCATION_INTERFACE: &str = "io.edgehog.devicemanager.Geolocation";
+
+const fn default_period() -> u64 {
+    300
+}
+
+/// Configuration of the geolocation providers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GeolocationConfig {
+    /// Seconds between two positions.
+    #[serde(default = "default_period")]
+    pub period: u64,
+    pub gpsd: Option<gpsd::GpsdConfig>,
+    pub http: Option<http::HttpConfig>,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum GeolocationError {
+    /// couldn't communicate with the provider
+    Io(#[from] std::io::Error),
+    /// invalid response from the provider
+    Json(#[from] serde_json::Error),
+    /// couldn't request the position
+    Http(#[from] reqwest::Error),
+    /// no position received in {0:?}
+    Timeout(Duration),
+}
+
+/// Position of the device.
+#[derive(Debug, Clone, PartialEq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct Geolocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Altitude in meters, 0 if not available.
+    pub altitude: f64,
+    /// Horizontal accuracy in meters, 0 if not available.
+    pub accuracy: f64,
+    /// Vertical accuracy in meters, 0 if not available.
+    pub altitude_accuracy: f64,
+    /// Heading in degrees from the true north, 0 if not available.
+    pub heading: f64,
+    /// Speed in meters per second, 0 if not available.
+    pub speed: f64,
+}
+
+/// Source of the position of the device.
+#[async_trait]
+pub trait GeolocationProvider: Send + Sync {
+    /// Name of the provider, used as path of the published position.
+    fn name(&self) -> &str;
+
+    /// Current position, if the provider has one.
+    async fn locate(&self) -> Result<Option<Geolocation>, GeolocationError>;
+}
+
+/// Periodically publishes the position of every provider.
+pub(crate) struct Geolocator<P> {
+    period: Duration,
+    providers: Vec<Box<dyn GeolocationProvider>>,
+    publisher: P,
+}
+
+impl<P> Geolocator<P>
+where
+    P: Publisher + Send + Sync,
+{
+    pub(crate) fn new(config: &GeolocationConfig, client: reqwest::Client, publisher: P) -> Self {
+        let mut providers: Vec<Box<dyn GeolocationProvider>> = Vec::new();
+
+        if let Some(gpsd) = &config.gpsd {
+            providers.push(Box::new(gpsd::Gpsd::new(gpsd)));
+        }
+
+        if let Some(http) = &config.http {
+            providers.push(Box::new(http::HttpGeolocation::new(http, client)));
+        }
+
+        Self {
+            period: Duration::from_secs(config.period),
+            providers,
+            publisher,
+        }
+    }
+
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(self.period);
+
+        loop {
+            interval.tick().await;
+
+            for provider in &self.providers {
+                self.send_position(provider.as_ref()).await;
+            }
+        }
+    }
+
+    async fn send_position(&self, provider: &dyn GeolocationProvider) {
+        let name = provider.name();
+
+        let position = match provider.locate().await {
+            Ok(Some(position)) => position,
+            Ok(None) => {
+                debug!("no position from {name}");
+
+                return;
+            }
+            Err(err) => {
+                error!("couldn't get the position from {name}: {err}");
+
+                return;
+            }
+        };
+
+        if let Err(err) = self
+            .publisher
+            .send_object(GEOLOCATION_INTERFACE, &format!("/{name}"), position)
+            .await
+        {
+            error!("couldn't send the position from {name}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    struct FixedProvider(Option<Geolocation>);
+
+    #[async_trait]
+    impl GeolocationProvider for FixedProvider {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn locate(&self) -> Result<Option<Geolocation>, GeolocationError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn position() -> Geolocation {
+        Geolocation {
+            latitude: 45.07,
+            longitude: 7.68,
+            altitude: 240.0,
+            accuracy: 5.0,
+            altitude_accuracy: 10.0,
+            heading: 0.0,
+            speed: 0.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn send_provider_position() {
+        let mut publisher = MockPublisher::new();
+
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, path: &str, data: &Geolocation| {
+                iface == GEOLOCATION_INTERFACE && path == "/fixed" && *data == position()
+            })
+            .once()
+            .returning(|_: &str, _: &str, _: Geolocation| Ok(()));
+
+        let geolocator = Geolocator {
+            period: Duration::from_secs(1),
+            providers: Vec::new(),
+            publisher,
+        };
+
+        geolocator
+            .send_position(&FixedProvider(Some(position())))
+            .await;
+        // no position, nothing is sent
+        geolocator.send_position(&FixedProvider(None)).await;
+    }
+
+    #[test]
+    fn config_providers() {
+        let config: GeolocationConfig = toml::from_str(
+            r#"
+            [gpsd]
+            [http]
+            url = "https://location.example.com/v1/geolocate"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.period, default_period());
+
+        let geolocator = Geolocator::new(&config, reqwest::Client::new(), MockPublisher::new());
+        let names: Vec<&str> = geolocator.providers.iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["gpsd", "http"]);
+    }
+}
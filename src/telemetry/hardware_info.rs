@@ -20,8 +20,89 @@
 
 use crate::error::DeviceManagerError;
 use astarte_device_sdk::types::AstarteType;
+use log::{debug, error};
 use procfs::{CpuInfo, Meminfo, ProcResult};
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::path::Path;
+
+/// File with the values overriding the detected ones, for boards lacking standard identifiers.
+#[cfg(not(test))]
+const HARDWARE_OVERRIDE_PATH: &str = "/etc/edgehog/hardware.toml";
+#[cfg(not(test))]
+const DMI_PATH: &str = "/sys/class/dmi/id";
+#[cfg(not(test))]
+const DEVICE_TREE_PATH: &str = "/proc/device-tree";
+
+/// Model and serial number of the board.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct BoardInfo {
+    model: Option<String>,
+    serial: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct CpuOverride {
+    architecture: Option<String>,
+    model: Option<String>,
+    model_name: Option<String>,
+    vendor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct BoardOverride {
+    model: Option<String>,
+    serial: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct MemOverride {
+    total_bytes: Option<i64>,
+}
+
+/// Content of the hardware override file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct HardwareOverride {
+    #[serde(default)]
+    cpu: CpuOverride,
+    #[serde(default)]
+    board: BoardOverride,
+    #[serde(default)]
+    mem: MemOverride,
+}
+
+impl HardwareOverride {
+    fn apply(self, ret: &mut HashMap<String, AstarteType>) {
+        let values = [
+            ("/cpu/architecture", self.cpu.architecture),
+            ("/cpu/model", self.cpu.model),
+            ("/cpu/modelName", self.cpu.model_name),
+            ("/cpu/vendor", self.cpu.vendor),
+        ];
+
+        for (path, value) in values {
+            if let Some(value) = value {
+                ret.insert(path.to_owned(), value.into());
+            }
+        }
+
+        if let Some(total_bytes) = self.mem.total_bytes {
+            ret.insert("/mem/totalBytes".to_owned(), total_bytes.into());
+        }
+    }
+}
+
+impl BoardOverride {
+    fn apply(self, board: &mut BoardInfo) {
+        if let Some(model) = self.model {
+            board.model = Some(model);
+        }
+
+        if let Some(serial) = self.serial {
+            board.serial = Some(serial);
+        }
+    }
+}
 
 /// get structured data for `io.edgehog.devicemanager.HardwareInfo` interface
 pub fn get_hardware_info() -> Result<HashMap<String, AstarteType>, DeviceManagerError> {
@@ -49,9 +130,79 @@ pub fn get_hardware_info() -> Result<HashMap<String, AstarteType>, DeviceManager
         (meminfo.mem_total as i64).into(),
     );
 
+    if let Some(hardware_override) = get_hardware_override() {
+        hardware_override.apply(&mut ret);
+    }
+
     Ok(ret)
 }
 
+/// get structured data for `io.edgehog.devicemanager.BoardInfo` interface
+pub fn get_board_info() -> HashMap<String, AstarteType> {
+    let mut board = get_system_board();
+
+    if let Some(hardware_override) = get_hardware_override() {
+        hardware_override.board.apply(&mut board);
+    }
+
+    let mut ret: HashMap<String, AstarteType> = HashMap::new();
+
+    if let Some(model) = board.model {
+        ret.insert("/model".to_owned(), model.into());
+    }
+
+    if let Some(serial) = board.serial {
+        ret.insert("/serial".to_owned(), serial.into());
+    }
+
+    ret
+}
+
+/// Read a sysfs or device tree attribute, the device tree strings are NUL terminated.
+fn read_attribute(path: &Path) -> Option<String> {
+    let value = std::fs::read_to_string(path)
+        .map_err(|err| debug!("couldn't read {}: {err}", path.display()))
+        .ok()?;
+
+    let value = value.trim_end_matches('\0').trim();
+
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Read the board information from DMI, falling back to the device tree.
+fn read_board_info(dmi: &Path, device_tree: &Path) -> BoardInfo {
+    let model = read_attribute(&dmi.join("product_name"))
+        .or_else(|| read_attribute(&device_tree.join("model")));
+    let serial = read_attribute(&dmi.join("product_serial"))
+        .or_else(|| read_attribute(&device_tree.join("serial-number")));
+
+    BoardInfo { model, serial }
+}
+
+fn read_hardware_override(path: &Path) -> Option<HardwareOverride> {
+    if !path.exists() {
+        return None;
+    }
+
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| error!("couldn't read {}: {err}", path.display()))
+        .ok()?;
+
+    toml::from_str(&content)
+        .map_err(|err| error!("invalid hardware override {}: {err}", path.display()))
+        .ok()
+}
+
+#[cfg(not(test))]
+fn get_system_board() -> BoardInfo {
+    read_board_info(Path::new(DMI_PATH), Path::new(DEVICE_TREE_PATH))
+}
+
+#[cfg(not(test))]
+fn get_hardware_override() -> Option<HardwareOverride> {
+    read_hardware_override(Path::new(HARDWARE_OVERRIDE_PATH))
+}
+
 #[cfg(not(test))]
 fn get_cpu_info() -> ProcResult<CpuInfo> {
     use procfs::Current;
@@ -102,6 +253,19 @@ fn get_machine_architecture() -> String {
     "test_architecture".to_owned()
 }
 
+#[cfg(test)]
+fn get_system_board() -> BoardInfo {
+    BoardInfo {
+        model: Some("SECO SBC-C61".to_owned()),
+        serial: Some("0123456789".to_owned()),
+    }
+}
+
+#[cfg(test)]
+fn get_hardware_override() -> Option<HardwareOverride> {
+    None
+}
+
 #[cfg(test)]
 fn get_meminfo() -> ProcResult<Meminfo> {
     use procfs::FromRead;
@@ -156,8 +320,8 @@ CmaFree:          194196 kB
 
 #[cfg(test)]
 mod tests {
-    use crate::telemetry::hardware_info::get_hardware_info;
-    use astarte_device_sdk::types::AstarteType;
+    use super::*;
+    use tempdir::TempDir;
 
     #[test]
     fn hardware_info_test() {
@@ -191,5 +355,88 @@ mod tests {
                 .to_owned(),
             AstarteType::LongInteger(1043820544)
         );
+        assert!(astarte_hardware_info.get("/board/model").is_none());
+    }
+
+    #[test]
+    fn board_info_test() {
+        let board_info = get_board_info();
+
+        assert_eq!(
+            board_info.get("/model").unwrap().to_owned(),
+            AstarteType::String("SECO SBC-C61".to_string())
+        );
+        assert_eq!(
+            board_info.get("/serial").unwrap().to_owned(),
+            AstarteType::String("0123456789".to_string())
+        );
+    }
+
+    #[test]
+    fn board_info_from_device_tree() {
+        let dmi = TempDir::new("edgehog-dmi").unwrap();
+        let device_tree = TempDir::new("edgehog-dt").unwrap();
+        std::fs::write(device_tree.path().join("model"), "SECO SBC-C61\0").unwrap();
+        std::fs::write(device_tree.path().join("serial-number"), "0123456789\0").unwrap();
+
+        let board = read_board_info(dmi.path(), device_tree.path());
+
+        assert_eq!(
+            board,
+            BoardInfo {
+                model: Some("SECO SBC-C61".to_string()),
+                serial: Some("0123456789".to_string()),
+            }
+        );
+
+        // DMI takes precedence over the device tree
+        std::fs::write(dmi.path().join("product_name"), "Standard PC\n").unwrap();
+
+        let board = read_board_info(dmi.path(), device_tree.path());
+
+        assert_eq!(board.model.as_deref(), Some("Standard PC"));
+        assert_eq!(board.serial.as_deref(), Some("0123456789"));
+    }
+
+    #[test]
+    fn hardware_override() {
+        let dir = TempDir::new("edgehog-hardware").unwrap();
+        let path = dir.path().join("hardware.toml");
+
+        assert!(read_hardware_override(&path).is_none());
+
+        std::fs::write(
+            &path,
+            r#"
+[cpu]
+model_name = "Custom SoC"
+[board]
+serial = "ABCD"
+[mem]
+total_bytes = 2048
+"#,
+        )
+        .unwrap();
+
+        let hardware_override = read_hardware_override(&path).unwrap();
+
+        let mut board = get_system_board();
+        hardware_override.board.clone().apply(&mut board);
+
+        assert_eq!(board.model.as_deref(), Some("SECO SBC-C61"));
+        assert_eq!(board.serial.as_deref(), Some("ABCD"));
+
+        let mut ret = get_hardware_info().unwrap();
+        hardware_override.apply(&mut ret);
+
+        assert_eq!(
+            ret["/cpu/modelName"],
+            AstarteType::String("Custom SoC".to_string())
+        );
+        assert_eq!(ret["/mem/totalBytes"], AstarteType::LongInteger(2048));
+        assert_eq!(
+            ret["/cpu/vendor"],
+            AstarteType::String("GenuineIntel".to_string())
+        );
     }
 }
@@ -0,0 +1,60 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use astarte_device_sdk::AstarteAggregate;
+use procfs::Current;
+
+use crate::error::DeviceManagerError;
+
+#[derive(Debug, AstarteAggregate)]
+pub struct LoadAverage {
+    pub load1: f64,
+    pub load5: f64,
+    pub load15: f64,
+}
+
+impl From<procfs::LoadAverage> for LoadAverage {
+    fn from(value: procfs::LoadAverage) -> Self {
+        Self {
+            load1: value.one.into(),
+            load5: value.five.into(),
+            load15: value.fifteen.into(),
+        }
+    }
+}
+
+/// get structured data for `io.edgehog.devicemanager.LoadAverage` interface
+pub fn get_load_average() -> Result<LoadAverage, DeviceManagerError> {
+    let load = procfs::LoadAverage::current()?;
+
+    Ok(load.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_load_average_test() {
+        let load = get_load_average().unwrap();
+
+        assert!(load.load1 >= 0.0);
+        assert!(load.load5 >= 0.0);
+        assert!(load.load15 >= 0.0);
+    }
+}
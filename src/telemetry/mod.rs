@@ -21,29 +21,44 @@
 use crate::error::DeviceManagerError;
 use crate::repository::file_state_repository::FileStateRepository;
 use crate::repository::StateRepository;
+use crate::state::RuntimeState;
 use astarte_device_sdk::types::AstarteType;
 use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 use tokio::sync::mpsc::Sender as MpscSender;
 use tokio::sync::RwLock;
 use tokio::task::spawn;
-use tokio::time::interval;
-use tokio::time::Duration;
+use tokio::time::{interval_at, Duration, Instant};
 
 pub(crate) mod base_image;
 pub(crate) mod battery_status;
+#[cfg(feature = "cellular")]
+pub(crate) mod cellular_connection;
+pub(crate) mod device_attributes;
+pub(crate) mod geolocation;
 pub(crate) mod hardware_info;
+pub(crate) mod load_average;
+#[cfg(feature = "cellular")]
+pub(crate) mod modem_manager;
 pub(crate) mod net_if_properties;
 pub(crate) mod os_info;
+pub(crate) mod overrides;
+pub(crate) mod pressure;
 pub(crate) mod runtime_info;
+pub(crate) mod scheduler_status;
+pub(crate) mod script;
 pub(crate) mod storage_usage;
 pub(crate) mod system_info;
 pub(crate) mod system_status;
+pub(crate) mod thermal;
 pub(crate) mod upower;
+#[cfg(feature = "wifi")]
 pub(crate) mod wifi_scan;
+#[cfg(feature = "wifi")]
+pub(crate) mod wpa_supplicant;
 
 const TELEMETRY_PATH: &str = "telemetry.json";
 
@@ -52,6 +67,11 @@ pub struct TelemetryInterfaceConfig {
     pub interface_name: String,
     pub enabled: Option<bool>,
     pub period: Option<u64>,
+    /// Maximum random delay in seconds of the first sample, to spread the tasks with the same
+    /// period. It's capped to the period.
+    pub jitter: Option<u64>,
+    /// Script printing the data of a custom interface, run through the executor.
+    pub script: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -60,6 +80,8 @@ struct TelemetryTaskConfig {
     default_period: Option<u64>,
     override_enabled: Option<bool>,
     override_period: Option<u64>,
+    jitter: Option<u64>,
+    script: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -68,12 +90,26 @@ pub struct Telemetry {
     kill_switches: HashMap<String, Sender<()>>,
     communication_channel: MpscSender<TelemetryMessage>,
     store_directory: PathBuf,
+    state: RuntimeState,
 }
 
 pub enum TelemetryPayload {
     SystemStatus(crate::telemetry::system_status::SystemStatus),
     StorageUsage(crate::telemetry::storage_usage::DiskUsage),
     BatteryStatus(crate::telemetry::battery_status::BatteryStatus),
+    LoadAverage(crate::telemetry::load_average::LoadAverage),
+    SchedulerStatus(crate::telemetry::scheduler_status::SchedulerStatus),
+    ThermalZone(crate::telemetry::thermal::ThermalZone),
+    PressureStall(crate::telemetry::pressure::PressureStall),
+    #[cfg(feature = "cellular")]
+    CellularConnectionStatus(crate::telemetry::cellular_connection::CellularConnectionStatus),
+    #[cfg(feature = "wifi")]
+    WiFiScanResult(crate::telemetry::wifi_scan::WifiScanResult),
+    /// Value printed by a custom telemetry script.
+    Custom {
+        interface_name: String,
+        data: AstarteType,
+    },
 }
 
 pub struct TelemetryMessage {
@@ -86,20 +122,10 @@ impl Telemetry {
         cfg: Option<Vec<TelemetryInterfaceConfig>>,
         communication_channel: MpscSender<TelemetryMessage>,
         store_directory: PathBuf,
+        state: RuntimeState,
     ) -> Self {
-        let cfg = match cfg {
-            None => {
-                return Telemetry {
-                    telemetry_task_configs: Arc::new(Default::default()),
-                    kill_switches: Default::default(),
-                    communication_channel,
-                    store_directory,
-                }
-            }
-            Some(conf) => conf,
-        };
         let mut telemetry_task_configs = HashMap::new();
-        for c in cfg {
+        for c in cfg.unwrap_or_default() {
             telemetry_task_configs.insert(
                 c.interface_name.clone(),
                 TelemetryTaskConfig {
@@ -107,30 +133,21 @@ impl Telemetry {
                     default_period: c.period,
                     override_enabled: None,
                     override_period: None,
+                    jitter: c.jitter,
+                    script: c.script,
                 },
             );
         }
 
+        // Overrides received from Astarte are applied also without a default configuration
         let telemetry_repo: FileStateRepository<Vec<TelemetryInterfaceConfig>> =
             FileStateRepository::new(&store_directory, TELEMETRY_PATH);
         if telemetry_repo.exists().await {
-            let saved_config: Vec<TelemetryInterfaceConfig> = telemetry_repo.read().await.unwrap();
-            for c in saved_config {
-                if let Some(rwlock_default_task) = telemetry_task_configs.get_mut(&c.interface_name)
-                {
-                    rwlock_default_task.override_enabled = c.enabled;
-                    rwlock_default_task.override_period = c.period;
-                } else {
-                    telemetry_task_configs.insert(
-                        c.interface_name.clone(),
-                        TelemetryTaskConfig {
-                            default_enabled: None,
-                            default_period: None,
-                            override_enabled: c.enabled,
-                            override_period: c.period,
-                        },
-                    );
-                };
+            match telemetry_repo.read().await {
+                Ok(saved_config) => {
+                    Self::apply_overrides(&mut telemetry_task_configs, saved_config)
+                }
+                Err(err) => error!("couldn't read the saved telemetry config: {err}"),
             }
         }
 
@@ -139,6 +156,21 @@ impl Telemetry {
             kill_switches: HashMap::new(),
             communication_channel,
             store_directory,
+            state,
+        }
+    }
+
+    fn apply_overrides(
+        telemetry_task_configs: &mut HashMap<String, TelemetryTaskConfig>,
+        saved_config: Vec<TelemetryInterfaceConfig>,
+    ) {
+        for c in saved_config {
+            let task_config = telemetry_task_configs
+                .entry(c.interface_name)
+                .or_insert_with(Default::default);
+
+            task_config.override_enabled = c.enabled;
+            task_config.override_period = c.period;
         }
     }
 
@@ -168,41 +200,62 @@ impl Telemetry {
         let comm = self.communication_channel.clone();
 
         if period > 0 && enabled {
+            let offset = jitter_offset(telemetry_task_config.jitter, period);
+            debug!("start {interface_name} with offset {offset:?}");
+            self.state
+                .set_telemetry_offset(&interface_name, offset.as_millis() as u64);
+
             let (tx, rx) = channel(1);
             spawn(Telemetry::start_task(
                 rx,
                 interface_name.clone(),
+                telemetry_task_config.script.clone(),
                 period,
+                offset,
                 comm,
             ));
 
             self.kill_switches.insert(interface_name, tx);
+        } else {
+            self.state.remove_telemetry_offset(&interface_name);
         }
     }
 
     async fn start_task(
         mut kill_switch: Receiver<()>,
         interface_name: String,
+        script: Option<PathBuf>,
         period: u64,
+        offset: Duration,
         communication_channel: MpscSender<TelemetryMessage>,
     ) {
         tokio::select! {
-            _output = Telemetry::data_send_loop(interface_name, period, communication_channel) => {debug!("data_send_loop ended")},
+            _output = Telemetry::data_send_loop(interface_name, script, period, offset, communication_channel) => {debug!("data_send_loop ended")},
             _ = kill_switch.recv() => {debug!("Kill switch triggered")},
         }
     }
 
     async fn data_send_loop(
         interface_name: String,
+        script: Option<PathBuf>,
         period: u64,
+        offset: Duration,
         communication_channel: MpscSender<TelemetryMessage>,
     ) {
-        let mut interval = interval(Duration::from_secs(period));
+        let mut interval = interval_at(Instant::now() + offset, Duration::from_secs(period));
+        let mut battery_monitor = battery_status::BatteryMonitor::default();
         loop {
             interval.tick().await;
 
             // TODO: the error should be bubbled up
-            if let Err(err) = send_data(&communication_channel, &interface_name).await {
+            if let Err(err) = send_data(
+                &communication_channel,
+                &interface_name,
+                script.as_deref(),
+                &mut battery_monitor,
+            )
+            .await
+            {
                 error!("coulnd't send telemetry data: {:#?}", err)
             }
         }
@@ -304,6 +357,8 @@ impl Telemetry {
                 interface_name: interface_name.to_string(),
                 enabled: telemetry_task_config.override_enabled,
                 period: telemetry_task_config.override_period,
+                jitter: None,
+                script: None,
             };
 
             telemetry_config.push(interface_config);
@@ -316,12 +371,42 @@ impl Telemetry {
     }
 }
 
+/// Random delay of the first sample, between zero and the jitter capped to the period.
+fn jitter_offset(jitter: Option<u64>, period: u64) -> Duration {
+    let max = jitter.unwrap_or(0).min(period).saturating_mul(1000);
+
+    if max == 0 {
+        return Duration::ZERO;
+    }
+
+    Duration::from_millis(fastrand::u64(0..=max))
+}
+
 async fn send_data(
     communication_channel: &MpscSender<TelemetryMessage>,
     interface_name: &str,
+    script: Option<&Path>,
+    battery_monitor: &mut battery_status::BatteryMonitor,
 ) -> Result<(), DeviceManagerError> {
     debug!("sending {interface_name}");
 
+    if let Some(script) = script {
+        let values = script::get_script_data(script).await?;
+        for (path, data) in values {
+            let _ = communication_channel
+                .send(TelemetryMessage {
+                    path,
+                    payload: TelemetryPayload::Custom {
+                        interface_name: interface_name.to_string(),
+                        data,
+                    },
+                })
+                .await;
+        }
+
+        return Ok(());
+    }
+
     match interface_name {
         "io.edgehog.devicemanager.SystemStatus" => {
             let sysstatus = system_status::get_system_status()?;
@@ -344,7 +429,7 @@ async fn send_data(
             }
         }
         "io.edgehog.devicemanager.BatteryStatus" => {
-            let battery_status = battery_status::get_battery_status().await?;
+            let battery_status = battery_monitor.battery_status().await?;
             for (path, payload) in battery_status {
                 let _ = communication_channel
                     .send(TelemetryMessage {
@@ -354,6 +439,70 @@ async fn send_data(
                     .await;
             }
         }
+        "io.edgehog.devicemanager.LoadAverage" => {
+            let load_average = load_average::get_load_average()?;
+            let _ = communication_channel
+                .send(TelemetryMessage {
+                    path: "".to_string(),
+                    payload: TelemetryPayload::LoadAverage(load_average),
+                })
+                .await;
+        }
+        "io.edgehog.devicemanager.SchedulerStatus" => {
+            let scheduler_status = scheduler_status::get_scheduler_status()?;
+            let _ = communication_channel
+                .send(TelemetryMessage {
+                    path: "".to_string(),
+                    payload: TelemetryPayload::SchedulerStatus(scheduler_status),
+                })
+                .await;
+        }
+        "io.edgehog.devicemanager.ThermalZones" => {
+            let thermal_zones = thermal::get_thermal_zones()?;
+            for (path, payload) in thermal_zones {
+                let _ = communication_channel
+                    .send(TelemetryMessage {
+                        path,
+                        payload: TelemetryPayload::ThermalZone(payload),
+                    })
+                    .await;
+            }
+        }
+        "io.edgehog.devicemanager.PressureStall" => {
+            let pressure_stall = pressure::get_pressure_stall()?;
+            for (path, payload) in pressure_stall {
+                let _ = communication_channel
+                    .send(TelemetryMessage {
+                        path,
+                        payload: TelemetryPayload::PressureStall(payload),
+                    })
+                    .await;
+            }
+        }
+        #[cfg(feature = "cellular")]
+        "io.edgehog.devicemanager.CellularConnectionStatus" => {
+            let cellular_status = cellular_connection::get_cellular_status().await?;
+            for (path, payload) in cellular_status {
+                let _ = communication_channel
+                    .send(TelemetryMessage {
+                        path,
+                        payload: TelemetryPayload::CellularConnectionStatus(payload),
+                    })
+                    .await;
+            }
+        }
+        #[cfg(feature = "wifi")]
+        "io.edgehog.devicemanager.WiFiScanResults" => {
+            let wifi_scan_results = wifi_scan::get_wifi_scan_results().await?;
+            for payload in wifi_scan_results {
+                let _ = communication_channel
+                    .send(TelemetryMessage {
+                        path: "".to_string(),
+                        payload: TelemetryPayload::WiFiScanResult(payload),
+                    })
+                    .await;
+            }
+        }
         interface => {
             warn!("unimplemented telemetry interface {}", interface)
         }
@@ -368,10 +517,13 @@ mod tests {
 
     use crate::repository::file_state_repository::FileStateRepository;
     use crate::repository::StateRepository;
-    use crate::telemetry::{send_data, Telemetry, TelemetryInterfaceConfig};
+    use crate::state::RuntimeState;
+    use crate::telemetry::battery_status::BatteryMonitor;
+    use crate::telemetry::{jitter_offset, send_data, Telemetry, TelemetryInterfaceConfig};
 
     use astarte_device_sdk::types::AstarteType;
     use tempdir::TempDir;
+    use tokio::time::Duration;
 
     const TELEMETRY_PATH: &str = "telemetry.json";
 
@@ -391,12 +543,15 @@ mod tests {
             interface_name: interface_name.to_string(),
             enabled: Some(true),
             period: Some(10),
+            jitter: None,
+            script: None,
         });
 
         let (_dir, t_dir) = temp_dir();
 
         let (tx, _) = tokio::sync::mpsc::channel(32);
-        let tel = Telemetry::from_default_config(Some(config), tx, t_dir).await;
+        let tel =
+            Telemetry::from_default_config(Some(config), tx, t_dir, RuntimeState::default()).await;
         let telemetry_config = tel.telemetry_task_configs.clone();
         let interface_configs = telemetry_config.read().await;
         let system_status_config = interface_configs.get(interface_name).unwrap();
@@ -413,12 +568,20 @@ mod tests {
             interface_name: interface_name.to_string(),
             enabled: Some(true),
             period: Some(10),
+            jitter: None,
+            script: None,
         });
 
         let (_dir, t_dir) = temp_dir();
 
         let (tx, _) = tokio::sync::mpsc::channel(32);
-        let mut tel = Telemetry::from_default_config(Some(config), tx, t_dir.clone()).await;
+        let mut tel = Telemetry::from_default_config(
+            Some(config),
+            tx,
+            t_dir.clone(),
+            RuntimeState::default(),
+        )
+        .await;
 
         tel.telemetry_config_event(interface_name, "enable", &AstarteType::Boolean(false))
             .await;
@@ -460,12 +623,20 @@ mod tests {
             interface_name: interface_name.to_string(),
             enabled: Some(true),
             period: Some(10),
+            jitter: None,
+            script: None,
         });
 
         let (_dir, t_dir) = temp_dir();
 
         let (tx, _) = tokio::sync::mpsc::channel(32);
-        let mut tel = Telemetry::from_default_config(Some(config), tx, t_dir.clone()).await;
+        let mut tel = Telemetry::from_default_config(
+            Some(config),
+            tx,
+            t_dir.clone(),
+            RuntimeState::default(),
+        )
+        .await;
 
         tel.telemetry_config_event(interface_name, "enable", &AstarteType::Unset)
             .await;
@@ -504,12 +675,15 @@ mod tests {
             interface_name: interface_name.to_string(),
             enabled: Some(true),
             period: Some(10),
+            jitter: None,
+            script: None,
         });
 
         let (_dir, t_dir) = temp_dir();
 
         let (tx, mut rx) = tokio::sync::mpsc::channel(32);
-        let mut tel = Telemetry::from_default_config(Some(config), tx, t_dir).await;
+        let mut tel =
+            Telemetry::from_default_config(Some(config), tx, t_dir, RuntimeState::default()).await;
         tel.telemetry_config_event(interface_name, "enable", &AstarteType::Boolean(true))
             .await;
         tel.telemetry_config_event(
@@ -527,10 +701,128 @@ mod tests {
         let (_dir, t_dir) = temp_dir();
 
         let (tx, _) = tokio::sync::mpsc::channel(32);
-        let tel = Telemetry::from_default_config(None, tx, t_dir).await;
+        let tel = Telemetry::from_default_config(None, tx, t_dir, RuntimeState::default()).await;
         assert!(tel.telemetry_task_configs.clone().read().await.is_empty());
     }
 
+    #[tokio::test]
+    async fn telemetry_reload_overrides_test() {
+        let interface_name = "io.edgehog.devicemanager.SystemStatus";
+        let config = vec![TelemetryInterfaceConfig {
+            interface_name: interface_name.to_string(),
+            enabled: Some(true),
+            period: Some(60),
+            jitter: None,
+            script: None,
+        }];
+
+        let (_dir, t_dir) = temp_dir();
+
+        let (tx, _) = tokio::sync::mpsc::channel(32);
+        let mut tel = Telemetry::from_default_config(
+            Some(config.clone()),
+            tx.clone(),
+            t_dir.clone(),
+            RuntimeState::default(),
+        )
+        .await;
+        tel.telemetry_config_event(interface_name, "enable", &AstarteType::Boolean(false))
+            .await;
+        tel.telemetry_config_event(
+            interface_name,
+            "periodSeconds",
+            &AstarteType::LongInteger(10),
+        )
+        .await;
+
+        let tel =
+            Telemetry::from_default_config(Some(config), tx, t_dir, RuntimeState::default()).await;
+        let telemetry_config = tel.telemetry_task_configs.clone();
+        let config = telemetry_config.read().await;
+        let system_status_config = config.get(interface_name).unwrap();
+
+        assert_eq!(system_status_config.default_enabled, Some(true));
+        assert_eq!(system_status_config.default_period, Some(60));
+        assert_eq!(system_status_config.override_enabled, Some(false));
+        assert_eq!(system_status_config.override_period, Some(10));
+    }
+
+    #[tokio::test]
+    async fn from_default_config_null_reload_overrides_test() {
+        let interface_name = "io.edgehog.devicemanager.SystemStatus";
+        let (_dir, t_dir) = temp_dir();
+
+        let (tx, _) = tokio::sync::mpsc::channel(32);
+        let mut tel = Telemetry::from_default_config(
+            None,
+            tx.clone(),
+            t_dir.clone(),
+            RuntimeState::default(),
+        )
+        .await;
+        tel.telemetry_config_event(interface_name, "periodSeconds", &AstarteType::Integer(30))
+            .await;
+
+        let tel = Telemetry::from_default_config(None, tx, t_dir, RuntimeState::default()).await;
+        let telemetry_config = tel.telemetry_task_configs.clone();
+        let config = telemetry_config.read().await;
+        let system_status_config = config.get(interface_name).unwrap();
+
+        assert!(system_status_config.default_period.is_none());
+        assert!(system_status_config.override_enabled.is_none());
+        assert_eq!(system_status_config.override_period, Some(30));
+    }
+
+    #[tokio::test]
+    async fn from_default_config_invalid_saved_config_test() {
+        let (_dir, t_dir) = temp_dir();
+
+        tokio::fs::write(t_dir.join(TELEMETRY_PATH), "not json")
+            .await
+            .unwrap();
+
+        let (tx, _) = tokio::sync::mpsc::channel(32);
+        let tel = Telemetry::from_default_config(None, tx, t_dir, RuntimeState::default()).await;
+        assert!(tel.telemetry_task_configs.clone().read().await.is_empty());
+    }
+
+    #[test]
+    fn jitter_offset_capped() {
+        assert_eq!(jitter_offset(None, 60), Duration::ZERO);
+
+        for _ in 0..100 {
+            assert!(jitter_offset(Some(5), 60) <= Duration::from_secs(5));
+            assert!(jitter_offset(Some(120), 60) <= Duration::from_secs(60));
+        }
+    }
+
+    #[tokio::test]
+    async fn telemetry_offsets_in_state() {
+        let interface_name = "io.edgehog.devicemanager.SystemStatus";
+        let config = vec![TelemetryInterfaceConfig {
+            interface_name: interface_name.to_string(),
+            enabled: Some(true),
+            period: Some(10),
+            jitter: Some(5),
+            script: None,
+        }];
+
+        let (_dir, t_dir) = temp_dir();
+        let state = RuntimeState::default();
+
+        let (tx, _) = tokio::sync::mpsc::channel(32);
+        let mut tel = Telemetry::from_default_config(Some(config), tx, t_dir, state.clone()).await;
+        tel.run_telemetry().await;
+
+        let offset = state.subscribe().borrow().telemetry_offsets[interface_name];
+        assert!(offset <= 5000);
+
+        tel.telemetry_config_event(interface_name, "enable", &AstarteType::Boolean(false))
+            .await;
+
+        assert!(state.subscribe().borrow().telemetry_offsets.is_empty());
+    }
+
     #[tokio::test]
     async fn send_data_test() {
         let (tx, mut rx) = tokio::sync::mpsc::channel(32);
@@ -540,8 +832,10 @@ mod tests {
             "io.edgehog.devicemanager.BatteryStatus",
         ];
 
+        let mut battery_monitor = BatteryMonitor::default();
+
         for interface in interfaces {
-            let res = send_data(&tx, interface).await;
+            let res = send_data(&tx, interface, None, &mut battery_monitor).await;
 
             assert!(
                 res.is_ok(),
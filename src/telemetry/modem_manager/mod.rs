@@ -0,0 +1,151 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Proxies for the ModemManager D-Bus API.
+//!
+//! See the [ModemManager API reference](https://www.freedesktop.org/software/ModemManager/api/latest/).
+
+use std::collections::HashMap;
+
+use zbus::dbus_proxy;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+pub(crate) const MODEM_MANAGER_SERVICE: &str = "org.freedesktop.ModemManager1";
+pub(crate) const MODEM_MANAGER_PATH: &str = "/org/freedesktop/ModemManager1";
+pub(crate) const MODEM_INTERFACE: &str = "org.freedesktop.ModemManager1.Modem";
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ModemManager1.Modem",
+    default_service = "org.freedesktop.ModemManager1"
+)]
+trait Modem {
+    /// A best-effort device identifier based on various device information, stable across reboots.
+    #[dbus_proxy(property)]
+    fn device_identifier(&self) -> zbus::Result<String>;
+
+    /// The object path of the SIM object available in the modem, or "/" if there is no SIM.
+    #[dbus_proxy(property)]
+    fn sim(&self) -> zbus::Result<OwnedObjectPath>;
+
+    /// The object paths of the packet data bearers of the modem.
+    #[dbus_proxy(property)]
+    fn bearers(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    /// Bitmask of the MMModemAccessTechnology values currently used by the modem.
+    #[dbus_proxy(property)]
+    fn access_technologies(&self) -> zbus::Result<u32>;
+
+    /// Signal quality in percent (0 - 100) and whether the value was recently taken.
+    #[dbus_proxy(property)]
+    fn signal_quality(&self) -> zbus::Result<(u32, bool)>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ModemManager1.Modem.Modem3gpp",
+    default_service = "org.freedesktop.ModemManager1"
+)]
+trait Modem3gpp {
+    /// The IMEI of the device.
+    #[dbus_proxy(property)]
+    fn imei(&self) -> zbus::Result<String>;
+
+    /// A MMModem3gppRegistrationState value specifying the mobile registration status.
+    #[dbus_proxy(property)]
+    fn registration_state(&self) -> zbus::Result<u32>;
+
+    /// Name of the operator to which the mobile is currently registered.
+    #[dbus_proxy(property)]
+    fn operator_name(&self) -> zbus::Result<String>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ModemManager1.Modem.Signal",
+    default_service = "org.freedesktop.ModemManager1"
+)]
+trait Signal {
+    /// Setup extended signal quality information retrieval, the rate is in seconds and 0 disables it.
+    fn setup(&self, rate: u32) -> zbus::Result<()>;
+
+    /// Refresh rate for the extended signal quality information, 0 if disabled.
+    #[dbus_proxy(property)]
+    fn rate(&self) -> zbus::Result<u32>;
+
+    /// GSM signal quality, with the `rssi` value in dBm.
+    #[dbus_proxy(property)]
+    fn gsm(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    /// UMTS signal quality, with the `rssi` value in dBm.
+    #[dbus_proxy(property)]
+    fn umts(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    /// LTE signal quality, with the `rssi` and `rsrp` values in dBm.
+    #[dbus_proxy(property)]
+    fn lte(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    /// 5G signal quality, with the `rsrp` value in dBm.
+    #[dbus_proxy(property)]
+    fn nr5g(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ModemManager1.Modem.Location",
+    default_service = "org.freedesktop.ModemManager1"
+)]
+trait Location {
+    /// Enable the MMModemLocationSource sources, the disabled ones are turned off.
+    fn setup(&self, sources: u32, signal_location: bool) -> zbus::Result<()>;
+
+    /// Location for each enabled MMModemLocationSource.
+    fn get_location(&self) -> zbus::Result<HashMap<u32, OwnedValue>>;
+
+    /// Bitmask of the MMModemLocationSource values supported by the modem.
+    #[dbus_proxy(property)]
+    fn capabilities(&self) -> zbus::Result<u32>;
+
+    /// Bitmask of the MMModemLocationSource values currently enabled.
+    #[dbus_proxy(property)]
+    fn enabled(&self) -> zbus::Result<u32>;
+
+    /// Whether the location is also exported in the `Location` property.
+    #[dbus_proxy(property)]
+    fn signals_location(&self) -> zbus::Result<bool>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ModemManager1.Sim",
+    default_service = "org.freedesktop.ModemManager1"
+)]
+trait Sim {
+    /// The IMSI of the SIM card, if any.
+    #[dbus_proxy(property)]
+    fn imsi(&self) -> zbus::Result<String>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.ModemManager1.Bearer",
+    default_service = "org.freedesktop.ModemManager1"
+)]
+trait Bearer {
+    /// Whether the bearer is connected.
+    #[dbus_proxy(property)]
+    fn connected(&self) -> zbus::Result<bool>;
+
+    /// The properties the bearer was created with, like the `apn`.
+    #[dbus_proxy(property, name = "Properties")]
+    fn bearer_properties(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+}
@@ -0,0 +1,420 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Operator provided overrides of the telemetry values.
+//!
+//! During maintenance an operator can force some telemetry paths to a fixed value, or suppress
+//! them, for example to mask the location of a device during transport. The overrides are read
+//! from a TOML file on startup:
+//!
+//! ```toml
+//! [[override]]
+//! interface = "io.edgehog.devicemanager.Geolocation"
+//! path = "/gpsd"
+//!
+//! [[override]]
+//! interface = "io.edgehog.devicemanager.BatteryStatus"
+//! path = "/BAT0/levelPercentage"
+//! value = 100.0
+//! ```
+//!
+//! The path of an object aggregated interface can match the whole object or a single field, an
+//! override without a value suppresses the data.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use astarte_device_sdk::store::StoredProp;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{error::Error as AstarteError, AstarteAggregate};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde::Deserialize;
+
+use crate::data::Publisher;
+use crate::error::DeviceManagerError;
+
+pub(crate) const DIAGNOSTICS_INTERFACE: &str = "io.edgehog.devicemanager.RuntimeDiagnostics";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct OverrideEntry {
+    interface: String,
+    path: String,
+    value: Option<toml::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OverridesFile {
+    #[serde(default, rename = "override")]
+    overrides: Vec<OverrideEntry>,
+}
+
+/// Value forced on a path, [`None`] to suppress it.
+#[derive(Debug, Clone, PartialEq)]
+struct Override {
+    path: String,
+    value: Option<AstarteType>,
+}
+
+/// Overrides indexed by interface.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TelemetryOverrides {
+    interfaces: HashMap<String, Vec<Override>>,
+}
+
+fn astarte_value(value: &toml::Value) -> Option<AstarteType> {
+    let value = match value {
+        toml::Value::String(value) => AstarteType::String(value.clone()),
+        toml::Value::Integer(value) => i32::try_from(*value)
+            .map(AstarteType::Integer)
+            .unwrap_or(AstarteType::LongInteger(*value)),
+        toml::Value::Float(value) => AstarteType::Double(*value),
+        toml::Value::Boolean(value) => AstarteType::Boolean(*value),
+        toml::Value::Datetime(_) | toml::Value::Array(_) | toml::Value::Table(_) => return None,
+    };
+
+    Some(value)
+}
+
+impl TelemetryOverrides {
+    /// Read the overrides from the file.
+    pub async fn read(path: &Path) -> Result<Self, DeviceManagerError> {
+        let content = tokio::fs::read_to_string(path).await?;
+
+        let overrides = Self::parse(&content)?;
+        if overrides.is_active() {
+            info!("telemetry overrides active from {}", path.display());
+        }
+
+        Ok(overrides)
+    }
+
+    fn parse(content: &str) -> Result<Self, DeviceManagerError> {
+        let file: OverridesFile = toml::from_str(content)?;
+
+        let mut interfaces: HashMap<String, Vec<Override>> = HashMap::new();
+        for entry in file.overrides {
+            let value = match &entry.value {
+                Some(value) => Some(astarte_value(value).ok_or_else(|| {
+                    DeviceManagerError::FatalError(format!(
+                        "unsupported override value for {}{}",
+                        entry.interface, entry.path
+                    ))
+                })?),
+                None => None,
+            };
+
+            interfaces
+                .entry(entry.interface)
+                .or_default()
+                .push(Override {
+                    path: entry.path,
+                    value,
+                });
+        }
+
+        Ok(Self { interfaces })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.interfaces.is_empty()
+    }
+
+    fn find(&self, interface_name: &str, path: &str) -> Option<&Override> {
+        self.interfaces
+            .get(interface_name)?
+            .iter()
+            .find(|entry| entry.path == path)
+    }
+
+    /// Apply the overrides to an individual value, returns [`None`] if it's suppressed.
+    fn apply_individual(
+        &self,
+        interface_name: &str,
+        path: &str,
+        data: AstarteType,
+    ) -> Option<AstarteType> {
+        match self.find(interface_name, path) {
+            Some(entry) => entry.value.clone(),
+            None => Some(data),
+        }
+    }
+
+    /// Apply the overrides to the fields of an object, returns [`None`] if it's suppressed.
+    fn apply_object(
+        &self,
+        interface_name: &str,
+        path: &str,
+        mut data: HashMap<String, AstarteType>,
+    ) -> Option<HashMap<String, AstarteType>> {
+        if let Some(entry) = self.find(interface_name, path) {
+            if entry.value.is_none() {
+                return None;
+            }
+        }
+
+        let path = path.trim_end_matches('/');
+        data.retain(
+            |field, value| match self.find(interface_name, &format!("{path}/{field}")) {
+                Some(Override {
+                    value: Some(forced),
+                    ..
+                }) => {
+                    *value = forced.clone();
+
+                    true
+                }
+                Some(Override { value: None, .. }) => false,
+                None => true,
+            },
+        );
+
+        Some(data)
+    }
+}
+
+/// Publisher applying the telemetry overrides.
+#[derive(Debug, Clone)]
+pub(crate) struct OverridePublisher<P> {
+    publisher: P,
+    overrides: Arc<TelemetryOverrides>,
+}
+
+impl<P> OverridePublisher<P> {
+    pub(crate) fn new(publisher: P, overrides: Arc<TelemetryOverrides>) -> Self {
+        Self {
+            publisher,
+            overrides,
+        }
+    }
+}
+
+#[async_trait]
+impl<P> Publisher for OverridePublisher<P>
+where
+    P: Publisher + Send + Sync,
+{
+    async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        // Keep the original data if there are no overrides for the interface
+        if !self.overrides.interfaces.contains_key(interface_name) {
+            return self
+                .publisher
+                .send_object(interface_name, interface_path, data)
+                .await;
+        }
+
+        let data = data.astarte_aggregate()?;
+        let Some(data) = self
+            .overrides
+            .apply_object(interface_name, interface_path, data)
+        else {
+            debug!("suppressed telemetry {interface_name}{interface_path}");
+
+            return Ok(());
+        };
+
+        self.publisher
+            .send_object(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError>
+    where
+        T: AstarteAggregate + Send + 'static,
+    {
+        if !self.overrides.interfaces.contains_key(interface_name) {
+            return self
+                .publisher
+                .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+                .await;
+        }
+
+        let data = data.astarte_aggregate()?;
+        let Some(data) = self
+            .overrides
+            .apply_object(interface_name, interface_path, data)
+        else {
+            debug!("suppressed telemetry {interface_name}{interface_path}");
+
+            return Ok(());
+        };
+
+        self.publisher
+            .send_object_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn send(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+    ) -> Result<(), AstarteError> {
+        let Some(data) = self
+            .overrides
+            .apply_individual(interface_name, interface_path, data)
+        else {
+            debug!("suppressed telemetry {interface_name}{interface_path}");
+
+            return Ok(());
+        };
+
+        self.publisher
+            .send(interface_name, interface_path, data)
+            .await
+    }
+
+    async fn send_with_timestamp(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: AstarteType,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), AstarteError> {
+        let Some(data) = self
+            .overrides
+            .apply_individual(interface_name, interface_path, data)
+        else {
+            debug!("suppressed telemetry {interface_name}{interface_path}");
+
+            return Ok(());
+        };
+
+        self.publisher
+            .send_with_timestamp(interface_name, interface_path, data, timestamp)
+            .await
+    }
+
+    async fn interface_props(&self, interface: &str) -> Result<Vec<StoredProp>, AstarteError> {
+        self.publisher.interface_props(interface).await
+    }
+
+    async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), AstarteError> {
+        self.publisher.unset(interface_name, interface_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERRIDES: &str = r#"
+        [[override]]
+        interface = "io.edgehog.devicemanager.Geolocation"
+        path = "/gpsd"
+
+        [[override]]
+        interface = "io.edgehog.devicemanager.BatteryStatus"
+        path = "/BAT0/levelPercentage"
+        value = 100.0
+
+        [[override]]
+        interface = "io.edgehog.devicemanager.BatteryStatus"
+        path = "/BAT0/status"
+
+        [[override]]
+        interface = "io.edgehog.devicemanager.SystemInfo"
+        path = "/serialNumber"
+        value = "masked"
+    "#;
+
+    #[test]
+    fn parse_overrides() {
+        let overrides = TelemetryOverrides::parse(OVERRIDES).unwrap();
+        assert!(overrides.is_active());
+
+        let empty = TelemetryOverrides::parse("").unwrap();
+        assert!(!empty.is_active());
+
+        let invalid = TelemetryOverrides::parse(
+            r#"
+            [[override]]
+            interface = "io.edgehog.devicemanager.SystemInfo"
+            path = "/serialNumber"
+            value = [1, 2]
+            "#,
+        );
+        assert!(matches!(invalid, Err(DeviceManagerError::FatalError(_))));
+    }
+
+    #[test]
+    fn apply_individual_overrides() {
+        let overrides = TelemetryOverrides::parse(OVERRIDES).unwrap();
+        let system_info = "io.edgehog.devicemanager.SystemInfo";
+
+        assert_eq!(
+            overrides.apply_individual(
+                system_info,
+                "/serialNumber",
+                AstarteType::String("42".to_string())
+            ),
+            Some(AstarteType::String("masked".to_string()))
+        );
+        assert_eq!(
+            overrides.apply_individual(
+                system_info,
+                "/partNumber",
+                AstarteType::String("42".to_string())
+            ),
+            Some(AstarteType::String("42".to_string()))
+        );
+    }
+
+    #[test]
+    fn apply_object_overrides() {
+        let overrides = TelemetryOverrides::parse(OVERRIDES).unwrap();
+
+        let position = HashMap::from([("latitude".to_string(), AstarteType::Double(45.0))]);
+        assert_eq!(
+            overrides.apply_object("io.edgehog.devicemanager.Geolocation", "/gpsd", position),
+            None
+        );
+
+        let battery = HashMap::from([
+            ("levelPercentage".to_string(), AstarteType::Double(12.0)),
+            (
+                "status".to_string(),
+                AstarteType::String("Charging".to_string()),
+            ),
+            ("levelAbsoluteError".to_string(), AstarteType::Double(0.0)),
+        ]);
+        assert_eq!(
+            overrides.apply_object("io.edgehog.devicemanager.BatteryStatus", "/BAT0", battery),
+            Some(HashMap::from([
+                ("levelPercentage".to_string(), AstarteType::Double(100.0)),
+                ("levelAbsoluteError".to_string(), AstarteType::Double(0.0)),
+            ]))
+        );
+    }
+}
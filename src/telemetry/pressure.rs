@@ -0,0 +1,88 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pressure stall information of the CPU, memory and IO.
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use procfs::{Current, PressureRecord};
+
+use crate::error::DeviceManagerError;
+
+#[derive(Debug, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct PressureStall {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    pub total_micros: i64,
+}
+
+impl From<&PressureRecord> for PressureStall {
+    fn from(value: &PressureRecord) -> Self {
+        Self {
+            avg10: value.avg10.into(),
+            avg60: value.avg60.into(),
+            avg300: value.avg300.into(),
+            total_micros: value.total.try_into().unwrap_or(i64::MAX),
+        }
+    }
+}
+
+/// get structured data for `io.edgehog.devicemanager.PressureStall` interface
+///
+/// The paths are in the form `{resource}/{some|full}`, the CPU only reports the `some` record.
+pub fn get_pressure_stall() -> Result<Vec<(String, PressureStall)>, DeviceManagerError> {
+    let cpu = procfs::CpuPressure::current()?;
+    let memory = procfs::MemoryPressure::current()?;
+    let io = procfs::IoPressure::current()?;
+
+    let records = [
+        ("cpu/some", &cpu.some),
+        ("memory/some", &memory.some),
+        ("memory/full", &memory.full),
+        ("io/some", &io.some),
+        ("io/full", &io.full),
+    ];
+
+    Ok(records
+        .into_iter()
+        .map(|(path, record)| (path.to_string(), PressureStall::from(record)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure_stall_from_record() {
+        let record = PressureRecord {
+            avg10: 1.5,
+            avg60: 0.5,
+            avg300: 0.25,
+            total: 1234,
+        };
+
+        let stall = PressureStall::from(&record);
+
+        assert_eq!(stall.avg10, 1.5);
+        assert_eq!(stall.avg60, 0.5);
+        assert_eq!(stall.avg300, 0.25);
+        assert_eq!(stall.total_micros, 1234);
+    }
+}
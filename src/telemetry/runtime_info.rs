@@ -20,23 +20,60 @@
 
 use crate::error::DeviceManagerError;
 use astarte_device_sdk::types::AstarteType;
+use edgehog_containers::image::Platform;
+use procfs::process::Process;
+use procfs::ProcResult;
 use std::collections::HashMap;
 
-/// get structured data for `io.edgehog.devicemanager.RuntimeInfo` interface
-pub fn get_runtime_info() -> Result<HashMap<String, AstarteType>, DeviceManagerError> {
-    let mut ret: HashMap<String, AstarteType> = HashMap::new();
+/// Cargo features of the runtime, with whether they are enabled.
+const FEATURES: [(&str, bool); 8] = [
+    ("message-hub", cfg!(feature = "message-hub")),
+    ("systemd", cfg!(feature = "systemd")),
+    ("forwarder", cfg!(feature = "forwarder")),
+    ("cellular", cfg!(feature = "cellular")),
+    ("wifi", cfg!(feature = "wifi")),
+    ("embedded-interfaces", cfg!(feature = "embedded-interfaces")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("e2e_test", cfg!(feature = "e2e_test")),
+];
 
-    if let Ok(f) = std::env::var("CARGO_PKG_NAME") {
-        ret.insert("/name".to_owned(), f.into());
-    }
+/// Optional features the runtime was built with.
+fn enabled_features() -> Vec<String> {
+    FEATURES
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then(|| name.to_string()))
+        .collect()
+}
 
-    if let Ok(f) = std::env::var("CARGO_PKG_HOMEPAGE") {
-        ret.insert("/url".to_owned(), f.into());
-    }
+/// OCI name of the architecture the runtime was compiled for, with the variant if any.
+fn architecture() -> String {
+    let Platform {
+        architecture,
+        variant,
+        ..
+    } = Platform::runtime();
 
-    if let Ok(f) = std::env::var("CARGO_PKG_VERSION") {
-        ret.insert("/version".to_owned(), f.into());
+    match variant {
+        Some(variant) => format!("{architecture}/{variant}"),
+        None => architecture,
     }
+}
+
+/// Unix timestamp in seconds of the start of the process.
+fn start_time() -> ProcResult<i64> {
+    let stat = Process::myself()?.stat()?;
+    let start = procfs::boot_time_secs()? + stat.starttime / procfs::ticks_per_second();
+
+    Ok(i64::try_from(start).unwrap_or(i64::MAX))
+}
+
+/// get structured data for `io.edgehog.devicemanager.RuntimeInfo` interface
+pub fn get_runtime_info() -> Result<HashMap<String, AstarteType>, DeviceManagerError> {
+    let mut ret: HashMap<String, AstarteType> = HashMap::new();
+
+    ret.insert("/name".to_owned(), env!("CARGO_PKG_NAME").into());
+    ret.insert("/url".to_owned(), env!("CARGO_PKG_HOMEPAGE").into());
+    ret.insert("/version".to_owned(), env!("CARGO_PKG_VERSION").into());
 
     ret.insert(
         "/environment".to_owned(),
@@ -45,3 +82,67 @@ pub fn get_runtime_info() -> Result<HashMap<String, AstarteType>, DeviceManagerE
 
     Ok(ret)
 }
+
+/// get structured data for `io.edgehog.devicemanager.RuntimeDetails` interface
+pub fn get_runtime_details() -> Result<HashMap<String, AstarteType>, DeviceManagerError> {
+    let mut ret: HashMap<String, AstarteType> = HashMap::new();
+
+    ret.insert("/architecture".to_owned(), architecture().into());
+
+    ret.insert(
+        "/features".to_owned(),
+        AstarteType::StringArray(enabled_features()),
+    );
+
+    ret.insert("/startTime".to_owned(), start_time()?.into());
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_info() {
+        let info = get_runtime_info().unwrap();
+
+        assert_eq!(
+            info["/version"],
+            AstarteType::String(env!("CARGO_PKG_VERSION").to_string())
+        );
+        assert!(!info.contains_key("/features"));
+    }
+
+    #[test]
+    fn runtime_details() {
+        let info = get_runtime_details().unwrap();
+
+        assert!(matches!(info["/startTime"], AstarteType::LongInteger(start) if start > 0));
+        assert_eq!(info["/architecture"], AstarteType::String(architecture()));
+        assert_eq!(
+            info["/features"],
+            AstarteType::StringArray(enabled_features())
+        );
+    }
+
+    #[test]
+    fn all_cargo_features() {
+        let manifest =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        let manifest: toml::Table = toml::from_str(&manifest).unwrap();
+
+        let features = manifest["features"].as_table().unwrap();
+        let mut names: Vec<&str> = features
+            .keys()
+            .map(String::as_str)
+            .filter(|name| *name != "default")
+            .collect();
+        names.sort_unstable();
+
+        let mut exp: Vec<&str> = FEATURES.iter().map(|(name, _)| *name).collect();
+        exp.sort_unstable();
+
+        assert_eq!(names, exp);
+    }
+}
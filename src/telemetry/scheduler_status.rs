@@ -0,0 +1,62 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use procfs::{Current, CurrentSI};
+
+use crate::error::DeviceManagerError;
+
+#[derive(Debug, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct SchedulerStatus {
+    /// Number of threads, including the kernel ones.
+    pub thread_count: i32,
+    /// Threads runnable or running on a CPU.
+    pub running_tasks: i32,
+    /// Threads blocked waiting for I/O.
+    pub blocked_tasks: i32,
+    /// Context switches since boot.
+    pub context_switches: i64,
+}
+
+/// get structured data for `io.edgehog.devicemanager.SchedulerStatus` interface
+pub fn get_scheduler_status() -> Result<SchedulerStatus, DeviceManagerError> {
+    let load_average = procfs::LoadAverage::current()?;
+    let kernel_stats = procfs::KernelStats::current()?;
+
+    Ok(SchedulerStatus {
+        thread_count: load_average.max as i32,
+        running_tasks: kernel_stats.procs_running.unwrap_or(0) as i32,
+        blocked_tasks: kernel_stats.procs_blocked.unwrap_or(0) as i32,
+        context_switches: kernel_stats.ctxt as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_scheduler_status_test() {
+        let status = get_scheduler_status().unwrap();
+
+        assert!(status.thread_count > 0);
+        assert!(status.running_tasks >= 0);
+        assert!(status.context_switches > 0);
+    }
+}
@@ -0,0 +1,139 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Custom telemetry read from the output of a script.
+//!
+//! The script runs through the [`Executor`] and prints a JSON object from the paths of the
+//! interface to the values, e.g. `{"/sensor/temperature": 21.5}`.
+
+use std::path::Path;
+
+use astarte_device_sdk::types::AstarteType;
+use serde_json::{Map, Value};
+
+use crate::executor::{Executor, ExecutorError};
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ScriptError {
+    /// script {0} failed
+    Failed(String),
+    /// invalid output of script {script}
+    Output {
+        #[source]
+        backtrace: serde_json::Error,
+        script: String,
+    },
+    /// unsupported value for the path {0}
+    Value(String),
+    /// couldn't execute the script
+    Executor(#[from] ExecutorError),
+}
+
+/// Run the script and convert the values it prints.
+pub(crate) async fn get_script_data(
+    script: &Path,
+) -> Result<Vec<(String, AstarteType)>, ScriptError> {
+    let output = Executor::new(script.to_string_lossy()).output().await?;
+
+    if !output.success() || output.truncated {
+        return Err(ScriptError::Failed(script.display().to_string()));
+    }
+
+    let values: Map<String, Value> =
+        serde_json::from_slice(&output.stdout).map_err(|err| ScriptError::Output {
+            backtrace: err,
+            script: script.display().to_string(),
+        })?;
+
+    values
+        .into_iter()
+        .map(|(path, value)| match astarte_value(value) {
+            Some(value) => Ok((path, value)),
+            None => Err(ScriptError::Value(path)),
+        })
+        .collect()
+}
+
+/// Convert a JSON value, the integers are sent with the smallest type they fit in.
+fn astarte_value(value: Value) -> Option<AstarteType> {
+    let value = match value {
+        Value::Bool(value) => AstarteType::Boolean(value),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => i32::try_from(value)
+                .map(AstarteType::Integer)
+                .unwrap_or(AstarteType::LongInteger(value)),
+            None => AstarteType::Double(number.as_f64()?),
+        },
+        Value::String(value) => AstarteType::String(value),
+        Value::Null | Value::Array(_) | Value::Object(_) => return None,
+    };
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    #[tokio::test]
+    async fn script_values() {
+        let dir = tempdir::TempDir::new("edgehog-script").unwrap();
+        let script = dir.path().join("telemetry.sh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho '{\"/count\": 3, \"/uptime\": 5000000000, \"/temperature\": 21.5, \"/name\": \"sensor\", \"/ok\": true}'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut data = get_script_data(&script).await.unwrap();
+        data.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        assert_eq!(
+            data,
+            [
+                ("/count".to_string(), AstarteType::Integer(3)),
+                (
+                    "/name".to_string(),
+                    AstarteType::String("sensor".to_string())
+                ),
+                ("/ok".to_string(), AstarteType::Boolean(true)),
+                ("/temperature".to_string(), AstarteType::Double(21.5)),
+                (
+                    "/uptime".to_string(),
+                    AstarteType::LongInteger(5_000_000_000)
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn script_failure() {
+        let res = get_script_data(Path::new("false")).await;
+
+        assert!(matches!(res, Err(ScriptError::Failed(_))));
+    }
+
+    #[test]
+    fn unsupported_values() {
+        assert_eq!(astarte_value(Value::Null), None);
+        assert_eq!(astarte_value(serde_json::json!([1, 2])), None);
+    }
+}
@@ -18,9 +18,11 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
+use std::collections::HashMap;
+use std::path::Path;
+
 use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
 use log::{error, warn};
-use std::collections::HashMap;
 use sysinfo::{DiskExt, System, SystemExt};
 
 #[derive(Debug, AstarteAggregate)]
@@ -31,34 +33,44 @@ pub struct DiskUsage {
 }
 
 /// get structured data for `io.edgehog.devicemanager.StorageUsage` interface
-/// /dev/ is excluded from the device names since it is common for all devices
+///
+/// The data is collected for each mounted filesystem, pseudo and network filesystems are excluded.
+/// The mount point is converted into a label usable in the interface path.
 pub fn get_storage_usage() -> HashMap<String, DiskUsage> {
-    let mut sys = System::new_all();
-    sys.refresh_disks();
-
-    sys.disks()
-        .iter()
-        .filter_map(|disk| {
-            let Some(name) = disk.name().to_str() else {
-                warn!("non-utf8 path {}, ignoring", disk.name().to_string_lossy());
+    let mut sys = System::new();
+    sys.refresh_disks_list();
+
+    storage_usage(sys.disks().iter().map(|disk| {
+        (
+            disk.mount_point(),
+            disk.total_space(),
+            disk.available_space(),
+        )
+    }))
+}
+
+/// Labels the usage of each mount point, given as `(mount_point, total, available)`.
+fn storage_usage<'a, I>(disks: I) -> HashMap<String, DiskUsage>
+where
+    I: IntoIterator<Item = (&'a Path, u64, u64)>,
+{
+    disks
+        .into_iter()
+        .filter_map(|(mount_point, total, available)| {
+            let Some(label) = mount_point_label(mount_point) else {
+                warn!("non-utf8 mount point {}, ignoring", mount_point.display());
                 return None;
             };
-            let name = name.strip_prefix("/dev/").unwrap_or(name);
-            // remove disks with a higher depth
-            if name.contains('/') {
-                warn!("not simple disks device, ignoring");
-                return None;
-            }
-            let Ok(total_bytes) = disk.total_space().try_into() else {
+            let Ok(total_bytes) = total.try_into() else {
                 error!("disk size too big, ignoring");
                 return None;
             };
-            let Ok(free_bytes) = disk.available_space().try_into() else {
+            let Ok(free_bytes) = available.try_into() else {
                 error!("available space too big, ignoring");
                 return None;
             };
             Some((
-                name.to_string(),
+                label,
                 DiskUsage {
                     total_bytes,
                     free_bytes,
@@ -67,3 +79,87 @@ pub fn get_storage_usage() -> HashMap<String, DiskUsage> {
         })
         .collect()
 }
+
+/// Converts a mount point into a single path segment.
+///
+/// Each `/` is replaced by `_`, while a `_` or `-` in the path is escaped with a `-`. The encoding
+/// can be reversed, so two different mount points never share a label: `/` is labeled `_`,
+/// `/var/lib` is `_var_lib` and `/var_lib` is `_var-_lib`.
+fn mount_point_label(mount_point: &Path) -> Option<String> {
+    let mount_point = mount_point.to_str()?;
+
+    let mut label = String::with_capacity(mount_point.len());
+    for c in mount_point.chars() {
+        match c {
+            '/' => label.push('_'),
+            '_' | '-' => {
+                label.push('-');
+                label.push(c);
+            }
+            c => label.push(c),
+        }
+    }
+
+    Some(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_point_labels() {
+        let cases = [
+            ("/", "_"),
+            ("/root", "_root"),
+            ("/data", "_data"),
+            ("/var/lib/edgehog", "_var_lib_edgehog"),
+            ("/var_lib", "_var-_lib"),
+            ("/var-/lib", "_var--_lib"),
+        ];
+
+        for (mount_point, exp) in cases {
+            assert_eq!(
+                mount_point_label(Path::new(mount_point)).as_deref(),
+                Some(exp)
+            );
+        }
+    }
+
+    #[test]
+    fn storage_usage_of_mount_points() {
+        let disks = [
+            (Path::new("/"), 1000, 400),
+            (Path::new("/root"), 200, 100),
+            (Path::new("/var/lib"), 300, 200),
+            (Path::new("/var_lib"), 400, 300),
+            (Path::new("/big"), u64::MAX, 0),
+        ];
+
+        let usage = storage_usage(disks);
+
+        let mut labels: Vec<_> = usage.keys().map(String::as_str).collect();
+        labels.sort_unstable();
+        assert_eq!(labels, ["_", "_root", "_var-_lib", "_var_lib"]);
+
+        let root = &usage["_"];
+        assert_eq!(root.total_bytes, 1000);
+        assert_eq!(root.free_bytes, 400);
+
+        let var_lib = &usage["_var-_lib"];
+        assert_eq!(var_lib.total_bytes, 400);
+        assert_eq!(var_lib.free_bytes, 300);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_mount_point() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mount_point = Path::new(OsStr::from_bytes(b"/mnt/\xff"));
+
+        assert!(mount_point_label(mount_point).is_none());
+        assert!(storage_usage([(mount_point, 10, 5)]).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Temperature of the thermal zones exposed in sysfs.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{debug, warn};
+
+use crate::error::DeviceManagerError;
+
+const THERMAL_CLASS_PATH: &str = "/sys/class/thermal";
+
+#[derive(Debug, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct ThermalZone {
+    pub zone_type: String,
+    pub temperature_celsius: f64,
+}
+
+/// get structured data for `io.edgehog.devicemanager.ThermalZones` interface
+pub fn get_thermal_zones() -> Result<HashMap<String, ThermalZone>, DeviceManagerError> {
+    read_thermal_zones(Path::new(THERMAL_CLASS_PATH))
+}
+
+fn read_thermal_zones(base: &Path) -> Result<HashMap<String, ThermalZone>, DeviceManagerError> {
+    let mut zones = HashMap::new();
+
+    let entries = match std::fs::read_dir(base) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            debug!("no thermal class in sysfs");
+
+            return Ok(zones);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+
+        if !name.starts_with("thermal_zone") {
+            continue;
+        }
+
+        match read_thermal_zone(&entry.path()) {
+            Ok(zone) => {
+                zones.insert(name, zone);
+            }
+            Err(err) => warn!("couldn't read thermal zone {name}: {err}"),
+        }
+    }
+
+    Ok(zones)
+}
+
+fn read_thermal_zone(path: &Path) -> Result<ThermalZone, DeviceManagerError> {
+    let zone_type = std::fs::read_to_string(path.join("type"))?;
+    let temp = std::fs::read_to_string(path.join("temp"))?;
+
+    // The temperature is in millidegree Celsius
+    let millidegree: i64 = temp.trim().parse()?;
+
+    Ok(ThermalZone {
+        zone_type: zone_type.trim().to_string(),
+        temperature_celsius: millidegree as f64 / 1000.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    fn create_zone(base: &Path, name: &str, zone_type: &str, temp: &str) {
+        let dir = base.join(name);
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("type"), zone_type).unwrap();
+        std::fs::write(dir.join("temp"), temp).unwrap();
+    }
+
+    #[test]
+    fn read_thermal_zones_success() {
+        let dir = TempDir::new("edgehog-thermal").unwrap();
+        create_zone(dir.path(), "thermal_zone0", "cpu-thermal\n", "42500\n");
+        create_zone(dir.path(), "thermal_zone1", "gpu-thermal\n", "invalid\n");
+        create_zone(dir.path(), "cooling_device0", "fan\n", "0\n");
+
+        let zones = read_thermal_zones(dir.path()).unwrap();
+
+        assert_eq!(zones.len(), 1);
+        let zone = &zones["thermal_zone0"];
+        assert_eq!(zone.zone_type, "cpu-thermal");
+        assert_eq!(zone.temperature_celsius, 42.5);
+    }
+
+    #[test]
+    fn read_thermal_zones_missing() {
+        let zones = read_thermal_zones(Path::new("/this/path/does/not/exist")).unwrap();
+
+        assert!(zones.is_empty());
+    }
+}
@@ -18,9 +18,16 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
-use crate::DeviceManagerError;
+//! WiFi access points visible from the device, read from wpa_supplicant over D-Bus or from a scan
+//! with the wireless tools when wpa_supplicant isn't running.
+
 use astarte_device_sdk::AstarteAggregate;
+use log::debug;
 use wifiscanner::Wifi;
+use zbus::Connection;
+
+use crate::telemetry::wpa_supplicant::{BssProxy, InterfaceProxy, WpaSupplicantProxy};
+use crate::DeviceManagerError;
 
 #[derive(Debug, AstarteAggregate, PartialEq)]
 #[allow(non_snake_case)]
@@ -32,7 +39,13 @@ pub struct WifiScanResult {
     rssi: i32,
 }
 
-pub fn get_wifi_scan_results() -> Result<Vec<WifiScanResult>, DeviceManagerError> {
+/// get structured data for `io.edgehog.devicemanager.WiFiScanResults` interface
+pub async fn get_wifi_scan_results() -> Result<Vec<WifiScanResult>, DeviceManagerError> {
+    match wpa_supplicant_scan_results().await {
+        Ok(results) => return Ok(results),
+        Err(err) => debug!("couldn't read the scan results from wpa_supplicant: {err}"),
+    }
+
     let mut ret = Vec::new();
     if let Ok(wifi_array) = wifiscanner::scan() {
         for wifi in wifi_array {
@@ -44,6 +57,60 @@ pub fn get_wifi_scan_results() -> Result<Vec<WifiScanResult>, DeviceManagerError
     Ok(ret)
 }
 
+async fn wpa_supplicant_scan_results() -> Result<Vec<WifiScanResult>, zbus::Error> {
+    let connection = Connection::system().await?;
+    let wpa_supplicant = WpaSupplicantProxy::new(&connection).await?;
+
+    let mut ret = Vec::new();
+    for path in wpa_supplicant.interfaces().await? {
+        let interface = InterfaceProxy::builder(&connection)
+            .path(path)?
+            .build()
+            .await?;
+
+        let current = interface.current_bss().await?;
+
+        for bss_path in interface.bsss().await? {
+            let connected = bss_path == current;
+            let bss = BssProxy::builder(&connection)
+                .path(bss_path)?
+                .build()
+                .await?;
+
+            ret.push(WifiScanResult {
+                channel: frequency_to_channel(bss.frequency().await?),
+                connected,
+                essid: String::from_utf8_lossy(&bss.ssid().await?).into_owned(),
+                macAddress: format_bssid(&bss.bssid().await?),
+                rssi: bss.signal().await?.into(),
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Channel number of a frequency in MHz, 0 if it's outside of the 2.4, 5 and 6 GHz bands.
+fn frequency_to_channel(frequency: u16) -> i32 {
+    let frequency = i32::from(frequency);
+
+    match frequency {
+        2484 => 14,
+        2412..=2472 => (frequency - 2407) / 5,
+        5160..=5885 => (frequency - 5000) / 5,
+        5955..=7115 => (frequency - 5950) / 5,
+        _ => 0,
+    }
+}
+
+fn format_bssid(bssid: &[u8]) -> String {
+    bssid
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
 impl TryFrom<Wifi> for WifiScanResult {
     type Error = DeviceManagerError;
     fn try_from(wifi: Wifi) -> Result<Self, Self::Error> {
@@ -59,12 +126,32 @@ impl TryFrom<Wifi> for WifiScanResult {
 
 #[cfg(test)]
 mod tests {
-    use crate::telemetry::wifi_scan::{get_wifi_scan_results, WifiScanResult};
+    use crate::telemetry::wifi_scan::{
+        format_bssid, frequency_to_channel, get_wifi_scan_results, WifiScanResult,
+    };
     use wifiscanner::Wifi;
 
+    #[tokio::test]
+    async fn wifi_scan_test() {
+        assert!(get_wifi_scan_results().await.is_ok());
+    }
+
     #[test]
-    fn wifi_scan_test() {
-        assert!(get_wifi_scan_results().is_ok());
+    fn channel_from_frequency() {
+        assert_eq!(frequency_to_channel(2412), 1);
+        assert_eq!(frequency_to_channel(2437), 6);
+        assert_eq!(frequency_to_channel(2484), 14);
+        assert_eq!(frequency_to_channel(5180), 36);
+        assert_eq!(frequency_to_channel(5975), 5);
+        assert_eq!(frequency_to_channel(900), 0);
+    }
+
+    #[test]
+    fn bssid_format() {
+        assert_eq!(
+            format_bssid(&[0xab, 0xcd, 0xef, 0x01, 0x23, 0x45]),
+            "ab:cd:ef:01:23:45"
+        );
     }
 
     #[test]
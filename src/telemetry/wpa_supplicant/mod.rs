@@ -0,0 +1,71 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Proxies for the wpa_supplicant D-Bus API.
+//!
+//! See the [wpa_supplicant D-Bus API](https://w1.fi/wpa_supplicant/devel/dbus.html).
+
+use zbus::dbus_proxy;
+use zbus::zvariant::OwnedObjectPath;
+
+#[dbus_proxy(
+    interface = "fi.w1.wpa_supplicant1",
+    default_service = "fi.w1.wpa_supplicant1",
+    default_path = "/fi/w1/wpa_supplicant1"
+)]
+trait WpaSupplicant {
+    /// The network interfaces controlled by wpa_supplicant.
+    #[dbus_proxy(property)]
+    fn interfaces(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[dbus_proxy(
+    interface = "fi.w1.wpa_supplicant1.Interface",
+    default_service = "fi.w1.wpa_supplicant1"
+)]
+trait Interface {
+    /// The BSSs found by the last scans.
+    #[dbus_proxy(property, name = "BSSs")]
+    fn bsss(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    /// The BSS the interface is associated with, or "/" if it isn't connected.
+    #[dbus_proxy(property, name = "CurrentBSS")]
+    fn current_bss(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    interface = "fi.w1.wpa_supplicant1.BSS",
+    default_service = "fi.w1.wpa_supplicant1"
+)]
+trait Bss {
+    /// The BSSID of the BSS.
+    #[dbus_proxy(property, name = "BSSID")]
+    fn bssid(&self) -> zbus::Result<Vec<u8>>;
+
+    /// The SSID of the BSS.
+    #[dbus_proxy(property, name = "SSID")]
+    fn ssid(&self) -> zbus::Result<Vec<u8>>;
+
+    /// Frequency of the BSS in MHz.
+    #[dbus_proxy(property)]
+    fn frequency(&self) -> zbus::Result<u16>;
+
+    /// Signal strength of the BSS in dBm.
+    #[dbus_proxy(property)]
+    fn signal(&self) -> zbus::Result<i16>;
+}
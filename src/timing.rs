@@ -0,0 +1,155 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Timing of the startup phases.
+//!
+//! The duration of each phase is logged and published once the runtime is initialized, so boot
+//! time regressions can be tracked across the runtime releases on real hardware.
+
+use std::time::Duration;
+
+use astarte_device_sdk::{astarte_aggregate, AstarteAggregate};
+use log::{error, info};
+use tokio::time::Instant;
+
+use crate::data::Publisher;
+
+const STARTUP_TIMING_INTERFACE: &str = "io.edgehog.devicemanager.StartupTiming";
+
+/// Measures the consecutive phases of the startup.
+#[derive(Debug)]
+pub struct StartupTimer {
+    start: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl StartupTimer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last: now,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Record the end of a phase, started at the end of the previous one.
+    pub fn phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+
+        self.phases.push((name, now - self.last));
+        self.last = now;
+    }
+
+    fn total(&self) -> Duration {
+        self.last - self.start
+    }
+
+    fn summary(&self) -> String {
+        self.phases
+            .iter()
+            .map(|(name, duration)| format!("{name}={}ms", duration.as_millis()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Log the summary and publish the timing breakdown.
+    pub async fn report<P>(&self, publisher: &P)
+    where
+        P: Publisher + Send + Sync,
+    {
+        info!(
+            "startup completed in {}ms: {}",
+            self.total().as_millis(),
+            self.summary()
+        );
+
+        let timing = StartupTiming::from(self);
+        if let Err(err) = publisher
+            .send_object(STARTUP_TIMING_INTERFACE, "/startup", timing)
+            .await
+        {
+            error!("couldn't send the startup timing: {err}");
+        }
+    }
+}
+
+fn millis(duration: Duration) -> i64 {
+    duration.as_millis().try_into().unwrap_or(i64::MAX)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+pub struct StartupTiming {
+    pub runtime_version: String,
+    pub phases: Vec<String>,
+    pub durations_ms: Vec<i64>,
+    pub total_ms: i64,
+}
+
+impl From<&StartupTimer> for StartupTiming {
+    fn from(value: &StartupTimer) -> Self {
+        let (phases, durations_ms) = value
+            .phases
+            .iter()
+            .map(|(name, duration)| (name.to_string(), millis(*duration)))
+            .unzip();
+
+        Self {
+            runtime_version: env!("CARGO_PKG_VERSION").to_string(),
+            phases,
+            durations_ms,
+            total_ms: millis(value.total()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::tests::MockPublisher;
+
+    #[tokio::test(start_paused = true)]
+    async fn report_startup_phases() {
+        let mut timer = StartupTimer::start();
+
+        tokio::time::advance(Duration::from_millis(10)).await;
+        timer.phase("config_load");
+        tokio::time::advance(Duration::from_millis(250)).await;
+        timer.phase("astarte_connect");
+
+        assert_eq!(timer.summary(), "config_load=10ms astarte_connect=250ms");
+
+        let mut publisher = MockPublisher::new();
+        publisher
+            .expect_send_object()
+            .withf(|iface: &str, path: &str, timing: &StartupTiming| {
+                iface == STARTUP_TIMING_INTERFACE
+                    && path == "/startup"
+                    && timing.phases == ["config_load", "astarte_connect"]
+                    && timing.durations_ms == [10, 250]
+                    && timing.total_ms == 260
+            })
+            .returning(|_: &str, _: &str, _: StartupTiming| Ok(()));
+
+        timer.report(&publisher).await;
+    }
+}
@@ -0,0 +1,172 @@
+// This file is part of Edgehog.
+//
+// Copyright 2024 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Detection of tasks blocking the async runtime.
+//!
+//! On single core devices a blocking call in a task stalls every other task. The watchdog measures
+//! how late a periodic probe task is scheduled by the runtime, logging a warning when the latency
+//! exceeds the threshold. Only the scheduling delay is measured, since the time spent by a task
+//! across its awaits includes the time waiting on I/O.
+//!
+//! When the systemd watchdog is enabled, the main loop also sends the keep-alive pings, so a hung
+//! runtime is restarted by systemd.
+
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+use log::warn;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval};
+
+fn default_interval_ms() -> NonZeroU64 {
+    NonZeroU64::new(1000).expect("the default interval is not zero")
+}
+
+const fn default_threshold_ms() -> u64 {
+    200
+}
+
+/// Configuration of the runtime watchdog.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WatchdogConfig {
+    /// Milliseconds between two probes of the runtime latency, zero is rejected.
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: NonZeroU64,
+    /// Latency in milliseconds after which a task is reported as stalled.
+    #[serde(default = "default_threshold_ms")]
+    pub threshold_ms: u64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_interval_ms(),
+            threshold_ms: default_threshold_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Watchdog {
+    interval: Duration,
+    threshold: Duration,
+}
+
+impl Watchdog {
+    pub(crate) fn new(config: &WatchdogConfig) -> Self {
+        Self {
+            interval: Duration::from_millis(config.interval_ms.get()),
+            threshold: Duration::from_millis(config.threshold_ms),
+        }
+    }
+
+    /// Spawn a task that checks how late the runtime wakes it up.
+    pub(crate) fn spawn_probe(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let deadline = Instant::now() + self.interval;
+
+                tokio::time::sleep_until(deadline).await;
+                crate::health::tick();
+
+                let lag = deadline.elapsed();
+                if lag > self.threshold {
+                    report("runtime", lag, self.threshold);
+                }
+            }
+        })
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new(&WatchdogConfig::default())
+    }
+}
+
+/// Periodic keep-alive of the systemd watchdog, polled by the main loop.
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+    interval: Option<Interval>,
+}
+
+impl Heartbeat {
+    pub(crate) fn new() -> Self {
+        #[cfg(feature = "systemd")]
+        let interval = crate::systemd_wrapper::watchdog_interval().map(tokio::time::interval);
+        #[cfg(not(feature = "systemd"))]
+        let interval = None;
+
+        Self { interval }
+    }
+
+    /// Wait for the next ping, never completes if the systemd watchdog is disabled.
+    pub(crate) async fn tick(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    pub(crate) fn notify(&self) {
+        #[cfg(feature = "systemd")]
+        crate::systemd_wrapper::systemd_notify_watchdog();
+    }
+}
+
+fn report(task: &str, latency: Duration, threshold: Duration) {
+    let thread = std::thread::current();
+    let workers = tokio::runtime::Handle::try_current()
+        .map(|handle| handle.metrics().num_workers())
+        .unwrap_or_default();
+
+    warn!(
+        "task stalled: task={task} latency_ms={} threshold_ms={} thread={} workers={workers}",
+        latency.as_millis(),
+        threshold.as_millis(),
+        thread.name().unwrap_or("unnamed"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_defaults() {
+        let config: WatchdogConfig = toml::from_str("threshold_ms = 50").unwrap();
+
+        assert_eq!(
+            config,
+            WatchdogConfig {
+                interval_ms: default_interval_ms(),
+                threshold_ms: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn config_zero_interval() {
+        let res = toml::from_str::<WatchdogConfig>("interval_ms = 0");
+
+        assert!(res.is_err());
+    }
+}